@@ -146,10 +146,10 @@ class LibraryTest {
         val nodeId2 = node2.nodeId()
         println("Node Id 2: $nodeId2")
 
-        val address1 = node1.onchainPayment().newAddress()
+        val address1 = node1.onchainPayment().newAddress(null)
         println("Funding address 1: $address1")
 
-        val address2 = node2.onchainPayment().newAddress()
+        val address2 = node2.onchainPayment().newAddress(null)
         println("Funding address 2: $address2")
 
         val txid1 = sendToAddress(address1, 100000u)
@@ -175,7 +175,7 @@ class LibraryTest {
         assertEquals(100000uL, totalBalance1)
         assertEquals(100000uL, totalBalance2)
 
-        node1.openChannel(nodeId2, listenAddress2, 50000u, null, null)
+        node1.openChannel(nodeId2, listenAddress2, 50000u, null, null, null, null)
 
         val channelPendingEvent1 = node1.waitNextEvent()
         println("Got event: $channelPendingEvent1")
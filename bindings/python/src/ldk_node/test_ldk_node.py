@@ -125,9 +125,9 @@ class TestLdkNode(unittest.TestCase):
         node_id_2 = node_2.node_id()
         print("Node ID 2:", node_id_2)
 
-        address_1 = node_1.onchain_payment().new_address()
+        address_1 = node_1.onchain_payment().new_address(None)
         txid_1 = send_to_address(address_1, 100000)
-        address_2 = node_2.onchain_payment().new_address()
+        address_2 = node_2.onchain_payment().new_address(None)
         txid_2 = send_to_address(address_2, 100000)
 
         wait_for_tx(esplora_endpoint, txid_1)
@@ -155,7 +155,7 @@ class TestLdkNode(unittest.TestCase):
         print("TOTAL 2:", total_balance_2)
         self.assertEqual(total_balance_2, 100000)
 
-        node_1.open_channel(node_id_2, listening_addresses_2[0], 50000, None, None)
+        node_1.open_channel(node_id_2, listening_addresses_2[0], 50000, None, None, None, None)
 
         channel_pending_event_1 = node_1.wait_next_event()
         assert isinstance(channel_pending_event_1, Event.CHANNEL_PENDING)
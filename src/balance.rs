@@ -29,7 +29,11 @@ pub struct BalanceDetails {
 	/// This includes any sufficiently confirmed funds, minus
 	/// [`total_anchor_channels_reserve_sats`].
 	///
+	/// This also includes funds received on addresses of other than the configured address type,
+	/// see [`NodeBuilder::set_onchain_wallet_address_type`].
+	///
 	/// [`total_anchor_channels_reserve_sats`]: Self::total_anchor_channels_reserve_sats
+	/// [`NodeBuilder::set_onchain_wallet_address_type`]: crate::NodeBuilder::set_onchain_wallet_address_type
 	pub spendable_onchain_balance_sats: u64,
 	/// The share of our total balance that we retain as an emergency reserve to (hopefully) be
 	/// able to spend the Anchor outputs when one of our channels is closed.
@@ -67,6 +71,25 @@ pub struct BalanceDetails {
 	///
 	/// [`total_onchain_balance_sats`]: Self::total_onchain_balance_sats
 	pub pending_balances_from_channel_closures: Vec<PendingSweepBalance>,
+	/// The balances of the descriptors imported via [`OnchainPayment::import_watch_only_descriptor`].
+	///
+	/// Note these funds can't be spent by our on-chain wallet and are therefore not included in
+	/// [`total_onchain_balance_sats`].
+	///
+	/// [`OnchainPayment::import_watch_only_descriptor`]: crate::payment::OnchainPayment::import_watch_only_descriptor
+	/// [`total_onchain_balance_sats`]: Self::total_onchain_balance_sats
+	pub watch_only_balances: Vec<WatchOnlyBalance>,
+}
+
+/// The balance of a descriptor we watch on behalf of the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchOnlyBalance {
+	/// The imported descriptor.
+	pub descriptor: String,
+	/// The total balance held by outputs of the descriptor.
+	pub total_balance_sats: u64,
+	/// The balance held by confirmed outputs of the descriptor.
+	pub confirmed_balance_sats: u64,
 }
 
 /// Details about the status of a known Lightning balance.
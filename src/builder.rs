@@ -6,27 +6,39 @@
 // accordance with one or both of these licenses.
 
 use crate::chain::{ChainSource, DEFAULT_ESPLORA_SERVER_URL};
-use crate::config::{default_user_config, Config, EsploraSyncConfig, WALLET_KEYS_SEED_LEN};
+use crate::config::{
+	default_user_config, AddressType, Config, EsploraSyncConfig, WALLET_KEYS_SEED_LEN,
+};
 
 use crate::connection::ConnectionManager;
 use crate::event::EventQueue;
 use crate::fee_estimator::OnchainFeeEstimator;
 use crate::gossip::GossipSource;
+use crate::hrn_resolver::HrnResolver;
 use crate::io::sqlite_store::SqliteStore;
-use crate::io::utils::{read_node_metrics, write_node_metrics};
+use crate::io::utils::{
+	read_frozen_utxos, read_node_metrics, read_watch_only_descriptors, write_node_metrics,
+};
 use crate::io::vss_store::VssStore;
+use crate::label_store::LabelStore;
 use crate::liquidity::LiquiditySource;
 use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
-use crate::message_handler::NodeCustomMessageHandler;
+use crate::message_handler::{NodeCustomMessageHandler, NodeOffersMessageHandler};
+use crate::payment::hold_invoice::HoldInvoiceManager;
+use crate::payment::offer_store::OfferStore;
+use crate::payment::payjoin::PayjoinReceiver;
+use crate::payment::retry::PaymentRetryStore;
 use crate::payment::store::PaymentStore;
 use crate::peer_store::PeerStore;
+use crate::probing::Prober;
+use crate::scheduler::{PaymentScheduler, ScheduledPaymentSpending};
 use crate::tx_broadcaster::TransactionBroadcaster;
 use crate::types::{
 	ChainMonitor, ChannelManager, DynStore, GossipSync, Graph, KeysManager, MessageRouter,
 	OnionMessenger, PeerManager,
 };
 use crate::wallet::persist::KVStoreWalletPersister;
-use crate::wallet::Wallet;
+use crate::wallet::{setup_watch_only_wallet, UnusedWallet, Wallet, WalletDescriptor};
 use crate::{io, NodeMetrics};
 use crate::{LogLevel, Node};
 
@@ -54,17 +66,20 @@ use lightning_persister::fs_store::FilesystemStore;
 use lightning_liquidity::lsps2::client::LSPS2ClientConfig;
 use lightning_liquidity::{LiquidityClientConfig, LiquidityManager};
 
-use bdk_wallet::template::Bip84;
-use bdk_wallet::KeychainKind;
+use bdk_wallet::descriptor::DescriptorError;
+use bdk_wallet::template::{
+	Bip49, Bip49Public, Bip84, Bip84Public, Bip86, Bip86Public, DescriptorTemplate,
+};
 use bdk_wallet::Wallet as BdkWallet;
+use bdk_wallet::{KeychainKind, PersistedWallet, Update};
 
 use bip39::Mnemonic;
 
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::{BlockHash, Network};
 
-use bitcoin::bip32::{ChildNumber, Xpriv};
-use std::collections::HashMap;
+use bitcoin::bip32::{ChildNumber, Fingerprint, Xpriv, Xpub};
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::default::Default;
 use std::fmt;
@@ -106,6 +121,14 @@ impl Default for LiquiditySourceConfig {
 	}
 }
 
+#[derive(Debug, Clone)]
+struct WatchOnlyWalletConfig {
+	// The account-level extended public key and the fingerprint of the master key it was derived
+	// from.
+	account_xpub: Xpub,
+	master_fingerprint: Fingerprint,
+}
+
 /// An error encountered during building a [`Node`].
 ///
 /// [`Node`]: crate::Node
@@ -123,6 +146,8 @@ pub enum BuildError {
 	InvalidListeningAddresses,
 	/// The provided alias is invalid.
 	InvalidNodeAlias,
+	/// The given payjoin configuration is invalid, e.g., the endpoint URL doesn't use HTTPS.
+	InvalidPayjoinConfig,
 	/// We failed to read data from the [`KVStore`].
 	///
 	/// [`KVStore`]: lightning::util::persist::KVStore
@@ -162,6 +187,7 @@ impl fmt::Display for BuildError {
 			Self::WalletSetupFailed => write!(f, "Failed to setup onchain wallet."),
 			Self::LoggerSetupFailed => write!(f, "Failed to setup the logger."),
 			Self::InvalidNodeAlias => write!(f, "Given node alias is invalid."),
+			Self::InvalidPayjoinConfig => write!(f, "Given payjoin configuration is invalid."),
 		}
 	}
 }
@@ -182,6 +208,8 @@ pub struct NodeBuilder {
 	chain_data_source_config: Option<ChainDataSourceConfig>,
 	gossip_source_config: Option<GossipSourceConfig>,
 	liquidity_source_config: Option<LiquiditySourceConfig>,
+	watch_only_wallet_config: Option<WatchOnlyWalletConfig>,
+	onchain_wallet_address_type: AddressType,
 }
 
 impl NodeBuilder {
@@ -197,12 +225,16 @@ impl NodeBuilder {
 		let chain_data_source_config = None;
 		let gossip_source_config = None;
 		let liquidity_source_config = None;
+		let watch_only_wallet_config = None;
+		let onchain_wallet_address_type = AddressType::default();
 		Self {
 			config,
 			entropy_source_config,
 			chain_data_source_config,
 			gossip_source_config,
 			liquidity_source_config,
+			watch_only_wallet_config,
+			onchain_wallet_address_type,
 		}
 	}
 
@@ -292,6 +324,61 @@ impl NodeBuilder {
 		self
 	}
 
+	/// Configures the [`Node`] instance to use a watch-only on-chain wallet derived from the given
+	/// account-level extended public key and the fingerprint of the master key it was derived from.
+	///
+	/// The key is expected to be derived according to the configured address type, e.g., at
+	/// `m/84'/<coin>'/<account>'` as per [BIP 84] by default, see
+	/// [`set_onchain_wallet_address_type`]. Watch-only wallets don't support
+	/// [`AddressType::NestedSegwit`].
+	///
+	/// As Anchor channels require the on-chain wallet to sign fee-bumping transactions on short
+	/// notice, they aren't supported by watch-only wallets, i.e.,
+	/// [`Config::anchor_channels_config`] needs to be unset.
+	///
+	/// The spending authority over the on-chain funds then remains with an external signer, and
+	/// transactions need to be created via [`OnchainPayment::create_psbt`] and broadcast via
+	/// [`OnchainPayment::finalize_and_broadcast`] once signed. Operations requiring the on-chain
+	/// wallet to sign internally, e.g., [`OnchainPayment::send_to_address`] or funding channels
+	/// via [`Node::open_channel`], will fail.
+	///
+	/// **Note:** The configured wallet entropy is still used to derive the node's Lightning keys.
+	///
+	/// [BIP 84]: https://github.com/bitcoin/bips/blob/master/bip-0084.mediawiki
+	/// [`set_onchain_wallet_address_type`]: Self::set_onchain_wallet_address_type
+	/// [`Config::anchor_channels_config`]: crate::config::Config::anchor_channels_config
+	/// [`OnchainPayment::create_psbt`]: crate::payment::OnchainPayment::create_psbt
+	/// [`OnchainPayment::finalize_and_broadcast`]: crate::payment::OnchainPayment::finalize_and_broadcast
+	/// [`OnchainPayment::send_to_address`]: crate::payment::OnchainPayment::send_to_address
+	/// [`Node::open_channel`]: crate::Node::open_channel
+	pub fn set_onchain_wallet_watch_only(
+		&mut self, account_xpub: Xpub, master_fingerprint: Fingerprint,
+	) -> &mut Self {
+		self.watch_only_wallet_config =
+			Some(WatchOnlyWalletConfig { account_xpub, master_fingerprint });
+		self
+	}
+
+	/// Configures the type of addresses the on-chain wallet uses, i.e., the descriptor template it
+	/// is derived from.
+	///
+	/// Defaults to [`AddressType::NativeSegwit`].
+	///
+	/// Unless the on-chain wallet is watch-only, the wallets of any previously used address types
+	/// keep being tracked, which allows to safely change the address type of an existing node.
+	/// Their funds count towards the spendable on-chain balance and are spent whenever the funds
+	/// of the wallet of the configured type don't suffice. Addresses of other types may still be
+	/// retrieved via [`OnchainPayment::new_address`].
+	///
+	/// **Note:** Channels are always closed to native SegWit addresses, as not all peers accept
+	/// other script types.
+	///
+	/// [`OnchainPayment::new_address`]: crate::payment::OnchainPayment::new_address
+	pub fn set_onchain_wallet_address_type(&mut self, address_type: AddressType) -> &mut Self {
+		self.onchain_wallet_address_type = address_type;
+		self
+	}
+
 	/// Sets the used storage directory path.
 	pub fn set_storage_dir_path(&mut self, storage_dir_path: String) -> &mut Self {
 		self.config.storage_dir_path = storage_dir_path;
@@ -480,6 +567,8 @@ impl NodeBuilder {
 			self.chain_data_source_config.as_ref(),
 			self.gossip_source_config.as_ref(),
 			self.liquidity_source_config.as_ref(),
+			self.watch_only_wallet_config.as_ref(),
+			self.onchain_wallet_address_type,
 			seed_bytes,
 			logger,
 			Arc::new(vss_store),
@@ -501,6 +590,8 @@ impl NodeBuilder {
 			self.chain_data_source_config.as_ref(),
 			self.gossip_source_config.as_ref(),
 			self.liquidity_source_config.as_ref(),
+			self.watch_only_wallet_config.as_ref(),
+			self.onchain_wallet_address_type,
 			seed_bytes,
 			logger,
 			kv_store,
@@ -605,6 +696,23 @@ impl ArcedNodeBuilder {
 		self.inner.write().unwrap().set_liquidity_source_lsps2(address, node_id, token);
 	}
 
+	/// Configures the [`Node`] instance to use a watch-only on-chain wallet derived from the given
+	/// account-level extended public key and the fingerprint of the master key it was derived from.
+	///
+	/// See [`NodeBuilder::set_onchain_wallet_watch_only`] for more details.
+	pub fn set_onchain_wallet_watch_only(
+		&self, account_xpub: Xpub, master_fingerprint: Fingerprint,
+	) {
+		self.inner.write().unwrap().set_onchain_wallet_watch_only(account_xpub, master_fingerprint);
+	}
+
+	/// Configures the type of addresses the on-chain wallet uses.
+	///
+	/// See [`NodeBuilder::set_onchain_wallet_address_type`] for more details.
+	pub fn set_onchain_wallet_address_type(&self, address_type: AddressType) {
+		self.inner.write().unwrap().set_onchain_wallet_address_type(address_type);
+	}
+
 	/// Sets the used storage directory path.
 	pub fn set_storage_dir_path(&self, storage_dir_path: String) {
 		self.inner.write().unwrap().set_storage_dir_path(storage_dir_path);
@@ -733,8 +841,10 @@ impl ArcedNodeBuilder {
 fn build_with_store_internal(
 	config: Arc<Config>, chain_data_source_config: Option<&ChainDataSourceConfig>,
 	gossip_source_config: Option<&GossipSourceConfig>,
-	liquidity_source_config: Option<&LiquiditySourceConfig>, seed_bytes: [u8; 64],
-	logger: Arc<FilesystemLogger>, kv_store: Arc<DynStore>,
+	liquidity_source_config: Option<&LiquiditySourceConfig>,
+	watch_only_wallet_config: Option<&WatchOnlyWalletConfig>,
+	onchain_wallet_address_type: AddressType, seed_bytes: [u8; 64], logger: Arc<FilesystemLogger>,
+	kv_store: Arc<DynStore>,
 ) -> Result<Node, BuildError> {
 	// Initialize the status fields.
 	let is_listening = Arc::new(AtomicBool::new(false));
@@ -755,36 +865,139 @@ fn build_with_store_internal(
 		BuildError::InvalidSeedBytes
 	})?;
 
-	let descriptor = Bip84(xprv, KeychainKind::External);
-	let change_descriptor = Bip84(xprv, KeychainKind::Internal);
-	let mut wallet_persister =
-		KVStoreWalletPersister::new(Arc::clone(&kv_store), Arc::clone(&logger));
-	let wallet_opt = BdkWallet::load()
-		.descriptor(KeychainKind::External, Some(descriptor.clone()))
-		.descriptor(KeychainKind::Internal, Some(change_descriptor.clone()))
-		.extract_keys()
-		.check_network(config.network)
-		.load_wallet(&mut wallet_persister)
-		.map_err(|e| {
-			log_error!(logger, "Failed to set up wallet: {}", e);
-			BuildError::WalletSetupFailed
-		})?;
-	let bdk_wallet = match wallet_opt {
-		Some(wallet) => wallet,
-		None => BdkWallet::create(descriptor, change_descriptor)
-			.network(config.network)
-			.create_wallet(&mut wallet_persister)
-			.map_err(|e| {
+	if watch_only_wallet_config.is_some()
+		&& onchain_wallet_address_type == AddressType::NestedSegwit
+	{
+		log_error!(logger, "Watch-only on-chain wallets don't support nested SegWit addresses");
+		return Err(BuildError::WalletSetupFailed);
+	}
+
+	// We couldn't sign the transactions needed to bump the fees of Anchor channels' transactions.
+	if watch_only_wallet_config.is_some() && config.anchor_channels_config.is_some() {
+		log_error!(logger, "Watch-only on-chain wallets don't support Anchor channels");
+		return Err(BuildError::WalletSetupFailed);
+	}
+
+	let (mut bdk_wallet, mut wallet_persister) = setup_bdk_wallet(
+		onchain_wallet_address_type,
+		xprv,
+		watch_only_wallet_config,
+		&config,
+		Arc::clone(&kv_store),
+		Arc::clone(&logger),
+	)?;
+
+	// Unless we're watch-only, we keep tracking the wallets of the address types we used before,
+	// so that we never lose track of funds received before the address type was changed. The
+	// wallets of the remaining address types are only created once we hand out their addresses.
+	let mut additional_wallets = Vec::new();
+	let mut unused_wallets = Vec::new();
+	if watch_only_wallet_config.is_none() {
+		for address_type in AddressType::ALL {
+			if address_type == onchain_wallet_address_type {
+				continue;
+			}
+			let (descriptor, change_descriptor) =
+				derive_wallet_descriptors(address_type, xprv, None, &config, &logger)?;
+			let (wallet_opt, persister) = load_bdk_wallet(
+				address_type,
+				descriptor.clone(),
+				change_descriptor.clone(),
+				&config,
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)?;
+			match wallet_opt {
+				Some(wallet) => additional_wallets.push((address_type, wallet, persister)),
+				None => unused_wallets.push(UnusedWallet {
+					address_type,
+					descriptor,
+					change_descriptor,
+					persister,
+				}),
+			}
+		}
+	}
+
+	// All wallets are synced in lockstep, so we connect a newly created primary wallet to the
+	// chain tip the wallets of the previously used address types are synced to.
+	let best_checkpoint = additional_wallets
+		.iter()
+		.map(|(_, wallet, _)| wallet.latest_checkpoint())
+		.max_by_key(|checkpoint| checkpoint.height());
+	if let Some(best_checkpoint) = best_checkpoint {
+		if bdk_wallet.latest_checkpoint().height() == 0 && best_checkpoint.height() > 0 {
+			let update = Update { chain: Some(best_checkpoint), ..Default::default() };
+			bdk_wallet.apply_update(update).map_err(|e| {
+				log_error!(logger, "Failed to set up wallet: {}", e);
+				BuildError::WalletSetupFailed
+			})?;
+			bdk_wallet.persist(&mut wallet_persister).map_err(|e| {
 				log_error!(logger, "Failed to set up wallet: {}", e);
 				BuildError::WalletSetupFailed
-			})?,
+			})?;
+		}
+	}
+
+	// Init payment info storage
+	let payment_store = match io::utils::read_payments(Arc::clone(&kv_store), Arc::clone(&logger)) {
+		Ok(payments) => {
+			Arc::new(PaymentStore::new(payments, Arc::clone(&kv_store), Arc::clone(&logger)))
+		},
+		Err(_) => {
+			return Err(BuildError::ReadFailed);
+		},
+	};
+
+	let frozen_utxos = match read_frozen_utxos(Arc::clone(&kv_store), Arc::clone(&logger)) {
+		Ok(frozen_utxos) => frozen_utxos,
+		Err(e) => {
+			if e.kind() == std::io::ErrorKind::NotFound {
+				HashSet::new()
+			} else {
+				return Err(BuildError::ReadFailed);
+			}
+		},
 	};
 
+	let watch_only_descriptors =
+		match read_watch_only_descriptors(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(watch_only_descriptors) => watch_only_descriptors,
+			Err(e) => {
+				if e.kind() == std::io::ErrorKind::NotFound {
+					Vec::new()
+				} else {
+					return Err(BuildError::ReadFailed);
+				}
+			},
+		};
+	let mut watch_only_wallets = Vec::with_capacity(watch_only_descriptors.len());
+	for descriptor in watch_only_descriptors {
+		let (wallet, persister) = setup_watch_only_wallet(
+			&descriptor.descriptor,
+			config.network,
+			Arc::clone(&kv_store),
+			Arc::clone(&logger),
+		)
+		.map_err(|e| {
+			log_error!(logger, "Failed to set up watch-only wallet: {}", e);
+			BuildError::WalletSetupFailed
+		})?;
+		watch_only_wallets.push((descriptor, wallet, persister));
+	}
+
 	let tx_broadcaster = Arc::new(TransactionBroadcaster::new(Arc::clone(&logger)));
 	let fee_estimator = Arc::new(OnchainFeeEstimator::new());
 	let wallet = Arc::new(Wallet::new(
+		onchain_wallet_address_type,
 		bdk_wallet,
 		wallet_persister,
+		additional_wallets,
+		unused_wallets,
+		watch_only_wallets,
+		frozen_utxos,
+		Arc::clone(&payment_store),
+		Arc::clone(&kv_store),
 		Arc::clone(&tx_broadcaster),
 		Arc::clone(&fee_estimator),
 		Arc::clone(&logger),
@@ -1005,6 +1218,33 @@ fn build_with_store_internal(
 		})?;
 	}
 
+	let offer_store = match io::utils::read_offers(Arc::clone(&kv_store), Arc::clone(&logger)) {
+		Ok(offers) => Arc::new(OfferStore::new(offers, Arc::clone(&kv_store), Arc::clone(&logger))),
+		Err(_) => {
+			return Err(BuildError::ReadFailed);
+		},
+	};
+
+	let label_store = match io::utils::read_labels(Arc::clone(&kv_store), Arc::clone(&logger)) {
+		Ok(labels) => Arc::new(LabelStore::new(labels, Arc::clone(&kv_store), Arc::clone(&logger))),
+		Err(_) => {
+			return Err(BuildError::ReadFailed);
+		},
+	};
+
+	let offers_message_handler = Arc::new(NodeOffersMessageHandler::new(
+		Arc::clone(&channel_manager),
+		Arc::clone(&offer_store),
+		Arc::clone(&logger),
+	));
+
+	let hrn_resolver = Arc::new(HrnResolver::new(
+		config.dns_resolvers_node_ids.clone(),
+		channel_manager.current_best_block(),
+		Arc::clone(&keys_manager),
+		Arc::clone(&logger),
+	));
+
 	// Initialize the PeerManager
 	let onion_messenger: Arc<OnionMessenger> = Arc::new(OnionMessenger::new(
 		Arc::clone(&keys_manager),
@@ -1012,9 +1252,9 @@ fn build_with_store_internal(
 		Arc::clone(&logger),
 		Arc::clone(&channel_manager),
 		message_router,
-		Arc::clone(&channel_manager),
-		IgnoringMessageHandler {},
+		offers_message_handler,
 		IgnoringMessageHandler {},
+		Arc::clone(&hrn_resolver),
 		IgnoringMessageHandler {},
 	));
 	let ephemeral_bytes: [u8; 32] = keys_manager.get_secure_random_bytes();
@@ -1170,15 +1410,17 @@ fn build_with_store_internal(
 		},
 	}
 
-	// Init payment info storage
-	let payment_store = match io::utils::read_payments(Arc::clone(&kv_store), Arc::clone(&logger)) {
-		Ok(payments) => {
-			Arc::new(PaymentStore::new(payments, Arc::clone(&kv_store), Arc::clone(&logger)))
-		},
-		Err(_) => {
-			return Err(BuildError::ReadFailed);
-		},
-	};
+	let payment_retry_store =
+		match io::utils::read_payment_retry_infos(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(retry_infos) => Arc::new(PaymentRetryStore::new(
+				retry_infos,
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)),
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
+			},
+		};
 
 	let event_queue = match io::utils::read_event_queue(Arc::clone(&kv_store), Arc::clone(&logger))
 	{
@@ -1192,6 +1434,71 @@ fn build_with_store_internal(
 		},
 	};
 
+	let scheduled_payment_spending = match io::utils::read_scheduled_payment_spending(
+		Arc::clone(&kv_store),
+		Arc::clone(&logger),
+	) {
+		Ok(spending) => spending,
+		Err(e) => {
+			if e.kind() == std::io::ErrorKind::NotFound {
+				ScheduledPaymentSpending::default()
+			} else {
+				return Err(BuildError::ReadFailed);
+			}
+		},
+	};
+
+	let payment_scheduler =
+		match io::utils::read_scheduled_payments(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(schedules) => Arc::new(PaymentScheduler::new(
+				schedules,
+				config.scheduled_payment_spending_limit.clone(),
+				scheduled_payment_spending,
+				Arc::clone(&event_queue),
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)),
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
+			},
+		};
+
+	let hold_invoice_manager =
+		match io::utils::read_held_payments(Arc::clone(&kv_store), Arc::clone(&logger)) {
+			Ok(held_payments) => Arc::new(HoldInvoiceManager::new(
+				held_payments,
+				Arc::clone(&payment_store),
+				Arc::clone(&event_queue),
+				config.hold_invoice_config.clone(),
+				Arc::clone(&kv_store),
+				Arc::clone(&logger),
+			)),
+			Err(_) => {
+				return Err(BuildError::ReadFailed);
+			},
+		};
+
+	let payjoin_receiver =
+		if config.payjoin_config.is_some() || config.payjoin_directory_config.is_some() {
+			match PayjoinReceiver::new(
+				config.payjoin_config.clone(),
+				config.payjoin_directory_config.clone(),
+				Arc::clone(&wallet),
+				Arc::clone(&chain_source),
+				Arc::clone(&logger),
+			) {
+				Some(payjoin_receiver) => Some(Arc::new(payjoin_receiver)),
+				None => {
+					log_error!(logger, "Failed to set up payjoin: invalid URL");
+					return Err(BuildError::InvalidPayjoinConfig);
+				},
+			}
+		} else {
+			None
+		};
+
+	let pending_bolt12_invoices = Arc::new(Mutex::new(HashMap::new()));
+
 	let peer_store = match io::utils::read_peer_info(Arc::clone(&kv_store), Arc::clone(&logger)) {
 		Ok(peer_store) => Arc::new(peer_store),
 		Err(e) => {
@@ -1203,6 +1510,13 @@ fn build_with_store_internal(
 		},
 	};
 
+	let prober = Arc::new(Prober::new(
+		Arc::clone(&channel_manager),
+		Arc::clone(&router),
+		Arc::clone(&network_graph),
+		Arc::clone(&logger),
+	));
+
 	let (stop_sender, _) = tokio::sync::watch::channel(());
 	let (event_handling_stopped_sender, _) = tokio::sync::watch::channel(());
 
@@ -1231,6 +1545,15 @@ fn build_with_store_internal(
 		scorer,
 		peer_store,
 		payment_store,
+		payment_retry_store,
+		offer_store,
+		label_store,
+		pending_bolt12_invoices,
+		hrn_resolver,
+		prober,
+		payment_scheduler,
+		hold_invoice_manager,
+		payjoin_receiver,
 		is_listening,
 		node_metrics,
 	})
@@ -1273,6 +1596,99 @@ fn seed_bytes_from_config(
 	}
 }
 
+// Returns the external and internal descriptors of the on-chain wallet of the given address type,
+// which will be watch-only if configured.
+fn wallet_descriptors(
+	address_type: AddressType, xprv: Xpriv,
+	watch_only_wallet_config: Option<&WatchOnlyWalletConfig>, network: Network,
+) -> Result<(WalletDescriptor, WalletDescriptor), DescriptorError> {
+	let build = |keychain: KeychainKind| match watch_only_wallet_config {
+		Some(WatchOnlyWalletConfig { account_xpub, master_fingerprint }) => match address_type {
+			AddressType::NativeSegwit => {
+				Bip84Public(*account_xpub, *master_fingerprint, keychain).build(network)
+			},
+			AddressType::Taproot => {
+				Bip86Public(*account_xpub, *master_fingerprint, keychain).build(network)
+			},
+			AddressType::NestedSegwit => {
+				Bip49Public(*account_xpub, *master_fingerprint, keychain).build(network)
+			},
+		},
+		None => match address_type {
+			AddressType::NativeSegwit => Bip84(xprv, keychain).build(network),
+			AddressType::Taproot => Bip86(xprv, keychain).build(network),
+			AddressType::NestedSegwit => Bip49(xprv, keychain).build(network),
+		},
+	};
+	let (descriptor, keymap, _) = build(KeychainKind::External)?;
+	let (change_descriptor, change_keymap, _) = build(KeychainKind::Internal)?;
+	Ok(((descriptor, keymap), (change_descriptor, change_keymap)))
+}
+
+// Returns the descriptors of the on-chain wallet of the given address type, logging any failure.
+fn derive_wallet_descriptors(
+	address_type: AddressType, xprv: Xpriv,
+	watch_only_wallet_config: Option<&WatchOnlyWalletConfig>, config: &Config,
+	logger: &FilesystemLogger,
+) -> Result<(WalletDescriptor, WalletDescriptor), BuildError> {
+	wallet_descriptors(address_type, xprv, watch_only_wallet_config, config.network).map_err(|e| {
+		log_error!(logger, "Failed to derive wallet descriptors: {}", e);
+		BuildError::WalletSetupFailed
+	})
+}
+
+// Loads the persisted on-chain wallet of the given address type, if any. Also returns the
+// persister to create the wallet with if it doesn't exist yet.
+fn load_bdk_wallet(
+	address_type: AddressType, descriptor: WalletDescriptor, change_descriptor: WalletDescriptor,
+	config: &Config, kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
+) -> Result<(Option<PersistedWallet<KVStoreWalletPersister>>, KVStoreWalletPersister), BuildError> {
+	let mut wallet_persister =
+		KVStoreWalletPersister::new(address_type, kv_store, Arc::clone(&logger));
+	let wallet_opt = BdkWallet::load()
+		.descriptor(KeychainKind::External, Some(descriptor))
+		.descriptor(KeychainKind::Internal, Some(change_descriptor))
+		.extract_keys()
+		.check_network(config.network)
+		.load_wallet(&mut wallet_persister)
+		.map_err(|e| {
+			log_error!(logger, "Failed to set up wallet: {}", e);
+			BuildError::WalletSetupFailed
+		})?;
+	Ok((wallet_opt, wallet_persister))
+}
+
+// Loads the on-chain wallet of the given address type, or creates it if it doesn't exist yet.
+fn setup_bdk_wallet(
+	address_type: AddressType, xprv: Xpriv,
+	watch_only_wallet_config: Option<&WatchOnlyWalletConfig>, config: &Config,
+	kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
+) -> Result<(PersistedWallet<KVStoreWalletPersister>, KVStoreWalletPersister), BuildError> {
+	let (descriptor, change_descriptor) =
+		derive_wallet_descriptors(address_type, xprv, watch_only_wallet_config, config, &logger)?;
+	let (wallet_opt, mut wallet_persister) = load_bdk_wallet(
+		address_type,
+		descriptor.clone(),
+		change_descriptor.clone(),
+		config,
+		kv_store,
+		Arc::clone(&logger),
+	)?;
+	match wallet_opt {
+		Some(wallet) => Ok((wallet, wallet_persister)),
+		None => {
+			let wallet = BdkWallet::create(descriptor, change_descriptor)
+				.network(config.network)
+				.create_wallet(&mut wallet_persister)
+				.map_err(|e| {
+					log_error!(logger, "Failed to set up wallet: {}", e);
+					BuildError::WalletSetupFailed
+				})?;
+			Ok((wallet, wallet_persister))
+		},
+	}
+}
+
 fn derive_vss_xprv(
 	config: Arc<Config>, seed_bytes: &[u8; 64], logger: Arc<FilesystemLogger>,
 ) -> Result<Xpriv, BuildError> {
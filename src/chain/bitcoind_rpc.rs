@@ -51,6 +51,15 @@ impl BitcoindRpcClient {
 		self.rpc_client.call_method::<Txid>("sendrawtransaction", &[tx_json]).await
 	}
 
+	pub(crate) async fn test_mempool_accept(&self, tx: &Transaction) -> std::io::Result<bool> {
+		let tx_serialized = bitcoin::consensus::encode::serialize_hex(tx);
+		let txs_json = serde_json::json!([tx_serialized]);
+		self.rpc_client
+			.call_method::<TestMempoolAcceptResponse>("testmempoolaccept", &[txs_json])
+			.await
+			.map(|resp| resp.0)
+	}
+
 	pub(crate) async fn get_fee_estimate_for_target(
 		&self, num_blocks: usize, estimation_mode: FeeRateEstimationMode,
 	) -> std::io::Result<FeeRate> {
@@ -72,6 +81,11 @@ impl BitcoindRpcClient {
 			.map(|resp| resp.0)
 	}
 
+	pub(crate) async fn get_block_hash(&self, height: u32) -> std::io::Result<BlockHash> {
+		let height_json = serde_json::json!(height);
+		self.rpc_client.call_method::<BlockHash>("getblockhash", &[height_json]).await
+	}
+
 	pub(crate) async fn get_raw_transaction(
 		&self, txid: &Txid,
 	) -> std::io::Result<Option<Transaction>> {
@@ -245,6 +259,25 @@ impl TryInto<MempoolMinFeeResponse> for JsonResponse {
 	}
 }
 
+pub struct TestMempoolAcceptResponse(pub bool);
+
+impl TryInto<TestMempoolAcceptResponse> for JsonResponse {
+	type Error = std::io::Error;
+	fn try_into(self) -> std::io::Result<TestMempoolAcceptResponse> {
+		let allowed = self
+			.0
+			.as_array()
+			.and_then(|results| results.first())
+			.and_then(|result| result["allowed"].as_bool())
+			.ok_or(std::io::Error::new(
+				std::io::ErrorKind::Other,
+				"Failed to parse testmempoolaccept response",
+			))?;
+
+		Ok(TestMempoolAcceptResponse(allowed))
+	}
+}
+
 pub struct GetRawTransactionResponse(pub Transaction);
 
 impl TryInto<GetRawTransactionResponse> for JsonResponse {
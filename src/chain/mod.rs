@@ -14,14 +14,16 @@ use crate::config::{
 	Config, EsploraSyncConfig, BDK_CLIENT_CONCURRENCY, BDK_CLIENT_STOP_GAP,
 	BDK_WALLET_SYNC_TIMEOUT_SECS, FEE_RATE_CACHE_UPDATE_TIMEOUT_SECS, LDK_WALLET_SYNC_TIMEOUT_SECS,
 	RESOLVED_CHANNEL_MONITOR_ARCHIVAL_INTERVAL, TX_BROADCAST_TIMEOUT_SECS,
-	WALLET_SYNC_INTERVAL_MINIMUM_SECS,
+	WALLET_SYNC_INTERVAL_MINIMUM_SECS, WATCH_ONLY_RESCAN_CHUNK_SIZE, WATCH_ONLY_RESCAN_INTERVAL,
 };
 use crate::fee_estimator::{
 	apply_post_estimation_adjustments, get_all_conf_targets, get_num_block_defaults_for_target,
 	ConfirmationTarget, OnchainFeeEstimator,
 };
 use crate::io::utils::write_node_metrics;
-use crate::logger::{log_bytes, log_error, log_info, log_trace, FilesystemLogger, Logger};
+use crate::logger::{
+	log_bytes, log_debug, log_error, log_info, log_trace, FilesystemLogger, Logger,
+};
 use crate::types::{Broadcaster, ChainMonitor, ChannelManager, DynStore, Sweeper, Wallet};
 use crate::{Error, NodeMetrics};
 
@@ -33,13 +35,14 @@ use lightning_transaction_sync::EsploraSyncClient;
 
 use lightning_block_sync::init::{synchronize_listeners, validate_best_block_header};
 use lightning_block_sync::poll::{ChainPoller, ChainTip, ValidatedBlockHeader};
-use lightning_block_sync::SpvClient;
+use lightning_block_sync::{BlockData, BlockSource, SpvClient};
 
+use bdk_chain::DescriptorId;
 use bdk_esplora::EsploraAsyncExt;
 
 use esplora_client::AsyncClient as EsploraAsyncClient;
 
-use bitcoin::{FeeRate, Network};
+use bitcoin::{FeeRate, Network, Transaction};
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex, RwLock};
@@ -501,6 +504,18 @@ impl ChainSource {
 					}
 				};
 
+				let res = match res {
+					Ok(()) => {
+						Self::scan_watch_only_wallets_esplora(
+							esplora_client,
+							onchain_wallet,
+							logger,
+						)
+						.await
+					},
+					Err(e) => Err(e),
+				};
+
 				onchain_wallet_sync_status.lock().unwrap().propagate_result_to_subscribers(res);
 
 				res
@@ -612,6 +627,152 @@ impl ChainSource {
 		}
 	}
 
+	// Scans for the history of any newly imported watch-only descriptors.
+	async fn scan_watch_only_wallets_esplora(
+		esplora_client: &EsploraAsyncClient, onchain_wallet: &Wallet,
+		logger: &Arc<FilesystemLogger>,
+	) -> Result<(), Error> {
+		let (ids, full_scan_request) = match onchain_wallet.get_watch_only_full_scan_request() {
+			Some(request) => request,
+			None => return Ok(()),
+		};
+
+		let now = Instant::now();
+		let full_scan_fut = tokio::time::timeout(
+			Duration::from_secs(BDK_WALLET_SYNC_TIMEOUT_SECS),
+			esplora_client.full_scan(
+				full_scan_request,
+				BDK_CLIENT_STOP_GAP,
+				BDK_CLIENT_CONCURRENCY,
+			),
+		);
+		let update = match full_scan_fut.await {
+			Ok(Ok(update)) => update,
+			Ok(Err(e)) => {
+				log_error!(
+					logger,
+					"Scan of watch-only descriptors failed due to Esplora error: {}",
+					e
+				);
+				return Err(Error::WalletOperationFailed);
+			},
+			Err(e) => {
+				log_error!(logger, "Scan of watch-only descriptors timed out: {}", e);
+				return Err(Error::WalletOperationTimeout);
+			},
+		};
+
+		onchain_wallet.apply_update(update)?;
+		onchain_wallet.complete_watch_only_scans(&ids)?;
+		log_info!(
+			logger,
+			"Scan of {} watch-only descriptor(s) finished in {}ms.",
+			ids.len(),
+			now.elapsed().as_millis()
+		);
+		Ok(())
+	}
+
+	// Continuously rescans the chain for the history of newly imported watch-only descriptors when
+	// syncing via Bitcoin Core RPC, independently of polling for new chain data.
+	pub(crate) async fn continuously_rescan_watch_only_wallets(
+		&self, mut stop_rescan_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		match self {
+			Self::Esplora { .. } => {
+				// In Esplora mode we scan for the history of imported descriptors as part of
+				// `sync_onchain_wallet`. So nothing to do here.
+			},
+			Self::BitcoindRpc { bitcoind_rpc_client, onchain_wallet, logger, .. } => {
+				let mut rescan_interval = tokio::time::interval(WATCH_ONLY_RESCAN_INTERVAL);
+				rescan_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+				loop {
+					tokio::select! {
+						_ = stop_rescan_receiver.changed() => {
+							log_trace!(
+								logger,
+								"Stopping background rescanning for watch-only descriptors.",
+							);
+							return;
+						}
+						_ = rescan_interval.tick() => {
+							for (id, rescan_height) in onchain_wallet.get_pending_watch_only_rescans() {
+								let rescan_res = Self::rescan_watch_only_wallet_bitcoind(
+									bitcoind_rpc_client,
+									onchain_wallet,
+									id,
+									rescan_height,
+									&stop_rescan_receiver,
+									logger,
+								)
+								.await;
+								if rescan_res.is_err()
+									|| stop_rescan_receiver.has_changed().unwrap_or(true)
+								{
+									break;
+								}
+							}
+						}
+					}
+				}
+			},
+		}
+	}
+
+	// Rescans the blocks from the given height up to our current best block for the history of a
+	// newly imported watch-only descriptor, in chunks whose progress we persist so that we don't
+	// start over if interrupted. Later blocks are applied to its wallet as they are connected.
+	async fn rescan_watch_only_wallet_bitcoind(
+		bitcoind_rpc_client: &BitcoindRpcClient, onchain_wallet: &Wallet, id: DescriptorId,
+		rescan_height: u32, stop_rescan_receiver: &tokio::sync::watch::Receiver<()>,
+		logger: &Arc<FilesystemLogger>,
+	) -> Result<(), Error> {
+		let now = Instant::now();
+		let tip_height = onchain_wallet.current_best_block().height;
+		for height in rescan_height..=tip_height {
+			if height > rescan_height
+				&& (height - rescan_height) % WATCH_ONLY_RESCAN_CHUNK_SIZE == 0
+			{
+				onchain_wallet.record_watch_only_rescan_progress(id, height)?;
+				if stop_rescan_receiver.has_changed().unwrap_or(true) {
+					log_debug!(
+						logger,
+						"Interrupted rescan for watch-only descriptor at height {}.",
+						height
+					);
+					return Ok(());
+				}
+			}
+
+			let block_hash = bitcoind_rpc_client.get_block_hash(height).await.map_err(|e| {
+				log_error!(logger, "Failed to retrieve block hash at height {}: {}", height, e);
+				Error::TxSyncFailed
+			})?;
+			let block = match bitcoind_rpc_client.get_block(&block_hash).await {
+				Ok(BlockData::FullBlock(block)) => block,
+				Ok(BlockData::HeaderOnly(_)) => {
+					log_error!(logger, "Failed to retrieve block {}: got header only", block_hash);
+					return Err(Error::TxSyncFailed);
+				},
+				Err(e) => {
+					log_error!(logger, "Failed to retrieve block {}: {:?}", block_hash, e);
+					return Err(Error::TxSyncFailed);
+				},
+			};
+			onchain_wallet.apply_watch_only_rescan_block(id, &block, height)?;
+		}
+
+		onchain_wallet.complete_watch_only_scans(&[id])?;
+		log_info!(
+			logger,
+			"Rescan of blocks {} to {} for watch-only descriptor finished in {}ms.",
+			rescan_height,
+			tip_height,
+			now.elapsed().as_millis()
+		);
+		Ok(())
+	}
+
 	pub(crate) async fn poll_and_update_listeners(
 		&self, channel_manager: Arc<ChannelManager>, chain_monitor: Arc<ChainMonitor>,
 		output_sweeper: Arc<Sweeper>,
@@ -964,6 +1125,57 @@ impl ChainSource {
 		}
 	}
 
+	/// Checks whether the given transaction would currently be accepted to the mempool.
+	///
+	/// As Esplora doesn't allow to test mempool acceptance, we only check that the transaction's
+	/// inputs spend existing and unspent outputs in this case.
+	pub(crate) async fn test_mempool_accept(&self, tx: &Transaction) -> Result<bool, Error> {
+		match self {
+			Self::Esplora { esplora_client, logger, .. } => {
+				for txin in &tx.input {
+					let outpoint = txin.previous_output;
+					let prev_tx = esplora_client.get_tx(&outpoint.txid).await.map_err(|e| {
+						log_error!(
+							logger,
+							"Failed to retrieve transaction {}: {}",
+							outpoint.txid,
+							e
+						);
+						Error::ConnectionFailed
+					})?;
+					if prev_tx
+						.map_or(true, |prev_tx| prev_tx.output.len() <= outpoint.vout as usize)
+					{
+						return Ok(false);
+					}
+
+					let output_status = esplora_client
+						.get_output_status(&outpoint.txid, outpoint.vout as u64)
+						.await
+						.map_err(|e| {
+							log_error!(
+								logger,
+								"Failed to retrieve status of output {}: {}",
+								outpoint,
+								e
+							);
+							Error::ConnectionFailed
+						})?;
+					if output_status.map_or(false, |status| status.spent) {
+						return Ok(false);
+					}
+				}
+				Ok(true)
+			},
+			Self::BitcoindRpc { bitcoind_rpc_client, logger, .. } => {
+				bitcoind_rpc_client.test_mempool_accept(tx).await.map_err(|e| {
+					log_error!(logger, "Failed to test mempool acceptance: {}", e);
+					Error::ConnectionFailed
+				})
+			},
+		}
+	}
+
 	pub(crate) async fn process_broadcast_queue(&self) {
 		match self {
 			Self::Esplora { esplora_client, tx_broadcaster, logger, .. } => {
@@ -7,7 +7,7 @@
 
 //! Objects for configuring the node.
 
-use crate::payment::SendingParameters;
+use crate::payment::{SendingParameters, UnifiedPaymentPolicy};
 
 use lightning::ln::msgs::SocketAddress;
 use lightning::routing::gossip::NodeAlias;
@@ -30,23 +30,84 @@ const DEFAULT_FEE_RATE_CACHE_UPDATE_INTERVAL_SECS: u64 = 60 * 10;
 const DEFAULT_PROBING_LIQUIDITY_LIMIT_MULTIPLIER: u64 = 3;
 const DEFAULT_LOG_LEVEL: LogLevel = LogLevel::Debug;
 const DEFAULT_ANCHOR_PER_CHANNEL_RESERVE_SATS: u64 = 25_000;
+const DEFAULT_PROBING_INTERVAL_SECS: u64 = 60;
+const DEFAULT_PROBING_MIN_AMOUNT_MSAT: u64 = 1_000_000;
+const DEFAULT_PROBING_MAX_AMOUNT_MSAT: u64 = 100_000_000;
+const DEFAULT_PROBING_MAX_LOCKED_MSAT: u64 = 500_000_000;
+const DEFAULT_PROBING_MAX_FEE_MSAT: u64 = 50_000;
+const DEFAULT_HELD_PAYMENT_FAIL_BACK_BUFFER_BLOCKS: u32 = 3;
+const DEFAULT_HELD_PAYMENT_EXPIRY_WARNING_BLOCKS: u32 = 12;
+const DEFAULT_EXTERNAL_FUNDING_TIMEOUT_SECS: u64 = 10 * 60;
 
 // The 'stop gap' parameter used by BDK's wallet sync. This seems to configure the threshold
 // number of derivation indexes after which BDK stops looking for new scripts belonging to the wallet.
 pub(crate) const BDK_CLIENT_STOP_GAP: usize = 20;
 
+// The number of unrevealed scripts following the last revealed one of each keychain of an imported
+// watch-only descriptor we check for new funds when incrementally syncing via Esplora. This must
+// not exceed BDK's default lookahead, as BDK otherwise wouldn't recognize the scripts as ours.
+pub(crate) const BDK_WATCH_ONLY_SYNC_LOOKAHEAD: u32 = 20;
+
+// The number of blocks we rescan in one go for the history of imported watch-only descriptors when
+// syncing via Bitcoin Core RPC, persisting our progress after each chunk.
+pub(crate) const WATCH_ONLY_RESCAN_CHUNK_SIZE: u32 = 100;
+
+// The time in-between checks whether we need to rescan the chain for the history of imported
+// watch-only descriptors.
+pub(crate) const WATCH_ONLY_RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
 // The number of concurrent requests made against the API provider.
 pub(crate) const BDK_CLIENT_CONCURRENCY: usize = 4;
 
 // The timeout after which we abandon retrying failed payments.
 pub(crate) const LDK_PAYMENT_RETRY_TIMEOUT: Duration = Duration::from_secs(10);
 
+// The default `final_cltv_expiry_delta` we apply when not set.
+pub(crate) const LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA: u32 = 144;
+
 // The interval (in block height) after which we retry archiving fully resolved channel monitors.
 pub(crate) const RESOLVED_CHANNEL_MONITOR_ARCHIVAL_INTERVAL: u32 = 6;
 
 // The time in-between peer reconnection attempts.
 pub(crate) const PEER_RECONNECTION_INTERVAL: Duration = Duration::from_secs(10);
 
+// The time in-between checks whether any scheduled payments are due.
+pub(crate) const SCHEDULED_PAYMENTS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The time in-between checks whether any held payments are about to expire.
+pub(crate) const HELD_PAYMENTS_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The time in-between checks whether any externally funded channel opens timed out.
+pub(crate) const EXTERNAL_FUNDING_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+// The minimum time in-between executions of a scheduled payment.
+pub(crate) const SCHEDULED_PAYMENT_MIN_INTERVAL_SECS: u64 = 60;
+
+// The number of most recent executions we keep track of for each scheduled payment.
+pub(crate) const SCHEDULED_PAYMENT_MAX_RECENT_EXECUTIONS: usize = 100;
+
+// The timeout after which we abandon waiting for a payjoin proposal from the receiver.
+pub(crate) const PAYJOIN_REQUEST_TIMEOUT_SECS: u64 = 30;
+
+// The maximum number of payjoin requests we serve concurrently.
+pub(crate) const PAYJOIN_MAX_CONCURRENT_REQUESTS: usize = 16;
+
+// The time after which we broadcast the original transaction of a payjoin we received, unless we
+// learned about the payjoin transaction in the meantime.
+pub(crate) const PAYJOIN_ORIGINAL_TX_BROADCAST_DELAY: Duration = Duration::from_secs(2 * 60);
+
+// The maximum number of payments we expect to receive via payjoin at any time. If exceeded, we stop
+// expecting the payment whose URI expires first.
+pub(crate) const PAYJOIN_MAX_EXPECTED_PAYMENTS: usize = 1000;
+
+// The time in-between checks of our payjoin directory mailboxes, and of the mailbox we expect the
+// receiver's response in when sending via a payjoin directory.
+pub(crate) const PAYJOIN_DIRECTORY_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+// The timeout after which we abandon waiting for the receiver to respond to a payjoin request we
+// sent via a payjoin directory.
+pub(crate) const PAYJOIN_DIRECTORY_RESPONSE_TIMEOUT_SECS: u64 = 60;
+
 // The time in-between RGS sync attempts.
 pub(crate) const RGS_SYNC_INTERVAL: Duration = Duration::from_secs(60 * 60);
 
@@ -56,6 +117,13 @@ pub(crate) const NODE_ANN_BCAST_INTERVAL: Duration = Duration::from_secs(60 * 60
 // The lower limit which we apply to any configured wallet sync intervals.
 pub(crate) const WALLET_SYNC_INTERVAL_MINIMUM_SECS: u64 = 10;
 
+// The lower limit which we apply to any configured background probing interval.
+pub(crate) const PROBING_INTERVAL_MINIMUM_SECS: u64 = 10;
+
+// The number of best-connected nodes (by total channel capacity) from which we pick background
+// probing destinations if none were configured.
+pub(crate) const PROBING_HIGH_CAPACITY_NODES_COUNT: usize = 100;
+
 // The timeout after which we abort a wallet syncing operation.
 pub(crate) const BDK_WALLET_SYNC_TIMEOUT_SECS: u64 = 90;
 
@@ -71,6 +139,12 @@ pub(crate) const TX_BROADCAST_TIMEOUT_SECS: u64 = 5;
 // The timeout after which we abort a RGS sync operation.
 pub(crate) const RGS_SYNC_TIMEOUT_SECS: u64 = 5;
 
+// The timeout after which we abort resolving a human-readable name.
+pub(crate) const HRN_RESOLUTION_TIMEOUT_SECS: u64 = 30;
+
+// The timeout after which we abort a request to an LNURL service.
+pub(crate) const LNURL_REQUEST_TIMEOUT_SECS: u64 = 10;
+
 // The length in bytes of our wallets' keys seed.
 pub(crate) const WALLET_KEYS_SEED_LEN: usize = 64;
 
@@ -95,8 +169,19 @@ pub(crate) const WALLET_KEYS_SEED_LEN: usize = 64;
 /// | `log_level`                            | Debug              |
 /// | `anchor_channels_config`               | Some(..)           |
 /// | `sending_parameters`                   | None               |
+/// | `probing_config`                       | None               |
+/// | `scheduled_payment_spending_limit`     | None               |
+/// | `manually_handle_bolt12_invoices`      | false              |
+/// | `dns_resolvers_node_ids`               | []                 |
+/// | `hold_invoice_config`                  | Default            |
+/// | `payjoin_config`                       | None               |
+/// | `payjoin_directory_config`             | None               |
+/// | `unified_payment_policy`               | Default            |
+/// | `external_funding_timeout_secs`        | 600                |
 ///
-/// See [`AnchorChannelsConfig`] and [`SendingParameters`] for more information regarding their
+/// See [`AnchorChannelsConfig`], [`SendingParameters`], [`ProbingConfig`],
+/// [`ScheduledPaymentSpendingLimit`], [`HoldInvoiceConfig`], [`PayjoinConfig`],
+/// [`PayjoinDirectoryConfig`], and [`UnifiedPaymentPolicy`] for more information regarding their
 /// respective default values.
 ///
 /// [`Node`]: crate::Node
@@ -162,6 +247,97 @@ pub struct Config {
 	/// **Note:** If unset, default parameters will be used, and you will be able to override the
 	/// parameters on a per-payment basis in the corresponding method calls.
 	pub sending_parameters: Option<SendingParameters>,
+	/// Configuration options for background probing.
+	///
+	/// If set to `Some`, we'll periodically send payment probes to destinations picked from the
+	/// network graph (or the configured list of destinations) in the background. The probe
+	/// outcomes are used to train our scorer, improving future pathfinding results.
+	///
+	/// Please refer to [`ProbingConfig`] for further information.
+	///
+	/// **Note:** Background probing is disabled if this is set to `None`.
+	pub probing_config: Option<ProbingConfig>,
+	/// A limit on the total amount spent by payments scheduled via [`Node::schedule_payment`].
+	///
+	/// Scheduled payments that would exceed the limit are skipped.
+	///
+	/// **Note:** Scheduled payments aren't limited if this is set to `None`.
+	///
+	/// [`Node::schedule_payment`]: crate::Node::schedule_payment
+	pub scheduled_payment_spending_limit: Option<ScheduledPaymentSpendingLimit>,
+	/// Whether BOLT12 invoices received when paying an offer need to be approved before they are
+	/// paid.
+	///
+	/// If set to `true`, an [`Event::Bolt12InvoiceReceived`] will be emitted for every invoice
+	/// received in response to our invoice requests, allowing to inspect it before paying it via
+	/// [`Bolt12Payment::pay_received_invoice`] or abandoning the payment via
+	/// [`Bolt12Payment::abandon`]. Otherwise, received invoices are paid right away.
+	///
+	/// [`Event::Bolt12InvoiceReceived`]: crate::Event::Bolt12InvoiceReceived
+	/// [`Bolt12Payment::pay_received_invoice`]: crate::payment::Bolt12Payment::pay_received_invoice
+	/// [`Bolt12Payment::abandon`]: crate::payment::Bolt12Payment::abandon
+	pub manually_handle_bolt12_invoices: bool,
+	/// A list of nodes offering DNSSEC-proving resolution services via onion messages as specified
+	/// in [bLIP 32].
+	///
+	/// These will be queried when resolving [BIP 353] human-readable names via
+	/// [`Node::pay_human_readable_name`]. The returned proofs are verified locally, i.e., the
+	/// resolvers don't need to be trusted to return correct results, but they will learn which
+	/// names we resolve.
+	///
+	/// **Note:** We need to be connected to at least one of the resolvers to resolve names.
+	///
+	/// [bLIP 32]: https://github.com/lightning/blips/blob/master/blip-0032.md
+	/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+	/// [`Node::pay_human_readable_name`]: crate::Node::pay_human_readable_name
+	pub dns_resolvers_node_ids: Vec<PublicKey>,
+	/// Configuration options for managing payments received for invoices created via
+	/// [`Bolt11Payment::receive_for_hash`] and friends.
+	///
+	/// Please refer to [`HoldInvoiceConfig`] for further information.
+	///
+	/// [`Bolt11Payment::receive_for_hash`]: crate::payment::Bolt11Payment::receive_for_hash
+	pub hold_invoice_config: HoldInvoiceConfig,
+	/// Configuration options for receiving on-chain payments via [BIP 78] payjoin.
+	///
+	/// If set to `Some`, we'll serve payjoin requests and include a `pj` parameter in the URIs
+	/// generated via [`UnifiedQrPayment::receive`].
+	///
+	/// Please refer to [`PayjoinConfig`] for further information.
+	///
+	/// **Note:** Sending payjoin payments doesn't require this to be set.
+	///
+	/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+	/// [`UnifiedQrPayment::receive`]: crate::payment::UnifiedQrPayment::receive
+	pub payjoin_config: Option<PayjoinConfig>,
+	/// Configuration options for sending and receiving on-chain payments asynchronously via a
+	/// [BIP 77] payjoin directory.
+	///
+	/// If set to `Some`, the `pj` parameter in the URIs generated via
+	/// [`UnifiedQrPayment::receive`] refers to a mailbox at the directory, which we check for
+	/// payjoin requests while running. This takes precedence over [`Config::payjoin_config`].
+	/// Additionally, it allows us to pay URIs referring to a payjoin directory.
+	///
+	/// Please refer to [`PayjoinDirectoryConfig`] for further information.
+	///
+	/// [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+	/// [`UnifiedQrPayment::receive`]: crate::payment::UnifiedQrPayment::receive
+	pub payjoin_directory_config: Option<PayjoinDirectoryConfig>,
+	/// The policy determining which payment methods are used when sending and receiving payments
+	/// via [`UnifiedQrPayment`].
+	///
+	/// Please refer to [`UnifiedPaymentPolicy`] for further information.
+	///
+	/// [`UnifiedQrPayment`]: crate::payment::UnifiedQrPayment
+	pub unified_payment_policy: UnifiedPaymentPolicy,
+	/// The time in seconds the user has to provide the funding transaction for a channel opened
+	/// via [`Node::open_channel_with_external_funding`], after which the channel open is aborted.
+	///
+	/// The time is counted from when the [`Event::FundingTransactionRequired`] event is emitted.
+	///
+	/// [`Node::open_channel_with_external_funding`]: crate::Node::open_channel_with_external_funding
+	/// [`Event::FundingTransactionRequired`]: crate::Event::FundingTransactionRequired
+	pub external_funding_timeout_secs: u64,
 }
 
 impl Default for Config {
@@ -177,6 +353,15 @@ impl Default for Config {
 			anchor_channels_config: Some(AnchorChannelsConfig::default()),
 			sending_parameters: None,
 			node_alias: None,
+			probing_config: None,
+			scheduled_payment_spending_limit: None,
+			manually_handle_bolt12_invoices: false,
+			dns_resolvers_node_ids: Vec::new(),
+			hold_invoice_config: HoldInvoiceConfig::default(),
+			payjoin_config: None,
+			payjoin_directory_config: None,
+			unified_payment_policy: UnifiedPaymentPolicy::default(),
+			external_funding_timeout_secs: DEFAULT_EXTERNAL_FUNDING_TIMEOUT_SECS,
 		}
 	}
 }
@@ -252,6 +437,177 @@ impl Default for AnchorChannelsConfig {
 	}
 }
 
+/// Options related to background probing.
+///
+/// Background probing periodically sends payment probes to selected destinations, which allows
+/// to learn about the liquidity available in the network and hence to train our scorer ahead of
+/// time.
+///
+/// ### Defaults
+///
+/// | Parameter         | Value      |
+/// |-------------------|------------|
+/// | `interval_secs`   | 60         |
+/// | `min_amount_msat` | 1000000    |
+/// | `max_amount_msat` | 100000000  |
+/// | `max_locked_msat` | 500000000  |
+/// | `max_fee_msat`    | 50000      |
+/// | `destinations`    | []         |
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbingConfig {
+	/// The time in-between background probing attempts, in seconds.
+	///
+	/// **Note:** A minimum of 10 seconds is always enforced.
+	pub interval_secs: u64,
+	/// The minimum amount, in millisatoshis, that we'll send a probe for.
+	pub min_amount_msat: u64,
+	/// The maximum amount, in millisatoshis, that we'll send a probe for.
+	///
+	/// The amount of each probe is randomly picked from the range between
+	/// [`ProbingConfig::min_amount_msat`] and this value.
+	pub max_amount_msat: u64,
+	/// The maximum total amount, in millisatoshis, that may be locked up in probes which are in
+	/// flight at any given time.
+	///
+	/// We won't send any further probes if doing so would exceed this liquidity budget.
+	pub max_locked_msat: u64,
+	/// The maximum total routing fee, in millisatoshis, that a probed path may incur.
+	///
+	/// As probes are never claimed no fees are actually paid. However, we refrain from probing
+	/// paths we wouldn't use to send actual payments due to their routing fees.
+	pub max_fee_msat: u64,
+	/// The list of nodes we'll pick probing destinations from.
+	///
+	/// If empty, we'll pick probing destinations from the nodes in the network graph with the
+	/// highest total channel capacity.
+	pub destinations: Vec<PublicKey>,
+}
+
+impl Default for ProbingConfig {
+	fn default() -> Self {
+		Self {
+			interval_secs: DEFAULT_PROBING_INTERVAL_SECS,
+			min_amount_msat: DEFAULT_PROBING_MIN_AMOUNT_MSAT,
+			max_amount_msat: DEFAULT_PROBING_MAX_AMOUNT_MSAT,
+			max_locked_msat: DEFAULT_PROBING_MAX_LOCKED_MSAT,
+			max_fee_msat: DEFAULT_PROBING_MAX_FEE_MSAT,
+			destinations: Vec::new(),
+		}
+	}
+}
+
+/// A limit on the total amount spent by scheduled payments within a period of time.
+///
+/// Time is divided into consecutive periods of [`ScheduledPaymentSpendingLimit::period_secs`]
+/// each, starting at the UNIX epoch. The amount of every scheduled payment is added to the total
+/// of the current period when the payment is initiated, and deducted again if the payment fails
+/// later on, i.e., only pending and successful payments count toward the limit. Scheduled
+/// payments that would have the total exceed [`ScheduledPaymentSpendingLimit::max_amount_msat`]
+/// are skipped. The total is reset once the next period begins.
+///
+/// See [`Config::scheduled_payment_spending_limit`] for more information.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledPaymentSpendingLimit {
+	/// The maximum total amount, in millisatoshis, scheduled payments may spend within a period.
+	pub max_amount_msat: u64,
+	/// The length of the periods the limit applies to, in seconds.
+	pub period_secs: u64,
+}
+
+/// Options related to payments we hold, i.e., inbound payments for invoices created via
+/// [`Bolt11Payment::receive_for_hash`] and friends that are awaiting to be claimed via
+/// [`Bolt11Payment::claim_for_hash`] or failed via [`Bolt11Payment::fail_for_hash`].
+///
+/// Held payments are tracked until they are claimed or failed. If neither happens in time, we'll
+/// fail them back automatically before their claim deadline is reached, as otherwise our
+/// counterparty would need to force-close the channel to get their funds back.
+///
+/// ### Defaults
+///
+/// | Parameter                 | Value |
+/// |---------------------------|-------|
+/// | `fail_back_buffer_blocks` | 3     |
+/// | `expiry_warning_blocks`   | 12    |
+///
+/// [`Bolt11Payment::receive_for_hash`]: crate::payment::Bolt11Payment::receive_for_hash
+/// [`Bolt11Payment::claim_for_hash`]: crate::payment::Bolt11Payment::claim_for_hash
+/// [`Bolt11Payment::fail_for_hash`]: crate::payment::Bolt11Payment::fail_for_hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoldInvoiceConfig {
+	/// The number of blocks before the claim deadline of a held payment at which we'll fail it
+	/// back automatically.
+	pub fail_back_buffer_blocks: u32,
+	/// The number of blocks before the claim deadline of a held payment at which we'll emit an
+	/// [`Event::HeldPaymentExpiring`].
+	///
+	/// [`Event::HeldPaymentExpiring`]: crate::Event::HeldPaymentExpiring
+	pub expiry_warning_blocks: u32,
+}
+
+impl Default for HoldInvoiceConfig {
+	fn default() -> Self {
+		Self {
+			fail_back_buffer_blocks: DEFAULT_HELD_PAYMENT_FAIL_BACK_BUFFER_BLOCKS,
+			expiry_warning_blocks: DEFAULT_HELD_PAYMENT_EXPIRY_WARNING_BLOCKS,
+		}
+	}
+}
+
+/// Options related to receiving on-chain payments via [BIP 78] payjoin.
+///
+/// When receiving a payjoin, we contribute one of our confirmed UTXOs to the sender's transaction,
+/// which breaks the common-input-ownership heuristic used to cluster on-chain payments.
+///
+/// **Note:** With this configuration, we need to be online and reachable under
+/// [`PayjoinConfig::endpoint_url`] when the sender initiates the payment. See
+/// [`PayjoinDirectoryConfig`] for receiving payjoins without being reachable.
+///
+/// See [`Config::payjoin_config`] for more information.
+///
+/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayjoinConfig {
+	/// The address on which we listen for incoming payjoin requests.
+	pub listening_address: SocketAddress,
+	/// The URL under which senders can reach our payjoin endpoint.
+	///
+	/// As required by [BIP 78], this needs to be an `https` URL or refer to an onion service.
+	/// Plain `http` is only allowed for loopback hosts, which is mostly useful for testing. Note we
+	/// don't terminate TLS ourselves, i.e., the endpoint needs to be exposed via a reverse proxy
+	/// forwarding requests to [`PayjoinConfig::listening_address`].
+	///
+	/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+	pub endpoint_url: String,
+}
+
+/// Options related to sending and receiving on-chain payments via a [BIP 77] payjoin directory.
+///
+/// The directory stores the end-to-end encrypted payjoin requests and proposals exchanged by
+/// senders and receivers, so that neither needs to be reachable by the other. All requests to the
+/// directory are made via an OHTTP relay, which keeps the directory from learning our IP address.
+/// The relay and the directory should therefore be operated by different parties.
+///
+/// **Note:** When sending via a payjoin directory, we wait for up to a minute for the receiver to
+/// respond, and broadcast the original transaction paying the receiver otherwise.
+///
+/// See [`Config::payjoin_directory_config`] for more information.
+///
+/// [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayjoinDirectoryConfig {
+	/// The URL of the payjoin directory.
+	///
+	/// This needs to be an `https` URL or refer to an onion service, while plain `http` is only
+	/// allowed for loopback hosts.
+	pub directory_url: String,
+	/// The URL of the OHTTP relay we reach the payjoin directory through.
+	///
+	/// The relay needs to forward OHTTP requests to the directory's gateway, and to allow
+	/// tunneling requests for the gateway's key configuration. The same restrictions as for
+	/// [`PayjoinDirectoryConfig::directory_url`] apply.
+	pub ohttp_relay_url: String,
+}
+
 /// Returns a [`Config`] object populated with default values.
 ///
 /// See the documentation of [`Config`] for more information on the used defaults.
@@ -276,6 +632,7 @@ pub(crate) fn default_user_config(config: &Config) -> UserConfig {
 	let mut user_config = UserConfig::default();
 	user_config.channel_handshake_limits.force_announced_channel_preference = false;
 	user_config.manually_accept_inbound_channels = true;
+	user_config.manually_handle_bolt12_invoices = config.manually_handle_bolt12_invoices;
 	user_config.channel_handshake_config.negotiate_anchors_zero_fee_htlc_tx =
 		config.anchor_channels_config.is_some();
 
@@ -439,6 +796,36 @@ impl From<MaxDustHTLCExposure> for LdkMaxDustHTLCExposure {
 	}
 }
 
+/// The type of addresses used by the on-chain wallet, determining the descriptor template it is
+/// derived from.
+///
+/// See [`NodeBuilder::set_onchain_wallet_address_type`] for details.
+///
+/// [`NodeBuilder::set_onchain_wallet_address_type`]: crate::NodeBuilder::set_onchain_wallet_address_type
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AddressType {
+	/// Native SegWit (P2WPKH) addresses, derived according to [BIP 84].
+	///
+	/// [BIP 84]: https://github.com/bitcoin/bips/blob/master/bip-0084.mediawiki
+	#[default]
+	NativeSegwit,
+	/// Taproot (P2TR) addresses, derived according to [BIP 86].
+	///
+	/// [BIP 86]: https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki
+	Taproot,
+	/// Nested SegWit (P2SH-P2WPKH) addresses, derived according to [BIP 49].
+	///
+	/// Mostly useful to recover funds of legacy wallets.
+	///
+	/// [BIP 49]: https://github.com/bitcoin/bips/blob/master/bip-0049.mediawiki
+	NestedSegwit,
+}
+
+impl AddressType {
+	pub(crate) const ALL: [AddressType; 3] =
+		[AddressType::NativeSegwit, AddressType::Taproot, AddressType::NestedSegwit];
+}
+
 #[cfg(test)]
 mod tests {
 	use std::str::FromStr;
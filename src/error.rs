@@ -10,6 +10,7 @@ use bdk_chain::local_chain::CannotConnectError as BdkChainConnectionError;
 use bdk_chain::tx_graph::CalculateFeeError as BdkChainCalculateFeeError;
 use bdk_wallet::error::CreateTxError as BdkCreateTxError;
 use bdk_wallet::signer::SignerError as BdkSignerError;
+use bdk_wallet::tx_builder::AddUtxoError as BdkAddUtxoError;
 
 use std::fmt;
 
@@ -38,6 +39,8 @@ pub enum Error {
 	InvalidCustomTlvs,
 	/// Sending a payment probe has failed.
 	ProbeSendingFailed,
+	/// A payment probe timed out.
+	ProbeTimeout,
 	/// A channel could not be opened.
 	ChannelCreationFailed,
 	/// A channel could not be closed.
@@ -106,6 +109,36 @@ pub enum Error {
 	InvalidQuantity,
 	/// The given node alias is invalid.
 	InvalidNodeAlias,
+	/// The given keysend message is invalid.
+	InvalidKeysendMessage,
+	/// The given scheduled payment id is invalid.
+	InvalidScheduledPaymentId,
+	/// The given payment schedule is invalid.
+	InvalidPaymentSchedule,
+	/// The given human-readable name is invalid.
+	InvalidHumanReadableName,
+	/// Resolving a human-readable name failed.
+	HumanReadableNameResolutionFailed,
+	/// The given LNURL is invalid.
+	InvalidLnurl,
+	/// A request to an LNURL service failed.
+	LnurlRequestFailed,
+	/// The given payment instructions are invalid or of an unknown format.
+	InvalidPaymentInstructions,
+	/// The given UTXO is invalid or unknown to the on-chain wallet.
+	InvalidUtxo,
+	/// The given fee rate is invalid, e.g., too low to replace a transaction.
+	InvalidFeeRate,
+	/// The given PSBT is invalid or not fully signed.
+	InvalidPsbt,
+	/// The given funding transaction is invalid.
+	InvalidFundingTransaction,
+	/// The given address type is not supported by the on-chain wallet.
+	InvalidAddressType,
+	/// The given output descriptor is invalid.
+	InvalidDescriptor,
+	/// The given label is invalid.
+	InvalidLabel,
 	/// A payment with the given hash has already been initiated.
 	DuplicatePayment,
 	/// The provided offer was denonminated in an unsupported currency.
@@ -134,6 +167,7 @@ impl fmt::Display for Error {
 			Self::PaymentSendingFailed => write!(f, "Failed to send the given payment."),
 			Self::InvalidCustomTlvs => write!(f, "Failed to construct payment with custom TLVs."),
 			Self::ProbeSendingFailed => write!(f, "Failed to send the given payment probe."),
+			Self::ProbeTimeout => write!(f, "Waiting for the payment probe outcome timed out."),
 			Self::ChannelCreationFailed => write!(f, "Failed to create channel."),
 			Self::ChannelClosingFailed => write!(f, "Failed to close channel."),
 			Self::ChannelConfigUpdateFailed => write!(f, "Failed to update channel config."),
@@ -172,6 +206,33 @@ impl fmt::Display for Error {
 			Self::InvalidUri => write!(f, "The given URI is invalid."),
 			Self::InvalidQuantity => write!(f, "The given quantity is invalid."),
 			Self::InvalidNodeAlias => write!(f, "The given node alias is invalid."),
+			Self::InvalidKeysendMessage => write!(f, "The given keysend message is invalid."),
+			Self::InvalidScheduledPaymentId => {
+				write!(f, "The given scheduled payment id is invalid.")
+			},
+			Self::InvalidPaymentSchedule => write!(f, "The given payment schedule is invalid."),
+			Self::InvalidHumanReadableName => {
+				write!(f, "The given human-readable name is invalid.")
+			},
+			Self::HumanReadableNameResolutionFailed => {
+				write!(f, "Resolving a human-readable name failed.")
+			},
+			Self::InvalidLnurl => write!(f, "The given LNURL is invalid."),
+			Self::LnurlRequestFailed => write!(f, "A request to an LNURL service failed."),
+			Self::InvalidPaymentInstructions => {
+				write!(f, "The given payment instructions are invalid or of an unknown format.")
+			},
+			Self::InvalidUtxo => write!(f, "The given UTXO is invalid or unknown."),
+			Self::InvalidFeeRate => write!(f, "The given fee rate is invalid."),
+			Self::InvalidPsbt => write!(f, "The given PSBT is invalid or not fully signed."),
+			Self::InvalidFundingTransaction => {
+				write!(f, "The given funding transaction is invalid.")
+			},
+			Self::InvalidAddressType => {
+				write!(f, "The given address type is not supported by the on-chain wallet.")
+			},
+			Self::InvalidDescriptor => write!(f, "The given output descriptor is invalid."),
+			Self::InvalidLabel => write!(f, "The given label is invalid."),
 			Self::DuplicatePayment => {
 				write!(f, "A payment with the given hash has already been initiated.")
 			},
@@ -203,11 +264,20 @@ impl From<BdkCreateTxError> for Error {
 	fn from(e: BdkCreateTxError) -> Self {
 		match e {
 			BdkCreateTxError::CoinSelection(_) => Self::InsufficientFunds,
+			BdkCreateTxError::FeeTooLow { .. } => Self::InvalidFeeRate,
+			BdkCreateTxError::FeeRateTooLow { .. } => Self::InvalidFeeRate,
+			BdkCreateTxError::OutputBelowDustLimit(_) => Self::InvalidAmount,
 			_ => Self::OnchainTxCreationFailed,
 		}
 	}
 }
 
+impl From<BdkAddUtxoError> for Error {
+	fn from(_: BdkAddUtxoError) -> Self {
+		Self::InvalidUtxo
+	}
+}
+
 impl From<BdkExtractTxError> for Error {
 	fn from(_: BdkExtractTxError) -> Self {
 		Self::OnchainTxCreationFailed
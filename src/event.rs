@@ -5,7 +5,8 @@
 // http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
 // accordance with one or both of these licenses.
 
-use crate::types::{CustomTlvRecord, DynStore, Sweeper, Wallet};
+use crate::types::{CustomTlvRecord, DynStore, PendingBolt12Invoices, Sweeper, Wallet};
+use crate::wallet::{ChannelFundingOutput, FundingBatch};
 
 use crate::{
 	hex_utils, BumpTransactionEventHandler, ChannelManager, Config, Error, Graph, PeerInfo,
@@ -14,11 +15,16 @@ use crate::{
 
 use crate::connection::ConnectionManager;
 use crate::fee_estimator::ConfirmationTarget;
+use crate::probing::Prober;
 
+use crate::payment::hold_invoice::{HeldPaymentDetails, HoldInvoiceManager};
+use crate::payment::retry::PaymentRetryStore;
 use crate::payment::store::{
 	PaymentDetails, PaymentDetailsUpdate, PaymentDirection, PaymentKind, PaymentStatus,
 	PaymentStore,
 };
+use crate::payment::KeysendMessage;
+use crate::scheduler::{PaymentScheduler, ScheduledPaymentFailureReason, ScheduledPaymentId};
 
 use crate::io::{
 	EVENT_QUEUE_PERSISTENCE_KEY, EVENT_QUEUE_PERSISTENCE_PRIMARY_NAMESPACE,
@@ -32,6 +38,7 @@ use lightning::events::{Event as LdkEvent, PaymentFailureReason};
 use lightning::impl_writeable_tlv_based_enum;
 use lightning::ln::channelmanager::PaymentId;
 use lightning::ln::types::ChannelId;
+use lightning::offers::invoice::Bolt12Invoice;
 use lightning::routing::gossip::NodeId;
 use lightning::util::errors::APIError;
 use lightning::util::ser::{Readable, ReadableArgs, Writeable, Writer};
@@ -42,7 +49,7 @@ use lightning_liquidity::lsps2::utils::compute_opening_fee;
 
 use bitcoin::blockdata::locktime::absolute::LockTime;
 use bitcoin::secp256k1::PublicKey;
-use bitcoin::{Amount, OutPoint};
+use bitcoin::{Amount, OutPoint, ScriptBuf};
 
 use rand::{thread_rng, Rng};
 
@@ -105,6 +112,10 @@ pub enum Event {
 		amount_msat: u64,
 		/// Custom TLV records received on the payment
 		custom_records: Vec<CustomTlvRecord>,
+		/// The message attached to the payment, decoded from the `custom_records`, if any.
+		///
+		/// This will be `None` for events serialized by LDK Node v0.4.2 and prior.
+		received_message: Option<KeysendMessage>,
 	},
 	/// A payment has been forwarded.
 	PaymentForwarded {
@@ -221,6 +232,90 @@ pub enum Event {
 		/// This will be `None` for events serialized by LDK Node v0.2.1 and prior.
 		reason: Option<ClosureReason>,
 	},
+	/// A payment scheduled via [`Node::schedule_payment`] has been initiated.
+	///
+	/// The outcome of the payment will be reported via a subsequent [`Event::PaymentSuccessful`] or
+	/// [`Event::PaymentFailed`] event.
+	///
+	/// [`Node::schedule_payment`]: crate::Node::schedule_payment
+	ScheduledPaymentExecuted {
+		/// The identifier of the schedule.
+		schedule_id: ScheduledPaymentId,
+		/// A local identifier used to track the initiated payment.
+		payment_id: PaymentId,
+		/// The amount, in millisatoshis, that is being sent.
+		amount_msat: u64,
+	},
+	/// A payment scheduled via [`Node::schedule_payment`] could not be initiated.
+	///
+	/// The schedule will continue with its next execution.
+	///
+	/// [`Node::schedule_payment`]: crate::Node::schedule_payment
+	ScheduledPaymentFailed {
+		/// The identifier of the schedule.
+		schedule_id: ScheduledPaymentId,
+		/// The reason why the payment could not be initiated.
+		reason: ScheduledPaymentFailureReason,
+	},
+	/// A BOLT12 invoice has been received in response to an invoice request we sent when paying
+	/// an offer.
+	///
+	/// The invoice will only be paid once approved via [`Bolt12Payment::pay_received_invoice`].
+	/// Alternatively, the payment may be abandoned via [`Bolt12Payment::abandon`].
+	///
+	/// This event will only be emitted if [`Config::manually_handle_bolt12_invoices`] is set.
+	///
+	/// **Note:** Received invoices are only kept in memory, i.e., if the node is restarted before
+	/// the invoice is paid, the payment can only be abandoned.
+	///
+	/// [`Bolt12Payment::pay_received_invoice`]: crate::payment::Bolt12Payment::pay_received_invoice
+	/// [`Bolt12Payment::abandon`]: crate::payment::Bolt12Payment::abandon
+	/// [`Config::manually_handle_bolt12_invoices`]: crate::config::Config::manually_handle_bolt12_invoices
+	Bolt12InvoiceReceived {
+		/// A local identifier used to track the payment.
+		payment_id: PaymentId,
+		/// The amount, in millisatoshis, the invoice asks us to pay.
+		amount_msat: u64,
+		/// The received invoice.
+		invoice: Bolt12Invoice,
+	},
+	/// A payment we hold is about to reach its claim deadline.
+	///
+	/// If it isn't claimed via [`claim_for_hash`] or failed via [`fail_for_hash`] in time, it will
+	/// be failed back automatically [`HoldInvoiceConfig::fail_back_buffer_blocks`] blocks before
+	/// the deadline.
+	///
+	/// This event will be emitted at most once per payment.
+	///
+	/// [`claim_for_hash`]: crate::payment::Bolt11Payment::claim_for_hash
+	/// [`fail_for_hash`]: crate::payment::Bolt11Payment::fail_for_hash
+	/// [`HoldInvoiceConfig::fail_back_buffer_blocks`]: crate::config::HoldInvoiceConfig::fail_back_buffer_blocks
+	HeldPaymentExpiring {
+		/// A local identifier used to track the payment.
+		payment_id: PaymentId,
+		/// The hash of the payment.
+		payment_hash: PaymentHash,
+		/// The block height at which the payment will no longer be eligible for claiming.
+		claim_deadline: u32,
+	},
+	/// A channel opened via [`Node::open_channel_with_external_funding`] was accepted by the
+	/// counterparty and now needs to be funded.
+	///
+	/// The funding transaction has to be provided via [`Node::provide_funding_transaction`]. If it
+	/// isn't provided within ten minutes, the channel open is aborted.
+	///
+	/// [`Node::open_channel_with_external_funding`]: crate::Node::open_channel_with_external_funding
+	/// [`Node::provide_funding_transaction`]: crate::Node::provide_funding_transaction
+	FundingTransactionRequired {
+		/// The `user_channel_id` of the channel.
+		user_channel_id: UserChannelId,
+		/// The `node_id` of the channel counterparty.
+		counterparty_node_id: PublicKey,
+		/// The script the funding output of the funding transaction needs to pay to.
+		output_script: ScriptBuf,
+		/// The value, in satoshis, the funding output needs to have.
+		amount_sats: u64,
+	},
 }
 
 impl_writeable_tlv_based_enum!(Event,
@@ -240,6 +335,7 @@ impl_writeable_tlv_based_enum!(Event,
 		(1, payment_id, option),
 		(2, amount_msat, required),
 		(3, custom_records, optional_vec),
+		(5, received_message, option),
 	},
 	(3, ChannelReady) => {
 		(0, channel_id, required),
@@ -277,6 +373,31 @@ impl_writeable_tlv_based_enum!(Event,
 		(10, skimmed_fee_msat, option),
 		(12, claim_from_onchain_tx, required),
 		(14, outbound_amount_forwarded_msat, option),
+	},
+	(8, ScheduledPaymentExecuted) => {
+		(0, schedule_id, required),
+		(2, payment_id, required),
+		(4, amount_msat, required),
+	},
+	(9, ScheduledPaymentFailed) => {
+		(0, schedule_id, required),
+		(2, reason, required),
+	},
+	(10, Bolt12InvoiceReceived) => {
+		(0, payment_id, required),
+		(2, amount_msat, required),
+		(4, invoice, required),
+	},
+	(11, HeldPaymentExpiring) => {
+		(0, payment_id, required),
+		(2, payment_hash, required),
+		(4, claim_deadline, required),
+	},
+	(12, FundingTransactionRequired) => {
+		(0, user_channel_id, required),
+		(2, counterparty_node_id, required),
+		(4, output_script, required),
+		(6, amount_sats, required),
 	}
 );
 
@@ -446,7 +567,12 @@ where
 	output_sweeper: Arc<Sweeper>,
 	network_graph: Arc<Graph>,
 	payment_store: Arc<PaymentStore<L>>,
+	payment_retry_store: Arc<PaymentRetryStore<L>>,
+	pending_bolt12_invoices: Arc<PendingBolt12Invoices>,
+	hold_invoice_manager: Arc<HoldInvoiceManager<L>>,
 	peer_store: Arc<PeerStore<L>>,
+	prober: Arc<Prober<L>>,
+	payment_scheduler: Arc<PaymentScheduler<L>>,
 	runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
 	logger: L,
 	config: Arc<Config>,
@@ -461,7 +587,10 @@ where
 		bump_tx_event_handler: Arc<BumpTransactionEventHandler>,
 		channel_manager: Arc<ChannelManager>, connection_manager: Arc<ConnectionManager<L>>,
 		output_sweeper: Arc<Sweeper>, network_graph: Arc<Graph>,
-		payment_store: Arc<PaymentStore<L>>, peer_store: Arc<PeerStore<L>>,
+		payment_store: Arc<PaymentStore<L>>, payment_retry_store: Arc<PaymentRetryStore<L>>,
+		pending_bolt12_invoices: Arc<PendingBolt12Invoices>,
+		hold_invoice_manager: Arc<HoldInvoiceManager<L>>, peer_store: Arc<PeerStore<L>>,
+		prober: Arc<Prober<L>>, payment_scheduler: Arc<PaymentScheduler<L>>,
 		runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>, logger: L, config: Arc<Config>,
 	) -> Self {
 		Self {
@@ -473,7 +602,12 @@ where
 			output_sweeper,
 			network_graph,
 			payment_store,
+			payment_retry_store,
+			pending_bolt12_invoices,
+			hold_invoice_manager,
 			peer_store,
+			prober,
+			payment_scheduler,
 			logger,
 			runtime,
 			config,
@@ -487,7 +621,7 @@ where
 				counterparty_node_id,
 				channel_value_satoshis,
 				output_script,
-				..
+				user_channel_id,
 			} => {
 				// Construct the raw transaction with the output that is paid the amount of the
 				// channel.
@@ -497,13 +631,66 @@ where
 				let cur_height = self.channel_manager.current_best_block().height;
 				let locktime = LockTime::from_height(cur_height).unwrap_or(LockTime::ZERO);
 
-				// Sign the final funding transaction and broadcast it.
 				let channel_amount = Amount::from_sat(channel_value_satoshis);
+
+				// If the channel is to be funded by the user, we hand the funding output over and
+				// wait for them to provide the funding transaction.
+				if self.wallet.is_externally_funded(user_channel_id) {
+					let output = ChannelFundingOutput {
+						temporary_channel_id,
+						counterparty_node_id,
+						output_script: output_script.clone(),
+						amount: channel_amount,
+					};
+					if !self.wallet.set_external_funding_output(
+						user_channel_id,
+						output,
+						self.config.external_funding_timeout_secs,
+					) {
+						return Ok(());
+					}
+
+					let event = Event::FundingTransactionRequired {
+						user_channel_id: UserChannelId(user_channel_id),
+						counterparty_node_id,
+						output_script,
+						amount_sats: channel_value_satoshis,
+					};
+					match self.event_queue.add_event(event) {
+						Ok(_) => return Ok(()),
+						Err(e) => {
+							log_error!(self.logger, "Failed to push to event queue: {}", e);
+							return Err(ReplayEvent());
+						},
+					};
+				}
+
+				// If the channel is part of a batch, we wait for all channels in the batch to be
+				// ready before funding them with a single transaction.
+				if self.wallet.is_batch_funded(user_channel_id) {
+					let output = ChannelFundingOutput {
+						temporary_channel_id,
+						counterparty_node_id,
+						output_script,
+						amount: channel_amount,
+					};
+					if let Some(batch) =
+						self.wallet.add_batch_funding_output(user_channel_id, output)
+					{
+						self.fund_channel_batch(batch, confirmation_target, locktime);
+					}
+					return Ok(());
+				}
+
+				// Apply the inputs and fee rate the user selected when opening the channel, if any.
+				let funding_options = self.wallet.take_funding_options(user_channel_id);
+
+				// Sign the final funding transaction and broadcast it.
 				match self.wallet.create_funding_transaction(
-					output_script,
-					channel_amount,
+					vec![(output_script, channel_amount)],
 					confirmation_target,
 					locktime,
+					funding_options,
 				) {
 					Ok(final_tx) => {
 						// Give the funding transaction back to LDK for opening the channel.
@@ -664,6 +851,22 @@ where
 									"We would have registered the preimage if we knew"
 								);
 
+								let held_payment = HeldPaymentDetails::new(
+									payment_id,
+									payment_hash,
+									amount_msat,
+									claim_deadline,
+								);
+								if let Err(e) = self.hold_invoice_manager.insert(held_payment) {
+									log_error!(
+										self.logger,
+										"Failed to track held payment with ID {}: {}",
+										payment_id,
+										e
+									);
+									return Err(ReplayEvent());
+								}
+
 								let custom_records = onion_fields
 									.map(|cf| {
 										cf.custom_tlvs().into_iter().map(|tlv| tlv.into()).collect()
@@ -756,9 +959,14 @@ where
 					},
 					PaymentPurpose::SpontaneousPayment(preimage) => {
 						// Since it's spontaneous, we insert it now into our store.
+						let custom_records = onion_fields
+							.as_ref()
+							.map(|cf| cf.custom_tlvs().iter().map(|tlv| tlv.into()).collect())
+							.unwrap_or_else(Vec::new);
 						let kind = PaymentKind::Spontaneous {
 							hash: payment_hash,
 							preimage: Some(preimage),
+							message: KeysendMessage::from_custom_records(&custom_records),
 						};
 
 						let payment = PaymentDetails::new(
@@ -837,6 +1045,11 @@ where
 					amount_msat,
 				);
 
+				if let Err(e) = self.hold_invoice_manager.remove(&payment_id) {
+					log_error!(self.logger, "Failed to remove held payment: {}", e);
+					return Err(ReplayEvent());
+				}
+
 				let update = match purpose {
 					PaymentPurpose::Bolt11InvoicePayment {
 						payment_preimage,
@@ -898,13 +1111,16 @@ where
 					},
 				}
 
+				let custom_records: Vec<CustomTlvRecord> = onion_fields
+					.map(|cf| cf.custom_tlvs().into_iter().map(|tlv| tlv.into()).collect())
+					.unwrap_or_default();
+				let received_message = KeysendMessage::from_custom_records(&custom_records);
 				let event = Event::PaymentReceived {
 					payment_id: Some(payment_id),
 					payment_hash,
 					amount_msat,
-					custom_records: onion_fields
-						.map(|cf| cf.custom_tlvs().into_iter().map(|tlv| tlv.into()).collect())
-						.unwrap_or_default(),
+					custom_records,
+					received_message,
 				};
 				match self.event_queue.add_event(event) {
 					Ok(_) => return Ok(()),
@@ -943,6 +1159,19 @@ where
 					},
 				};
 
+				// The payment succeeded, so we won't need to retry it anymore.
+				if let Err(e) = self.payment_retry_store.remove(&payment_id) {
+					log_error!(self.logger, "Failed to remove payment retry data: {}", e);
+				}
+
+				if let Err(e) = self
+					.payment_scheduler
+					.update_payment_status(&payment_id, PaymentStatus::Succeeded)
+				{
+					log_error!(self.logger, "Failed to update scheduled payment: {}", e);
+					return Err(ReplayEvent());
+				}
+
 				self.payment_store.get(&payment_id).map(|payment| {
 					log_info!(
 						self.logger,
@@ -994,6 +1223,20 @@ where
 					},
 				};
 
+				// The user gave up on the payment, so we won't need to retry it anymore.
+				if reason == Some(PaymentFailureReason::UserAbandoned) {
+					if let Err(e) = self.payment_retry_store.remove(&payment_id) {
+						log_error!(self.logger, "Failed to remove payment retry data: {}", e);
+					}
+				}
+
+				if let Err(e) =
+					self.payment_scheduler.update_payment_status(&payment_id, PaymentStatus::Failed)
+				{
+					log_error!(self.logger, "Failed to update scheduled payment: {}", e);
+					return Err(ReplayEvent());
+				}
+
 				let event =
 					Event::PaymentFailed { payment_id: Some(payment_id), payment_hash, reason };
 				match self.event_queue.add_event(event) {
@@ -1007,8 +1250,12 @@ where
 
 			LdkEvent::PaymentPathSuccessful { .. } => {},
 			LdkEvent::PaymentPathFailed { .. } => {},
-			LdkEvent::ProbeSuccessful { .. } => {},
-			LdkEvent::ProbeFailed { .. } => {},
+			LdkEvent::ProbeSuccessful { payment_id, path, .. } => {
+				self.prober.handle_probe_successful(&payment_id, &path);
+			},
+			LdkEvent::ProbeFailed { payment_id, short_channel_id, .. } => {
+				self.prober.handle_probe_failed(&payment_id, short_channel_id);
+			},
 			LdkEvent::HTLCHandlingFailed { .. } => {},
 			LdkEvent::PendingHTLCsForwardable { time_forwardable } => {
 				let forwarding_channel_manager = self.channel_manager.clone();
@@ -1325,6 +1572,16 @@ where
 			} => {
 				log_info!(self.logger, "Channel {} closed due to: {}", channel_id, reason);
 
+				// Drop any options selected for funding the channel in case it closed before we
+				// got to fund it.
+				self.wallet.take_funding_options(user_channel_id);
+				self.wallet.remove_external_funding(user_channel_id);
+
+				// Abort the funding batch the channel was part of, if it wasn't funded yet.
+				if let Some(batch) = self.wallet.take_funding_batch(user_channel_id) {
+					self.abort_channel_batch(&batch);
+				}
+
 				let event = Event::ChannelClosed {
 					channel_id,
 					user_channel_id: UserChannelId(user_channel_id),
@@ -1342,8 +1599,33 @@ where
 			},
 			LdkEvent::DiscardFunding { .. } => {},
 			LdkEvent::HTLCIntercepted { .. } => {},
-			LdkEvent::InvoiceReceived { .. } => {
-				debug_assert!(false, "We currently don't handle BOLT12 invoices manually, so this event should never be emitted.");
+			LdkEvent::InvoiceReceived { payment_id, invoice, context, .. } => {
+				if !self.config.manually_handle_bolt12_invoices {
+					debug_assert!(false, "We don't handle BOLT12 invoices manually unless configured to, so this event should never be emitted.");
+					return Ok(());
+				}
+
+				let amount_msat = invoice.amount_msats();
+				log_info!(
+					self.logger,
+					"Received BOLT12 invoice of {}msat for payment with ID {}.",
+					amount_msat,
+					payment_id
+				);
+
+				self.pending_bolt12_invoices
+					.lock()
+					.unwrap()
+					.insert(payment_id, (invoice.clone(), context));
+
+				let event = Event::Bolt12InvoiceReceived { payment_id, amount_msat, invoice };
+				match self.event_queue.add_event(event) {
+					Ok(_) => {},
+					Err(e) => {
+						log_error!(self.logger, "Failed to push to event queue: {}", e);
+						return Err(ReplayEvent());
+					},
+				};
 			},
 			LdkEvent::ConnectionNeeded { node_id, addresses } => {
 				let runtime_lock = self.runtime.read().unwrap();
@@ -1410,6 +1692,79 @@ where
 		}
 		Ok(())
 	}
+
+	fn fund_channel_batch(
+		&self, batch: FundingBatch, confirmation_target: ConfirmationTarget, locktime: LockTime,
+	) {
+		let outputs =
+			batch.outputs.iter().map(|o| (o.output_script.clone(), o.amount)).collect::<Vec<_>>();
+		match self.wallet.create_funding_transaction(outputs, confirmation_target, locktime, None) {
+			Ok(final_tx) => {
+				let txid = final_tx.compute_txid();
+				let temporary_channels = batch
+					.outputs
+					.iter()
+					.map(|o| (&o.temporary_channel_id, &o.counterparty_node_id))
+					.collect::<Vec<_>>();
+				// Give the funding transaction back to LDK, which will only broadcast it once all
+				// channels in the batch have been signed by the respective counterparty.
+				match self
+					.channel_manager
+					.batch_funding_transaction_generated(&temporary_channels, final_tx)
+				{
+					Ok(()) => {
+						log_info!(
+							self.logger,
+							"Funding batch of {} channels with transaction {}",
+							temporary_channels.len(),
+							txid
+						);
+					},
+					Err(APIError::APIMisuseError { err }) => {
+						log_error!(self.logger, "Panicking due to APIMisuseError: {}", err);
+						panic!("APIMisuseError: {}", err);
+					},
+					Err(err) => {
+						// LDK considers all channels of the batch closed at this point.
+						log_error!(
+							self.logger,
+							"Failed to process batch funding transaction: {:?}",
+							err
+						)
+					},
+				}
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to create batch funding transaction: {}", err);
+				self.abort_channel_batch(&batch);
+			},
+		}
+	}
+
+	// Closes all not-yet-funded channels of the given batch.
+	fn abort_channel_batch(&self, batch: &FundingBatch) {
+		let batch_channels = self
+			.channel_manager
+			.list_channels()
+			.into_iter()
+			.filter(|c| batch.user_channel_ids.contains(&c.user_channel_id));
+		for channel in batch_channels {
+			self.channel_manager
+				.force_close_without_broadcasting_txn(
+					&channel.channel_id,
+					&channel.counterparty.node_id,
+					"Channel funding batch was aborted".to_string(),
+				)
+				.unwrap_or_else(|e| {
+					log_error!(
+						self.logger,
+						"Failed to close channel {} of aborted funding batch: {:?}",
+						channel.channel_id,
+						e
+					)
+				});
+		}
+	}
 }
 
 #[cfg(test)]
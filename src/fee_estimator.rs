@@ -9,6 +9,8 @@ use lightning::chain::chaininterface::ConfirmationTarget as LdkConfirmationTarge
 use lightning::chain::chaininterface::FeeEstimator as LdkFeeEstimator;
 use lightning::chain::chaininterface::FEERATE_FLOOR_SATS_PER_KW;
 
+use crate::Error;
+
 use bitcoin::FeeRate;
 
 use std::collections::HashMap;
@@ -143,3 +145,11 @@ pub(crate) fn apply_post_estimation_adjustments(
 		_ => estimated_rate,
 	}
 }
+
+/// Converts a user-provided fee rate in sats/vbyte, rejecting zero or overflowing values.
+pub(crate) fn fee_rate_from_sat_per_vb(fee_rate_sat_per_vb: u64) -> Result<FeeRate, Error> {
+	match FeeRate::from_sat_per_vb(fee_rate_sat_per_vb) {
+		Some(fee_rate) if fee_rate_sat_per_vb > 0 => Ok(fee_rate),
+		_ => Err(Error::InvalidFeeRate),
+	}
+}
@@ -0,0 +1,424 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for resolving [BIP 353] Human Readable Names via DNSSEC-proving onion message
+//! resolvers as specified in [bLIP 32].
+//!
+//! [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+//! [bLIP 32]: https://github.com/lightning/blips/blob/master/blip-0032.md
+
+use crate::config::HRN_RESOLUTION_TIMEOUT_SECS;
+use crate::logger::{log_error, log_info, Logger};
+use crate::Error;
+
+use lightning::blinded_path::message::{DNSResolverContext, MessageContext};
+use lightning::chain::BestBlock;
+use lightning::ln::channelmanager::PaymentId;
+use lightning::onion_message::dns_resolution::{
+	DNSResolverMessage, DNSResolverMessageHandler, DNSSECProof, DNSSECQuery, HumanReadableName,
+	OMNameResolver,
+};
+use lightning::onion_message::messenger::{
+	Destination, MessageSendInstructions, Responder, ResponseInstruction,
+};
+use lightning::sign::EntropySource;
+
+use bitcoin::secp256k1::PublicKey;
+
+use tokio::sync::oneshot;
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Resolves [BIP 353] Human Readable Names to `bitcoin:` URIs by querying the configured
+/// DNSSEC-proving resolvers via onion messages and verifying the returned proofs.
+///
+/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+pub(crate) struct HrnResolver<ES: Deref, L: Deref>
+where
+	ES::Target: EntropySource,
+	L::Target: Logger,
+{
+	resolver: OMNameResolver,
+	dns_resolvers: Vec<PublicKey>,
+	entropy_source: ES,
+	pending_messages: Mutex<Vec<(DNSResolverMessage, MessageSendInstructions)>>,
+	pending_resolutions: Mutex<HashMap<PaymentId, oneshot::Sender<String>>>,
+	logger: L,
+}
+
+impl<ES: Deref, L: Deref> HrnResolver<ES, L>
+where
+	ES::Target: EntropySource,
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		dns_resolvers: Vec<PublicKey>, best_block: BestBlock, entropy_source: ES, logger: L,
+	) -> Self {
+		let resolver = OMNameResolver::new(unix_time_secs(), best_block.height);
+		let pending_messages = Mutex::new(Vec::new());
+		let pending_resolutions = Mutex::new(HashMap::new());
+		Self {
+			resolver,
+			dns_resolvers,
+			entropy_source,
+			pending_messages,
+			pending_resolutions,
+			logger,
+		}
+	}
+
+	/// Resolves the given name to a `bitcoin:` URI, waiting for a verified response of any of the
+	/// configured resolvers for up to [`HRN_RESOLUTION_TIMEOUT_SECS`].
+	pub(crate) async fn resolve(
+		&self, name: HumanReadableName, best_block: BestBlock,
+	) -> Result<String, Error> {
+		let (resolution_id, receiver) =
+			self.send_queries(name.clone(), best_block, unix_time_secs())?;
+
+		match tokio::time::timeout(Duration::from_secs(HRN_RESOLUTION_TIMEOUT_SECS), receiver).await
+		{
+			Ok(Ok(uri)) => Ok(uri),
+			_ => {
+				self.pending_resolutions.lock().unwrap().remove(&resolution_id);
+				log_error!(
+					self.logger,
+					"Failed to resolve {}@{}: no valid response received in time.",
+					name.user(),
+					name.domain()
+				);
+				Err(Error::HumanReadableNameResolutionFailed)
+			},
+		}
+	}
+
+	// Sends queries for the given name to all configured resolvers, verifying the proofs returned
+	// against the given time, in seconds since the UNIX epoch.
+	fn send_queries(
+		&self, name: HumanReadableName, best_block: BestBlock, now: u32,
+	) -> Result<(PaymentId, oneshot::Receiver<String>), Error> {
+		if self.dns_resolvers.is_empty() {
+			log_error!(
+				self.logger,
+				"Failed to resolve {}@{} as no DNS resolvers are configured.",
+				name.user(),
+				name.domain()
+			);
+			return Err(Error::HumanReadableNameResolutionFailed);
+		}
+
+		// Queries are timed out after two blocks, and proofs are validated against the latest
+		// time we know of.
+		self.resolver.new_best_block(best_block.height.max(1), now);
+
+		let resolution_id = PaymentId(self.entropy_source.get_secure_random_bytes());
+		let (query, context) = self
+			.resolver
+			.resolve_name(resolution_id, name, &*self.entropy_source)
+			.map_err(|()| Error::InvalidHumanReadableName)?;
+
+		let (sender, receiver) = oneshot::channel();
+		self.pending_resolutions.lock().unwrap().insert(resolution_id, sender);
+
+		let mut pending_messages = self.pending_messages.lock().unwrap();
+		for resolver in &self.dns_resolvers {
+			let instructions = MessageSendInstructions::WithReplyPath {
+				destination: Destination::Node(*resolver),
+				context: MessageContext::DNSResolver(context.clone()),
+			};
+			pending_messages.push((DNSResolverMessage::DNSSECQuery(query.clone()), instructions));
+		}
+
+		Ok((resolution_id, receiver))
+	}
+}
+
+impl<ES: Deref, L: Deref> DNSResolverMessageHandler for HrnResolver<ES, L>
+where
+	ES::Target: EntropySource,
+	L::Target: Logger,
+{
+	fn handle_dnssec_query(
+		&self, _message: DNSSECQuery, _responder: Option<Responder>,
+	) -> Option<(DNSResolverMessage, ResponseInstruction)> {
+		// We don't provide resolution services to others.
+		None
+	}
+
+	fn handle_dnssec_proof(&self, message: DNSSECProof, context: DNSResolverContext) {
+		if let Some((completed, uri)) = self.resolver.handle_dnssec_proof_for_uri(message, context)
+		{
+			let mut pending_resolutions = self.pending_resolutions.lock().unwrap();
+			for (name, resolution_id) in completed {
+				if let Some(sender) = pending_resolutions.remove(&resolution_id) {
+					log_info!(self.logger, "Resolved {}@{} to {}", name.user(), name.domain(), uri);
+					let _ = sender.send(uri.clone());
+				}
+			}
+		}
+	}
+
+	fn release_pending_messages(&self) -> Vec<(DNSResolverMessage, MessageSendInstructions)> {
+		core::mem::take(&mut self.pending_messages.lock().unwrap())
+	}
+}
+
+fn unix_time_secs() -> u32 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)).as_secs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::hex_utils;
+	use crate::payment::instructions::{parse_payment_instructions, PaymentInstructions};
+	use crate::payment::UnifiedPaymentMethod;
+	use lightning::sign::KeysManager;
+	use lightning::util::test_utils::TestLogger;
+
+	use bitcoin::Network;
+
+	use std::str::FromStr;
+	use std::sync::Arc;
+
+	// A proof of the `matt@mattcorallo.com` BIP 353 record, chaining up to the DNSSEC root, as used
+	// in the test vectors of `dnssec-prover`. It's valid from 1785988800 until 1786415920.
+	const MATTCORALLO_PROOF_HEX: &str = concat!(
+		"00003000010000000101080100030803010001e0980fa67b5962952deb96828c0a3fede0f86b357272caabb6",
+		"b709a431429bfc6dfb85548d169c6df7a9a487fccc3d2018227eb7737f85d8fc340b9f2049f4c7da3b2016b8",
+		"468499827e1903e2c1555fb2d1b0480d4c71f14952db5382ad87baeef8280461b40f303e8fcddd7732610b4d",
+		"873faa08ce4d05bdde731fe76b0eac61a6fd2f14ba7f6714d2ad37fbe04fe4ab3451e7fc58909aff58b30981",
+		"3ebcc930a25b55fad10d6b78695e267b8e57bfc5d81a66b3e2e591a6c8b548df88355d562b365b0209398dbc",
+		"54087f35b949315016c4298b3733c859fdaf72f34b1c4f08dc1d9421bce1b111d0199dc2a6c5e936a7bfe171",
+		"30e6afada8648f8c08cb9900003000010000000101080101030803010001acffb409bcc939f831f7a1e5ec88",
+		"f7a59255ec53040be432027390a4ce896d6f9086f3c5e177fbfe118163aaec7af1462c47945944c4e2c026be",
+		"5e98bbcded25978272e1e3e079c5094d573f0e83c92f02b32d3513b1550b826929c80dd0f92cac966d17769f",
+		"d5867b647c3f38029abdc48152eb8f207159ecc5d232c7c1537c79f4b7ac28ff11682f21681bf6d6aba55503",
+		"2bf6f9f036beb2aaa5b3778d6eebfba6bf9ea191be4ab0caea759e2f773a1f9029c73ecb8d5735b9321db085",
+		"f1b8e2d8038fe2941992548cee0d67dd4547e11dd63af9c9fc1c5466fb684cf009d7197c2cf79e792ab501e6",
+		"a8a1ca519af2cb9b5f6367e94c0d47502451357be1b500003000010000000101080101030803010001af7a8d",
+		"eba49d995a792aefc80263e991efdbc86138a931deb2c65d5682eab5d3b03738e3dfdc89d96da64c86c0224d",
+		"9ce02514d285da3068b19054e5e787b2969058e98e12566c8c808c40c0b769e1db1a24a1bd9b31e303184a31",
+		"fc7bb56b85bbba8abc02cd5040a444a36d47695969849e16ad856bb58e8fac8855224400319bdab224d83fc0",
+		"e66aab32ff74bfeaf0f91c454e6850a1295207bbd4cdde8f6ffb08faa9755c2e3284efa01f99393e18786cb1",
+		"32f1e66ebc6517318e1ce8a3b7337ebb54d035ab57d9706ecd9350d4afacd825e43c8668eece89819caf6817",
+		"af62dc4fbd82f0e33f6647b2b6bda175f14607f59f4635451e6b27df282ef73d8700002e0001000000010113",
+		"003008000002a3006a8795006a6be5804f660090aa5a3c4c8a0e134db3dae2ce9c8e11c2b6370834e2539011",
+		"e5202f5ef97d1d56ef34f30a8f7a2e97f9c6e31e37dbd9287ab7eb6622ea95f5d417822f925e8d1274ab839f",
+		"94dbb68e48d9a6a5b825635e8a6d20689c5dd9f83c30c87baa0f6066731f14bccaf32d87b9dbdef891772c3f",
+		"cc8c83e974f77f184212ab29e638b631366a25d9e28c7620c9dd13715537f3984a3c42a5c6183128cd67f9a4",
+		"cf57bd36b6d8b2c9dfb3476e5c13d1e7879e1c4ac2de6ae111fb61a6e60b63655207b947f1a80900f4589766",
+		"8b5dfd3bb0f2c910dad5810748f8a074b0d18d7b809b0d5a701acb6e6bd2ec5f63d596dcd8c9b530ef76e33e",
+		"b3f4bdc65cf95a495d4d2703636f6d00002b00010000000100244d060d028acbb0cd28f41250a80a49138942",
+		"4d341522d946b0da0c0291f2d3d771d7805a03636f6d00002e0001000000010113002b0801000151806a8538",
+		"506a7406c0e1b4009fcfce833941e0537edd099dbfe53bb55ac75c846e99fd9fa915ee9f54d05b8194c10afb",
+		"44c14b898bb29b3a20f1c84803eed1b0891331336657615ecc5a865ad976fcaa753d24ed7442a0ce22c3b57c",
+		"f67a6d0a666be839d9bfb87fb7ef046d80af751dd557a4a2a1112a78cc661928140fd6831943886c0879dd66",
+		"b221eec1e09a9edbee5ca96eaacf1529439bbd5566c629117285fb79c3dc2503dd2d2ca421e44b94ad71735f",
+		"c59183cabfd4057dff81858fc97f55537cdc1df4a139c917f902c82aab5b6d7cccf3f2e33306479d975e86fd",
+		"79e89433389468186ae10e5299e8be42e4f3d75518ba26d84ff9bc0644559e27dff2c966c7ea8fd1b5425d2e",
+		"03636f6d00003000010000000100440100030da3aa27a7eb7aea8960dcf161b885da69ab68813dceaea1ebaa",
+		"de3c224606bb1c16f2d4a7415306c8ef934a6a7cb1346d903f649be37f550befb6a54cf2b6bdfd03636f6d00",
+		"003000010000000100440101030db71f0465101ddbe2bf0c9455d12fa16c1cda44f4bf1ba2553418ad1f3aa9",
+		"b06973f21b84eb532cf4035ee8d4832ca26d89306a7d32560c0cb0129d450ac1083503636f6d00002e000100",
+		"000001005700300d01000151806a8708fb6a73414f4d0603636f6d00fa687995d6b32faf2850b99ab9de9542",
+		"b1d76297236d467cb092c195cf0759fe23681ed35c24ac9d4090871a59f70b5e995c63ed3a6d486b008475c4",
+		"beb829bf0b6d617474636f72616c6c6f03636f6d00002b000100000001002423490d02282511c13788321885",
+		"75a172f29a89c09ac28c826fc4fe78534d4c6df5eed2f00b6d617474636f72616c6c6f03636f6d00002b0001",
+		"000000010024e2f50d02f0e161567d468087ff27b051abc94476178a7cb635da1aa705e05c77ca81de520b6d",
+		"617474636f72616c6c6f03636f6d00002e0001000000010057002b0d02000151806a7a8b306a714048a1e603",
+		"636f6d007b48f6f4d233b8702fb5229fd382ca4a19b6bd53a2c242a596ea17c93b2af7207ea6bab227f45353",
+		"835afd9047b87d32827414113e82e5211a42e33981477dfb0b6d617474636f72616c6c6f03636f6d00003000",
+		"010000000100440100030d78402053f892f1547bb9b839940aea4c009d047c4af04471e53a53582c3dc1cdb9",
+		"a5ed8988dd3f88ddd081f98e06ce8052956efa2177e9a6dd51d72dfd344b8d0b6d617474636f72616c6c6f03",
+		"636f6d00003000010000000100440100030dc8f01e60f6a7940c401d9f6b6fb2c0a8fd8bad3658561102c9b7",
+		"f05ea9fce1bd64be1b41092db623efa64c871877af3504ac2fe966b33a2d3787cb1ee662bf280b6d61747463",
+		"6f72616c6c6f03636f6d00003000010000000100440101030dc8ddab89616f09312505c8732f2b7453546309",
+		"c5d6f9ebd172aec0a79114ea039a72578e5dfb78f47da8403711a8ae82fdd2433db679d840790b55a52180d8",
+		"e10b6d617474636f72616c6c6f03636f6d00002e000100000001006300300d0200093a806a848d6a6a720352",
+		"23490b6d617474636f72616c6c6f03636f6d009e3bee39bcc687f99d5235f99a624a23e870ec1c8f94a7ba38",
+		"a2399bfce1425a0b5c483d0d4c604b64a157eb70599baeba7e71ed15ecd45cc4207c5ff16ea291046d617474",
+		"0475736572105f626974636f696e2d7061796d656e740b6d617474636f72616c6c6f03636f6d000010000100",
+		"000001004b4a6173206c6f6e6720617320697420646f65736e2774207374617274207769746820626974636f",
+		"696e3a2c206f74686572207265636f7264732073686f756c642062652069676e6f726564046d617474047573",
+		"6572105f626974636f696e2d7061796d656e740b6d617474636f72616c6c6f03636f6d000010000100000001",
+		"01ecff626974636f696e3a626331717a7477793678656e337a647474377a3076726761706d6a74667a386163",
+		"6a6b6670356670376c3f6c6e6f3d6c6e6f317a7235717975677167736b726b37306b716d7571377633646e72",
+		"32666e6d68756b7073396e386875743438766b7170716e736b743273767371776a616b70376b36707968746b",
+		"7578773779326b716d73786c777275687a7176307a736e686839713374397868783339737563367173723037",
+		"656b6d3565736479756d307736366d6e783876647175777670376470356a70376a337635637036616a307733",
+		"3239666e6b7171763630713936737a356e6b726335723935716666783030327135337471646beb3878396d32",
+		"746d7438356a74706d63796376666e727078336c723435683267376e6133736563377867756374667a7a636d",
+		"386a6a71746a3579613237746536306a303376707430767139746d326e3979786c32686e67666e6d79676573",
+		"613235733475347a6c78657771707670393478743772757234726878756e776b74686b39766c79336c6d3568",
+		"68307071763461796d6371656a6c6773736e6c707a776c6767796b6b616a7037796a73356a76723261676b79",
+		"797063646c6a323830637934366a70796e73657a72636a326b7761326c797238787664366c666b7068347872",
+		"78746b327863336c7071046d6174740475736572105f626974636f696e2d7061796d656e740b6d617474636f",
+		"72616c6c6f03636f6d00002e000100000001006300100d0500000e106a844e8d6a71c475a0b50b6d61747463",
+		"6f72616c6c6f03636f6d00bb54a5eae349bd265cc6812c7b0ebf30cec623cb2674f92e0d24b6eadf2d12215e",
+		"71ee9df1ecc8eb071a9526e42ca39b5de52675b5b99476639c12a451c93730",
+	);
+
+	fn test_resolver(
+		dns_resolvers: Vec<PublicKey>,
+	) -> HrnResolver<Arc<KeysManager>, Arc<TestLogger>> {
+		let keys_manager = Arc::new(KeysManager::new(&[42u8; 32], 0, 0));
+		let best_block = BestBlock::from_network(Network::Regtest);
+		HrnResolver::new(dns_resolvers, best_block, keys_manager, Arc::new(TestLogger::new()))
+	}
+
+	#[test]
+	fn queries_are_sent_to_all_resolvers() {
+		let resolver_a = PublicKey::from_str(
+			"02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+		)
+		.unwrap();
+		let resolver_b = PublicKey::from_str(
+			"0324653eac434488002cc06bbfb7f10fe18991e35f9fe4302dbea6d2353dc0ab1c",
+		)
+		.unwrap();
+		let hrn_resolver = test_resolver(vec![resolver_a, resolver_b]);
+		let best_block = BestBlock::from_network(Network::Regtest);
+
+		let name = HumanReadableName::from_encoded("₿alice@example.com").unwrap();
+		let (resolution_id, mut receiver) =
+			hrn_resolver.send_queries(name, best_block, unix_time_secs()).unwrap();
+
+		let messages = hrn_resolver.release_pending_messages();
+		assert_eq!(messages.len(), 2);
+		let mut destinations = Vec::new();
+		let mut contexts = Vec::new();
+		for (message, instructions) in messages {
+			match message {
+				DNSResolverMessage::DNSSECQuery(query) => {
+					assert_eq!(query.0.as_str(), "alice.user._bitcoin-payment.example.com.");
+				},
+				_ => panic!("Unexpected message"),
+			}
+			match instructions {
+				MessageSendInstructions::WithReplyPath {
+					destination: Destination::Node(node_id),
+					context: MessageContext::DNSResolver(context),
+				} => {
+					destinations.push(node_id);
+					contexts.push(context);
+				},
+				_ => panic!("Unexpected instructions"),
+			}
+		}
+		assert_eq!(destinations, vec![resolver_a, resolver_b]);
+		assert_eq!(contexts[0], contexts[1]);
+		assert!(hrn_resolver.release_pending_messages().is_empty());
+
+		// Stand in for a resolver responding with a bogus proof, which must not complete the
+		// resolution.
+		let bogus_proof = DNSSECProof {
+			name: "alice.user._bitcoin-payment.example.com.".try_into().unwrap(),
+			proof: vec![0u8; 32],
+		};
+		hrn_resolver.handle_dnssec_proof(bogus_proof, contexts[0].clone());
+		assert!(receiver.try_recv().is_err());
+		assert!(hrn_resolver.pending_resolutions.lock().unwrap().contains_key(&resolution_id));
+	}
+
+	// Sends a query for `matt@mattcorallo.com` at the given time, returning the context a resolver
+	// would need to respond with.
+	fn query_mattcorallo(
+		hrn_resolver: &HrnResolver<Arc<KeysManager>, Arc<TestLogger>>, now: u32,
+	) -> (oneshot::Receiver<String>, DNSResolverContext) {
+		let best_block = BestBlock::from_network(Network::Regtest);
+		let name = HumanReadableName::from_encoded("matt@mattcorallo.com").unwrap();
+		let (_, receiver) = hrn_resolver.send_queries(name, best_block, now).unwrap();
+		let context = match hrn_resolver.release_pending_messages().pop() {
+			Some((
+				_,
+				MessageSendInstructions::WithReplyPath {
+					context: MessageContext::DNSResolver(context),
+					..
+				},
+			)) => context,
+			_ => panic!("Unexpected messages"),
+		};
+		(receiver, context)
+	}
+
+	#[test]
+	fn verified_proofs_complete_resolution() {
+		let resolver = PublicKey::from_str(
+			"02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+		)
+		.unwrap();
+		let hrn_resolver = test_resolver(vec![resolver]);
+		let (mut receiver, context) = query_mattcorallo(&hrn_resolver, 1786000000);
+
+		let proof = DNSSECProof {
+			name: "matt.user._bitcoin-payment.mattcorallo.com.".try_into().unwrap(),
+			proof: hex_utils::to_vec(MATTCORALLO_PROOF_HEX).unwrap(),
+		};
+		hrn_resolver.handle_dnssec_proof(proof, context);
+		let uri = receiver.try_recv().unwrap();
+		assert!(uri.starts_with("bitcoin:bc1qztwy6xen3zdtt7z0vrgapmjtfz8acjkfp5fp7l?lno=lno1"));
+		assert!(hrn_resolver.pending_resolutions.lock().unwrap().is_empty());
+
+		// The resolved URI is what we go on to pay.
+		match parse_payment_instructions(&uri, Network::Bitcoin) {
+			Ok(PaymentInstructions::Bip21Uri { payment_methods, .. }) => assert_eq!(
+				payment_methods,
+				vec![UnifiedPaymentMethod::Bolt12, UnifiedPaymentMethod::Onchain]
+			),
+			res => panic!("Unexpected result: {:?}", res),
+		}
+	}
+
+	#[test]
+	fn tampered_or_expired_proofs_are_rejected() {
+		let resolver = PublicKey::from_str(
+			"02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+		)
+		.unwrap();
+		let valid_proof = hex_utils::to_vec(MATTCORALLO_PROOF_HEX).unwrap();
+
+		// Altering the signed URI invalidates the record's signature.
+		let hrn_resolver = test_resolver(vec![resolver]);
+		let (mut receiver, context) = query_mattcorallo(&hrn_resolver, 1786000000);
+		let uri_offset = valid_proof.windows(12).position(|w| w == b"bitcoin:bc1q").unwrap();
+		let mut tampered_proof = valid_proof.clone();
+		tampered_proof[uri_offset + 12] ^= 1;
+		let proof = DNSSECProof {
+			name: "matt.user._bitcoin-payment.mattcorallo.com.".try_into().unwrap(),
+			proof: tampered_proof,
+		};
+		hrn_resolver.handle_dnssec_proof(proof, context);
+		assert!(receiver.try_recv().is_err());
+		assert_eq!(hrn_resolver.pending_resolutions.lock().unwrap().len(), 1);
+
+		// Proofs are only accepted while their signatures are valid.
+		let hrn_resolver = test_resolver(vec![resolver]);
+		let (mut receiver, context) = query_mattcorallo(&hrn_resolver, 1787000000);
+		let proof = DNSSECProof {
+			name: "matt.user._bitcoin-payment.mattcorallo.com.".try_into().unwrap(),
+			proof: valid_proof,
+		};
+		hrn_resolver.handle_dnssec_proof(proof, context);
+		assert!(receiver.try_recv().is_err());
+		assert_eq!(hrn_resolver.pending_resolutions.lock().unwrap().len(), 1);
+	}
+
+	#[test]
+	fn resolution_fails_without_resolvers() {
+		let hrn_resolver = test_resolver(Vec::new());
+		let best_block = BestBlock::from_network(Network::Regtest);
+		let name = HumanReadableName::from_encoded("alice@example.com").unwrap();
+		assert_eq!(
+			hrn_resolver.send_queries(name, best_block, unix_time_secs()).map(|_| ()),
+			Err(Error::HumanReadableNameResolutionFailed)
+		);
+		assert!(hrn_resolver.release_pending_messages().is_empty());
+	}
+}
@@ -27,6 +27,42 @@ pub(crate) const PEER_INFO_PERSISTENCE_KEY: &str = "peers";
 pub(crate) const PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str = "payments";
 pub(crate) const PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
 
+/// The information required to retry outbound payments will be persisted under this prefix.
+pub(crate) const PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str = "payment_retry_info";
+pub(crate) const PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+
+/// The BOLT12 offers we issued will be persisted under this prefix.
+pub(crate) const OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str = "offers";
+pub(crate) const OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+
+/// The recurring payments we scheduled will be persisted under this prefix.
+pub(crate) const SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE: &str = "scheduled_payments";
+pub(crate) const SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+
+/// The amount spent by scheduled payments within the current spending limit period will be
+/// persisted under this key.
+pub(crate) const SCHEDULED_PAYMENT_SPENDING_PRIMARY_NAMESPACE: &str = "";
+pub(crate) const SCHEDULED_PAYMENT_SPENDING_SECONDARY_NAMESPACE: &str = "";
+pub(crate) const SCHEDULED_PAYMENT_SPENDING_KEY: &str = "scheduled_payment_spending";
+
+/// The inbound payments we currently hold will be persisted under this prefix.
+pub(crate) const HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE: &str = "held_payments";
+pub(crate) const HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+
+/// The UTXOs frozen by the user will be persisted under this key.
+pub(crate) const FROZEN_UTXOS_PRIMARY_NAMESPACE: &str = "";
+pub(crate) const FROZEN_UTXOS_SECONDARY_NAMESPACE: &str = "";
+pub(crate) const FROZEN_UTXOS_KEY: &str = "frozen_utxos";
+
+/// The labels assigned by the user will be persisted under this prefix.
+pub(crate) const LABEL_PERSISTENCE_PRIMARY_NAMESPACE: &str = "labels";
+pub(crate) const LABEL_PERSISTENCE_SECONDARY_NAMESPACE: &str = "";
+
+/// The descriptors imported into the on-chain wallet for watching will be persisted under this key.
+pub(crate) const WATCH_ONLY_DESCRIPTORS_PRIMARY_NAMESPACE: &str = "";
+pub(crate) const WATCH_ONLY_DESCRIPTORS_SECONDARY_NAMESPACE: &str = "";
+pub(crate) const WATCH_ONLY_DESCRIPTORS_KEY: &str = "watch_only_descriptors";
+
 /// The spendable output information used to persisted under this prefix until LDK Node v0.3.0.
 pub(crate) const DEPRECATED_SPENDABLE_OUTPUT_INFO_PERSISTENCE_PRIMARY_NAMESPACE: &str =
 	"spendable_outputs";
@@ -37,44 +73,50 @@ pub(crate) const NODE_METRICS_PRIMARY_NAMESPACE: &str = "";
 pub(crate) const NODE_METRICS_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const NODE_METRICS_KEY: &str = "node_metrics";
 
+/// The BDK wallet using native SegWit addresses will be persisted under this secondary namespace.
+///
+/// Note this is kept empty as the on-chain wallet used native SegWit addresses exclusively before.
+pub(crate) const BDK_WALLET_NATIVE_SEGWIT_SECONDARY_NAMESPACE: &str = "";
+/// The BDK wallet using Taproot addresses will be persisted under this secondary namespace.
+pub(crate) const BDK_WALLET_TAPROOT_SECONDARY_NAMESPACE: &str = "taproot";
+/// The BDK wallet using nested SegWit addresses will be persisted under this secondary namespace.
+pub(crate) const BDK_WALLET_NESTED_SEGWIT_SECONDARY_NAMESPACE: &str = "nested_segwit";
+/// The BDK wallets watching imported descriptors will be persisted under secondary namespaces
+/// starting with this prefix, followed by the hex-encoded id of the respective descriptor.
+pub(crate) const BDK_WALLET_WATCH_ONLY_SECONDARY_NAMESPACE_PREFIX: &str = "watch_only_";
+
 /// The BDK wallet's [`ChangeSet::descriptor`] will be persisted under this key.
 ///
 /// [`ChangeSet::descriptor`]: bdk_wallet::ChangeSet::descriptor
 pub(crate) const BDK_WALLET_DESCRIPTOR_PRIMARY_NAMESPACE: &str = "bdk_wallet";
-pub(crate) const BDK_WALLET_DESCRIPTOR_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const BDK_WALLET_DESCRIPTOR_KEY: &str = "descriptor";
 
 /// The BDK wallet's [`ChangeSet::change_descriptor`] will be persisted under this key.
 ///
 /// [`ChangeSet::change_descriptor`]: bdk_wallet::ChangeSet::change_descriptor
 pub(crate) const BDK_WALLET_CHANGE_DESCRIPTOR_PRIMARY_NAMESPACE: &str = "bdk_wallet";
-pub(crate) const BDK_WALLET_CHANGE_DESCRIPTOR_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const BDK_WALLET_CHANGE_DESCRIPTOR_KEY: &str = "change_descriptor";
 
 /// The BDK wallet's [`ChangeSet::network`] will be persisted under this key.
 ///
 /// [`ChangeSet::network`]: bdk_wallet::ChangeSet::network
 pub(crate) const BDK_WALLET_NETWORK_PRIMARY_NAMESPACE: &str = "bdk_wallet";
-pub(crate) const BDK_WALLET_NETWORK_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const BDK_WALLET_NETWORK_KEY: &str = "network";
 
 /// The BDK wallet's [`ChangeSet::local_chain`] will be persisted under this key.
 ///
 /// [`ChangeSet::local_chain`]: bdk_wallet::ChangeSet::local_chain
 pub(crate) const BDK_WALLET_LOCAL_CHAIN_PRIMARY_NAMESPACE: &str = "bdk_wallet";
-pub(crate) const BDK_WALLET_LOCAL_CHAIN_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const BDK_WALLET_LOCAL_CHAIN_KEY: &str = "local_chain";
 
 /// The BDK wallet's [`ChangeSet::tx_graph`] will be persisted under this key.
 ///
 /// [`ChangeSet::tx_graph`]: bdk_wallet::ChangeSet::tx_graph
 pub(crate) const BDK_WALLET_TX_GRAPH_PRIMARY_NAMESPACE: &str = "bdk_wallet";
-pub(crate) const BDK_WALLET_TX_GRAPH_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const BDK_WALLET_TX_GRAPH_KEY: &str = "tx_graph";
 
 /// The BDK wallet's [`ChangeSet::indexer`] will be persisted under this key.
 ///
 /// [`ChangeSet::indexer`]: bdk_wallet::ChangeSet::indexer
 pub(crate) const BDK_WALLET_INDEXER_PRIMARY_NAMESPACE: &str = "bdk_wallet";
-pub(crate) const BDK_WALLET_INDEXER_SECONDARY_NAMESPACE: &str = "";
 pub(crate) const BDK_WALLET_INDEXER_KEY: &str = "indexer";
@@ -13,11 +13,17 @@ use crate::fee_estimator::OnchainFeeEstimator;
 use crate::io::{
 	NODE_METRICS_KEY, NODE_METRICS_PRIMARY_NAMESPACE, NODE_METRICS_SECONDARY_NAMESPACE,
 };
+use crate::label_store::Label;
 use crate::logger::{log_error, FilesystemLogger};
+use crate::payment::hold_invoice::HeldPaymentDetails;
+use crate::payment::retry::PaymentRetryInfo;
+use crate::payment::OfferDetails;
 use crate::peer_store::PeerStore;
+use crate::scheduler::{ScheduledPaymentDetails, ScheduledPaymentSpending};
 use crate::sweep::DeprecatedSpendableOutputInfo;
 use crate::types::{Broadcaster, DynStore, KeysManager, Sweeper};
 use crate::wallet::ser::{ChangeSetDeserWrapper, ChangeSetSerWrapper};
+use crate::wallet::{FrozenUtxos, WatchOnlyDescriptor, WatchOnlyDescriptors};
 use crate::{Error, EventQueue, NodeMetrics, PaymentDetails};
 
 use lightning::io::Cursor;
@@ -44,9 +50,10 @@ use bdk_chain::ConfirmationBlockTime;
 use bdk_wallet::ChangeSet as BdkWalletChangeSet;
 
 use bip39::Mnemonic;
-use bitcoin::Network;
+use bitcoin::{Network, OutPoint};
 use rand::{thread_rng, RngCore};
 
+use std::collections::HashSet;
 use std::fs;
 use std::io::Write;
 use std::ops::Deref;
@@ -222,6 +229,173 @@ where
 	Ok(res)
 }
 
+/// Read previously persisted payment retry information from the store.
+pub(crate) fn read_payment_retry_infos<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<Vec<PaymentRetryInfo>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+		PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+			PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let retry_info = PaymentRetryInfo::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize PaymentRetryInfo: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize PaymentRetryInfo",
+			)
+		})?;
+		res.push(retry_info);
+	}
+	Ok(res)
+}
+
+/// Read previously persisted offers from the store.
+pub(crate) fn read_offers<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<Vec<OfferDetails>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+		OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+			OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let offer = OfferDetails::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize OfferDetails: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize OfferDetails",
+			)
+		})?;
+		res.push(offer);
+	}
+	Ok(res)
+}
+
+/// Read previously persisted labels from the store.
+pub(crate) fn read_labels<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<Vec<Label>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in
+		kv_store.list(LABEL_PERSISTENCE_PRIMARY_NAMESPACE, LABEL_PERSISTENCE_SECONDARY_NAMESPACE)?
+	{
+		let mut reader = Cursor::new(kv_store.read(
+			LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+			LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let label = Label::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize Label: {}", e);
+			std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize Label")
+		})?;
+		res.push(label);
+	}
+	Ok(res)
+}
+
+/// Read previously persisted payment schedules from the store.
+pub(crate) fn read_scheduled_payments<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<Vec<ScheduledPaymentDetails>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+		SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+			SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let schedule = ScheduledPaymentDetails::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize ScheduledPaymentDetails: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize ScheduledPaymentDetails",
+			)
+		})?;
+		res.push(schedule);
+	}
+	Ok(res)
+}
+
+/// Read the previously persisted amount spent by scheduled payments from the store.
+pub(crate) fn read_scheduled_payment_spending<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<ScheduledPaymentSpending, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		SCHEDULED_PAYMENT_SPENDING_PRIMARY_NAMESPACE,
+		SCHEDULED_PAYMENT_SPENDING_SECONDARY_NAMESPACE,
+		SCHEDULED_PAYMENT_SPENDING_KEY,
+	)?);
+	ScheduledPaymentSpending::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize ScheduledPaymentSpending: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize ScheduledPaymentSpending",
+		)
+	})
+}
+
+/// Read previously persisted held payments from the store.
+pub(crate) fn read_held_payments<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<Vec<HeldPaymentDetails>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut res = Vec::new();
+
+	for stored_key in kv_store.list(
+		HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+		HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+	)? {
+		let mut reader = Cursor::new(kv_store.read(
+			HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+			HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+			&stored_key,
+		)?);
+		let held_payment = HeldPaymentDetails::read(&mut reader).map_err(|e| {
+			log_error!(logger, "Failed to deserialize HeldPaymentDetails: {}", e);
+			std::io::Error::new(
+				std::io::ErrorKind::InvalidData,
+				"Failed to deserialize HeldPaymentDetails",
+			)
+		})?;
+		res.push(held_payment);
+	}
+	Ok(res)
+}
+
 /// Read `OutputSweeper` state from the store.
 pub(crate) fn read_output_sweeper(
 	broadcaster: Arc<Broadcaster>, fee_estimator: Arc<OnchainFeeEstimator>,
@@ -389,6 +563,99 @@ where
 		})
 }
 
+pub(crate) fn read_frozen_utxos<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<HashSet<OutPoint>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		FROZEN_UTXOS_PRIMARY_NAMESPACE,
+		FROZEN_UTXOS_SECONDARY_NAMESPACE,
+		FROZEN_UTXOS_KEY,
+	)?);
+	let frozen_utxos = FrozenUtxos::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize frozen UTXOs: {}", e);
+		std::io::Error::new(std::io::ErrorKind::InvalidData, "Failed to deserialize frozen UTXOs")
+	})?;
+	Ok(frozen_utxos.outpoints.into_iter().collect())
+}
+
+pub(crate) fn write_frozen_utxos<L: Deref>(
+	frozen_utxos: &HashSet<OutPoint>, kv_store: Arc<DynStore>, logger: L,
+) -> Result<(), Error>
+where
+	L::Target: Logger,
+{
+	let data = FrozenUtxos { outpoints: frozen_utxos.iter().copied().collect() }.encode();
+	kv_store
+		.write(
+			FROZEN_UTXOS_PRIMARY_NAMESPACE,
+			FROZEN_UTXOS_SECONDARY_NAMESPACE,
+			FROZEN_UTXOS_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				FROZEN_UTXOS_PRIMARY_NAMESPACE,
+				FROZEN_UTXOS_SECONDARY_NAMESPACE,
+				FROZEN_UTXOS_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
+}
+
+pub(crate) fn read_watch_only_descriptors<L: Deref>(
+	kv_store: Arc<DynStore>, logger: L,
+) -> Result<Vec<WatchOnlyDescriptor>, std::io::Error>
+where
+	L::Target: Logger,
+{
+	let mut reader = Cursor::new(kv_store.read(
+		WATCH_ONLY_DESCRIPTORS_PRIMARY_NAMESPACE,
+		WATCH_ONLY_DESCRIPTORS_SECONDARY_NAMESPACE,
+		WATCH_ONLY_DESCRIPTORS_KEY,
+	)?);
+	let watch_only_descriptors = WatchOnlyDescriptors::read(&mut reader).map_err(|e| {
+		log_error!(logger, "Failed to deserialize watch-only descriptors: {}", e);
+		std::io::Error::new(
+			std::io::ErrorKind::InvalidData,
+			"Failed to deserialize watch-only descriptors",
+		)
+	})?;
+	Ok(watch_only_descriptors.descriptors)
+}
+
+pub(crate) fn write_watch_only_descriptors<L: Deref>(
+	descriptors: Vec<WatchOnlyDescriptor>, kv_store: Arc<DynStore>, logger: L,
+) -> Result<(), Error>
+where
+	L::Target: Logger,
+{
+	let data = WatchOnlyDescriptors { descriptors }.encode();
+	kv_store
+		.write(
+			WATCH_ONLY_DESCRIPTORS_PRIMARY_NAMESPACE,
+			WATCH_ONLY_DESCRIPTORS_SECONDARY_NAMESPACE,
+			WATCH_ONLY_DESCRIPTORS_KEY,
+			&data,
+		)
+		.map_err(|e| {
+			log_error!(
+				logger,
+				"Writing data to key {}/{}/{} failed due to: {}",
+				WATCH_ONLY_DESCRIPTORS_PRIMARY_NAMESPACE,
+				WATCH_ONLY_DESCRIPTORS_SECONDARY_NAMESPACE,
+				WATCH_ONLY_DESCRIPTORS_KEY,
+				e
+			);
+			Error::PersistenceFailed
+		})
+}
+
 pub(crate) fn is_valid_kvstore_str(key: &str) -> bool {
 	key.len() <= KVSTORE_NAMESPACE_KEY_MAX_LEN
 		&& key.chars().all(|c| KVSTORE_NAMESPACE_KEY_ALPHABET.contains(c))
@@ -481,14 +748,14 @@ pub(crate) fn check_namespace_key_validity(
 }
 
 macro_rules! impl_read_write_change_set_type {
-	( $read_name: ident, $write_name: ident, $change_set_type:ty, $primary_namespace: expr, $secondary_namespace: expr, $key: expr ) => {
+	( $read_name: ident, $write_name: ident, $change_set_type:ty, $primary_namespace: expr, $key: expr ) => {
 		pub(crate) fn $read_name<L: Deref>(
-			kv_store: Arc<DynStore>, logger: L,
+			secondary_namespace: &str, kv_store: Arc<DynStore>, logger: L,
 		) -> Result<Option<$change_set_type>, std::io::Error>
 		where
 			L::Target: Logger,
 		{
-			let bytes = match kv_store.read($primary_namespace, $secondary_namespace, $key) {
+			let bytes = match kv_store.read($primary_namespace, secondary_namespace, $key) {
 				Ok(bytes) => bytes,
 				Err(e) => {
 					if e.kind() == lightning::io::ErrorKind::NotFound {
@@ -498,7 +765,7 @@ macro_rules! impl_read_write_change_set_type {
 							logger,
 							"Reading data from key {}/{}/{} failed due to: {}",
 							$primary_namespace,
-							$secondary_namespace,
+							secondary_namespace,
 							$key,
 							e
 						);
@@ -523,18 +790,18 @@ macro_rules! impl_read_write_change_set_type {
 		}
 
 		pub(crate) fn $write_name<L: Deref>(
-			value: &$change_set_type, kv_store: Arc<DynStore>, logger: L,
+			value: &$change_set_type, secondary_namespace: &str, kv_store: Arc<DynStore>, logger: L,
 		) -> Result<(), std::io::Error>
 		where
 			L::Target: Logger,
 		{
 			let data = ChangeSetSerWrapper(value).encode();
-			kv_store.write($primary_namespace, $secondary_namespace, $key, &data).map_err(|e| {
+			kv_store.write($primary_namespace, secondary_namespace, $key, &data).map_err(|e| {
 				log_error!(
 					logger,
 					"Writing data to key {}/{}/{} failed due to: {}",
 					$primary_namespace,
-					$secondary_namespace,
+					secondary_namespace,
 					$key,
 					e
 				);
@@ -549,7 +816,6 @@ impl_read_write_change_set_type!(
 	write_bdk_wallet_descriptor,
 	Descriptor<DescriptorPublicKey>,
 	BDK_WALLET_DESCRIPTOR_PRIMARY_NAMESPACE,
-	BDK_WALLET_DESCRIPTOR_SECONDARY_NAMESPACE,
 	BDK_WALLET_DESCRIPTOR_KEY
 );
 
@@ -558,7 +824,6 @@ impl_read_write_change_set_type!(
 	write_bdk_wallet_change_descriptor,
 	Descriptor<DescriptorPublicKey>,
 	BDK_WALLET_CHANGE_DESCRIPTOR_PRIMARY_NAMESPACE,
-	BDK_WALLET_CHANGE_DESCRIPTOR_SECONDARY_NAMESPACE,
 	BDK_WALLET_CHANGE_DESCRIPTOR_KEY
 );
 
@@ -567,7 +832,6 @@ impl_read_write_change_set_type!(
 	write_bdk_wallet_network,
 	Network,
 	BDK_WALLET_NETWORK_PRIMARY_NAMESPACE,
-	BDK_WALLET_NETWORK_SECONDARY_NAMESPACE,
 	BDK_WALLET_NETWORK_KEY
 );
 
@@ -576,7 +840,6 @@ impl_read_write_change_set_type!(
 	write_bdk_wallet_local_chain,
 	BdkLocalChainChangeSet,
 	BDK_WALLET_LOCAL_CHAIN_PRIMARY_NAMESPACE,
-	BDK_WALLET_LOCAL_CHAIN_SECONDARY_NAMESPACE,
 	BDK_WALLET_LOCAL_CHAIN_KEY
 );
 
@@ -585,7 +848,6 @@ impl_read_write_change_set_type!(
 	write_bdk_wallet_tx_graph,
 	BdkTxGraphChangeSet<ConfirmationBlockTime>,
 	BDK_WALLET_TX_GRAPH_PRIMARY_NAMESPACE,
-	BDK_WALLET_TX_GRAPH_SECONDARY_NAMESPACE,
 	BDK_WALLET_TX_GRAPH_KEY
 );
 
@@ -594,46 +856,46 @@ impl_read_write_change_set_type!(
 	write_bdk_wallet_indexer,
 	BdkIndexerChangeSet,
 	BDK_WALLET_INDEXER_PRIMARY_NAMESPACE,
-	BDK_WALLET_INDEXER_SECONDARY_NAMESPACE,
 	BDK_WALLET_INDEXER_KEY
 );
 
 // Reads the full BdkWalletChangeSet or returns default fields
 pub(crate) fn read_bdk_wallet_change_set(
-	kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
+	secondary_namespace: &str, kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
 ) -> Result<Option<BdkWalletChangeSet>, std::io::Error> {
 	let mut change_set = BdkWalletChangeSet::default();
 
 	// We require a descriptor and return `None` to signal creation of a new wallet otherwise.
 	if let Some(descriptor) =
-		read_bdk_wallet_descriptor(Arc::clone(&kv_store), Arc::clone(&logger))?
+		read_bdk_wallet_descriptor(secondary_namespace, Arc::clone(&kv_store), Arc::clone(&logger))?
 	{
 		change_set.descriptor = Some(descriptor);
 	} else {
 		return Ok(None);
 	}
 
-	// We require a change_descriptor and return `None` to signal creation of a new wallet otherwise.
-	if let Some(change_descriptor) =
-		read_bdk_wallet_change_descriptor(Arc::clone(&kv_store), Arc::clone(&logger))?
-	{
-		change_set.change_descriptor = Some(change_descriptor);
-	} else {
-		return Ok(None);
-	}
+	// The change_descriptor is optional, as watch-only wallets tracking a single descriptor don't
+	// have one.
+	change_set.change_descriptor = read_bdk_wallet_change_descriptor(
+		secondary_namespace,
+		Arc::clone(&kv_store),
+		Arc::clone(&logger),
+	)?;
 
 	// We require a network and return `None` to signal creation of a new wallet otherwise.
-	if let Some(network) = read_bdk_wallet_network(Arc::clone(&kv_store), Arc::clone(&logger))? {
+	if let Some(network) =
+		read_bdk_wallet_network(secondary_namespace, Arc::clone(&kv_store), Arc::clone(&logger))?
+	{
 		change_set.network = Some(network);
 	} else {
 		return Ok(None);
 	}
 
-	read_bdk_wallet_local_chain(Arc::clone(&kv_store), Arc::clone(&logger))?
+	read_bdk_wallet_local_chain(secondary_namespace, Arc::clone(&kv_store), Arc::clone(&logger))?
 		.map(|local_chain| change_set.local_chain = local_chain);
-	read_bdk_wallet_tx_graph(Arc::clone(&kv_store), Arc::clone(&logger))?
+	read_bdk_wallet_tx_graph(secondary_namespace, Arc::clone(&kv_store), Arc::clone(&logger))?
 		.map(|tx_graph| change_set.tx_graph = tx_graph);
-	read_bdk_wallet_indexer(Arc::clone(&kv_store), Arc::clone(&logger))?
+	read_bdk_wallet_indexer(secondary_namespace, Arc::clone(&kv_store), Arc::clone(&logger))?
 		.map(|indexer| change_set.indexer = indexer);
 	Ok(Some(change_set))
 }
@@ -0,0 +1,419 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for keeping track of the labels the user assigned to addresses, transactions, outputs
+//! and payments, which can be exported and imported in the [BIP 329] format.
+//!
+//! [BIP 329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+
+use crate::hex_utils;
+use crate::io::{LABEL_PERSISTENCE_PRIMARY_NAMESPACE, LABEL_PERSISTENCE_SECONDARY_NAMESPACE};
+use crate::logger::{log_error, Logger};
+use crate::types::DynStore;
+use crate::Error;
+
+use lightning::ln::channelmanager::PaymentId;
+use lightning::util::ser::Writeable;
+use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
+
+use bitcoin::bip32::Xpub;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Address, Network, OutPoint, PublicKey, Txid};
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// The maximum length of a label, in characters, as recommended by BIP 329.
+pub(crate) const MAX_LABEL_LEN: usize = 255;
+
+/// The kind of object a [`Label`] refers to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LabelType {
+	/// A transaction, referenced by its txid.
+	Transaction,
+	/// An address.
+	Address,
+	/// A public key, referenced by its hex encoding.
+	PublicKey,
+	/// A transaction input, referenced as `<txid>:<index>`, where `txid` is the id of the
+	/// transaction spending the input.
+	Input,
+	/// A transaction output, referenced as `<txid>:<vout>`.
+	Output,
+	/// An extended public key, referenced by its Base58 encoding.
+	ExtendedPublicKey,
+	/// A payment, referenced by the hex encoding of its [`PaymentId`].
+	///
+	/// This is not part of BIP 329 and hence ignored by other wallets when importing labels.
+	Payment,
+}
+
+impl LabelType {
+	fn to_bip329_type(self) -> &'static str {
+		match self {
+			Self::Transaction => "tx",
+			Self::Address => "addr",
+			Self::PublicKey => "pubkey",
+			Self::Input => "input",
+			Self::Output => "output",
+			Self::ExtendedPublicKey => "xpub",
+			Self::Payment => "payment",
+		}
+	}
+
+	fn from_bip329_type(record_type: &str) -> Option<Self> {
+		match record_type {
+			"tx" => Some(Self::Transaction),
+			"addr" => Some(Self::Address),
+			"pubkey" => Some(Self::PublicKey),
+			"input" => Some(Self::Input),
+			"output" => Some(Self::Output),
+			"xpub" => Some(Self::ExtendedPublicKey),
+			"payment" => Some(Self::Payment),
+			_ => None,
+		}
+	}
+}
+
+impl_writeable_tlv_based_enum!(LabelType,
+	(0, Transaction) => {},
+	(2, Address) => {},
+	(4, PublicKey) => {},
+	(6, Input) => {},
+	(8, Output) => {},
+	(10, ExtendedPublicKey) => {},
+	(12, Payment) => {}
+);
+
+/// A label the user assigned to an address, transaction, output or payment.
+///
+/// Labels are set via [`Node::set_label`], and may be exported and imported in the [BIP 329]
+/// format via [`Node::export_labels`] and [`Node::import_labels`].
+///
+/// [`Node::set_label`]: crate::Node::set_label
+/// [`Node::export_labels`]: crate::Node::export_labels
+/// [`Node::import_labels`]: crate::Node::import_labels
+/// [BIP 329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+	/// The kind of object the label refers to.
+	pub label_type: LabelType,
+	/// The reference to the labeled object, in the format given by its [`LabelType`].
+	pub reference: String,
+	/// The label itself.
+	pub label: String,
+	/// The origin of the labeled object, e.g., the descriptor of the wallet it belongs to, if
+	/// known.
+	pub origin: Option<String>,
+}
+
+impl_writeable_tlv_based!(Label, {
+	(0, label_type, required),
+	(2, reference, required),
+	(4, label, required),
+	(6, origin, option),
+});
+
+/// Parses the given reference to an object of the given type, returning it in its canonical form.
+pub(crate) fn normalize_reference(
+	label_type: LabelType, reference: &str, network: Network,
+) -> Result<String, Error> {
+	let normalized = match label_type {
+		LabelType::Transaction => Txid::from_str(reference).ok().map(|t| t.to_string()),
+		LabelType::Address => Address::from_str(reference)
+			.ok()
+			.and_then(|a| a.require_network(network).ok())
+			.map(|a| a.to_string()),
+		LabelType::PublicKey => PublicKey::from_str(reference).ok().map(|p| p.to_string()),
+		LabelType::Input | LabelType::Output => {
+			OutPoint::from_str(reference).ok().map(|o| o.to_string())
+		},
+		LabelType::ExtendedPublicKey => Xpub::from_str(reference).ok().map(|x| x.to_string()),
+		LabelType::Payment => Some(reference)
+			.filter(|r| r.len() == 64)
+			.and_then(hex_utils::to_vec)
+			.and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+			.map(|id| hex_utils::to_string(&PaymentId(id).0)),
+	};
+	normalized.ok_or(Error::InvalidLabel)
+}
+
+// A record of the BIP 329 format.
+#[derive(Serialize, Deserialize)]
+struct Bip329Record {
+	#[serde(rename = "type")]
+	record_type: String,
+	#[serde(rename = "ref")]
+	reference: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	label: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	origin: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	spendable: Option<bool>,
+}
+
+/// The contents of a BIP 329 export.
+pub(crate) struct Bip329Export {
+	pub(crate) labels: Vec<Label>,
+	// Whether the given outputs may be spent, i.e., aren't frozen.
+	pub(crate) spendable_outputs: Vec<(OutPoint, bool)>,
+}
+
+impl Bip329Export {
+	/// Parses the given BIP 329 export, given in the JSON Lines format.
+	///
+	/// Records of unknown types are ignored, and labels longer than [`MAX_LABEL_LEN`] truncated.
+	pub(crate) fn from_jsonl(jsonl: &str, network: Network) -> Result<Self, Error> {
+		let mut labels = Vec::new();
+		let mut spendable_outputs = Vec::new();
+		for line in jsonl.lines().map(str::trim).filter(|l| !l.is_empty()) {
+			let record: Bip329Record =
+				serde_json::from_str(line).map_err(|_| Error::InvalidLabel)?;
+			let label_type = match LabelType::from_bip329_type(&record.record_type) {
+				Some(label_type) => label_type,
+				None => continue,
+			};
+			let reference = normalize_reference(label_type, &record.reference, network)?;
+
+			if let (LabelType::Output, Some(spendable)) = (label_type, record.spendable) {
+				let outpoint = OutPoint::from_str(&reference).map_err(|_| Error::InvalidLabel)?;
+				spendable_outputs.push((outpoint, spendable));
+			}
+
+			match record.label {
+				Some(label) if !label.is_empty() => {
+					let label = label.chars().take(MAX_LABEL_LEN).collect();
+					labels.push(Label { label_type, reference, label, origin: record.origin });
+				},
+				_ => {},
+			}
+		}
+		Ok(Self { labels, spendable_outputs })
+	}
+
+	/// Returns the export in the JSON Lines format.
+	pub(crate) fn to_jsonl(&self) -> String {
+		let mut spendable_outputs: HashMap<String, bool> =
+			self.spendable_outputs.iter().map(|(o, s)| (o.to_string(), *s)).collect();
+
+		let mut records = Vec::new();
+		for label in &self.labels {
+			let spendable = if label.label_type == LabelType::Output {
+				spendable_outputs.remove(&label.reference)
+			} else {
+				None
+			};
+			records.push(Bip329Record {
+				record_type: label.label_type.to_bip329_type().to_string(),
+				reference: label.reference.clone(),
+				label: Some(label.label.clone()),
+				origin: label.origin.clone(),
+				spendable,
+			});
+		}
+
+		// Outputs without a label are only included to convey their spendability.
+		let mut unlabeled_outputs: Vec<(String, bool)> = spendable_outputs.into_iter().collect();
+		unlabeled_outputs.sort();
+		for (reference, spendable) in unlabeled_outputs {
+			records.push(Bip329Record {
+				record_type: LabelType::Output.to_bip329_type().to_string(),
+				reference,
+				label: None,
+				origin: None,
+				spendable: Some(spendable),
+			});
+		}
+
+		records
+			.iter()
+			.filter_map(|r| serde_json::to_string(r).ok())
+			.map(|line| line + "\n")
+			.collect()
+	}
+}
+
+pub(crate) struct LabelStore<L: Deref>
+where
+	L::Target: Logger,
+{
+	labels: Mutex<HashMap<(LabelType, String), Label>>,
+	kv_store: Arc<DynStore>,
+	logger: L,
+}
+
+impl<L: Deref> LabelStore<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(labels: Vec<Label>, kv_store: Arc<DynStore>, logger: L) -> Self {
+		let labels = Mutex::new(
+			labels.into_iter().map(|l| ((l.label_type, l.reference.clone()), l)).collect(),
+		);
+		Self { labels, kv_store, logger }
+	}
+
+	/// Inserts the given label, replacing any existing label of the same object.
+	pub(crate) fn insert(&self, label: Label) -> Result<(), Error> {
+		let mut locked_labels = self.labels.lock().unwrap();
+		let key = (label.label_type, label.reference.clone());
+		if locked_labels.get(&key) == Some(&label) {
+			return Ok(());
+		}
+
+		let store_key = Self::store_key(label.label_type, &label.reference);
+		self.kv_store
+			.write(
+				LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+				LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&label.encode(),
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+					LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+		locked_labels.insert(key, label);
+		Ok(())
+	}
+
+	pub(crate) fn remove(&self, label_type: LabelType, reference: &str) -> Result<(), Error> {
+		let mut locked_labels = self.labels.lock().unwrap();
+		if !locked_labels.contains_key(&(label_type, reference.to_string())) {
+			return Ok(());
+		}
+
+		let store_key = Self::store_key(label_type, reference);
+		self.kv_store
+			.remove(
+				LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+				LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				false,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Removing key {}/{}/{} failed due to: {}",
+					LABEL_PERSISTENCE_PRIMARY_NAMESPACE,
+					LABEL_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+		locked_labels.remove(&(label_type, reference.to_string()));
+		Ok(())
+	}
+
+	/// Returns the label of the object with the given type and canonical reference, if any.
+	pub(crate) fn get(&self, label_type: LabelType, reference: &str) -> Option<String> {
+		self.labels
+			.lock()
+			.unwrap()
+			.get(&(label_type, reference.to_string()))
+			.map(|l| l.label.clone())
+	}
+
+	/// Returns all labels, ordered by type and reference.
+	pub(crate) fn list(&self) -> Vec<Label> {
+		let mut labels: Vec<Label> = self.labels.lock().unwrap().values().cloned().collect();
+		labels.sort_by(|a, b| (a.label_type, &a.reference).cmp(&(b.label_type, &b.reference)));
+		labels
+	}
+
+	// References may contain characters not allowed in keys, so we key labels by a hash instead.
+	fn store_key(label_type: LabelType, reference: &str) -> String {
+		let preimage = format!("{}:{}", label_type.to_bip329_type(), reference);
+		hex_utils::to_string(&sha256::Hash::hash(preimage.as_bytes()).to_byte_array())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	use bitcoin::CompressedPublicKey;
+
+	#[test]
+	fn bip329_export_round_trip() {
+		let txid = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd";
+		let pubkey = "02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619";
+		let address =
+			Address::p2wpkh(&CompressedPublicKey::from_str(pubkey).unwrap(), Network::Regtest);
+		let jsonl = format!(
+			concat!(
+				"{{\"type\":\"tx\",\"ref\":\"{}\",\"label\":\"Transaction\",\"origin\":\"wpkh([d34db33f/84'/0'/0'])\"}}\n",
+				"{{\"type\":\"addr\",\"ref\":\"{}\",\"label\":\"Address\"}}\n",
+				"\n",
+				"{{\"type\":\"output\",\"ref\":\"{}:1\",\"label\":\"Output\",\"spendable\":false}}\n",
+				"{{\"type\":\"output\",\"ref\":\"{}:2\",\"spendable\":true}}\n",
+				"{{\"type\":\"unknown\",\"ref\":\"foo\",\"label\":\"Ignored\"}}\n",
+			),
+			txid,
+			address.to_string().to_uppercase(),
+			txid,
+			txid
+		);
+
+		let export = Bip329Export::from_jsonl(&jsonl, Network::Regtest).unwrap();
+		assert_eq!(export.labels.len(), 3);
+		assert_eq!(export.labels[0].label_type, LabelType::Transaction);
+		assert_eq!(export.labels[0].origin.as_deref(), Some("wpkh([d34db33f/84'/0'/0'])"));
+		assert_eq!(export.labels[1].reference, address.to_string());
+		assert_eq!(export.spendable_outputs.len(), 2);
+
+		let exported = export.to_jsonl();
+		assert_eq!(exported.lines().count(), 4);
+		let reimported = Bip329Export::from_jsonl(&exported, Network::Regtest).unwrap();
+		assert_eq!(reimported.labels, export.labels);
+		assert_eq!(reimported.spendable_outputs, export.spendable_outputs);
+
+		// Addresses of other networks and malformed records are rejected.
+		assert!(Bip329Export::from_jsonl(&jsonl, Network::Bitcoin).is_err());
+		assert!(Bip329Export::from_jsonl("{\"type\":\"tx\"}", Network::Regtest).is_err());
+	}
+
+	#[test]
+	fn labels_are_persisted() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let label_store = LabelStore::new(Vec::new(), Arc::clone(&store), Arc::clone(&logger));
+
+		let reference = "f91d0a8a78462bc59398f2c5d7a84fcff491c26ba54c4833478b202796c8aafd:0";
+		let label = Label {
+			label_type: LabelType::Output,
+			reference: reference.to_string(),
+			label: "Cold storage".to_string(),
+			origin: None,
+		};
+		label_store.insert(label.clone()).unwrap();
+		assert_eq!(label_store.get(LabelType::Output, reference), Some("Cold storage".to_string()));
+		assert_eq!(label_store.get(LabelType::Input, reference), None);
+
+		let labels =
+			crate::io::utils::read_labels(Arc::clone(&store), Arc::clone(&logger)).unwrap();
+		assert_eq!(labels, vec![label]);
+
+		label_store.remove(LabelType::Output, reference).unwrap();
+		assert!(label_store.list().is_empty());
+		assert!(crate::io::utils::read_labels(store, logger).unwrap().is_empty());
+	}
+}
@@ -40,13 +40,13 @@
 //!
 //! 	node.start().unwrap();
 //!
-//! 	let funding_address = node.onchain_payment().new_address();
+//! 	let funding_address = node.onchain_payment().new_address(None);
 //!
 //! 	// .. fund address ..
 //!
 //! 	let node_id = PublicKey::from_str("NODE_ID").unwrap();
 //! 	let node_addr = SocketAddress::from_str("IP_ADDR:PORT").unwrap();
-//! 	node.open_channel(node_id, node_addr, 10000, None, None).unwrap();
+//! 	node.open_channel(node_id, node_addr, 10000, None, None, None, None).unwrap();
 //!
 //! 	let event = node.wait_next_event();
 //! 	println!("EVENT: {:?}", event);
@@ -83,12 +83,16 @@ mod fee_estimator;
 mod gossip;
 pub mod graph;
 mod hex_utils;
+mod hrn_resolver;
 pub mod io;
+mod label_store;
 mod liquidity;
 mod logger;
 mod message_handler;
 pub mod payment;
 mod peer_store;
+mod probing;
+mod scheduler;
 mod sweep;
 mod tx_broadcaster;
 mod types;
@@ -103,12 +107,14 @@ pub use lightning_invoice;
 pub use lightning_types;
 pub use vss_client;
 
-pub use balance::{BalanceDetails, LightningBalance, PendingSweepBalance};
+pub use balance::{BalanceDetails, LightningBalance, PendingSweepBalance, WatchOnlyBalance};
 pub use error::Error as NodeError;
 use error::Error;
 
 pub use event::Event;
 
+pub use label_store::{Label, LabelType};
+
 pub use io::utils::generate_entropy_mnemonic;
 
 #[cfg(feature = "uniffi")]
@@ -122,26 +128,44 @@ pub use builder::NodeBuilder as Builder;
 
 use chain::ChainSource;
 use config::{
-	default_user_config, may_announce_channel, ChannelConfig, Config, NODE_ANN_BCAST_INTERVAL,
-	PEER_RECONNECTION_INTERVAL, RGS_SYNC_INTERVAL,
+	default_user_config, may_announce_channel, ChannelConfig, Config,
+	EXTERNAL_FUNDING_CHECK_INTERVAL, NODE_ANN_BCAST_INTERVAL, PEER_RECONNECTION_INTERVAL,
+	RGS_SYNC_INTERVAL, SCHEDULED_PAYMENT_MIN_INTERVAL_SECS,
 };
 use connection::ConnectionManager;
 use event::{EventHandler, EventQueue};
+use fee_estimator::fee_rate_from_sat_per_vb;
 use gossip::GossipSource;
 use graph::NetworkGraph;
+use hrn_resolver::HrnResolver;
 use io::utils::write_node_metrics;
+use label_store::{Bip329Export, LabelStore, MAX_LABEL_LEN};
 use liquidity::LiquiditySource;
+use payment::hold_invoice::HoldInvoiceManager;
+use payment::offer_store::OfferStore;
+use payment::payjoin::PayjoinReceiver;
+use payment::retry::{PaymentRetryStore, PaymentRetryTarget};
 use payment::store::PaymentStore;
 use payment::{
-	Bolt11Payment, Bolt12Payment, OnchainPayment, PaymentDetails, SpontaneousPayment,
-	UnifiedQrPayment,
+	Bolt11Payment, Bolt12Payment, HeldPaymentDetails, LnurlPayment, OnchainPayment, PaymentDetails,
+	PaymentDirection, PaymentInstructions, PaymentKind, PaymentStatus, QrPaymentResult,
+	SpontaneousPayment, UnifiedQrPayment,
 };
 use peer_store::{PeerInfo, PeerStore};
+use probing::Prober;
+pub use probing::{ProbeHop, ProbeResult};
+use scheduler::PaymentScheduler;
+pub use scheduler::{
+	ScheduledPaymentDetails, ScheduledPaymentExecution, ScheduledPaymentFailureReason,
+	ScheduledPaymentId, ScheduledPaymentTarget,
+};
 use types::{
 	Broadcaster, BumpTransactionEventHandler, ChainMonitor, ChannelManager, DynStore, Graph,
-	KeysManager, OnionMessenger, PeerManager, Router, Scorer, Sweeper, Wallet,
+	KeysManager, OnionMessenger, PeerManager, PendingBolt12Invoices, Router, Scorer, Sweeper,
+	Wallet,
 };
-pub use types::{ChannelDetails, CustomTlvRecord, PeerDetails, UserChannelId};
+pub use types::{ChannelDetails, ChannelOpenRequest, CustomTlvRecord, PeerDetails, UserChannelId};
+use wallet::FundingOptions;
 
 use logger::{log_error, log_info, log_trace, FilesystemLogger, Logger};
 
@@ -151,16 +175,23 @@ use lightning::impl_writeable_tlv_based;
 use lightning::ln::channel_state::ChannelShutdownState;
 use lightning::ln::channelmanager::PaymentId;
 use lightning::ln::msgs::SocketAddress;
+use lightning::ln::types::ChannelId;
+use lightning::offers::offer::Amount;
+use lightning::onion_message::dns_resolution::HumanReadableName;
 use lightning::routing::gossip::NodeAlias;
+use lightning::sign::EntropySource;
+use lightning::util::config::UserConfig;
 
 pub use lightning::util::logger::Level as LogLevel;
 
 use lightning_background_processor::process_events_async;
 
 use bitcoin::secp256k1::PublicKey;
+use bitcoin::{OutPoint, Transaction};
 
 use rand::Rng;
 
+use std::collections::HashMap;
 use std::default::Default;
 use std::net::ToSocketAddrs;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -198,6 +229,15 @@ pub struct Node {
 	scorer: Arc<Mutex<Scorer>>,
 	peer_store: Arc<PeerStore<Arc<FilesystemLogger>>>,
 	payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+	payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>,
+	offer_store: Arc<OfferStore<Arc<FilesystemLogger>>>,
+	label_store: Arc<LabelStore<Arc<FilesystemLogger>>>,
+	pending_bolt12_invoices: Arc<PendingBolt12Invoices>,
+	hrn_resolver: Arc<HrnResolver<Arc<KeysManager>, Arc<FilesystemLogger>>>,
+	prober: Arc<Prober<Arc<FilesystemLogger>>>,
+	payment_scheduler: Arc<PaymentScheduler<Arc<FilesystemLogger>>>,
+	hold_invoice_manager: Arc<HoldInvoiceManager<Arc<FilesystemLogger>>>,
+	payjoin_receiver: Option<Arc<PayjoinReceiver>>,
 	is_listening: Arc<AtomicBool>,
 	node_metrics: Arc<RwLock<NodeMetrics>>,
 }
@@ -256,6 +296,14 @@ impl Node {
 				.await;
 		});
 
+		// Spawn background task rescanning the chain for the history of imported watch-only
+		// descriptors.
+		let stop_rescan_receiver = self.stop_sender.subscribe();
+		let chain_source = Arc::clone(&self.chain_source);
+		runtime.spawn(async move {
+			chain_source.continuously_rescan_watch_only_wallets(stop_rescan_receiver).await;
+		});
+
 		if self.gossip_source.is_rgs() {
 			let gossip_source = Arc::clone(&self.gossip_source);
 			let gossip_sync_store = Arc::clone(&self.kv_store);
@@ -519,6 +567,96 @@ impl Node {
 			}
 		});
 
+		if let Some(probing_config) = self.config.probing_config.clone() {
+			let stop_probing_receiver = self.stop_sender.subscribe();
+			let prober = Arc::clone(&self.prober);
+			runtime.spawn(async move {
+				prober.continuously_probe(&probing_config, stop_probing_receiver).await;
+			});
+		}
+
+		let stop_scheduler_receiver = self.stop_sender.subscribe();
+		let payment_scheduler = Arc::clone(&self.payment_scheduler);
+		let bolt12_payment = self.bolt12_payment();
+		let spontaneous_payment = self.spontaneous_payment();
+		runtime.spawn(async move {
+			let execute = |target: &ScheduledPaymentTarget, amount_msat: u64| match target {
+				ScheduledPaymentTarget::Bolt12Offer { offer } => {
+					bolt12_payment.send_using_amount(offer, amount_msat, None, None)
+				},
+				ScheduledPaymentTarget::Spontaneous { node_id } => {
+					spontaneous_payment.send(amount_msat, *node_id, None)
+				},
+			};
+			payment_scheduler.continuously_execute(execute, stop_scheduler_receiver).await;
+		});
+
+		let stop_hold_invoice_receiver = self.stop_sender.subscribe();
+		let hold_invoice_manager = Arc::clone(&self.hold_invoice_manager);
+		let hold_invoice_channel_manager = Arc::clone(&self.channel_manager);
+		runtime.spawn(async move {
+			hold_invoice_manager
+				.continuously_check(hold_invoice_channel_manager, stop_hold_invoice_receiver)
+				.await;
+		});
+
+		let mut stop_funding_check = self.stop_sender.subscribe();
+		let funding_check_wallet = Arc::clone(&self.wallet);
+		let funding_check_cm = Arc::clone(&self.channel_manager);
+		let funding_check_logger = Arc::clone(&self.logger);
+		runtime.spawn(async move {
+			let mut interval = tokio::time::interval(EXTERNAL_FUNDING_CHECK_INTERVAL);
+			interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+			loop {
+				tokio::select! {
+					_ = stop_funding_check.changed() => {
+						log_trace!(
+							funding_check_logger,
+							"Stopping checking externally funded channels.",
+						);
+						return;
+					}
+					_ = interval.tick() => {
+						// Abort the channel opens the user didn't provide a funding transaction for
+						// in time.
+						for output in funding_check_wallet.take_expired_external_fundings() {
+							log_info!(
+								funding_check_logger,
+								"Aborting channel {} as no funding transaction was provided in time.",
+								output.temporary_channel_id
+							);
+							if let Err(e) = funding_check_cm.force_close_without_broadcasting_txn(
+								&output.temporary_channel_id,
+								&output.counterparty_node_id,
+								"Funding transaction wasn't provided in time".to_string(),
+							) {
+								log_error!(
+									funding_check_logger,
+									"Failed to close channel {} awaiting external funding: {:?}",
+									output.temporary_channel_id,
+									e
+								);
+							}
+						}
+					}
+				}
+			}
+		});
+
+		if let Some(payjoin_receiver) = self.payjoin_receiver.as_ref().map(Arc::clone) {
+			let stop_payjoin_receiver = self.stop_sender.subscribe();
+			let payjoin_directory_receiver = Arc::clone(&payjoin_receiver);
+			let stop_payjoin_directory_receiver = self.stop_sender.subscribe();
+			runtime.spawn(async move {
+				payjoin_receiver.continuously_serve(stop_payjoin_receiver).await;
+			});
+			runtime.spawn(async move {
+				payjoin_directory_receiver
+					.continuously_poll_directory(stop_payjoin_directory_receiver)
+					.await;
+			});
+		}
+
 		let bump_tx_event_handler = Arc::new(BumpTransactionEventHandler::new(
 			Arc::clone(&self.tx_broadcaster),
 			Arc::new(LdkWallet::new(Arc::clone(&self.wallet), Arc::clone(&self.logger))),
@@ -535,7 +673,12 @@ impl Node {
 			Arc::clone(&self.output_sweeper),
 			Arc::clone(&self.network_graph),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
+			Arc::clone(&self.pending_bolt12_invoices),
+			Arc::clone(&self.hold_invoice_manager),
 			Arc::clone(&self.peer_store),
+			Arc::clone(&self.prober),
+			Arc::clone(&self.payment_scheduler),
 			Arc::clone(&self.runtime),
 			Arc::clone(&self.logger),
 			Arc::clone(&self.config),
@@ -826,6 +969,8 @@ impl Node {
 			Arc::clone(&self.connection_manager),
 			self.liquidity_source.clone(),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
+			Arc::clone(&self.hold_invoice_manager),
 			Arc::clone(&self.peer_store),
 			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
@@ -843,6 +988,8 @@ impl Node {
 			Arc::clone(&self.connection_manager),
 			self.liquidity_source.clone(),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
+			Arc::clone(&self.hold_invoice_manager),
 			Arc::clone(&self.peer_store),
 			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
@@ -858,6 +1005,10 @@ impl Node {
 			Arc::clone(&self.runtime),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
+			Arc::clone(&self.offer_store),
+			Arc::clone(&self.pending_bolt12_invoices),
+			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
 		)
 	}
@@ -871,6 +1022,10 @@ impl Node {
 			Arc::clone(&self.runtime),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
+			Arc::clone(&self.offer_store),
+			Arc::clone(&self.pending_bolt12_invoices),
+			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
 		))
 	}
@@ -882,7 +1037,9 @@ impl Node {
 			Arc::clone(&self.runtime),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.keys_manager),
+			Arc::clone(&self.network_graph),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
 			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
 		)
@@ -895,7 +1052,9 @@ impl Node {
 			Arc::clone(&self.runtime),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.keys_manager),
+			Arc::clone(&self.network_graph),
 			Arc::clone(&self.payment_store),
+			Arc::clone(&self.payment_retry_store),
 			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
 		))
@@ -909,6 +1068,8 @@ impl Node {
 			Arc::clone(&self.wallet),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.config),
+			Arc::clone(&self.kv_store),
+			Arc::clone(&self.label_store),
 			Arc::clone(&self.logger),
 		)
 	}
@@ -921,6 +1082,8 @@ impl Node {
 			Arc::clone(&self.wallet),
 			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.config),
+			Arc::clone(&self.kv_store),
+			Arc::clone(&self.label_store),
 			Arc::clone(&self.logger),
 		))
 	}
@@ -937,6 +1100,8 @@ impl Node {
 			self.onchain_payment().into(),
 			self.bolt11_payment().into(),
 			self.bolt12_payment().into(),
+			self.payjoin_receiver.as_ref().map(Arc::clone),
+			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
 		)
@@ -954,11 +1119,111 @@ impl Node {
 			self.onchain_payment(),
 			self.bolt11_payment(),
 			self.bolt12_payment(),
+			self.payjoin_receiver.as_ref().map(Arc::clone),
+			Arc::clone(&self.channel_manager),
 			Arc::clone(&self.config),
 			Arc::clone(&self.logger),
 		))
 	}
 
+	/// Returns a payment handler allowing to pay [LNURL-pay] requests and [Lightning Addresses],
+	/// and to withdraw funds via [LNURL-withdraw].
+	///
+	/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+	/// [Lightning Addresses]: https://github.com/lnurl/luds/blob/luds/16.md
+	/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+	#[cfg(not(feature = "uniffi"))]
+	pub fn lnurl_payment(&self) -> LnurlPayment {
+		LnurlPayment::new(
+			Arc::clone(&self.runtime),
+			self.bolt11_payment().into(),
+			Arc::clone(&self.logger),
+		)
+	}
+
+	/// Returns a payment handler allowing to pay [LNURL-pay] requests and [Lightning Addresses],
+	/// and to withdraw funds via [LNURL-withdraw].
+	///
+	/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+	/// [Lightning Addresses]: https://github.com/lnurl/luds/blob/luds/16.md
+	/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+	#[cfg(feature = "uniffi")]
+	pub fn lnurl_payment(&self) -> Arc<LnurlPayment> {
+		Arc::new(LnurlPayment::new(
+			Arc::clone(&self.runtime),
+			self.bolt11_payment(),
+			Arc::clone(&self.logger),
+		))
+	}
+
+	/// Sends a payment of the given amount to a [BIP 353] human-readable name, e.g.,
+	/// `₿alice@example.com`.
+	///
+	/// The name is resolved to a [BIP 21] URI by querying the DNSSEC-proving resolvers configured
+	/// via [`Config::dns_resolvers_node_ids`]. After the returned proof has been verified, the URI
+	/// is paid as via [`UnifiedQrPayment::send`], i.e., trying the [BOLT 12] offer, [BOLT 11]
+	/// invoice, and on-chain address as determined by [`Config::unified_payment_policy`].
+	///
+	/// Records lacking an on-chain address, e.g., `bitcoin:?lno=lno1...`, are supported, in which
+	/// case only the Lightning payment options are tried. As on-chain payments can't carry
+	/// fractions of a satoshi, paying the on-chain address fails with [`Error::InvalidAmount`] if
+	/// `amount_msat` isn't a multiple of 1000.
+	///
+	/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	pub fn pay_human_readable_name(
+		&self, name: &str, amount_msat: u64,
+	) -> Result<QrPaymentResult, Error> {
+		if amount_msat == 0 {
+			return Err(Error::InvalidAmount);
+		}
+
+		let hrn = HumanReadableName::from_encoded(name).map_err(|()| {
+			log_error!(self.logger, "Failed to parse human-readable name {}", name);
+			Error::InvalidHumanReadableName
+		})?;
+
+		let uri = {
+			let rt_lock = self.runtime.read().unwrap();
+			let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+			let hrn_resolver = Arc::clone(&self.hrn_resolver);
+			let best_block = self.channel_manager.current_best_block();
+			tokio::task::block_in_place(move || {
+				runtime.block_on(async move { hrn_resolver.resolve(hrn, best_block).await })
+			})?
+		};
+
+		self.unified_qr_payment().send_inner(
+			&uri,
+			Some(amount_msat),
+			&self.config.unified_payment_policy,
+			None,
+		)
+	}
+
+	/// Decodes the given payment string into structured [`PaymentInstructions`].
+	///
+	/// Supports [BOLT 11] invoices, [BOLT 12] offers and refunds, [BIP 21] URIs, on-chain
+	/// addresses, node URIs, [LNURL]s, and Lightning Addresses, optionally prefixed with
+	/// `lightning:`. This allows to present the details of a payment to the user before passing
+	/// it to the respective payment handler.
+	///
+	/// Returns [`Error::InvalidNetwork`] if the payment string is meant for a different network
+	/// than the one configured via [`Config::network`].
+	///
+	/// **Note:** This doesn't contact any remote services, i.e., [LNURL]s and Lightning Addresses
+	/// are not resolved.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	/// [LNURL]: https://github.com/lnurl/luds
+	pub fn parse_payment_instructions(&self, input: &str) -> Result<PaymentInstructions, Error> {
+		payment::instructions::parse_payment_instructions(input, self.config.network)
+	}
+
 	/// Retrieve a list of known channels.
 	pub fn list_channels(&self) -> Vec<ChannelDetails> {
 		self.channel_manager.list_channels().into_iter().map(|c| c.into()).collect()
@@ -1025,7 +1290,8 @@ impl Node {
 	fn open_channel_inner(
 		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
 		push_to_counterparty_msat: Option<u64>, channel_config: Option<ChannelConfig>,
-		announce_for_forwarding: bool,
+		announce_for_forwarding: bool, utxos_to_spend: Option<Vec<OutPoint>>,
+		fee_rate_sat_per_vb: Option<u64>,
 	) -> Result<UserChannelId, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
@@ -1033,6 +1299,8 @@ impl Node {
 		}
 		let runtime = rt_lock.as_ref().unwrap();
 
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+
 		let peer_info = PeerInfo { node_id, address };
 
 		let con_node_id = peer_info.node_id;
@@ -1053,6 +1321,18 @@ impl Node {
 			return Err(Error::InsufficientFunds);
 		}
 
+		// Fail early if the explicitly selected outputs can't cover the channel value.
+		if let Some(outpoints) = utxos_to_spend.as_ref() {
+			let selected_amount_sats = self.wallet.utxos_value_sats(outpoints)?;
+			if selected_amount_sats < channel_amount_sats {
+				log_error!(self.logger,
+					"Unable to create channel due to insufficient funds in selected UTXOs. Available: {}sats, Required: {}sats",
+					selected_amount_sats, channel_amount_sats
+				);
+				return Err(Error::InsufficientFunds);
+			}
+		}
+
 		// We need to use our main runtime here as a local runtime might not be around to poll
 		// connection futures going forward.
 		tokio::task::block_in_place(move || {
@@ -1062,22 +1342,7 @@ impl Node {
 		})?;
 
 		// Fail if we have less than the channel value + anchor reserve available (if applicable).
-		let init_features = self
-			.peer_manager
-			.peer_by_node_id(&node_id)
-			.ok_or(Error::ConnectionFailed)?
-			.init_features;
-		let required_funds_sats = channel_amount_sats
-			+ self.config.anchor_channels_config.as_ref().map_or(0, |c| {
-				if init_features.requires_anchors_zero_fee_htlc_tx()
-					&& !c.trusted_peers_no_reserve.contains(&node_id)
-				{
-					c.per_channel_reserve_sats
-				} else {
-					0
-				}
-			});
-
+		let required_funds_sats = self.required_channel_funds_sats(node_id, channel_amount_sats)?;
 		if spendable_amount_sats < required_funds_sats {
 			log_error!(self.logger,
 				"Unable to create channel due to insufficient funds. Available: {}sats, Required: {}sats",
@@ -1086,21 +1351,16 @@ impl Node {
 			return Err(Error::InsufficientFunds);
 		}
 
-		let mut user_config = default_user_config(&self.config);
-		user_config.channel_handshake_config.announce_for_forwarding = announce_for_forwarding;
-		user_config.channel_config = (channel_config.unwrap_or_default()).clone().into();
-		// We set the max inflight to 100% for private channels.
-		// FIXME: LDK will default to this behavior soon, too, at which point we should drop this
-		// manual override.
-		if !announce_for_forwarding {
-			user_config
-				.channel_handshake_config
-				.max_inbound_htlc_value_in_flight_percent_of_channel = 100;
-		}
+		let user_config = self.channel_user_config(channel_config, announce_for_forwarding);
 
 		let push_msat = push_to_counterparty_msat.unwrap_or(0);
 		let user_channel_id: u128 = rand::thread_rng().gen::<u128>();
 
+		if utxos_to_spend.is_some() || fee_rate.is_some() {
+			let funding_options = FundingOptions { utxos_to_spend, fee_rate };
+			self.wallet.set_funding_options(user_channel_id, funding_options);
+		}
+
 		match self.channel_manager.create_channel(
 			peer_info.node_id,
 			channel_amount_sats,
@@ -1120,11 +1380,51 @@ impl Node {
 			},
 			Err(e) => {
 				log_error!(self.logger, "Failed to initiate channel creation: {:?}", e);
+				self.wallet.take_funding_options(user_channel_id);
 				Err(Error::ChannelCreationFailed)
 			},
 		}
 	}
 
+	// Returns the funds required to open a channel of the given value with the given connected
+	// peer, including the anchor reserve (if applicable).
+	fn required_channel_funds_sats(
+		&self, node_id: PublicKey, channel_amount_sats: u64,
+	) -> Result<u64, Error> {
+		let init_features = self
+			.peer_manager
+			.peer_by_node_id(&node_id)
+			.ok_or(Error::ConnectionFailed)?
+			.init_features;
+		Ok(channel_amount_sats
+			+ self.config.anchor_channels_config.as_ref().map_or(0, |c| {
+				if init_features.requires_anchors_zero_fee_htlc_tx()
+					&& !c.trusted_peers_no_reserve.contains(&node_id)
+				{
+					c.per_channel_reserve_sats
+				} else {
+					0
+				}
+			}))
+	}
+
+	fn channel_user_config(
+		&self, channel_config: Option<ChannelConfig>, announce_for_forwarding: bool,
+	) -> UserConfig {
+		let mut user_config = default_user_config(&self.config);
+		user_config.channel_handshake_config.announce_for_forwarding = announce_for_forwarding;
+		user_config.channel_config = (channel_config.unwrap_or_default()).clone().into();
+		// We set the max inflight to 100% for private channels.
+		// FIXME: LDK will default to this behavior soon, too, at which point we should drop this
+		// manual override.
+		if !announce_for_forwarding {
+			user_config
+				.channel_handshake_config
+				.max_inbound_htlc_value_in_flight_percent_of_channel = 100;
+		}
+		user_config
+	}
+
 	/// Connect to a node and open a new unannounced channel.
 	///
 	/// To open an announced channel, see [`Node::open_announced_channel`].
@@ -1139,12 +1439,20 @@ impl Node {
 	/// [`AnchorChannelsConfig::per_channel_reserve_sats`] is available and will be retained before
 	/// opening the channel.
 	///
+	/// If `utxos_to_spend` is set, the funding transaction will only spend the given outputs, which
+	/// may include frozen ones. See [`OnchainPayment::freeze_utxo`] for more details.
+	///
+	/// If `fee_rate_sat_per_vb` is set, the funding transaction will pay the given fee rate, in
+	/// satoshis per virtual byte. Otherwise, the fee rate is estimated based on the current chain
+	/// state.
+	///
 	/// Returns a [`UserChannelId`] allowing to locally keep track of the channel.
 	///
 	/// [`AnchorChannelsConfig::per_channel_reserve_sats`]: crate::config::AnchorChannelsConfig::per_channel_reserve_sats
 	pub fn open_channel(
 		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
 		push_to_counterparty_msat: Option<u64>, channel_config: Option<ChannelConfig>,
+		utxos_to_spend: Option<Vec<OutPoint>>, fee_rate_sat_per_vb: Option<u64>,
 	) -> Result<UserChannelId, Error> {
 		self.open_channel_inner(
 			node_id,
@@ -1153,6 +1461,8 @@ impl Node {
 			push_to_counterparty_msat,
 			channel_config,
 			false,
+			utxos_to_spend,
+			fee_rate_sat_per_vb,
 		)
 	}
 
@@ -1174,12 +1484,20 @@ impl Node {
 	/// [`AnchorChannelsConfig::per_channel_reserve_sats`] is available and will be retained before
 	/// opening the channel.
 	///
+	/// If `utxos_to_spend` is set, the funding transaction will only spend the given outputs, which
+	/// may include frozen ones. See [`OnchainPayment::freeze_utxo`] for more details.
+	///
+	/// If `fee_rate_sat_per_vb` is set, the funding transaction will pay the given fee rate, in
+	/// satoshis per virtual byte. Otherwise, the fee rate is estimated based on the current chain
+	/// state.
+	///
 	/// Returns a [`UserChannelId`] allowing to locally keep track of the channel.
 	///
 	/// [`AnchorChannelsConfig::per_channel_reserve_sats`]: crate::config::AnchorChannelsConfig::per_channel_reserve_sats
 	pub fn open_announced_channel(
 		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
 		push_to_counterparty_msat: Option<u64>, channel_config: Option<ChannelConfig>,
+		utxos_to_spend: Option<Vec<OutPoint>>, fee_rate_sat_per_vb: Option<u64>,
 	) -> Result<UserChannelId, Error> {
 		if may_announce_channel(&self.config) {
 			self.open_channel_inner(
@@ -1189,6 +1507,8 @@ impl Node {
 				push_to_counterparty_msat,
 				channel_config,
 				true,
+				utxos_to_spend,
+				fee_rate_sat_per_vb,
 			)
 		} else {
 			log_error!(self.logger, "Failed to open announced channel as the node hasn't been sufficiently configured to act as a forwarding node. Please make sure to configure listening addreesses and node alias");
@@ -1196,6 +1516,341 @@ impl Node {
 		}
 	}
 
+	/// Connect to the given nodes and open new channels to them, all funded by a single
+	/// transaction.
+	///
+	/// Funding multiple channels at once saves on-chain fees compared to opening them one by one
+	/// via [`Node::open_channel`] or [`Node::open_announced_channel`].
+	///
+	/// The batch is aborted if any of the channels fails to be created or is rejected by the
+	/// respective counterparty before the funding transaction is signed, in which case all other
+	/// channels of the batch are closed again. The funding transaction is only broadcast once all
+	/// counterparties have signed their channel, so no funds are spent unless all channels open.
+	///
+	/// If Anchor channels are enabled, this will ensure the configured
+	/// [`AnchorChannelsConfig::per_channel_reserve_sats`] is available and will be retained for
+	/// each of the channels.
+	///
+	/// Returns the [`UserChannelId`]s of the channels, in the order of the given requests.
+	///
+	/// [`AnchorChannelsConfig::per_channel_reserve_sats`]: crate::config::AnchorChannelsConfig::per_channel_reserve_sats
+	pub fn open_channels_batch(
+		&self, requests: Vec<ChannelOpenRequest>,
+	) -> Result<Vec<UserChannelId>, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+		let runtime = rt_lock.as_ref().unwrap();
+
+		if requests.is_empty() {
+			log_error!(self.logger, "Failed to open channel batch as no channels were requested.");
+			return Err(Error::ChannelCreationFailed);
+		}
+
+		if requests.iter().any(|r| r.announce_for_forwarding) && !may_announce_channel(&self.config)
+		{
+			log_error!(self.logger, "Failed to open announced channel as the node hasn't been sufficiently configured to act as a forwarding node. Please make sure to configure listening addreesses and node alias");
+			return Err(Error::ChannelCreationFailed);
+		}
+
+		let cur_anchor_reserve_sats =
+			total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		let spendable_amount_sats =
+			self.wallet.get_spendable_amount_sats(cur_anchor_reserve_sats).unwrap_or(0);
+
+		// Fail early if we have less than the total channel value available.
+		let total_channel_amount_sats =
+			requests.iter().map(|r| r.channel_amount_sats).fold(0u64, u64::saturating_add);
+		if spendable_amount_sats < total_channel_amount_sats {
+			log_error!(self.logger,
+				"Unable to create channel batch due to insufficient funds. Available: {}sats, Required: {}sats",
+				spendable_amount_sats, total_channel_amount_sats
+			);
+			return Err(Error::InsufficientFunds);
+		}
+
+		// We need to use our main runtime here as a local runtime might not be around to poll
+		// connection futures going forward.
+		for request in requests.iter() {
+			let con_node_id = request.node_id;
+			let con_addr = request.address.clone();
+			let con_cm = Arc::clone(&self.connection_manager);
+			tokio::task::block_in_place(move || {
+				runtime.block_on(async move {
+					con_cm.connect_peer_if_necessary(con_node_id, con_addr).await
+				})
+			})?;
+		}
+
+		// Fail if we have less than the channel values + anchor reserves available (if
+		// applicable).
+		let mut required_funds_sats = 0u64;
+		for request in requests.iter() {
+			let channel_funds_sats =
+				self.required_channel_funds_sats(request.node_id, request.channel_amount_sats)?;
+			required_funds_sats = required_funds_sats.saturating_add(channel_funds_sats);
+		}
+		if spendable_amount_sats < required_funds_sats {
+			log_error!(self.logger,
+				"Unable to create channel batch due to insufficient funds. Available: {}sats, Required: {}sats",
+				spendable_amount_sats, required_funds_sats
+			);
+			return Err(Error::InsufficientFunds);
+		}
+
+		// We register the batch before creating the channels, so that their funding is deferred
+		// until all of them are ready.
+		let user_channel_ids =
+			requests.iter().map(|_| rand::thread_rng().gen::<u128>()).collect::<Vec<_>>();
+		self.wallet.add_funding_batch(user_channel_ids.clone());
+
+		let mut temporary_channels = Vec::with_capacity(requests.len());
+		for (request, user_channel_id) in requests.iter().zip(user_channel_ids.iter()) {
+			let user_config =
+				self.channel_user_config(request.channel_config, request.announce_for_forwarding);
+			match self.channel_manager.create_channel(
+				request.node_id,
+				request.channel_amount_sats,
+				request.push_to_counterparty_msat.unwrap_or(0),
+				*user_channel_id,
+				None,
+				Some(user_config),
+			) {
+				Ok(temporary_channel_id) => {
+					temporary_channels.push((temporary_channel_id, request.node_id));
+				},
+				Err(e) => {
+					log_error!(
+						self.logger,
+						"Failed to initiate channel creation with peer {}, aborting channel batch: {:?}",
+						request.node_id,
+						e
+					);
+					self.wallet.take_funding_batch(*user_channel_id);
+					self.close_unfunded_channels(&temporary_channels);
+					return Err(Error::ChannelCreationFailed);
+				},
+			}
+		}
+
+		// One of the counterparties might have rejected its channel while we were still creating
+		// the others, in which case the batch was aborted already.
+		if !self.wallet.is_batch_funded(user_channel_ids[0]) {
+			log_error!(
+				self.logger,
+				"Channel batch was aborted as a counterparty rejected its channel."
+			);
+			self.close_unfunded_channels(&temporary_channels);
+			return Err(Error::ChannelCreationFailed);
+		}
+
+		for request in requests {
+			let peer_info = PeerInfo { node_id: request.node_id, address: request.address };
+			self.peer_store.add_peer(peer_info)?;
+		}
+
+		log_info!(
+			self.logger,
+			"Initiated creation of a batch of {} channels.",
+			user_channel_ids.len()
+		);
+		Ok(user_channel_ids.into_iter().map(UserChannelId).collect())
+	}
+
+	/// Connect to a node and open a new channel which is funded by a transaction the user
+	/// constructs themselves, e.g., using an external wallet.
+	///
+	/// Once the counterparty accepted the channel, an [`Event::FundingTransactionRequired`] event
+	/// is emitted, detailing the output the funding transaction needs to create. The transaction
+	/// then has to be handed over via [`Node::provide_funding_transaction`] within
+	/// [`Config::external_funding_timeout_secs`], after which the channel open is aborted. The
+	/// funding transaction will be broadcast once the counterparty signed the channel, so it must
+	/// not be broadcast by the user.
+	///
+	/// **Note:** Channels awaiting their funding transaction don't survive restarts of the node.
+	///
+	/// If `announce_for_forwarding` is set, the channel will be announced to the network, which
+	/// requires the node to be configured to operate as a forwarding node. See
+	/// [`Node::open_announced_channel`] for more details.
+	///
+	/// If `push_to_counterparty_msat` is set, the given value will be pushed (read: sent) to the
+	/// channel counterparty on channel open.
+	///
+	/// If Anchor channels are enabled, this will ensure the configured
+	/// [`AnchorChannelsConfig::per_channel_reserve_sats`] is available in the on-chain wallet and
+	/// will be retained there, as it is required to bump the fees of the channel's transactions.
+	///
+	/// Returns a [`UserChannelId`] allowing to locally keep track of the channel.
+	///
+	/// [`AnchorChannelsConfig::per_channel_reserve_sats`]: crate::config::AnchorChannelsConfig::per_channel_reserve_sats
+	pub fn open_channel_with_external_funding(
+		&self, node_id: PublicKey, address: SocketAddress, channel_amount_sats: u64,
+		push_to_counterparty_msat: Option<u64>, channel_config: Option<ChannelConfig>,
+		announce_for_forwarding: bool,
+	) -> Result<UserChannelId, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+		let runtime = rt_lock.as_ref().unwrap();
+
+		if announce_for_forwarding && !may_announce_channel(&self.config) {
+			log_error!(self.logger, "Failed to open announced channel as the node hasn't been sufficiently configured to act as a forwarding node. Please make sure to configure listening addreesses and node alias");
+			return Err(Error::ChannelCreationFailed);
+		}
+
+		let peer_info = PeerInfo { node_id, address };
+
+		let con_node_id = peer_info.node_id;
+		let con_addr = peer_info.address.clone();
+		let con_cm = Arc::clone(&self.connection_manager);
+
+		// We need to use our main runtime here as a local runtime might not be around to poll
+		// connection futures going forward.
+		tokio::task::block_in_place(move || {
+			runtime.block_on(async move {
+				con_cm.connect_peer_if_necessary(con_node_id, con_addr).await
+			})
+		})?;
+
+		// While the channel value is provided externally, the anchor reserve (if applicable) still
+		// needs to be available in our own wallet.
+		let required_reserve_sats =
+			self.required_channel_funds_sats(node_id, channel_amount_sats)? - channel_amount_sats;
+		if required_reserve_sats > 0 {
+			let cur_anchor_reserve_sats =
+				total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+			let spendable_amount_sats =
+				self.wallet.get_spendable_amount_sats(cur_anchor_reserve_sats).unwrap_or(0);
+			if spendable_amount_sats < required_reserve_sats {
+				log_error!(self.logger,
+					"Unable to create channel due to insufficient funds for the anchor reserve. Available: {}sats, Required: {}sats",
+					spendable_amount_sats, required_reserve_sats
+				);
+				return Err(Error::InsufficientFunds);
+			}
+		}
+
+		let user_config = self.channel_user_config(channel_config, announce_for_forwarding);
+
+		let push_msat = push_to_counterparty_msat.unwrap_or(0);
+		let user_channel_id: u128 = rand::thread_rng().gen::<u128>();
+
+		// We register the channel before creating it, so that its funding is left to the user.
+		self.wallet.add_external_funding(user_channel_id);
+
+		match self.channel_manager.create_channel(
+			peer_info.node_id,
+			channel_amount_sats,
+			push_msat,
+			user_channel_id,
+			None,
+			Some(user_config),
+		) {
+			Ok(_) => {
+				log_info!(
+					self.logger,
+					"Initiated externally funded channel creation with peer {}. ",
+					peer_info.node_id
+				);
+				self.peer_store.add_peer(peer_info)?;
+				Ok(UserChannelId(user_channel_id))
+			},
+			Err(e) => {
+				log_error!(self.logger, "Failed to initiate channel creation: {:?}", e);
+				self.wallet.remove_external_funding(user_channel_id);
+				Err(Error::ChannelCreationFailed)
+			},
+		}
+	}
+
+	/// Provides the funding transaction for a channel opened via
+	/// [`Node::open_channel_with_external_funding`], as requested by an
+	/// [`Event::FundingTransactionRequired`] event.
+	///
+	/// The transaction needs to be fully signed, only spend SegWit outputs, and create an output
+	/// paying the requested amount to the requested script. It will be broadcast once the
+	/// counterparty signed the channel.
+	///
+	/// Returns [`Error::InvalidFundingTransaction`] if the transaction doesn't meet these
+	/// requirements, in which case a corrected transaction may be provided. If the transaction is
+	/// rejected for any other reason, the channel is closed.
+	pub fn provide_funding_transaction(
+		&self, user_channel_id: &UserChannelId, funding_transaction: Transaction,
+	) -> Result<(), Error> {
+		let output = self.wallet.external_funding_output(user_channel_id.0).ok_or_else(|| {
+			log_error!(
+				self.logger,
+				"Channel {} isn't awaiting a funding transaction.",
+				user_channel_id.0
+			);
+			Error::InvalidChannelId
+		})?;
+
+		// We check the transaction upfront, as LDK would close the channel if it's invalid.
+		let has_funding_output = funding_transaction
+			.output
+			.iter()
+			.any(|o| o.script_pubkey == output.output_script && o.value == output.amount);
+		if !has_funding_output {
+			log_error!(
+				self.logger,
+				"Funding transaction for channel {} doesn't pay {} to the funding output script.",
+				user_channel_id.0,
+				output.amount
+			);
+			return Err(Error::InvalidFundingTransaction);
+		}
+		if funding_transaction.input.iter().any(|i| i.witness.is_empty()) {
+			log_error!(
+				self.logger,
+				"Funding transaction for channel {} isn't fully signed or spends non-SegWit outputs.",
+				user_channel_id.0
+			);
+			return Err(Error::InvalidFundingTransaction);
+		}
+
+		self.wallet.remove_external_funding(user_channel_id.0);
+
+		match self.channel_manager.funding_transaction_generated(
+			output.temporary_channel_id,
+			output.counterparty_node_id,
+			funding_transaction,
+		) {
+			Ok(()) => {
+				log_info!(
+					self.logger,
+					"Provided funding transaction for channel {} with peer {}.",
+					user_channel_id.0,
+					output.counterparty_node_id
+				);
+				Ok(())
+			},
+			Err(e) => {
+				log_error!(self.logger, "Failed to process funding transaction: {:?}", e);
+				Err(Error::ChannelCreationFailed)
+			},
+		}
+	}
+
+	fn close_unfunded_channels(&self, temporary_channels: &[(ChannelId, PublicKey)]) {
+		for (temporary_channel_id, counterparty_node_id) in temporary_channels {
+			if let Err(e) = self.channel_manager.force_close_without_broadcasting_txn(
+				temporary_channel_id,
+				counterparty_node_id,
+				"Channel funding batch was aborted".to_string(),
+			) {
+				log_error!(
+					self.logger,
+					"Failed to close channel {} of aborted funding batch: {:?}",
+					temporary_channel_id,
+					e
+				);
+			}
+		}
+	}
+
 	/// Manually sync the LDK and BDK wallets with the current chain state and update the fee rate
 	/// cache.
 	///
@@ -1354,18 +2009,216 @@ impl Node {
 		}
 	}
 
+	/// Sends a payment probe of the given amount to the given destination and blocks until its
+	/// outcome is known or `timeout_secs` have elapsed.
+	///
+	/// If the probe reaches the destination, a [`ProbeResult::Succeeded`] is returned, giving the
+	/// probed path as well as the total routing fee a payment along it would incur. Otherwise, a
+	/// [`ProbeResult::Failed`] is returned, giving the short channel id of the channel at which
+	/// the probe failed, if known.
+	///
+	/// As with any probe, the outcome is also used to train our scorer.
+	///
+	/// Will return [`Error::ProbeTimeout`] if the outcome isn't known before the timeout.
+	pub fn probe(
+		&self, destination: PublicKey, amount_msat: u64, timeout_secs: u64,
+	) -> Result<ProbeResult, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+		let runtime = rt_lock.as_ref().unwrap();
+
+		let prober = Arc::clone(&self.prober);
+		let timeout = Duration::from_secs(timeout_secs);
+
+		// We need to use our main runtime here as the probe outcome will be propagated by our
+		// event handling.
+		tokio::task::block_in_place(move || {
+			runtime.block_on(async move { prober.probe(destination, amount_msat, timeout).await })
+		})
+	}
+
 	/// Retrieve the details of a specific payment with the given id.
 	///
 	/// Returns `Some` if the payment was known and `None` otherwise.
 	pub fn payment(&self, payment_id: &PaymentId) -> Option<PaymentDetails> {
-		self.payment_store.get(payment_id)
+		self.payment_store.get(payment_id).map(|p| self.with_label(p))
 	}
 
 	/// Remove the payment with the given id from the store.
+	///
+	/// Any label assigned to the payment via [`Node::set_label`] is removed, too.
 	pub fn remove_payment(&self, payment_id: &PaymentId) -> Result<(), Error> {
+		self.payment_retry_store.remove(payment_id)?;
+		self.label_store.remove(LabelType::Payment, &hex_utils::to_string(&payment_id.0))?;
 		self.payment_store.remove(&payment_id)
 	}
 
+	/// Re-sends a failed outbound [BOLT 11], [BOLT 12], or spontaneous payment with the given id.
+	///
+	/// The payment will be routed anew, but keeps its [`PaymentId`], i.e., its status will be
+	/// updated and a new [`Event::PaymentSuccessful`] or [`Event::PaymentFailed`] will be emitted
+	/// for the same id. The node-wide [`Config::sending_parameters`] apply to the new attempt.
+	///
+	/// Only payments that were accepted for sending can be retried, and payments that were
+	/// abandoned, e.g., via [`Bolt12Payment::abandon`], can't be retried.
+	///
+	/// Returns [`Error::InvalidPaymentId`] if the payment is unknown, isn't an outbound payment,
+	/// is still pending or already succeeded, or can't be re-sent as no data to do so is available.
+	///
+	/// Note that it might take a short while until LDK allows to re-use the [`PaymentId`] of a
+	/// payment that just failed, in which case [`Error::DuplicatePayment`] is returned.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	pub fn retry_payment(&self, payment_id: &PaymentId) -> Result<(), Error> {
+		let payment = self.payment_store.get(payment_id).ok_or(Error::InvalidPaymentId)?;
+
+		if payment.direction != PaymentDirection::Outbound {
+			log_error!(
+				self.logger,
+				"Failed to retry payment {}: not an outbound payment.",
+				payment_id
+			);
+			return Err(Error::InvalidPaymentId);
+		}
+
+		if payment.status != PaymentStatus::Failed {
+			log_error!(
+				self.logger,
+				"Failed to retry payment {}: payment has not failed.",
+				payment_id
+			);
+			return Err(Error::InvalidPaymentId);
+		}
+
+		let retry_info = self.payment_retry_store.get(payment_id).ok_or_else(|| {
+			log_error!(
+				self.logger,
+				"Failed to retry payment {}: no data to re-send the payment is available.",
+				payment_id
+			);
+			Error::InvalidPaymentId
+		})?;
+
+		log_info!(self.logger, "Retrying payment {}", payment_id);
+
+		match retry_info.target {
+			PaymentRetryTarget::Bolt11 { invoice } => {
+				self.bolt11_payment().retry(&payment, &invoice)
+			},
+			PaymentRetryTarget::Bolt12Offer { offer, amount_msat } => {
+				self.bolt12_payment().retry(&payment, &offer, amount_msat)
+			},
+			PaymentRetryTarget::Spontaneous { node_id, custom_tlvs, allow_mpp } => {
+				self.spontaneous_payment().retry(&payment, node_id, custom_tlvs, allow_mpp)
+			},
+		}
+	}
+
+	/// Schedules a recurring payment of the given amount to the given target.
+	///
+	/// The first payment will be made at `start_at`, given in seconds since start of the UNIX
+	/// epoch, or right away if `None`. Subsequent payments will be made every `interval_secs`
+	/// seconds, which needs to be at least 60 seconds, until `max_count` payments were made or
+	/// the schedule is cancelled via [`Node::cancel_scheduled_payment`].
+	///
+	/// Schedules are persisted and resumed on restart. Executions that were missed while the node
+	/// wasn't running are skipped, i.e., at most one payment per schedule is made on startup.
+	///
+	/// Payments are only made while the node is running. For each execution an
+	/// [`Event::ScheduledPaymentExecuted`] or, if the payment couldn't be initiated, an
+	/// [`Event::ScheduledPaymentFailed`] event is emitted. The total amount spent can be capped via
+	/// [`Config::scheduled_payment_spending_limit`].
+	pub fn schedule_payment(
+		&self, target: ScheduledPaymentTarget, amount_msat: u64, interval_secs: u64,
+		start_at: Option<u64>, max_count: Option<u64>,
+	) -> Result<ScheduledPaymentId, Error> {
+		if amount_msat == 0 {
+			log_error!(self.logger, "Failed to schedule payment: amount can't be zero.");
+			return Err(Error::InvalidAmount);
+		}
+
+		if interval_secs < SCHEDULED_PAYMENT_MIN_INTERVAL_SECS || max_count == Some(0) {
+			log_error!(
+				self.logger,
+				"Failed to schedule payment: interval must be at least {} seconds and count must be non-zero.",
+				SCHEDULED_PAYMENT_MIN_INTERVAL_SECS
+			);
+			return Err(Error::InvalidPaymentSchedule);
+		}
+
+		if let ScheduledPaymentTarget::Bolt12Offer { ref offer } = target {
+			match offer.amount() {
+				Some(Amount::Bitcoin { amount_msats }) if amount_msat < amount_msats => {
+					log_error!(
+						self.logger,
+						"Failed to schedule payment as the amount is less than the offer's amount of {}msat.",
+						amount_msats
+					);
+					return Err(Error::InvalidAmount);
+				},
+				Some(Amount::Currency { .. }) => {
+					log_error!(
+						self.logger,
+						"Failed to schedule payment as the offer is denominated in an unsupported currency."
+					);
+					return Err(Error::UnsupportedCurrency);
+				},
+				_ => {},
+			}
+		}
+
+		let now = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.expect("system time must be after Unix epoch")
+			.as_secs();
+		let id = ScheduledPaymentId(self.keys_manager.get_secure_random_bytes());
+		let schedule = ScheduledPaymentDetails {
+			id,
+			target,
+			amount_msat,
+			interval_secs,
+			next_execution_at: Some(start_at.unwrap_or(now)),
+			max_count,
+			execution_count: 0,
+			executions: Vec::new(),
+		};
+		self.payment_scheduler.insert(schedule)?;
+
+		log_info!(
+			self.logger,
+			"Scheduled payment {} of {}msat every {} seconds.",
+			hex_utils::to_string(&id.0),
+			amount_msat,
+			interval_secs
+		);
+		Ok(id)
+	}
+
+	/// Cancels the recurring payment with the given id, i.e., no further payments will be made.
+	pub fn cancel_scheduled_payment(&self, schedule_id: &ScheduledPaymentId) -> Result<(), Error> {
+		self.payment_scheduler.remove(schedule_id)
+	}
+
+	/// Retrieves all recurring payments scheduled via [`Node::schedule_payment`].
+	pub fn list_scheduled_payments(&self) -> Vec<ScheduledPaymentDetails> {
+		self.payment_scheduler.list()
+	}
+
+	/// Retrieves all inbound payments we currently hold, i.e., payments for invoices created via
+	/// [`Bolt11Payment::receive_for_hash`] or [`Bolt11Payment::receive_variable_amount_for_hash`]
+	/// that have yet to be claimed or failed.
+	///
+	/// Held payments that are neither claimed nor failed in time will be failed back
+	/// automatically, see [`HoldInvoiceConfig`] for more information.
+	///
+	/// [`HoldInvoiceConfig`]: crate::config::HoldInvoiceConfig
+	pub fn list_held_payments(&self) -> Vec<HeldPaymentDetails> {
+		self.hold_invoice_manager.list()
+	}
+
 	/// Retrieves an overview of all known balances.
 	pub fn list_balances(&self) -> BalanceDetails {
 		let cur_anchor_reserve_sats =
@@ -1413,6 +2266,7 @@ impl Node {
 			total_lightning_balance_sats,
 			lightning_balances,
 			pending_balances_from_channel_closures,
+			watch_only_balances: self.wallet.get_watch_only_balances(),
 		}
 	}
 
@@ -1434,12 +2288,115 @@ impl Node {
 	pub fn list_payments_with_filter<F: FnMut(&&PaymentDetails) -> bool>(
 		&self, f: F,
 	) -> Vec<PaymentDetails> {
-		self.payment_store.list_filter(f)
+		self.payment_store.list_filter(f).into_iter().map(|p| self.with_label(p)).collect()
 	}
 
 	/// Retrieves all payments.
 	pub fn list_payments(&self) -> Vec<PaymentDetails> {
-		self.payment_store.list_filter(|_| true)
+		self.payment_store.list_filter(|_| true).into_iter().map(|p| self.with_label(p)).collect()
+	}
+
+	fn with_label(&self, mut payment: PaymentDetails) -> PaymentDetails {
+		payment.label =
+			self.label_store.get(LabelType::Payment, &hex_utils::to_string(&payment.id.0));
+		if payment.label.is_none() {
+			if let PaymentKind::Onchain { txid, .. } = &payment.kind {
+				payment.label = self.label_store.get(LabelType::Transaction, &txid.to_string());
+			}
+		}
+		payment
+	}
+
+	/// Assigns the given label to the object of the given type, replacing any existing label.
+	///
+	/// The `reference` identifies the object as described for the respective [`LabelType`].
+	/// Labels are persisted, can be exported via [`Node::export_labels`], and are reported by
+	/// the respective listing APIs, e.g., [`Node::list_payments`] and
+	/// [`OnchainPayment::list_utxos`].
+	///
+	/// Returns [`Error::InvalidLabel`] if the reference can't be parsed or the label is empty or
+	/// longer than 255 characters.
+	pub fn set_label(
+		&self, label_type: LabelType, reference: String, label: String,
+	) -> Result<(), Error> {
+		let reference =
+			label_store::normalize_reference(label_type, &reference, self.config.network)?;
+		if label.is_empty() || label.chars().count() > MAX_LABEL_LEN {
+			log_error!(self.logger, "Failed to set label: invalid label length");
+			return Err(Error::InvalidLabel);
+		}
+		self.label_store.insert(Label { label_type, reference, label, origin: None })
+	}
+
+	/// Removes the label of the object of the given type, if any.
+	pub fn remove_label(&self, label_type: LabelType, reference: String) -> Result<(), Error> {
+		let reference =
+			label_store::normalize_reference(label_type, &reference, self.config.network)?;
+		self.label_store.remove(label_type, &reference)
+	}
+
+	/// Retrieves all labels, ordered by type and reference.
+	pub fn list_labels(&self) -> Vec<Label> {
+		self.label_store.list()
+	}
+
+	/// Exports all labels in the [BIP 329] JSON Lines format.
+	///
+	/// Besides the labels themselves, the export records whether each of the on-chain wallet's
+	/// outputs is spendable, i.e., not frozen.
+	///
+	/// Note that labels of [`LabelType::Payment`] are exported with the non-standard `payment`
+	/// type and will hence be ignored by other wallets.
+	///
+	/// [BIP 329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+	pub fn export_labels(&self) -> String {
+		let spendable_outputs =
+			self.wallet.list_utxos().into_iter().map(|u| (u.outpoint, !u.is_frozen)).collect();
+		Bip329Export { labels: self.label_store.list(), spendable_outputs }.to_jsonl()
+	}
+
+	/// Imports labels given in the [BIP 329] JSON Lines format, replacing any existing labels of
+	/// the same objects.
+	///
+	/// Records of types unknown to us are ignored. If a record marks one of the on-chain wallet's
+	/// outputs as not spendable, the output is frozen, see [`OnchainPayment::freeze_utxo`].
+	///
+	/// Returns [`Error::InvalidLabel`] if any of the records can't be parsed, in which case
+	/// nothing is imported.
+	///
+	/// Note that if persisting a label or an output's frozen state fails, the import is aborted
+	/// with [`Error::PersistenceFailed`] and the records processed up to that point remain
+	/// imported. As importing the same records again yields the same result, the import can
+	/// simply be retried.
+	///
+	/// [BIP 329]: https://github.com/bitcoin/bips/blob/master/bip-0329.mediawiki
+	pub fn import_labels(&self, jsonl: String) -> Result<(), Error> {
+		let export = Bip329Export::from_jsonl(&jsonl, self.config.network)?;
+
+		// Determine the frozen state changes before writing anything, ignoring records of outputs
+		// that aren't ours.
+		let our_utxos: HashMap<OutPoint, bool> =
+			self.wallet.list_utxos().into_iter().map(|u| (u.outpoint, u.is_frozen)).collect();
+		let frozen_updates: Vec<(OutPoint, bool)> = export
+			.spendable_outputs
+			.into_iter()
+			.filter(|(outpoint, spendable)| {
+				our_utxos.get(outpoint).map_or(false, |is_frozen| *is_frozen == *spendable)
+			})
+			.map(|(outpoint, spendable)| (outpoint, !spendable))
+			.collect();
+
+		let num_labels = export.labels.len();
+		for label in export.labels {
+			self.label_store.insert(label)?;
+		}
+
+		for (outpoint, frozen) in frozen_updates {
+			self.wallet.set_utxo_frozen(outpoint, frozen)?;
+		}
+
+		log_info!(self.logger, "Imported {} labels.", num_labels);
+		Ok(())
 	}
 
 	/// Retrieves a list of known peers.
@@ -6,9 +6,18 @@
 // accordance with one or both of these licenses.
 
 use crate::liquidity::LiquiditySource;
+use crate::logger::log_info;
+use crate::payment::offer_store::OfferStore;
+use crate::types::ChannelManager;
 
+use lightning::blinded_path::message::OffersContext;
 use lightning::ln::peer_handler::CustomMessageHandler;
 use lightning::ln::wire::CustomMessageReader;
+use lightning::offers::invoice_error::InvoiceError;
+use lightning::onion_message::messenger::{
+	MessageSendInstructions, Responder, ResponseInstruction,
+};
+use lightning::onion_message::offers::{OffersMessage, OffersMessageHandler};
 use lightning::util::logger::Logger;
 
 use lightning_types::features::{InitFeatures, NodeFeatures};
@@ -121,3 +130,48 @@ where
 		}
 	}
 }
+
+/// Handles BOLT12 offers messages, rejecting invoice requests for offers we deactivated before
+/// handing them to the [`ChannelManager`].
+pub(crate) struct NodeOffersMessageHandler<L: Deref>
+where
+	L::Target: Logger,
+{
+	channel_manager: Arc<ChannelManager>,
+	offer_store: Arc<OfferStore<L>>,
+	logger: L,
+}
+
+impl<L: Deref> NodeOffersMessageHandler<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		channel_manager: Arc<ChannelManager>, offer_store: Arc<OfferStore<L>>, logger: L,
+	) -> Self {
+		Self { channel_manager, offer_store, logger }
+	}
+}
+
+impl<L: Deref> OffersMessageHandler for NodeOffersMessageHandler<L>
+where
+	L::Target: Logger,
+{
+	fn handle_message(
+		&self, message: OffersMessage, context: Option<OffersContext>, responder: Option<Responder>,
+	) -> Option<(OffersMessage, ResponseInstruction)> {
+		if let OffersMessage::InvoiceRequest(ref invoice_request) = message {
+			if self.offer_store.is_deactivated_offer(invoice_request) {
+				log_info!(self.logger, "Rejecting invoice request for deactivated offer.");
+				let error = InvoiceError::from_string("Offer is no longer active".to_string());
+				return responder.map(|r| (OffersMessage::InvoiceError(error), r.respond()));
+			}
+		}
+
+		self.channel_manager.handle_message(message, context, responder)
+	}
+
+	fn release_pending_messages(&self) -> Vec<(OffersMessage, MessageSendInstructions)> {
+		self.channel_manager.release_pending_messages()
+	}
+}
@@ -9,11 +9,13 @@
 //!
 //! [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
 
-use crate::config::{Config, LDK_PAYMENT_RETRY_TIMEOUT};
+use crate::config::Config;
 use crate::connection::ConnectionManager;
 use crate::error::Error;
 use crate::liquidity::LiquiditySource;
 use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::payment::hold_invoice::HoldInvoiceManager;
+use crate::payment::retry::{PaymentRetryInfo, PaymentRetryStore, PaymentRetryTarget};
 use crate::payment::store::{
 	LSPFeeLimits, PaymentDetails, PaymentDetailsUpdate, PaymentDirection, PaymentKind,
 	PaymentStatus, PaymentStore,
@@ -24,7 +26,7 @@ use crate::types::ChannelManager;
 
 use lightning::ln::bolt11_payment;
 use lightning::ln::channelmanager::{
-	Bolt11InvoiceParameters, PaymentId, RecipientOnionFields, Retry, RetryableSendFailure,
+	Bolt11InvoiceParameters, PaymentId, RecipientOnionFields, RetryableSendFailure,
 };
 use lightning::routing::router::{PaymentParameters, RouteParameters};
 
@@ -35,6 +37,7 @@ use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription, Description};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 
+use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 
 /// A payment handler allowing to create and pay [BOLT 11] invoices.
@@ -49,6 +52,8 @@ pub struct Bolt11Payment {
 	connection_manager: Arc<ConnectionManager<Arc<FilesystemLogger>>>,
 	liquidity_source: Option<Arc<LiquiditySource<Arc<FilesystemLogger>>>>,
 	payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+	payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>,
+	hold_invoice_manager: Arc<HoldInvoiceManager<Arc<FilesystemLogger>>>,
 	peer_store: Arc<PeerStore<Arc<FilesystemLogger>>>,
 	config: Arc<Config>,
 	logger: Arc<FilesystemLogger>,
@@ -61,6 +66,8 @@ impl Bolt11Payment {
 		connection_manager: Arc<ConnectionManager<Arc<FilesystemLogger>>>,
 		liquidity_source: Option<Arc<LiquiditySource<Arc<FilesystemLogger>>>>,
 		payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+		payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>,
+		hold_invoice_manager: Arc<HoldInvoiceManager<Arc<FilesystemLogger>>>,
 		peer_store: Arc<PeerStore<Arc<FilesystemLogger>>>, config: Arc<Config>,
 		logger: Arc<FilesystemLogger>,
 	) -> Self {
@@ -70,6 +77,8 @@ impl Bolt11Payment {
 			connection_manager,
 			liquidity_source,
 			payment_store,
+			payment_retry_store,
+			hold_invoice_manager,
 			peer_store,
 			config,
 			logger,
@@ -119,7 +128,8 @@ impl Bolt11Payment {
 		};
 
 		let payment_secret = Some(*invoice.payment_secret());
-		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_strategy =
+			override_params.and_then(|p| p.retry_strategy).unwrap_or_default().into();
 
 		match self.channel_manager.send_payment(
 			payment_hash,
@@ -147,6 +157,7 @@ impl Bolt11Payment {
 				);
 
 				self.payment_store.insert(payment)?;
+				self.persist_retry_info(payment_id, invoice);
 
 				Ok(payment_id)
 			},
@@ -246,7 +257,8 @@ impl Bolt11Payment {
 				.map(|s| route_params.payment_params.max_channel_saturation_power_of_half = s);
 		};
 
-		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_strategy =
+			override_params.and_then(|p| p.retry_strategy).unwrap_or_default().into();
 		let recipient_fields = RecipientOnionFields::secret_only(*payment_secret);
 
 		match self.channel_manager.send_payment(
@@ -279,6 +291,7 @@ impl Bolt11Payment {
 					PaymentStatus::Pending,
 				);
 				self.payment_store.insert(payment)?;
+				self.persist_retry_info(payment_id, invoice);
 
 				Ok(payment_id)
 			},
@@ -361,6 +374,10 @@ impl Bolt11Payment {
 			return Err(Error::InvalidPaymentHash);
 		}
 
+		// We stop tracking the payment before claiming it to make sure we won't fail it back in the
+		// meantime.
+		self.hold_invoice_manager.remove(&payment_id)?;
+
 		self.channel_manager.claim_funds(preimage);
 		Ok(())
 	}
@@ -395,6 +412,8 @@ impl Bolt11Payment {
 			return Err(Error::InvalidPaymentHash);
 		}
 
+		self.hold_invoice_manager.remove(&payment_id)?;
+
 		self.channel_manager.fail_htlc_backwards(&payment_hash);
 		Ok(())
 	}
@@ -567,6 +586,10 @@ impl Bolt11Payment {
 		)
 	}
 
+	pub(crate) fn has_liquidity_source(&self) -> bool {
+		self.liquidity_source.is_some()
+	}
+
 	fn receive_via_jit_channel_inner(
 		&self, amount_msat: Option<u64>, description: &str, expiry_secs: u32,
 		max_total_lsp_fee_limit_msat: Option<u64>,
@@ -739,4 +762,21 @@ impl Bolt11Payment {
 
 		Ok(())
 	}
+
+	/// Re-sends the given failed payment of the given invoice, reusing its [`PaymentId`].
+	pub(crate) fn retry(&self, payment: &PaymentDetails, invoice: &str) -> Result<(), Error> {
+		let invoice = Bolt11Invoice::from_str(invoice).map_err(|e| {
+			log_error!(self.logger, "Failed to parse stored invoice: {:?}", e);
+			Error::InvalidInvoice
+		})?;
+		let amount_msat =
+			payment.amount_msat.or(invoice.amount_milli_satoshis()).ok_or(Error::InvalidAmount)?;
+		self.send_using_amount(&invoice, amount_msat, None).map(|_| ())
+	}
+
+	fn persist_retry_info(&self, payment_id: PaymentId, invoice: &Bolt11Invoice) {
+		let target = PaymentRetryTarget::Bolt11 { invoice: invoice.to_string() };
+		let retry_info = PaymentRetryInfo { id: payment_id, target };
+		self.payment_retry_store.insert_for_sent_payment(retry_info, &self.payment_store);
+	}
 }
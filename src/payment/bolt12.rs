@@ -9,17 +9,19 @@
 //!
 //! [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
 
-use crate::config::LDK_PAYMENT_RETRY_TIMEOUT;
+use crate::config::Config;
 use crate::error::Error;
 use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::payment::offer_store::{OfferDetails, OfferStats, OfferStore};
+use crate::payment::retry::{PaymentRetryInfo, PaymentRetryStore, PaymentRetryTarget};
 use crate::payment::store::{
 	PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus, PaymentStore,
 };
-use crate::types::ChannelManager;
+use crate::types::{ChannelManager, PendingBolt12Invoices};
 
 use lightning::ln::channelmanager::{PaymentId, Retry};
 use lightning::offers::invoice::Bolt12Invoice;
-use lightning::offers::offer::{Amount, Offer, Quantity};
+use lightning::offers::offer::{Amount, Offer, OfferId, Quantity};
 use lightning::offers::parse::Bolt12SemanticError;
 use lightning::offers::refund::Refund;
 use lightning::util::string::UntrustedString;
@@ -27,6 +29,7 @@ use lightning::util::string::UntrustedString;
 use rand::RngCore;
 
 use std::num::NonZeroU64;
+use std::str::FromStr;
 use std::sync::{Arc, RwLock};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -40,6 +43,10 @@ pub struct Bolt12Payment {
 	runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
 	channel_manager: Arc<ChannelManager>,
 	payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+	payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>,
+	offer_store: Arc<OfferStore<Arc<FilesystemLogger>>>,
+	pending_bolt12_invoices: Arc<PendingBolt12Invoices>,
+	config: Arc<Config>,
 	logger: Arc<FilesystemLogger>,
 }
 
@@ -47,9 +54,22 @@ impl Bolt12Payment {
 	pub(crate) fn new(
 		runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
 		channel_manager: Arc<ChannelManager>,
-		payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>, logger: Arc<FilesystemLogger>,
+		payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+		payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>,
+		offer_store: Arc<OfferStore<Arc<FilesystemLogger>>>,
+		pending_bolt12_invoices: Arc<PendingBolt12Invoices>, config: Arc<Config>,
+		logger: Arc<FilesystemLogger>,
 	) -> Self {
-		Self { runtime, channel_manager, payment_store, logger }
+		Self {
+			runtime,
+			channel_manager,
+			payment_store,
+			payment_retry_store,
+			offer_store,
+			pending_bolt12_invoices,
+			config,
+			logger,
+		}
 	}
 
 	/// Send a payment given an offer.
@@ -58,17 +78,34 @@ impl Bolt12Payment {
 	/// response.
 	///
 	/// If `quantity` is `Some` it represents the number of items requested.
+	///
+	/// Failed payment paths will be retried according to the [`SendingParameters::retry_strategy`]
+	/// configured via [`Config::sending_parameters`].
+	///
+	/// If [`Config::manually_handle_bolt12_invoices`] is set, the invoice received in response
+	/// will only be paid once approved via [`pay_received_invoice`].
+	///
+	/// [`SendingParameters::retry_strategy`]: crate::payment::SendingParameters::retry_strategy
+	/// [`Config::manually_handle_bolt12_invoices`]: crate::config::Config::manually_handle_bolt12_invoices
+	/// [`pay_received_invoice`]: Self::pay_received_invoice
 	pub fn send(
 		&self, offer: &Offer, quantity: Option<u64>, payer_note: Option<String>,
+	) -> Result<PaymentId, Error> {
+		let mut random_bytes = [0u8; 32];
+		rand::thread_rng().fill_bytes(&mut random_bytes);
+		let payment_id = PaymentId(random_bytes);
+		self.send_inner(offer, quantity, payer_note, payment_id)
+	}
+
+	fn send_inner(
+		&self, offer: &Offer, quantity: Option<u64>, payer_note: Option<String>,
+		payment_id: PaymentId,
 	) -> Result<PaymentId, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
-		let mut random_bytes = [0u8; 32];
-		rand::thread_rng().fill_bytes(&mut random_bytes);
-		let payment_id = PaymentId(random_bytes);
-		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_strategy = self.retry_strategy();
 		let max_total_routing_fee_msat = None;
 
 		let offer_amount_msat = match offer.amount() {
@@ -117,6 +154,7 @@ impl Bolt12Payment {
 					PaymentStatus::Pending,
 				);
 				self.payment_store.insert(payment)?;
+				self.persist_retry_info(payment_id, offer, None);
 
 				Ok(payment_id)
 			},
@@ -157,18 +195,30 @@ impl Bolt12Payment {
 	///
 	/// If `payer_note` is `Some` it will be seen by the recipient and reflected back in the invoice
 	/// response.
+	///
+	/// Failed payment paths will be retried according to the [`SendingParameters::retry_strategy`]
+	/// configured via [`Config::sending_parameters`].
+	///
+	/// [`SendingParameters::retry_strategy`]: crate::payment::SendingParameters::retry_strategy
 	pub fn send_using_amount(
 		&self, offer: &Offer, amount_msat: u64, quantity: Option<u64>, payer_note: Option<String>,
+	) -> Result<PaymentId, Error> {
+		let mut random_bytes = [0u8; 32];
+		rand::thread_rng().fill_bytes(&mut random_bytes);
+		let payment_id = PaymentId(random_bytes);
+		self.send_using_amount_inner(offer, amount_msat, quantity, payer_note, payment_id)
+	}
+
+	fn send_using_amount_inner(
+		&self, offer: &Offer, amount_msat: u64, quantity: Option<u64>, payer_note: Option<String>,
+		payment_id: PaymentId,
 	) -> Result<PaymentId, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
 
-		let mut random_bytes = [0u8; 32];
-		rand::thread_rng().fill_bytes(&mut random_bytes);
-		let payment_id = PaymentId(random_bytes);
-		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_strategy = self.retry_strategy();
 		let max_total_routing_fee_msat = None;
 
 		let offer_amount_msat = match offer.amount() {
@@ -221,6 +271,7 @@ impl Bolt12Payment {
 					PaymentStatus::Pending,
 				);
 				self.payment_store.insert(payment)?;
+				self.persist_retry_info(payment_id, offer, Some(amount_msat));
 
 				Ok(payment_id)
 			},
@@ -252,6 +303,83 @@ impl Bolt12Payment {
 		}
 	}
 
+	/// Pays the BOLT12 invoice received for the outbound payment with the given [`PaymentId`].
+	///
+	/// This is only applicable if [`Config::manually_handle_bolt12_invoices`] is set, in which case
+	/// received invoices are surfaced via [`Event::Bolt12InvoiceReceived`] and need to be approved
+	/// before they are paid.
+	///
+	/// Will return [`Error::InvalidPaymentId`] if no invoice is awaiting approval for the given
+	/// payment.
+	///
+	/// [`Config::manually_handle_bolt12_invoices`]: crate::config::Config::manually_handle_bolt12_invoices
+	/// [`Event::Bolt12InvoiceReceived`]: crate::Event::Bolt12InvoiceReceived
+	pub fn pay_received_invoice(&self, payment_id: &PaymentId) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let (invoice, context) = self
+			.pending_bolt12_invoices
+			.lock()
+			.unwrap()
+			.remove(payment_id)
+			.ok_or(Error::InvalidPaymentId)?;
+
+		self.channel_manager.send_payment_for_bolt12_invoice(&invoice, context.as_ref()).map_err(
+			|e| {
+				log_error!(self.logger, "Failed to pay BOLT12 invoice: {:?}", e);
+				Error::PaymentSendingFailed
+			},
+		)?;
+
+		log_info!(
+			self.logger,
+			"Initiated sending {}msat for payment with ID {}",
+			invoice.amount_msats(),
+			payment_id
+		);
+		Ok(())
+	}
+
+	/// Abandons the pending outbound BOLT12 payment with the given [`PaymentId`].
+	///
+	/// This may be used to reject an invoice surfaced via [`Event::Bolt12InvoiceReceived`], or to
+	/// give up on a payment for which no invoice has been received yet. A
+	/// [`Event::PaymentFailed`] will be emitted once the payment has been abandoned.
+	///
+	/// Note that payments for which HTLCs are already in flight will only fail once the HTLCs are
+	/// resolved.
+	///
+	/// [`Event::Bolt12InvoiceReceived`]: crate::Event::Bolt12InvoiceReceived
+	/// [`Event::PaymentFailed`]: crate::Event::PaymentFailed
+	pub fn abandon(&self, payment_id: &PaymentId) -> Result<(), Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		match self.payment_store.get(payment_id) {
+			Some(payment)
+				if payment.direction == PaymentDirection::Outbound
+					&& payment.status == PaymentStatus::Pending => {},
+			_ => {
+				log_error!(
+					self.logger,
+					"Failed to abandon payment with ID {} as no pending outbound payment was found.",
+					payment_id
+				);
+				return Err(Error::InvalidPaymentId);
+			},
+		}
+
+		self.pending_bolt12_invoices.lock().unwrap().remove(payment_id);
+		self.channel_manager.abandon_payment(*payment_id);
+		log_info!(self.logger, "Abandoned payment with ID {}", payment_id);
+		Ok(())
+	}
+
 	/// Returns a payable offer that can be used to request and receive a payment of the amount
 	/// given.
 	pub fn receive(
@@ -286,6 +414,15 @@ impl Bolt12Payment {
 			Error::OfferCreationFailed
 		})?;
 
+		let offer_details = OfferDetails::new(
+			finalized_offer.clone(),
+			description.to_string(),
+			Some(amount_msat),
+			quantity,
+			expiry_secs,
+		);
+		self.offer_store.insert(offer_details)?;
+
 		Ok(finalized_offer)
 	}
 
@@ -310,9 +447,65 @@ impl Bolt12Payment {
 			Error::OfferCreationFailed
 		})?;
 
+		let offer_details =
+			OfferDetails::new(offer.clone(), description.to_string(), None, None, expiry_secs);
+		self.offer_store.insert(offer_details)?;
+
 		Ok(offer)
 	}
 
+	/// Retrieves all offers previously issued via [`receive`] or [`receive_variable_amount`].
+	///
+	/// [`receive`]: Self::receive
+	/// [`receive_variable_amount`]: Self::receive_variable_amount
+	pub fn list_offers(&self) -> Vec<OfferDetails> {
+		self.offer_store.list()
+	}
+
+	/// Retrieves the details of the previously issued offer with the given id.
+	///
+	/// Returns `None` if the offer is unknown.
+	pub fn offer(&self, offer_id: &OfferId) -> Option<OfferDetails> {
+		self.offer_store.get(offer_id)
+	}
+
+	/// Deactivates the previously issued offer with the given id.
+	///
+	/// Any subsequent invoice requests for the offer will be rejected, i.e., it can't be paid
+	/// anymore. Note that invoices we already responded with before deactivation may still be
+	/// paid.
+	pub fn deactivate_offer(&self, offer_id: &OfferId) -> Result<(), Error> {
+		self.offer_store.deactivate(offer_id).map_err(|e| {
+			log_error!(self.logger, "Failed to deactivate offer {}: {}", offer_id, e);
+			e
+		})?;
+		log_info!(self.logger, "Deactivated offer {}", offer_id);
+		Ok(())
+	}
+
+	/// Returns statistics aggregated over the payments received for the offer with the given id.
+	pub fn offer_stats(&self, offer_id: &OfferId) -> OfferStats {
+		let payments = self.payment_store.list_filter(|p| {
+			p.direction == PaymentDirection::Inbound
+				&& matches!(p.kind, PaymentKind::Bolt12Offer { offer_id: id, .. } if id == *offer_id)
+		});
+
+		let mut stats = OfferStats::default();
+		for payment in payments {
+			match payment.status {
+				PaymentStatus::Succeeded => {
+					stats.payments_received += 1;
+					stats.amount_received_msat += payment.amount_msat.unwrap_or(0);
+					stats.last_payment_received_at =
+						stats.last_payment_received_at.max(Some(payment.latest_update_timestamp));
+				},
+				PaymentStatus::Pending => stats.payments_pending += 1,
+				PaymentStatus::Failed => {},
+			}
+		}
+		stats
+	}
+
 	/// Requests a refund payment for the given [`Refund`].
 	///
 	/// The returned [`Bolt12Invoice`] is for informational purposes only (i.e., isn't needed to
@@ -359,7 +552,7 @@ impl Bolt12Payment {
 		let absolute_expiry = (SystemTime::now() + Duration::from_secs(expiry_secs as u64))
 			.duration_since(UNIX_EPOCH)
 			.unwrap();
-		let retry_strategy = Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT);
+		let retry_strategy = self.retry_strategy();
 		let max_total_routing_fee_msat = None;
 
 		let mut refund_builder = self
@@ -410,4 +603,42 @@ impl Bolt12Payment {
 
 		Ok(refund)
 	}
+
+	/// Re-sends the given failed payment for the given offer, reusing its [`PaymentId`].
+	pub(crate) fn retry(
+		&self, payment: &PaymentDetails, offer: &str, amount_msat: Option<u64>,
+	) -> Result<(), Error> {
+		let offer = Offer::from_str(offer).map_err(|e| {
+			log_error!(self.logger, "Failed to parse stored offer: {:?}", e);
+			Error::InvalidOffer
+		})?;
+		let (quantity, payer_note) = match payment.kind {
+			PaymentKind::Bolt12Offer { quantity, ref payer_note, .. } => {
+				(quantity, payer_note.as_ref().map(|note| note.0.clone()))
+			},
+			_ => return Err(Error::InvalidPaymentId),
+		};
+
+		if let Some(amount_msat) = amount_msat {
+			self.send_using_amount_inner(&offer, amount_msat, quantity, payer_note, payment.id)
+		} else {
+			self.send_inner(&offer, quantity, payer_note, payment.id)
+		}
+		.map(|_| ())
+	}
+
+	fn retry_strategy(&self) -> Retry {
+		self.config
+			.sending_parameters
+			.as_ref()
+			.and_then(|params| params.retry_strategy)
+			.unwrap_or_default()
+			.into()
+	}
+
+	fn persist_retry_info(&self, payment_id: PaymentId, offer: &Offer, amount_msat: Option<u64>) {
+		let target = PaymentRetryTarget::Bolt12Offer { offer: offer.to_string(), amount_msat };
+		let retry_info = PaymentRetryInfo { id: payment_id, target };
+		self.payment_retry_store.insert_for_sent_payment(retry_info, &self.payment_store);
+	}
 }
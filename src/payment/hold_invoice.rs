@@ -0,0 +1,357 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for keeping track of inbound payments we hold until they are manually claimed or
+//! failed.
+
+use crate::config::{HoldInvoiceConfig, HELD_PAYMENTS_CHECK_INTERVAL};
+use crate::event::{Event, EventQueue};
+use crate::hex_utils;
+use crate::io::{
+	HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE, HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, log_info, log_trace, Logger};
+use crate::payment::store::{PaymentDetailsUpdate, PaymentStatus, PaymentStore};
+use crate::types::{ChannelManager, DynStore};
+use crate::Error;
+
+use lightning::impl_writeable_tlv_based;
+use lightning::ln::channelmanager::PaymentId;
+use lightning::util::ser::Writeable;
+
+use lightning_types::payment::PaymentHash;
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Details of an inbound payment we hold, i.e., a payment for an invoice created via
+/// [`Bolt11Payment::receive_for_hash`] or [`Bolt11Payment::receive_variable_amount_for_hash`]
+/// that has yet to be claimed or failed.
+///
+/// [`Bolt11Payment::receive_for_hash`]: crate::payment::Bolt11Payment::receive_for_hash
+/// [`Bolt11Payment::receive_variable_amount_for_hash`]: crate::payment::Bolt11Payment::receive_variable_amount_for_hash
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeldPaymentDetails {
+	/// A local identifier used to track the payment.
+	pub payment_id: PaymentId,
+	/// The hash of the payment.
+	pub payment_hash: PaymentHash,
+	/// The value, in thousandths of a satoshi, that is claimable.
+	pub claimable_amount_msat: u64,
+	/// The block height at which the payment will no longer be eligible for claiming.
+	///
+	/// Payments without a deadline won't be failed back automatically.
+	pub claim_deadline: Option<u32>,
+	/// The timestamp, in seconds since start of the UNIX epoch, when the payment became claimable.
+	pub received_at: u64,
+	/// Whether we already emitted an [`Event::HeldPaymentExpiring`] for the payment.
+	pub expiry_warning_emitted: bool,
+}
+
+impl_writeable_tlv_based!(HeldPaymentDetails, {
+	(0, payment_id, required),
+	(2, payment_hash, required),
+	(4, claimable_amount_msat, required),
+	(6, claim_deadline, option),
+	(8, received_at, required),
+	(10, expiry_warning_emitted, required),
+});
+
+impl HeldPaymentDetails {
+	pub(crate) fn new(
+		payment_id: PaymentId, payment_hash: PaymentHash, claimable_amount_msat: u64,
+		claim_deadline: Option<u32>,
+	) -> Self {
+		let received_at = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or(std::time::Duration::from_secs(0))
+			.as_secs();
+		Self {
+			payment_id,
+			payment_hash,
+			claimable_amount_msat,
+			claim_deadline,
+			received_at,
+			expiry_warning_emitted: false,
+		}
+	}
+}
+
+pub(crate) struct HoldInvoiceManager<L: Deref>
+where
+	L::Target: Logger,
+{
+	held_payments: Mutex<HashMap<PaymentId, HeldPaymentDetails>>,
+	payment_store: Arc<PaymentStore<L>>,
+	event_queue: Arc<EventQueue<L>>,
+	config: HoldInvoiceConfig,
+	kv_store: Arc<DynStore>,
+	logger: L,
+}
+
+impl<L: Deref> HoldInvoiceManager<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		held_payments: Vec<HeldPaymentDetails>, payment_store: Arc<PaymentStore<L>>,
+		event_queue: Arc<EventQueue<L>>, config: HoldInvoiceConfig, kv_store: Arc<DynStore>,
+		logger: L,
+	) -> Self {
+		let held_payments =
+			Mutex::new(HashMap::from_iter(held_payments.into_iter().map(|p| (p.payment_id, p))));
+		Self { held_payments, payment_store, event_queue, config, kv_store, logger }
+	}
+
+	/// Starts tracking the given payment, unless it is already known.
+	pub(crate) fn insert(&self, held_payment: HeldPaymentDetails) -> Result<(), Error> {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		// `PaymentClaimable` events may be replayed, e.g., after a restart. We keep the original
+		// entry to not emit the expiry warning twice.
+		if locked_held_payments.contains_key(&held_payment.payment_id) {
+			return Ok(());
+		}
+
+		self.persist_info(&held_payment)?;
+		locked_held_payments.insert(held_payment.payment_id, held_payment);
+		Ok(())
+	}
+
+	/// Stops tracking the payment with the given id, if it is known.
+	pub(crate) fn remove(&self, payment_id: &PaymentId) -> Result<(), Error> {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		if !locked_held_payments.contains_key(payment_id) {
+			return Ok(());
+		}
+
+		let store_key = hex_utils::to_string(&payment_id.0);
+		self.kv_store
+			.remove(
+				HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				false,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Removing held payment data for key {}/{}/{} failed due to: {}",
+					HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+					HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+
+		locked_held_payments.remove(payment_id);
+		Ok(())
+	}
+
+	pub(crate) fn list(&self) -> Vec<HeldPaymentDetails> {
+		self.held_payments.lock().unwrap().values().cloned().collect::<Vec<HeldPaymentDetails>>()
+	}
+
+	pub(crate) async fn continuously_check(
+		&self, channel_manager: Arc<ChannelManager>,
+		mut stop_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let mut interval = tokio::time::interval(HELD_PAYMENTS_CHECK_INTERVAL);
+		interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+		loop {
+			tokio::select! {
+				_ = stop_receiver.changed() => {
+					log_trace!(
+						self.logger,
+						"Stopping checking held payments.",
+					);
+					return;
+				}
+				_ = interval.tick() => {
+					let best_height = channel_manager.current_best_block().height;
+					let fail_back =
+						|payment_hash: &PaymentHash| channel_manager.fail_htlc_backwards(payment_hash);
+					self.check_deadlines(best_height, &fail_back);
+				}
+			}
+		}
+	}
+
+	/// Fails back all held payments whose claim deadline is within the configured buffer of the
+	/// given height, and warns about the ones that are about to be.
+	pub(crate) fn check_deadlines<F>(&self, best_height: u32, fail_back: &F)
+	where
+		F: Fn(&PaymentHash),
+	{
+		let held_payments = self.list();
+
+		for held_payment in held_payments {
+			let claim_deadline = match held_payment.claim_deadline {
+				Some(claim_deadline) => claim_deadline,
+				None => continue,
+			};
+
+			if best_height.saturating_add(self.config.fail_back_buffer_blocks) >= claim_deadline {
+				log_info!(
+					self.logger,
+					"Failing back held payment with ID {} as its claim deadline at height {} is near.",
+					held_payment.payment_id,
+					claim_deadline
+				);
+				fail_back(&held_payment.payment_hash);
+
+				let update = PaymentDetailsUpdate {
+					status: Some(PaymentStatus::Failed),
+					..PaymentDetailsUpdate::new(held_payment.payment_id)
+				};
+				if let Err(e) = self.payment_store.update(&update) {
+					log_error!(self.logger, "Failed to access payment store: {}", e);
+				}
+
+				if let Err(e) = self.remove(&held_payment.payment_id) {
+					log_error!(self.logger, "Failed to remove held payment: {}", e);
+				}
+				continue;
+			}
+
+			if !held_payment.expiry_warning_emitted
+				&& best_height.saturating_add(self.config.expiry_warning_blocks) >= claim_deadline
+			{
+				let event = Event::HeldPaymentExpiring {
+					payment_id: held_payment.payment_id,
+					payment_hash: held_payment.payment_hash,
+					claim_deadline,
+				};
+				if let Err(e) = self.event_queue.add_event(event) {
+					log_error!(self.logger, "Failed to push to event queue: {}", e);
+					continue;
+				}
+
+				if let Err(e) = self.mark_expiry_warning_emitted(&held_payment.payment_id) {
+					log_error!(self.logger, "Failed to update held payment: {}", e);
+				}
+			}
+		}
+	}
+
+	fn mark_expiry_warning_emitted(&self, payment_id: &PaymentId) -> Result<(), Error> {
+		let mut locked_held_payments = self.held_payments.lock().unwrap();
+		let held_payment = match locked_held_payments.get_mut(payment_id) {
+			Some(held_payment) => held_payment,
+			// The payment was claimed or failed in the meantime.
+			None => return Ok(()),
+		};
+
+		let mut updated_held_payment = held_payment.clone();
+		updated_held_payment.expiry_warning_emitted = true;
+		self.persist_info(&updated_held_payment)?;
+		*held_payment = updated_held_payment;
+		Ok(())
+	}
+
+	fn persist_info(&self, held_payment: &HeldPaymentDetails) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&held_payment.payment_id.0);
+		let data = held_payment.encode();
+		self.kv_store
+			.write(
+				HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					HELD_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+					HELD_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::io::utils::read_held_payments;
+	use crate::payment::store::{PaymentDetails, PaymentDirection, PaymentKind};
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	#[test]
+	fn held_payments_are_failed_back_before_deadline() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let payment_store =
+			Arc::new(PaymentStore::new(Vec::new(), Arc::clone(&store), Arc::clone(&logger)));
+		let event_queue = Arc::new(EventQueue::new(Arc::clone(&store), Arc::clone(&logger)));
+		let config = HoldInvoiceConfig { fail_back_buffer_blocks: 3, expiry_warning_blocks: 10 };
+		let manager = HoldInvoiceManager::new(
+			Vec::new(),
+			Arc::clone(&payment_store),
+			Arc::clone(&event_queue),
+			config,
+			Arc::clone(&store),
+			Arc::clone(&logger),
+		);
+
+		let payment_hash = PaymentHash([42u8; 32]);
+		let payment_id = PaymentId(payment_hash.0);
+		let kind = PaymentKind::Bolt11 { hash: payment_hash, preimage: None, secret: None };
+		let payment = PaymentDetails::new(
+			payment_id,
+			kind,
+			Some(1000),
+			PaymentDirection::Inbound,
+			PaymentStatus::Pending,
+		);
+		payment_store.insert(payment).unwrap();
+
+		let held_payment = HeldPaymentDetails::new(payment_id, payment_hash, 1000, Some(100));
+		manager.insert(held_payment.clone()).unwrap();
+		assert_eq!(manager.list(), vec![held_payment.clone()]);
+		assert_eq!(read_held_payments(Arc::clone(&store), Arc::clone(&logger)).unwrap().len(), 1);
+
+		let failed_back = Mutex::new(Vec::new());
+		let fail_back = |hash: &PaymentHash| failed_back.lock().unwrap().push(*hash);
+
+		// Nothing happens while the deadline is far away.
+		manager.check_deadlines(89, &fail_back);
+		assert!(event_queue.next_event().is_none());
+		assert!(failed_back.lock().unwrap().is_empty());
+
+		// We warn exactly once when the deadline is near.
+		manager.check_deadlines(90, &fail_back);
+		manager.check_deadlines(91, &fail_back);
+		assert_eq!(
+			event_queue.next_event(),
+			Some(Event::HeldPaymentExpiring { payment_id, payment_hash, claim_deadline: 100 })
+		);
+		event_queue.event_handled().unwrap();
+		assert!(event_queue.next_event().is_none());
+		assert!(manager.list()[0].expiry_warning_emitted);
+
+		// Replayed `PaymentClaimable` events don't reset the warning.
+		manager.insert(held_payment).unwrap();
+		assert!(manager.list()[0].expiry_warning_emitted);
+
+		// We fail back within the buffer.
+		manager.check_deadlines(96, &fail_back);
+		assert!(failed_back.lock().unwrap().is_empty());
+		manager.check_deadlines(97, &fail_back);
+		assert_eq!(*failed_back.lock().unwrap(), vec![payment_hash]);
+		assert!(manager.list().is_empty());
+		assert!(read_held_payments(Arc::clone(&store), Arc::clone(&logger)).unwrap().is_empty());
+		assert_eq!(payment_store.get(&payment_id).unwrap().status, PaymentStatus::Failed);
+	}
+}
@@ -0,0 +1,403 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Holds a decoder for the various payment strings users may provide.
+
+use crate::error::Error;
+use crate::payment::lnurl::parse_lnurl;
+use crate::payment::unified_qr::PaymentUri;
+use crate::payment::UnifiedPaymentMethod;
+
+use lightning::ln::msgs::SocketAddress;
+use lightning::offers::offer::{Amount as OfferAmount, Offer};
+use lightning::offers::refund::Refund;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
+
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::constants::ChainHash;
+use bitcoin::secp256k1::PublicKey;
+use bitcoin::{Address, Network};
+
+use std::str::FromStr;
+
+/// The payment instructions decoded from a payment string via
+/// [`Node::parse_payment_instructions`].
+///
+/// [`Node::parse_payment_instructions`]: crate::Node::parse_payment_instructions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentInstructions {
+	/// A [BOLT 11] invoice, payable via [`Bolt11Payment`].
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	/// [`Bolt11Payment`]: crate::payment::Bolt11Payment
+	Bolt11Invoice {
+		/// The invoice.
+		invoice: Bolt11Invoice,
+		/// The amount requested by the invoice, if any.
+		amount_msat: Option<u64>,
+		/// The description of the invoice, unless only a hash of it was committed to.
+		description: Option<String>,
+		/// The time at which the invoice expires, in seconds since the UNIX epoch.
+		expires_at_secs: Option<u64>,
+		/// The node ID of the payee.
+		payee_node_id: PublicKey,
+	},
+	/// A [BOLT 12] offer, payable via [`Bolt12Payment`].
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	/// [`Bolt12Payment`]: crate::payment::Bolt12Payment
+	Bolt12Offer {
+		/// The offer.
+		offer: Offer,
+		/// The amount requested by the offer, if any and if denominated in bitcoin.
+		amount_msat: Option<u64>,
+		/// The description of the offer, if any.
+		description: Option<String>,
+		/// The issuer of the offer, if any.
+		issuer: Option<String>,
+		/// The time at which the offer expires, in seconds since the UNIX epoch, if any.
+		expires_at_secs: Option<u64>,
+	},
+	/// A [BOLT 12] refund, which we can pay out via [`Bolt12Payment::request_refund_payment`].
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	/// [`Bolt12Payment::request_refund_payment`]: crate::payment::Bolt12Payment::request_refund_payment
+	Bolt12Refund {
+		/// The refund.
+		refund: Refund,
+		/// The amount to be refunded.
+		amount_msat: u64,
+		/// The description of the refund.
+		description: String,
+		/// The time at which the refund expires, in seconds since the UNIX epoch, if any.
+		expires_at_secs: Option<u64>,
+	},
+	/// A [BIP 21] URI, payable via [`UnifiedQrPayment`].
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	/// [`UnifiedQrPayment`]: crate::payment::UnifiedQrPayment
+	Bip21Uri {
+		/// The on-chain address of the URI, if any.
+		///
+		/// Will be `None` for URIs only offering Lightning payment methods, e.g., URIs of the form
+		/// `bitcoin:?lno=...` as resolved from [BIP 353] names.
+		///
+		/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+		address: Option<Address>,
+		/// The amount requested by the URI, if any.
+		amount_sats: Option<u64>,
+		/// The label of the URI, if any.
+		label: Option<String>,
+		/// The message of the URI, if any.
+		message: Option<String>,
+		/// The [BOLT 11] invoice included in the URI, if any.
+		///
+		/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+		bolt11_invoice: Option<Bolt11Invoice>,
+		/// The [BOLT 12] offer included in the URI, if any.
+		///
+		/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+		bolt12_offer: Option<Offer>,
+		/// The [BIP 78] payjoin endpoint included in the URI, if any.
+		///
+		/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+		payjoin_endpoint: Option<String>,
+		/// The payment methods the URI can be paid with.
+		payment_methods: Vec<UnifiedPaymentMethod>,
+	},
+	/// An on-chain address, payable via [`OnchainPayment`].
+	///
+	/// [`OnchainPayment`]: crate::payment::OnchainPayment
+	OnchainAddress {
+		/// The address.
+		address: Address,
+	},
+	/// A node URI of the form `node_id@host:port`, or a bare node ID, which can be paid via
+	/// [`SpontaneousPayment`].
+	///
+	/// [`SpontaneousPayment`]: crate::payment::SpontaneousPayment
+	NodeUri {
+		/// The node ID.
+		node_id: PublicKey,
+		/// The address the node can be reached at, if given.
+		address: Option<SocketAddress>,
+	},
+	/// An [LNURL], which can be used via [`LnurlPayment`].
+	///
+	/// Note we don't contact the LNURL service while parsing, i.e., whether this is a pay or a
+	/// withdraw request is only known after fetching it.
+	///
+	/// [LNURL]: https://github.com/lnurl/luds
+	/// [`LnurlPayment`]: crate::payment::LnurlPayment
+	Lnurl {
+		/// The URL of the LNURL service.
+		url: String,
+	},
+	/// A Lightning Address of the form `user@domain`, which can be paid via [`LnurlPayment`] or,
+	/// if the domain supports [BIP 353], via [`Node::pay_human_readable_name`].
+	///
+	/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+	/// [`LnurlPayment`]: crate::payment::LnurlPayment
+	/// [`Node::pay_human_readable_name`]: crate::Node::pay_human_readable_name
+	LightningAddress {
+		/// The Lightning Address.
+		address: String,
+	},
+}
+
+/// Decodes the given payment string, checking it's valid for the given network.
+pub(crate) fn parse_payment_instructions(
+	input: &str, network: Network,
+) -> Result<PaymentInstructions, Error> {
+	let input = input.trim();
+	let input = match input.get(..10) {
+		Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &input[10..],
+		_ => input,
+	};
+	let lowercase_input = input.to_ascii_lowercase();
+
+	if lowercase_input.starts_with("bitcoin:") {
+		return parse_bip21_uri(input, network);
+	}
+
+	if let Some((user, host)) = input.split_once('@') {
+		if let Ok(node_id) = PublicKey::from_str(user) {
+			let address = SocketAddress::from_str(host).map_err(|_| Error::InvalidSocketAddress)?;
+			return Ok(PaymentInstructions::NodeUri { node_id, address: Some(address) });
+		}
+
+		// Lightning Addresses and BIP 353 names share the same format, the latter optionally
+		// prefixed by a bitcoin sign.
+		let address = input.strip_prefix('₿').unwrap_or(input);
+		parse_lnurl(address)?;
+		return Ok(PaymentInstructions::LightningAddress { address: address.to_string() });
+	}
+
+	// Covers both bech32-encoded LNURLs and `lnurlp://`/`lnurlw://`/`lnurlc://` URLs.
+	if lowercase_input.starts_with("lnurl") {
+		let url = parse_lnurl(input)?;
+		return Ok(PaymentInstructions::Lnurl { url: url.to_string() });
+	}
+
+	if lowercase_input.starts_with("lno1") {
+		let offer = Offer::from_str(input).map_err(|_| Error::InvalidOffer)?;
+		return offer_instructions(offer, network);
+	}
+
+	if lowercase_input.starts_with("lnr1") {
+		let refund = Refund::from_str(input).map_err(|_| Error::InvalidRefund)?;
+		if refund.chain() != ChainHash::using_genesis_block(network) {
+			return Err(Error::InvalidNetwork);
+		}
+		return Ok(PaymentInstructions::Bolt12Refund {
+			amount_msat: refund.amount_msats(),
+			description: refund.description().to_string(),
+			expires_at_secs: refund.absolute_expiry().map(|expiry| expiry.as_secs()),
+			refund,
+		});
+	}
+
+	if lowercase_input.starts_with("ln") {
+		let invoice = Bolt11Invoice::from_str(input).map_err(|_| Error::InvalidInvoice)?;
+		return invoice_instructions(invoice, network);
+	}
+
+	if let Ok(node_id) = PublicKey::from_str(input) {
+		return Ok(PaymentInstructions::NodeUri { node_id, address: None });
+	}
+
+	if let Ok(address) = Address::<NetworkUnchecked>::from_str(input) {
+		let address = address.require_network(network).map_err(|_| Error::InvalidNetwork)?;
+		return Ok(PaymentInstructions::OnchainAddress { address });
+	}
+
+	Err(Error::InvalidPaymentInstructions)
+}
+
+fn parse_bip21_uri(input: &str, network: Network) -> Result<PaymentInstructions, Error> {
+	let uri = PaymentUri::parse(input, network)?;
+
+	if let Some(ref invoice) = uri.extras.bolt11_invoice {
+		if invoice.network() != network {
+			return Err(Error::InvalidNetwork);
+		}
+	}
+	if let Some(ref offer) = uri.extras.bolt12_offer {
+		if !offer.supports_chain(ChainHash::using_genesis_block(network)) {
+			return Err(Error::InvalidNetwork);
+		}
+	}
+
+	let mut payment_methods = Vec::new();
+	if uri.extras.bolt12_offer.is_some() {
+		payment_methods.push(UnifiedPaymentMethod::Bolt12);
+	}
+	if uri.extras.bolt11_invoice.is_some() {
+		payment_methods.push(UnifiedPaymentMethod::Bolt11);
+	}
+	if uri.address.is_some() {
+		payment_methods.push(UnifiedPaymentMethod::Onchain);
+	}
+	if payment_methods.is_empty() {
+		return Err(Error::InvalidUri);
+	}
+
+	Ok(PaymentInstructions::Bip21Uri {
+		address: uri.address,
+		amount_sats: uri.amount.map(|amount| amount.to_sat()),
+		label: uri.label,
+		message: uri.message,
+		bolt11_invoice: uri.extras.bolt11_invoice,
+		bolt12_offer: uri.extras.bolt12_offer,
+		payjoin_endpoint: uri.extras.payjoin_endpoint,
+		payment_methods,
+	})
+}
+
+fn invoice_instructions(
+	invoice: Bolt11Invoice, network: Network,
+) -> Result<PaymentInstructions, Error> {
+	if invoice.network() != network {
+		return Err(Error::InvalidNetwork);
+	}
+
+	let description = match invoice.description() {
+		Bolt11InvoiceDescriptionRef::Direct(description) => Some(description.to_string()),
+		Bolt11InvoiceDescriptionRef::Hash(_) => None,
+	};
+
+	Ok(PaymentInstructions::Bolt11Invoice {
+		amount_msat: invoice.amount_milli_satoshis(),
+		description,
+		expires_at_secs: invoice.expires_at().map(|expiry| expiry.as_secs()),
+		payee_node_id: invoice.get_payee_pub_key(),
+		invoice,
+	})
+}
+
+fn offer_instructions(offer: Offer, network: Network) -> Result<PaymentInstructions, Error> {
+	if !offer.supports_chain(ChainHash::using_genesis_block(network)) {
+		return Err(Error::InvalidNetwork);
+	}
+
+	let amount_msat = match offer.amount() {
+		Some(OfferAmount::Bitcoin { amount_msats }) => Some(amount_msats),
+		_ => None,
+	};
+
+	Ok(PaymentInstructions::Bolt12Offer {
+		amount_msat,
+		description: offer.description().map(|description| description.to_string()),
+		issuer: offer.issuer().map(|issuer| issuer.to_string()),
+		expires_at_secs: offer.absolute_expiry().map(|expiry| expiry.as_secs()),
+		offer,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn payment_instructions_are_parsed() {
+		let offer = "LNO1QGSQVGNWGCG35Z6EE2H3YCZRADDM72XRFUA9UVE2RLRM9DEU7XYFZRCYZPGTGRDWMGU44QPYUXLHLLMLWN4QSPQ97HSSQZSYV9EKGESSWCPK7JRAAUZ6574TSTVFJFSE20LSFWH8G9GTPFHL4RRJN23VX4TH35SRWKCNQ6S8R9ZW9HU5RXMPXVYCJVK2KY3NTEA8VXZTMWJF4NAJCCAQZQ7YZ7KDDZ600LAW2S2E7Q6XDYLPSMLMV4YAY0QXX5NC8QH05JRNUYQPQCAHK8Y5KQ8H9X624LS6A9GWFTGKYYPUZVUKKM93DWETTL8A7NE84L7SNHCSGR006EACQRQP8YWY6WPS0TS";
+		let invoice = "LNBCRT1M1PNGMY98DQ8V9EKGESNP4QDH5SL00QK4842UZMZVJVX2NLUZT4E6P2ZC2DLAGCU565TP42AUDYPP5XD0PRS5CRDLZVU8DNQQU08W9F4YP0XRXW06ZSHCLCHZU9X28HSSSSP5ES30JG9J4VK2CRW80YXTLRJU2M097TXMFTHR00VC5V0LGKVMURRQ9QYYSGQCQPCXQRRAQRZJQ0Q0K9CDYFSVZAJ5V3PDWYWDMHLEYCVD7TG0SVMY4AM4P6GQZJZ5XQQQQYQQX2QQQUQQQQLGQQQQQQQQFQWDQZX24PSHN68A9D4X4HD89F3XVC7DGGRDTFCA5WH4KZ546GSRTJVACA34QQ3DZ9W4JHLJD3XZRW44RA0RET6RDSRJCEZQC6AXANX6QPHZKHJK";
+		let address = "BCRT1QM0NW9S05QDPGC6F52FPKA9U6Q6VWTT5WVS30R2";
+		let uri = format!(
+			"BITCOIN:{}?amount=0.001&message=asdf&lightning={}&lno={}",
+			address, invoice, offer
+		);
+
+		match parse_payment_instructions(&uri, Network::Regtest).unwrap() {
+			PaymentInstructions::Bip21Uri { amount_sats, message, payment_methods, .. } => {
+				assert_eq!(amount_sats, Some(100_000));
+				assert_eq!(message, Some("asdf".to_string()));
+				assert_eq!(
+					payment_methods,
+					vec![
+						UnifiedPaymentMethod::Bolt12,
+						UnifiedPaymentMethod::Bolt11,
+						UnifiedPaymentMethod::Onchain
+					]
+				);
+			},
+			instructions => panic!("Unexpected instructions: {:?}", instructions),
+		}
+		assert_eq!(parse_payment_instructions(&uri, Network::Bitcoin), Err(Error::InvalidNetwork));
+
+		// URIs resolved from BIP 353 names commonly omit the on-chain address.
+		let uri = format!("bitcoin:?lno={}&label=Alice", offer);
+		match parse_payment_instructions(&uri, Network::Regtest).unwrap() {
+			PaymentInstructions::Bip21Uri {
+				address, label, bolt12_offer, payment_methods, ..
+			} => {
+				assert_eq!(address, None);
+				assert_eq!(label, Some("Alice".to_string()));
+				assert_eq!(bolt12_offer, Some(Offer::from_str(offer).unwrap()));
+				assert_eq!(payment_methods, vec![UnifiedPaymentMethod::Bolt12]);
+			},
+			instructions => panic!("Unexpected instructions: {:?}", instructions),
+		}
+		assert_eq!(parse_payment_instructions(&uri, Network::Bitcoin), Err(Error::InvalidNetwork));
+		assert_eq!(
+			parse_payment_instructions("bitcoin:?amount=0.001", Network::Regtest),
+			Err(Error::InvalidUri)
+		);
+
+		match parse_payment_instructions(&format!("lightning:{}", invoice), Network::Regtest) {
+			Ok(PaymentInstructions::Bolt11Invoice { amount_msat, description, .. }) => {
+				assert_eq!(amount_msat, Some(100_000_000));
+				assert_eq!(description, Some("asdf".to_string()));
+			},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+		assert_eq!(
+			parse_payment_instructions(invoice, Network::Testnet),
+			Err(Error::InvalidNetwork)
+		);
+
+		match parse_payment_instructions(offer, Network::Regtest) {
+			Ok(PaymentInstructions::Bolt12Offer { amount_msat, description, .. }) => {
+				assert_eq!(amount_msat, Some(100_000_000));
+				assert_eq!(description, Some("asdf".to_string()));
+			},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		match parse_payment_instructions(address, Network::Regtest) {
+			Ok(PaymentInstructions::OnchainAddress { .. }) => {},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+		assert_eq!(
+			parse_payment_instructions(address, Network::Bitcoin),
+			Err(Error::InvalidNetwork)
+		);
+
+		let node_id = "02eadbd9e7557375161df8b646776a547c5cbc2e95b3071ec81553f8ec821a9d8b";
+		match parse_payment_instructions(&format!("{}@127.0.0.1:9735", node_id), Network::Regtest) {
+			Ok(PaymentInstructions::NodeUri { node_id: parsed_node_id, address }) => {
+				assert_eq!(parsed_node_id, PublicKey::from_str(node_id).unwrap());
+				assert_eq!(address, Some(SocketAddress::from_str("127.0.0.1:9735").unwrap()));
+			},
+			res => panic!("Unexpected result: {:?}", res),
+		}
+
+		assert_eq!(
+			parse_payment_instructions("alice@example.com", Network::Regtest),
+			Ok(PaymentInstructions::LightningAddress { address: "alice@example.com".to_string() })
+		);
+		assert_eq!(
+			parse_payment_instructions("lnurlp://example.com/pay", Network::Regtest),
+			Ok(PaymentInstructions::Lnurl { url: "https://example.com/pay".to_string() })
+		);
+
+		assert_eq!(
+			parse_payment_instructions("not a payment", Network::Regtest),
+			Err(Error::InvalidPaymentInstructions)
+		);
+	}
+}
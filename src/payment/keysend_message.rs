@@ -0,0 +1,189 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for exchanging messages via spontaneous ("keysend") payments.
+
+use crate::error::Error;
+use crate::types::CustomTlvRecord;
+
+use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
+
+use serde::{Deserialize, Serialize};
+
+/// The custom TLV type used by chat applications to attach a text message to a payment.
+pub(crate) const KEYSEND_TEXT_MESSAGE_TLV_TYPE: u64 = 34349334;
+
+/// The custom TLV type used to attach a [bLIP 10] boostagram to a payment.
+///
+/// [bLIP 10]: https://github.com/lightning/blips/blob/master/blip-0010.md
+pub(crate) const KEYSEND_BOOSTAGRAM_TLV_TYPE: u64 = 7629169;
+
+/// The maximum size, in bytes, of an encoded message we allow to send.
+///
+/// The message has to fit into the final hop's onion payload, which shares its space with the
+/// payloads for all other hops of the route.
+pub(crate) const MAX_KEYSEND_MESSAGE_LEN: usize = 800;
+
+/// A message attached to a spontaneous ("keysend") payment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeysendMessage {
+	/// A plain-text message, as exchanged by chat applications via TLV type `34349334`.
+	Text {
+		/// The text of the message.
+		text: String,
+	},
+	/// A [bLIP 10] boostagram, as exchanged by podcasting applications via TLV type `7629169`.
+	///
+	/// [bLIP 10]: https://github.com/lightning/blips/blob/master/blip-0010.md
+	Boostagram {
+		/// The boostagram.
+		boostagram: Boostagram,
+	},
+}
+
+impl KeysendMessage {
+	/// Decodes a message from the custom TLV records of a payment, if any is present.
+	///
+	/// Will return `None` if no record of a known type is present or if it fails to decode.
+	pub(crate) fn from_custom_records(custom_records: &[CustomTlvRecord]) -> Option<Self> {
+		if let Some(record) =
+			custom_records.iter().find(|r| r.type_num == KEYSEND_TEXT_MESSAGE_TLV_TYPE)
+		{
+			let text = String::from_utf8(record.value.clone()).ok()?;
+			return Some(Self::Text { text });
+		}
+
+		if let Some(record) =
+			custom_records.iter().find(|r| r.type_num == KEYSEND_BOOSTAGRAM_TLV_TYPE)
+		{
+			let boostagram = serde_json::from_slice(&record.value).ok()?;
+			return Some(Self::Boostagram { boostagram });
+		}
+
+		None
+	}
+
+	/// Encodes the message into a custom TLV record to be attached to a payment.
+	///
+	/// Will return an error if the message is empty or exceeds [`MAX_KEYSEND_MESSAGE_LEN`].
+	pub(crate) fn to_custom_record(&self) -> Result<CustomTlvRecord, Error> {
+		let (type_num, value) = match self {
+			Self::Text { text } => (KEYSEND_TEXT_MESSAGE_TLV_TYPE, text.as_bytes().to_vec()),
+			Self::Boostagram { boostagram } => {
+				let value =
+					serde_json::to_vec(boostagram).map_err(|_| Error::InvalidKeysendMessage)?;
+				(KEYSEND_BOOSTAGRAM_TLV_TYPE, value)
+			},
+		};
+
+		if value.is_empty() || value.len() > MAX_KEYSEND_MESSAGE_LEN {
+			return Err(Error::InvalidKeysendMessage);
+		}
+
+		Ok(CustomTlvRecord { type_num, value })
+	}
+}
+
+impl_writeable_tlv_based_enum!(KeysendMessage,
+	(0, Text) => {
+		(0, text, required),
+	},
+	(2, Boostagram) => {
+		(0, boostagram, required),
+	}
+);
+
+/// A [bLIP 10] boostagram, i.e., a message and metadata sent along with a payment to a podcast.
+///
+/// All fields are optional. Unknown fields of received boostagrams are ignored.
+///
+/// [bLIP 10]: https://github.com/lightning/blips/blob/master/blip-0010.md
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Boostagram {
+	/// The kind of the payment, e.g., `boost` or `stream`.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub action: Option<String>,
+	/// The name of the application the payment was sent from.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub app_name: Option<String>,
+	/// The name of the sender.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub sender_name: Option<String>,
+	/// The message to the recipient.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub message: Option<String>,
+	/// The title of the podcast.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub podcast: Option<String>,
+	/// The title of the episode.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub episode: Option<String>,
+	/// The total amount, in millisatoshis, sent to all recipients of the boost.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub value_msat_total: Option<u64>,
+}
+
+impl_writeable_tlv_based!(Boostagram, {
+	(0, action, option),
+	(2, app_name, option),
+	(4, sender_name, option),
+	(6, message, option),
+	(8, podcast, option),
+	(10, episode, option),
+	(12, value_msat_total, option),
+});
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn text_message_round_trip() {
+		let message = KeysendMessage::Text { text: "Hello, world!".to_string() };
+		let record = message.to_custom_record().unwrap();
+		assert_eq!(record.type_num, KEYSEND_TEXT_MESSAGE_TLV_TYPE);
+		assert_eq!(KeysendMessage::from_custom_records(&[record]), Some(message));
+
+		let too_long = KeysendMessage::Text { text: "a".repeat(MAX_KEYSEND_MESSAGE_LEN + 1) };
+		assert_eq!(too_long.to_custom_record(), Err(Error::InvalidKeysendMessage));
+
+		let empty = KeysendMessage::Text { text: String::new() };
+		assert_eq!(empty.to_custom_record(), Err(Error::InvalidKeysendMessage));
+
+		let invalid_utf8 =
+			CustomTlvRecord { type_num: KEYSEND_TEXT_MESSAGE_TLV_TYPE, value: vec![0xff, 0xfe] };
+		assert_eq!(KeysendMessage::from_custom_records(&[invalid_utf8]), None);
+	}
+
+	#[test]
+	fn boostagram_round_trip() {
+		let boostagram = Boostagram {
+			action: Some("boost".to_string()),
+			sender_name: Some("Satoshi".to_string()),
+			message: Some("Great episode!".to_string()),
+			value_msat_total: Some(21_000),
+			..Default::default()
+		};
+		let message = KeysendMessage::Boostagram { boostagram };
+		let record = message.to_custom_record().unwrap();
+		assert_eq!(record.type_num, KEYSEND_BOOSTAGRAM_TLV_TYPE);
+		assert_eq!(KeysendMessage::from_custom_records(&[record]), Some(message));
+
+		// Unknown fields are ignored.
+		let value = br#"{"action":"stream","feedID":920666,"app_name":"Fountain"}"#.to_vec();
+		let record = CustomTlvRecord { type_num: KEYSEND_BOOSTAGRAM_TLV_TYPE, value };
+		let expected = Boostagram {
+			action: Some("stream".to_string()),
+			app_name: Some("Fountain".to_string()),
+			..Default::default()
+		};
+		assert_eq!(
+			KeysendMessage::from_custom_records(&[record]),
+			Some(KeysendMessage::Boostagram { boostagram: expected })
+		);
+	}
+}
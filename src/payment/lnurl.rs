@@ -0,0 +1,603 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Holds a payment handler allowing to pay and withdraw via [LNURL].
+//!
+//! [LNURL]: https://github.com/lnurl/luds
+
+use crate::config::LNURL_REQUEST_TIMEOUT_SECS;
+use crate::error::Error;
+use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::payment::Bolt11Payment;
+
+use lightning::ln::channelmanager::PaymentId;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef};
+
+use bitcoin::bech32;
+use bitcoin::hashes::{sha256, Hash};
+
+use reqwest::Url;
+use serde::Deserialize;
+
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// The maximum length of the texts included in success actions as per LUD-09.
+const MAX_SUCCESS_ACTION_TEXT_LEN: usize = 144;
+
+// The expiry of the invoices we hand to LNURL-withdraw services.
+const LNURL_WITHDRAW_INVOICE_EXPIRY_SECS: u32 = 3600;
+
+/// The parameters of an [LNURL-pay] request, as retrieved via [`LnurlPayment::fetch_pay_request`].
+///
+/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LnurlPayRequest {
+	/// The URL to request an invoice from.
+	pub callback: String,
+	/// The minimum amount, in millisatoshis, the service accepts.
+	pub min_sendable_msat: u64,
+	/// The maximum amount, in millisatoshis, the service accepts.
+	pub max_sendable_msat: u64,
+	/// The raw metadata of the request, i.e., a JSON-encoded array of entries.
+	///
+	/// The invoices returned by the service commit to the hash of this metadata.
+	pub metadata: String,
+	/// The short description included in the metadata, if any.
+	pub description: Option<String>,
+}
+
+/// The parameters of an [LNURL-withdraw] request, as retrieved via
+/// [`LnurlPayment::fetch_withdraw_request`].
+///
+/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LnurlWithdrawRequest {
+	/// The URL to submit the invoice to.
+	pub callback: String,
+	/// The secret identifying the withdrawal.
+	pub k1: String,
+	/// The minimum amount, in millisatoshis, that may be withdrawn.
+	pub min_withdrawable_msat: u64,
+	/// The maximum amount, in millisatoshis, that may be withdrawn.
+	pub max_withdrawable_msat: u64,
+	/// The description the service asks us to use for the invoice.
+	pub default_description: String,
+}
+
+/// The result of paying an [LNURL-pay] request via [`LnurlPayment::pay`].
+///
+/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LnurlPayResult {
+	/// A local identifier used to track the payment.
+	pub payment_id: PaymentId,
+	/// The action the service asks us to present once the payment succeeded, if any.
+	pub success_action: Option<LnurlSuccessAction>,
+}
+
+/// An action to be presented to the user once an [LNURL-pay] payment succeeded, as specified by
+/// [LUD-09].
+///
+/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+/// [LUD-09]: https://github.com/lnurl/luds/blob/luds/09.md
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LnurlSuccessAction {
+	/// A message to be shown to the user.
+	Message {
+		/// The message.
+		message: String,
+	},
+	/// A URL to be shown to the user alongside a description.
+	Url {
+		/// The description of the URL.
+		description: String,
+		/// The URL, which is guaranteed to share its domain with the callback the invoice was
+		/// retrieved from.
+		url: String,
+	},
+}
+
+/// A payment handler allowing to pay [LNURL-pay] requests and [Lightning Addresses], and to
+/// withdraw funds via [LNURL-withdraw].
+///
+/// Should be retrieved by calling [`Node::lnurl_payment`].
+///
+/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+/// [Lightning Addresses]: https://github.com/lnurl/luds/blob/luds/16.md
+/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+/// [`Node::lnurl_payment`]: crate::Node::lnurl_payment
+pub struct LnurlPayment {
+	runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
+	bolt11_payment: Arc<Bolt11Payment>,
+	logger: Arc<FilesystemLogger>,
+}
+
+impl LnurlPayment {
+	pub(crate) fn new(
+		runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
+		bolt11_payment: Arc<Bolt11Payment>, logger: Arc<FilesystemLogger>,
+	) -> Self {
+		Self { runtime, bolt11_payment, logger }
+	}
+
+	/// Retrieves the parameters of the [LNURL-pay] request behind the given LNURL.
+	///
+	/// Accepts bech32-encoded LNURLs, `lnurlp://` URLs as per [LUD-17], and
+	/// [Lightning Addresses] such as `alice@example.com`, each optionally prefixed with
+	/// `lightning:`.
+	///
+	/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+	/// [LUD-17]: https://github.com/lnurl/luds/blob/luds/17.md
+	/// [Lightning Addresses]: https://github.com/lnurl/luds/blob/luds/16.md
+	pub fn fetch_pay_request(&self, lnurl: &str) -> Result<LnurlPayRequest, Error> {
+		let url = parse_lnurl(lnurl)?;
+		let response = self.block_on(get_json(url))?.map_err(|e| {
+			log_error!(self.logger, "Failed to fetch LNURL-pay request: {}", e);
+			Error::LnurlRequestFailed
+		})?;
+		parse_pay_request(response).map_err(|e| {
+			log_error!(self.logger, "Failed to parse LNURL-pay request: {}", e);
+			Error::InvalidLnurl
+		})
+	}
+
+	/// Pays the given [LNURL-pay] request the given amount.
+	///
+	/// Will return an error if the amount is outside of the range accepted by the service, or if
+	/// the invoice returned by the service doesn't commit to the request's amount and metadata.
+	///
+	/// [LNURL-pay]: https://github.com/lnurl/luds/blob/luds/06.md
+	pub fn pay(
+		&self, request: &LnurlPayRequest, amount_msat: u64,
+	) -> Result<LnurlPayResult, Error> {
+		if amount_msat < request.min_sendable_msat || amount_msat > request.max_sendable_msat {
+			log_error!(
+				self.logger,
+				"Failed to pay LNURL-pay request as the amount of {}msat is outside of the accepted range of {}-{}msat.",
+				amount_msat,
+				request.min_sendable_msat,
+				request.max_sendable_msat
+			);
+			return Err(Error::InvalidAmount);
+		}
+
+		let mut callback = parse_callback(&request.callback)?;
+		callback.query_pairs_mut().append_pair("amount", &amount_msat.to_string());
+
+		let response = self.block_on(get_json(callback.clone()))?.map_err(|e| {
+			log_error!(self.logger, "Failed to fetch LNURL-pay invoice: {}", e);
+			Error::LnurlRequestFailed
+		})?;
+		let response: PayCallbackResponse = serde_json::from_value(response).map_err(|e| {
+			log_error!(self.logger, "Failed to parse LNURL-pay invoice response: {}", e);
+			Error::InvalidLnurl
+		})?;
+
+		let invoice = Bolt11Invoice::from_str(&response.pr).map_err(|e| {
+			log_error!(self.logger, "Failed to parse LNURL-pay invoice: {}", e);
+			Error::InvalidInvoice
+		})?;
+		validate_pay_invoice(&invoice, request, amount_msat).map_err(|e| {
+			log_error!(self.logger, "Received invalid LNURL-pay invoice: {}", e);
+			Error::InvalidInvoice
+		})?;
+
+		let success_action = response.success_action.and_then(|action| {
+			parse_success_action(action, &callback)
+				.map_err(|e| log_error!(self.logger, "Ignoring invalid success action: {}", e))
+				.ok()
+		});
+
+		let payment_id = self.bolt11_payment.send(&invoice, None)?;
+		log_info!(
+			self.logger,
+			"Initiated sending {}msat to LNURL-pay service at {}",
+			amount_msat,
+			request.callback
+		);
+		Ok(LnurlPayResult { payment_id, success_action })
+	}
+
+	/// Retrieves the parameters of the [LNURL-withdraw] request behind the given LNURL.
+	///
+	/// Accepts bech32-encoded LNURLs and `lnurlw://` URLs as per [LUD-17], each optionally
+	/// prefixed with `lightning:`.
+	///
+	/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+	/// [LUD-17]: https://github.com/lnurl/luds/blob/luds/17.md
+	pub fn fetch_withdraw_request(&self, lnurl: &str) -> Result<LnurlWithdrawRequest, Error> {
+		let url = parse_lnurl(lnurl)?;
+		let response = self.block_on(get_json(url))?.map_err(|e| {
+			log_error!(self.logger, "Failed to fetch LNURL-withdraw request: {}", e);
+			Error::LnurlRequestFailed
+		})?;
+		parse_withdraw_request(response).map_err(|e| {
+			log_error!(self.logger, "Failed to parse LNURL-withdraw request: {}", e);
+			Error::InvalidLnurl
+		})
+	}
+
+	/// Withdraws the given amount via the given [LNURL-withdraw] request.
+	///
+	/// Creates an invoice for the given amount via [`Bolt11Payment::receive`] and submits it to
+	/// the service, which will then pay it asynchronously. Returns the submitted invoice.
+	///
+	/// [LNURL-withdraw]: https://github.com/lnurl/luds/blob/luds/03.md
+	pub fn withdraw(
+		&self, request: &LnurlWithdrawRequest, amount_msat: u64,
+	) -> Result<Bolt11Invoice, Error> {
+		if amount_msat < request.min_withdrawable_msat
+			|| amount_msat > request.max_withdrawable_msat
+		{
+			log_error!(
+				self.logger,
+				"Failed to withdraw as the amount of {}msat is outside of the accepted range of {}-{}msat.",
+				amount_msat,
+				request.min_withdrawable_msat,
+				request.max_withdrawable_msat
+			);
+			return Err(Error::InvalidAmount);
+		}
+
+		let mut callback = parse_callback(&request.callback)?;
+
+		let invoice = self.bolt11_payment.receive(
+			amount_msat,
+			&request.default_description,
+			LNURL_WITHDRAW_INVOICE_EXPIRY_SECS,
+		)?;
+
+		callback
+			.query_pairs_mut()
+			.append_pair("k1", &request.k1)
+			.append_pair("pr", &invoice.to_string());
+
+		self.block_on(get_json(callback))?.map_err(|e| {
+			log_error!(self.logger, "Failed to submit LNURL-withdraw invoice: {}", e);
+			Error::LnurlRequestFailed
+		})?;
+
+		log_info!(
+			self.logger,
+			"Requested withdrawal of {}msat from LNURL-withdraw service at {}",
+			amount_msat,
+			request.callback
+		);
+		Ok(invoice)
+	}
+
+	fn block_on<F: Future>(&self, future: F) -> Result<F::Output, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+		Ok(tokio::task::block_in_place(move || runtime.block_on(future)))
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayRequestResponse {
+	tag: String,
+	callback: String,
+	min_sendable: u64,
+	max_sendable: u64,
+	metadata: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PayCallbackResponse {
+	pr: String,
+	#[serde(default)]
+	success_action: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WithdrawRequestResponse {
+	tag: String,
+	callback: String,
+	k1: String,
+	min_withdrawable: u64,
+	max_withdrawable: u64,
+	#[serde(default)]
+	default_description: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "tag", rename_all = "camelCase")]
+enum SuccessActionResponse {
+	Message { message: String },
+	Url { description: String, url: String },
+}
+
+/// Resolves the given LNURL, `lnurlp://`/`lnurlw://` URL, or Lightning Address to the URL to
+/// query.
+pub(crate) fn parse_lnurl(lnurl: &str) -> Result<Url, Error> {
+	let lnurl = lnurl.trim();
+	let lnurl = match lnurl.get(..10) {
+		Some(prefix) if prefix.eq_ignore_ascii_case("lightning:") => &lnurl[10..],
+		_ => lnurl,
+	};
+
+	if let Some((user, domain)) = lnurl.split_once('@') {
+		let user_valid = !user.is_empty()
+			&& user.chars().all(|c| {
+				c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_' || c == '.'
+			});
+		if !user_valid || domain.is_empty() {
+			return Err(Error::InvalidLnurl);
+		}
+		let scheme = if domain.ends_with(".onion") { "http" } else { "https" };
+		return Url::parse(&format!("{}://{}/.well-known/lnurlp/{}", scheme, domain, user))
+			.map_err(|_| Error::InvalidLnurl);
+	}
+
+	if let Some((scheme, rest)) = lnurl.split_once("://") {
+		if !["lnurlp", "lnurlw", "lnurlc"].iter().any(|s| scheme.eq_ignore_ascii_case(s)) {
+			return Err(Error::InvalidLnurl);
+		}
+		let url = Url::parse(&format!("https://{}", rest)).map_err(|_| Error::InvalidLnurl)?;
+		if is_onion(&url) {
+			return Url::parse(&format!("http://{}", rest)).map_err(|_| Error::InvalidLnurl);
+		}
+		return Ok(url);
+	}
+
+	let (hrp, data) = bech32::decode(lnurl).map_err(|_| Error::InvalidLnurl)?;
+	if !hrp.as_str().eq_ignore_ascii_case("lnurl") {
+		return Err(Error::InvalidLnurl);
+	}
+	let url = String::from_utf8(data).map_err(|_| Error::InvalidLnurl)?;
+	parse_callback(&url)
+}
+
+/// Parses the given URL, checking it uses HTTPS or refers to an onion service as required by
+/// LUD-01.
+fn parse_callback(url: &str) -> Result<Url, Error> {
+	let url = Url::parse(url).map_err(|_| Error::InvalidLnurl)?;
+	match url.scheme() {
+		"https" => Ok(url),
+		"http" if is_onion(&url) => Ok(url),
+		_ => Err(Error::InvalidLnurl),
+	}
+}
+
+fn is_onion(url: &Url) -> bool {
+	url.host_str().map_or(false, |host| host.ends_with(".onion"))
+}
+
+/// Fetches the given URL, returning the JSON response unless it signals an error as per LUD-06.
+async fn get_json(url: Url) -> Result<serde_json::Value, String> {
+	let client = reqwest::Client::builder()
+		.timeout(Duration::from_secs(LNURL_REQUEST_TIMEOUT_SECS))
+		.build()
+		.map_err(|e| e.to_string())?;
+	let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+	let value: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+
+	let is_error = value
+		.get("status")
+		.and_then(|status| status.as_str())
+		.map_or(false, |status| status.eq_ignore_ascii_case("ERROR"));
+	if is_error {
+		let reason = value.get("reason").and_then(|reason| reason.as_str()).unwrap_or_default();
+		return Err(format!("service returned an error: {}", reason));
+	}
+
+	Ok(value)
+}
+
+fn parse_pay_request(response: serde_json::Value) -> Result<LnurlPayRequest, String> {
+	let response: PayRequestResponse =
+		serde_json::from_value(response).map_err(|e| e.to_string())?;
+	if response.tag != "payRequest" {
+		return Err(format!("unexpected tag {}", response.tag));
+	}
+	if response.min_sendable == 0 || response.min_sendable > response.max_sendable {
+		return Err("invalid amount range".to_string());
+	}
+	parse_callback(&response.callback).map_err(|_| "invalid callback".to_string())?;
+
+	let entries: Vec<Vec<serde_json::Value>> =
+		serde_json::from_str(&response.metadata).map_err(|e| format!("invalid metadata: {}", e))?;
+	let description = entries.iter().find_map(|entry| match entry.as_slice() {
+		[kind, value] if kind == "text/plain" => value.as_str().map(|s| s.to_string()),
+		_ => None,
+	});
+
+	Ok(LnurlPayRequest {
+		callback: response.callback,
+		min_sendable_msat: response.min_sendable,
+		max_sendable_msat: response.max_sendable,
+		metadata: response.metadata,
+		description,
+	})
+}
+
+fn parse_withdraw_request(response: serde_json::Value) -> Result<LnurlWithdrawRequest, String> {
+	let response: WithdrawRequestResponse =
+		serde_json::from_value(response).map_err(|e| e.to_string())?;
+	if response.tag != "withdrawRequest" {
+		return Err(format!("unexpected tag {}", response.tag));
+	}
+	if response.max_withdrawable == 0 || response.min_withdrawable > response.max_withdrawable {
+		return Err("invalid amount range".to_string());
+	}
+	parse_callback(&response.callback).map_err(|_| "invalid callback".to_string())?;
+
+	Ok(LnurlWithdrawRequest {
+		callback: response.callback,
+		k1: response.k1,
+		min_withdrawable_msat: response.min_withdrawable,
+		max_withdrawable_msat: response.max_withdrawable,
+		default_description: response.default_description,
+	})
+}
+
+/// Checks the invoice commits to the requested amount and the hash of the request's metadata.
+fn validate_pay_invoice(
+	invoice: &Bolt11Invoice, request: &LnurlPayRequest, amount_msat: u64,
+) -> Result<(), String> {
+	if invoice.amount_milli_satoshis() != Some(amount_msat) {
+		return Err(format!(
+			"amount {:?}msat doesn't match the requested {}msat",
+			invoice.amount_milli_satoshis(),
+			amount_msat
+		));
+	}
+
+	let metadata_hash = sha256::Hash::hash(request.metadata.as_bytes());
+	match invoice.description() {
+		Bolt11InvoiceDescriptionRef::Hash(hash) if hash.0 == metadata_hash => Ok(()),
+		_ => Err("description hash doesn't match the metadata".to_string()),
+	}
+}
+
+fn parse_success_action(
+	action: serde_json::Value, callback: &Url,
+) -> Result<LnurlSuccessAction, String> {
+	let action: SuccessActionResponse =
+		serde_json::from_value(action).map_err(|e| e.to_string())?;
+	match action {
+		SuccessActionResponse::Message { message } => {
+			if message.chars().count() > MAX_SUCCESS_ACTION_TEXT_LEN {
+				return Err("message too long".to_string());
+			}
+			Ok(LnurlSuccessAction::Message { message })
+		},
+		SuccessActionResponse::Url { description, url } => {
+			if description.chars().count() > MAX_SUCCESS_ACTION_TEXT_LEN {
+				return Err("description too long".to_string());
+			}
+			let parsed_url = Url::parse(&url).map_err(|e| e.to_string())?;
+			if parsed_url.host_str() != callback.host_str() {
+				return Err("URL domain doesn't match the callback domain".to_string());
+			}
+			Ok(LnurlSuccessAction::Url { description, url })
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+
+	use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+	#[test]
+	fn lnurls_are_parsed() {
+		// Test vector from LUD-01.
+		let lnurl = "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS";
+		let expected = "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df";
+		assert_eq!(parse_lnurl(lnurl).unwrap().as_str(), expected);
+		assert_eq!(
+			parse_lnurl(&format!("lightning:{}", lnurl.to_lowercase())).unwrap().as_str(),
+			expected
+		);
+
+		assert_eq!(
+			parse_lnurl("alice@example.com").unwrap().as_str(),
+			"https://example.com/.well-known/lnurlp/alice"
+		);
+		assert_eq!(
+			parse_lnurl("alice@example.onion").unwrap().as_str(),
+			"http://example.onion/.well-known/lnurlp/alice"
+		);
+		assert_eq!(
+			parse_lnurl("lnurlw://example.com/withdraw?k1=abc").unwrap().as_str(),
+			"https://example.com/withdraw?k1=abc"
+		);
+
+		assert_eq!(parse_lnurl("Alice@example.com"), Err(Error::InvalidLnurl));
+		assert_eq!(parse_lnurl("ftp://example.com"), Err(Error::InvalidLnurl));
+		assert_eq!(parse_callback("http://example.com/pay"), Err(Error::InvalidLnurl));
+	}
+
+	#[test]
+	fn pay_requests_are_validated() {
+		let metadata = r#"[["text/plain","Coffee"],["text/identifier","alice@example.com"]]"#;
+		let response = serde_json::json!({
+			"tag": "payRequest",
+			"callback": "https://example.com/pay",
+			"minSendable": 1_000,
+			"maxSendable": 100_000_000,
+			"metadata": metadata,
+		});
+		let request = parse_pay_request(response.clone()).unwrap();
+		assert_eq!(request.min_sendable_msat, 1_000);
+		assert_eq!(request.max_sendable_msat, 100_000_000);
+		assert_eq!(request.description, Some("Coffee".to_string()));
+
+		let mut invalid_range = response.clone();
+		invalid_range["minSendable"] = serde_json::json!(200_000_000);
+		assert!(parse_pay_request(invalid_range).is_err());
+
+		let mut invalid_tag = response;
+		invalid_tag["tag"] = serde_json::json!("withdrawRequest");
+		assert!(parse_pay_request(invalid_tag).is_err());
+
+		let secp_ctx = Secp256k1::new();
+		let key = SecretKey::from_slice(&[42u8; 32]).unwrap();
+		let build_invoice = |amount_msat: u64, description_hash: sha256::Hash| {
+			InvoiceBuilder::new(Currency::Regtest)
+				.description_hash(description_hash)
+				.payment_hash(sha256::Hash::hash(&[1u8; 32]))
+				.payment_secret(PaymentSecret([2u8; 32]))
+				.current_timestamp()
+				.min_final_cltv_expiry_delta(144)
+				.amount_milli_satoshis(amount_msat)
+				.build_signed(|hash| secp_ctx.sign_ecdsa_recoverable(hash, &key))
+				.unwrap()
+		};
+
+		let metadata_hash = sha256::Hash::hash(metadata.as_bytes());
+		let invoice = build_invoice(50_000, metadata_hash);
+		assert!(validate_pay_invoice(&invoice, &request, 50_000).is_ok());
+		assert!(validate_pay_invoice(&invoice, &request, 60_000).is_err());
+		let invoice = build_invoice(50_000, sha256::Hash::hash(b"other"));
+		assert!(validate_pay_invoice(&invoice, &request, 50_000).is_err());
+	}
+
+	#[test]
+	fn success_actions_are_parsed() {
+		let callback = Url::parse("https://example.com/pay").unwrap();
+
+		let action = serde_json::json!({ "tag": "message", "message": "Thanks!" });
+		assert_eq!(
+			parse_success_action(action, &callback),
+			Ok(LnurlSuccessAction::Message { message: "Thanks!".to_string() })
+		);
+
+		let action = serde_json::json!({
+			"tag": "url",
+			"description": "Your order",
+			"url": "https://example.com/order/42",
+		});
+		assert_eq!(
+			parse_success_action(action, &callback),
+			Ok(LnurlSuccessAction::Url {
+				description: "Your order".to_string(),
+				url: "https://example.com/order/42".to_string(),
+			})
+		);
+
+		let action = serde_json::json!({
+			"tag": "url",
+			"description": "Phishing",
+			"url": "https://evil.com/order/42",
+		});
+		assert!(parse_success_action(action, &callback).is_err());
+
+		let action = serde_json::json!({ "tag": "message", "message": "a".repeat(145) });
+		assert!(parse_success_action(action, &callback).is_err());
+	}
+}
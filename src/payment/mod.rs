@@ -9,17 +9,40 @@
 
 mod bolt11;
 mod bolt12;
+pub(crate) mod hold_invoice;
+pub(crate) mod instructions;
+mod keysend_message;
+mod lnurl;
+pub(crate) mod offer_store;
 mod onchain;
+pub(crate) mod payjoin;
+pub(crate) mod retry;
 mod spontaneous;
 pub(crate) mod store;
 mod unified_qr;
 
 pub use bolt11::Bolt11Payment;
 pub use bolt12::Bolt12Payment;
-pub use onchain::OnchainPayment;
+pub use hold_invoice::HeldPaymentDetails;
+pub use instructions::PaymentInstructions;
+pub use keysend_message::{Boostagram, KeysendMessage};
+pub use lnurl::{
+	LnurlPayRequest, LnurlPayResult, LnurlPayment, LnurlSuccessAction, LnurlWithdrawRequest,
+};
+pub use offer_store::{OfferDetails, OfferStats};
+pub use onchain::{OnchainFeePreview, OnchainPayment, OnchainRecipient, SpendableUtxo};
 pub use spontaneous::SpontaneousPayment;
 pub use store::{LSPFeeLimits, PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus};
-pub use unified_qr::{QrPaymentResult, UnifiedQrPayment};
+pub use unified_qr::{
+	QrPaymentAttemptFailure, QrPaymentResult, UnifiedPaymentMethod, UnifiedPaymentPolicy,
+	UnifiedQrPayment,
+};
+
+use crate::config::LDK_PAYMENT_RETRY_TIMEOUT;
+
+use lightning::ln::channelmanager::Retry;
+
+use std::time::Duration;
 
 /// Represents information used to send a payment.
 #[derive(Clone, Debug, PartialEq)]
@@ -74,6 +97,51 @@ pub struct SendingParameters {
 	///
 	/// Default value: 2
 	pub max_channel_saturation_power_of_half: Option<u8>,
+	/// The strategy used to retry failed payment paths.
+	///
+	/// Defaults to retrying for up to 10 seconds.
+	pub retry_strategy: Option<PaymentRetryStrategy>,
+}
+
+/// The strategy used to retry failed payment paths.
+///
+/// Note that retries are done automatically and silently, i.e., a payment will only be considered
+/// failed once the strategy is exhausted. A payment that failed can be re-sent via
+/// [`Node::retry_payment`].
+///
+/// [`Node::retry_payment`]: crate::Node::retry_payment
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PaymentRetryStrategy {
+	/// Retry failed payment paths up to the given number of times.
+	///
+	/// A value of `0` disables retrying failed paths altogether.
+	Attempts {
+		/// The maximum number of times we retry.
+		max_attempts: u32,
+	},
+	/// Retry failed payment paths until the given time has elapsed since the payment was
+	/// initiated.
+	Timeout {
+		/// The time, in seconds, after which we stop retrying.
+		timeout_secs: u64,
+	},
+}
+
+impl Default for PaymentRetryStrategy {
+	fn default() -> Self {
+		Self::Timeout { timeout_secs: LDK_PAYMENT_RETRY_TIMEOUT.as_secs() }
+	}
+}
+
+impl From<PaymentRetryStrategy> for Retry {
+	fn from(value: PaymentRetryStrategy) -> Self {
+		match value {
+			PaymentRetryStrategy::Attempts { max_attempts } => Retry::Attempts(max_attempts),
+			PaymentRetryStrategy::Timeout { timeout_secs } => {
+				Retry::Timeout(Duration::from_secs(timeout_secs))
+			},
+		}
+	}
 }
 
 /// Represents the possible states of [`SendingParameters::max_total_routing_fee_msat`].
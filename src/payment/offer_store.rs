@@ -0,0 +1,244 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for keeping track of the BOLT12 offers we issued.
+
+use crate::hex_utils;
+use crate::io::{
+	OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE, OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, Logger};
+use crate::types::DynStore;
+use crate::Error;
+
+use lightning::impl_writeable_tlv_based;
+use lightning::offers::invoice_request::InvoiceRequest;
+use lightning::offers::offer::{Offer, OfferId};
+use lightning::util::ser::Writeable;
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Represents a BOLT12 offer we issued.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OfferDetails {
+	/// The identifier of the offer.
+	pub id: OfferId,
+	/// The offer itself.
+	pub offer: Offer,
+	/// The description the offer was created with.
+	pub description: String,
+	/// The amount, in millisatoshis, the offer was created with.
+	///
+	/// Will be `None` for "zero-amount" offers.
+	pub amount_msat: Option<u64>,
+	/// The maximum quantity of items the offer may be paid for at once, if any.
+	pub quantity: Option<u64>,
+	/// The number of seconds after creation the offer expires, if any.
+	pub expiry_secs: Option<u32>,
+	/// The timestamp, in seconds since start of the UNIX epoch, when the offer was created.
+	pub created_at: u64,
+	/// Whether we still respond to invoice requests for the offer.
+	///
+	/// Offers can be deactivated via [`Bolt12Payment::deactivate_offer`].
+	///
+	/// [`Bolt12Payment::deactivate_offer`]: crate::payment::Bolt12Payment::deactivate_offer
+	pub is_active: bool,
+}
+
+impl OfferDetails {
+	pub(crate) fn new(
+		offer: Offer, description: String, amount_msat: Option<u64>, quantity: Option<u64>,
+		expiry_secs: Option<u32>,
+	) -> Self {
+		let created_at = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or(std::time::Duration::from_secs(0))
+			.as_secs();
+		Self {
+			id: offer.id(),
+			offer,
+			description,
+			amount_msat,
+			quantity,
+			expiry_secs,
+			created_at,
+			is_active: true,
+		}
+	}
+}
+
+impl_writeable_tlv_based!(OfferDetails, {
+	(0, id, required),
+	(2, offer, required),
+	(4, description, required),
+	(6, amount_msat, option),
+	(8, quantity, option),
+	(10, expiry_secs, option),
+	(12, created_at, required),
+	(14, is_active, required),
+});
+
+/// Aggregated statistics over the payments received for a BOLT12 offer we issued.
+///
+/// Retrieved via [`Bolt12Payment::offer_stats`].
+///
+/// [`Bolt12Payment::offer_stats`]: crate::payment::Bolt12Payment::offer_stats
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct OfferStats {
+	/// The number of payments successfully received for the offer.
+	pub payments_received: u64,
+	/// The total amount, in millisatoshis, successfully received for the offer.
+	pub amount_received_msat: u64,
+	/// The number of payments for the offer that are still pending.
+	pub payments_pending: u64,
+	/// The timestamp, in seconds since start of the UNIX epoch, of the latest successfully
+	/// received payment for the offer, if any.
+	pub last_payment_received_at: Option<u64>,
+}
+
+pub(crate) struct OfferStore<L: Deref>
+where
+	L::Target: Logger,
+{
+	// `OfferId` doesn't implement `Hash`, so we key by its raw bytes.
+	offers: Mutex<HashMap<[u8; 32], OfferDetails>>,
+	kv_store: Arc<DynStore>,
+	logger: L,
+}
+
+impl<L: Deref> OfferStore<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(offers: Vec<OfferDetails>, kv_store: Arc<DynStore>, logger: L) -> Self {
+		let offers = Mutex::new(HashMap::from_iter(offers.into_iter().map(|o| (o.id.0, o))));
+		Self { offers, kv_store, logger }
+	}
+
+	pub(crate) fn insert(&self, offer: OfferDetails) -> Result<(), Error> {
+		let mut locked_offers = self.offers.lock().unwrap();
+		self.persist_info(&offer)?;
+		locked_offers.insert(offer.id.0, offer);
+		Ok(())
+	}
+
+	pub(crate) fn get(&self, id: &OfferId) -> Option<OfferDetails> {
+		self.offers.lock().unwrap().get(&id.0).cloned()
+	}
+
+	pub(crate) fn list(&self) -> Vec<OfferDetails> {
+		self.offers.lock().unwrap().values().cloned().collect::<Vec<OfferDetails>>()
+	}
+
+	/// Marks the offer with the given id as inactive, returning an error if it is unknown.
+	pub(crate) fn deactivate(&self, id: &OfferId) -> Result<(), Error> {
+		let mut locked_offers = self.offers.lock().unwrap();
+		let offer = locked_offers.get_mut(&id.0).ok_or(Error::InvalidOfferId)?;
+		if !offer.is_active {
+			return Ok(());
+		}
+
+		let mut updated_offer = offer.clone();
+		updated_offer.is_active = false;
+		self.persist_info(&updated_offer)?;
+		*offer = updated_offer;
+		Ok(())
+	}
+
+	/// Returns whether the given invoice request is for an offer we deactivated.
+	pub(crate) fn is_deactivated_offer(&self, invoice_request: &InvoiceRequest) -> bool {
+		// The offer id is only available after verifying the request, so we compare the fields
+		// that identify the offer instead. As offers are built using a unique derived signing key
+		// and unique blinded paths, these suffice to tell them apart.
+		self.offers.lock().unwrap().values().filter(|o| !o.is_active).any(|o| {
+			o.offer.issuer_signing_pubkey() == invoice_request.issuer_signing_pubkey()
+				&& o.offer.paths() == invoice_request.paths()
+				&& o.offer.metadata() == invoice_request.metadata()
+		})
+	}
+
+	fn persist_info(&self, offer: &OfferDetails) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&offer.id.0);
+		let data = offer.encode();
+		self.kv_store
+			.write(
+				OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::offers::offer::OfferBuilder;
+	use lightning::util::ser::Readable;
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	use bitcoin::secp256k1::PublicKey;
+
+	use std::io::Cursor;
+	use std::str::FromStr;
+
+	#[test]
+	fn offer_deactivation_is_persisted() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let offer_store = OfferStore::new(Vec::new(), Arc::clone(&store), logger);
+
+		let signing_pubkey = PublicKey::from_str(
+			"02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+		)
+		.unwrap();
+		let offer = OfferBuilder::new(signing_pubkey)
+			.amount_msats(1000)
+			.description("coffee".to_string())
+			.build()
+			.unwrap();
+		let details = OfferDetails::new(offer, "coffee".to_string(), Some(1000), None, Some(3600));
+		let id = details.id;
+
+		assert!(offer_store.get(&id).is_none());
+		assert_eq!(offer_store.deactivate(&id), Err(Error::InvalidOfferId));
+
+		offer_store.insert(details.clone()).unwrap();
+		assert_eq!(offer_store.get(&id), Some(details.clone()));
+		assert_eq!(offer_store.list(), vec![details.clone()]);
+
+		offer_store.deactivate(&id).unwrap();
+		assert!(!offer_store.get(&id).unwrap().is_active);
+
+		let store_key = hex_utils::to_string(&id.0);
+		let data = store
+			.read(
+				OFFER_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				OFFER_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+			)
+			.unwrap();
+		let read_details = OfferDetails::read(&mut Cursor::new(data)).unwrap();
+		assert_eq!(read_details.offer, details.offer);
+		assert!(!read_details.is_active);
+	}
+}
@@ -7,16 +7,72 @@
 
 //! Holds a payment handler allowing to send and receive on-chain payments.
 
-use crate::config::Config;
+use crate::config::{AddressType, Config};
 use crate::error::Error;
-use crate::logger::{log_info, FilesystemLogger, Logger};
-use crate::types::{ChannelManager, Wallet};
-use crate::wallet::OnchainSendAmount;
+use crate::fee_estimator::fee_rate_from_sat_per_vb;
+use crate::label_store::{LabelStore, LabelType};
+use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::payment::payjoin;
+use crate::payment::payjoin::directory::{self, DirectoryEndpoint};
+use crate::types::{ChannelManager, DynStore, Wallet};
+use crate::wallet::{setup_watch_only_wallet, OnchainSendAmount, WatchOnlyDescriptor};
 
-use bitcoin::{Address, Txid};
+use bitcoin::psbt::Psbt;
+use bitcoin::{Address, FeeRate, OutPoint, Script, Transaction, Txid};
 
 use std::sync::{Arc, RwLock};
 
+/// Details of an unspent transaction output held by the on-chain wallet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpendableUtxo {
+	/// The outpoint identifying the output.
+	pub outpoint: OutPoint,
+	/// The value of the output, in satoshis.
+	pub value_sats: u64,
+	/// The number of confirmations of the transaction creating the output.
+	///
+	/// Will be `0` if the transaction is still unconfirmed.
+	pub confirmations: u32,
+	/// The address the output is paying to.
+	pub address: Address,
+	/// Whether the output is currently frozen, i.e., excluded from automatic coin selection.
+	///
+	/// See [`OnchainPayment::freeze_utxo`] for more details.
+	pub is_frozen: bool,
+	/// The label assigned to the output via [`Node::set_label`], or, if it has none, the one
+	/// assigned to its address.
+	///
+	/// [`Node::set_label`]: crate::Node::set_label
+	pub label: Option<String>,
+}
+
+/// A recipient of an on-chain payment sent via [`OnchainPayment::send_batch`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OnchainRecipient {
+	/// The address to pay.
+	pub address: Address,
+	/// The amount to pay, in satoshis.
+	pub amount_sats: u64,
+}
+
+impl From<(Address, u64)> for OnchainRecipient {
+	fn from((address, amount_sats): (Address, u64)) -> Self {
+		Self { address, amount_sats }
+	}
+}
+
+/// The fee a transaction would pay if it was broadcast.
+///
+/// Returned by [`OnchainPayment::preview_send_to_address`] and
+/// [`OnchainPayment::preview_send_all_to_address`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnchainFeePreview {
+	/// The absolute fee paid by the transaction, in satoshis.
+	pub fee_sats: u64,
+	/// The virtual size of the transaction, in virtual bytes.
+	pub vsize: u64,
+}
+
 /// A payment handler allowing to send and receive on-chain payments.
 ///
 /// Should be retrieved by calling [`Node::onchain_payment`].
@@ -27,43 +83,256 @@ pub struct OnchainPayment {
 	wallet: Arc<Wallet>,
 	channel_manager: Arc<ChannelManager>,
 	config: Arc<Config>,
+	kv_store: Arc<DynStore>,
+	label_store: Arc<LabelStore<Arc<FilesystemLogger>>>,
 	logger: Arc<FilesystemLogger>,
 }
 
 impl OnchainPayment {
 	pub(crate) fn new(
 		runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>, wallet: Arc<Wallet>,
-		channel_manager: Arc<ChannelManager>, config: Arc<Config>, logger: Arc<FilesystemLogger>,
+		channel_manager: Arc<ChannelManager>, config: Arc<Config>, kv_store: Arc<DynStore>,
+		label_store: Arc<LabelStore<Arc<FilesystemLogger>>>, logger: Arc<FilesystemLogger>,
 	) -> Self {
-		Self { runtime, wallet, channel_manager, config, logger }
+		Self { runtime, wallet, channel_manager, config, kv_store, label_store, logger }
 	}
 
 	/// Retrieve a new on-chain/funding address.
-	pub fn new_address(&self) -> Result<Address, Error> {
-		let funding_address = self.wallet.get_new_address()?;
+	///
+	/// If `address_type` is set, an address of the given type is returned, otherwise one of the
+	/// type configured via [`NodeBuilder::set_onchain_wallet_address_type`]. Funds received on
+	/// addresses of any other than the configured type are only spent if the funds received on
+	/// addresses of the configured type don't suffice.
+	///
+	/// The returned address may be labeled via [`Node::set_label`].
+	///
+	/// [`NodeBuilder::set_onchain_wallet_address_type`]: crate::NodeBuilder::set_onchain_wallet_address_type
+	/// [`Node::set_label`]: crate::Node::set_label
+	pub fn new_address(&self, address_type: Option<AddressType>) -> Result<Address, Error> {
+		let funding_address = self.wallet.get_new_address(address_type)?;
 		log_info!(self.logger, "Generated new funding address: {}", funding_address);
 		Ok(funding_address)
 	}
 
+	/// Returns the unspent transaction outputs currently held by the on-chain wallet.
+	pub fn list_utxos(&self) -> Vec<SpendableUtxo> {
+		let mut utxos = self.wallet.list_utxos();
+		for utxo in utxos.iter_mut() {
+			utxo.label = self
+				.label_store
+				.get(LabelType::Output, &utxo.outpoint.to_string())
+				.or_else(|| self.label_store.get(LabelType::Address, &utxo.address.to_string()));
+		}
+		utxos
+	}
+
+	/// Freezes the given output, excluding it from coin selection.
+	///
+	/// Frozen outputs won't be spent by any on-chain payment or channel funding transaction, unless
+	/// they are explicitly selected via the respective `utxos_to_spend` parameter. The frozen state
+	/// is persisted across restarts.
+	///
+	/// **Note:** To not endanger any funds held in Anchor channels, frozen outputs may still be
+	/// used to bump the fees of channel transactions if no other confirmed outputs are available.
+	pub fn freeze_utxo(&self, outpoint: OutPoint) -> Result<(), Error> {
+		self.wallet.set_utxo_frozen(outpoint, true)?;
+		log_info!(self.logger, "Froze UTXO {}", outpoint);
+		Ok(())
+	}
+
+	/// Unfreezes the given output, making it available to coin selection again.
+	///
+	/// See [`OnchainPayment::freeze_utxo`] for more details.
+	pub fn unfreeze_utxo(&self, outpoint: OutPoint) -> Result<(), Error> {
+		self.wallet.set_utxo_frozen(outpoint, false)?;
+		log_info!(self.logger, "Unfroze UTXO {}", outpoint);
+		Ok(())
+	}
+
+	/// Imports the given public output descriptor, e.g., of a cold-storage wallet, for watching.
+	///
+	/// The descriptor may be a multipath descriptor such as `wpkh(xpub.../<0;1>/*)`, in which case
+	/// both its receive and change keychains are watched. Single addresses can be watched by
+	/// importing a non-ranged descriptor, e.g., `wpkh(<public key>)`. Descriptors containing
+	/// private keys are rejected.
+	///
+	/// The chain will be scanned for the descriptor's history on the next wallet sync. When
+	/// syncing via Bitcoin Core RPC, the blocks starting from `birthday_height` are instead
+	/// rescanned in the background, so it should be set to a height before the descriptor was first
+	/// used. The imported descriptors are
+	/// persisted and kept in sync across restarts.
+	///
+	/// The balances of the imported descriptors are reported separately in
+	/// [`BalanceDetails::watch_only_balances`], as their funds can't be spent by the node.
+	///
+	/// Importing an already imported descriptor is a no-op.
+	///
+	/// [`BalanceDetails::watch_only_balances`]: crate::BalanceDetails::watch_only_balances
+	pub fn import_watch_only_descriptor(
+		&self, descriptor: String, birthday_height: u32,
+	) -> Result<(), Error> {
+		let (wallet, persister) = setup_watch_only_wallet(
+			&descriptor,
+			self.config.network,
+			Arc::clone(&self.kv_store),
+			Arc::clone(&self.logger),
+		)
+		.map_err(|e| {
+			log_error!(self.logger, "Failed to import watch-only descriptor: {}", e);
+			e
+		})?;
+
+		let descriptor = WatchOnlyDescriptor {
+			descriptor,
+			birthday_height,
+			pending_scan: true,
+			rescan_height: None,
+		};
+		self.wallet.add_watch_only_wallet(descriptor, wallet, persister)?;
+		log_info!(
+			self.logger,
+			"Imported watch-only descriptor with birthday height {}",
+			birthday_height
+		);
+		Ok(())
+	}
+
 	/// Send an on-chain payment to the given address.
 	///
 	/// This will respect any on-chain reserve we need to keep, i.e., won't allow to cut into
 	/// [`BalanceDetails::total_anchor_channels_reserve_sats`].
 	///
+	/// If `utxos_to_spend` is set, the transaction will only spend the given outputs, which may
+	/// include frozen ones. Otherwise, the inputs are chosen among all non-frozen outputs.
+	///
+	/// If `fee_rate_sat_per_vb` is set, the transaction will pay the given fee rate, in satoshis
+	/// per virtual byte. Otherwise, the fee rate is estimated based on the current chain state.
+	///
 	/// [`BalanceDetails::total_anchor_channels_reserve_sats`]: crate::BalanceDetails::total_anchor_channels_reserve_sats
 	pub fn send_to_address(
-		&self, address: &bitcoin::Address, amount_sats: u64,
+		&self, address: &bitcoin::Address, amount_sats: u64, utxos_to_spend: Option<Vec<OutPoint>>,
+		fee_rate_sat_per_vb: Option<u64>,
 	) -> Result<Txid, Error> {
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+		self.send_to_address_inner(address, amount_sats, utxos_to_spend.as_deref(), fee_rate)
+	}
+
+	pub(crate) fn send_to_address_inner(
+		&self, address: &bitcoin::Address, amount_sats: u64, utxos_to_spend: Option<&[OutPoint]>,
+		fee_rate: Option<FeeRate>,
+	) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let send_amount = self.exact_send_amount(amount_sats);
+		self.wallet.send_to_address(address, send_amount, utxos_to_spend, fee_rate)
+	}
+
+	/// Send on-chain payments to multiple recipients in a single transaction.
+	///
+	/// Batching payments saves fees compared to sending them via separate
+	/// [`OnchainPayment::send_to_address`] calls, and avoids creating a change output per payment.
+	///
+	/// This will respect any on-chain reserve we need to keep, i.e., won't allow to cut into
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`].
+	///
+	/// A separate payment is recorded for each recipient, all referencing the returned [`Txid`].
+	///
+	/// If `fee_rate_sat_per_vb` is set, the transaction will pay the given fee rate, in satoshis
+	/// per virtual byte. Otherwise, the fee rate is estimated based on the current chain state.
+	///
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`]: crate::BalanceDetails::total_anchor_channels_reserve_sats
+	pub fn send_batch(
+		&self, recipients: Vec<OnchainRecipient>, fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+		let cur_anchor_reserve_sats =
+			crate::total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		self.wallet.send_batch(&recipients, cur_anchor_reserve_sats, fee_rate)
+	}
+
+	/// Creates an unsigned [`Psbt`] paying the given recipients, to be signed by an external
+	/// signer.
+	///
+	/// Once signed, the PSBT can be passed to [`OnchainPayment::finalize_and_broadcast`] to
+	/// broadcast the transaction. This allows to keep the spending authority over the on-chain
+	/// funds on a separate device, e.g., when using a watch-only wallet configured via
+	/// [`NodeBuilder::set_onchain_wallet_watch_only`].
+	///
+	/// This will respect any on-chain reserve we need to keep, i.e., won't allow to cut into
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`].
+	///
+	/// If `fee_rate_sat_per_vb` is set, the transaction will pay the given fee rate, in satoshis
+	/// per virtual byte. Otherwise, the fee rate is estimated based on the current chain state.
+	///
+	/// The outputs spent by the PSBT are reserved, i.e., excluded from coin selection, until the
+	/// PSBT is either broadcast via [`OnchainPayment::finalize_and_broadcast`] or cancelled via
+	/// [`OnchainPayment::cancel_psbt`].
+	///
+	/// **Note:** Reservations are not persisted, i.e., they are released when the node restarts.
+	///
+	/// [`NodeBuilder::set_onchain_wallet_watch_only`]: crate::builder::NodeBuilder::set_onchain_wallet_watch_only
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`]: crate::BalanceDetails::total_anchor_channels_reserve_sats
+	pub fn create_psbt(
+		&self, recipients: Vec<OnchainRecipient>, fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<Psbt, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
 
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
 		let cur_anchor_reserve_sats =
 			crate::total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
-		let send_amount =
-			OnchainSendAmount::ExactRetainingReserve { amount_sats, cur_anchor_reserve_sats };
-		self.wallet.send_to_address(address, send_amount)
+		self.wallet.create_psbt(&recipients, cur_anchor_reserve_sats, fee_rate)
+	}
+
+	/// Finalizes the given externally signed [`Psbt`] and broadcasts the resulting transaction.
+	///
+	/// The PSBT is expected to have been created via [`OnchainPayment::create_psbt`] and to carry
+	/// signatures for all of its inputs. The transaction will be recorded as an outbound on-chain
+	/// payment.
+	pub fn finalize_and_broadcast(&self, psbt: Psbt) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		self.wallet.finalize_and_broadcast(psbt)
+	}
+
+	/// Cancels the given [`Psbt`] created via [`OnchainPayment::create_psbt`], releasing the
+	/// outputs it reserved so they can be spent otherwise.
+	///
+	/// Note that a PSBT that was already signed could still be broadcast by the signer, so the
+	/// PSBT should be discarded after cancelling it.
+	pub fn cancel_psbt(&self, psbt: Psbt) -> Result<(), Error> {
+		self.wallet.cancel_psbt(&psbt)?;
+		log_info!(self.logger, "Cancelled PSBT spending {} outputs", psbt.unsigned_tx.input.len());
+		Ok(())
+	}
+
+	/// Returns the fee and size of the transaction [`OnchainPayment::send_to_address`] would
+	/// broadcast when called with the same parameters, without broadcasting anything.
+	///
+	/// As coin selection and fee estimation depend on the current state of the wallet and the
+	/// chain, the actual transaction may slightly differ if the wallet state changes in between.
+	pub fn preview_send_to_address(
+		&self, address: &bitcoin::Address, amount_sats: u64, utxos_to_spend: Option<Vec<OutPoint>>,
+		fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<OnchainFeePreview, Error> {
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+		let send_amount = self.exact_send_amount(amount_sats);
+		let (fee, vsize) =
+			self.wallet.preview_send(address, &send_amount, utxos_to_spend.as_deref(), fee_rate)?;
+		Ok(OnchainFeePreview { fee_sats: fee.to_sat(), vsize })
 	}
 
 	/// Send an on-chain payment to the given address, draining the available funds.
@@ -75,25 +344,227 @@ impl OnchainPayment {
 	/// dangerous if you have open Anchor channels for which you can't trust the counterparty to
 	/// spend the Anchor output after channel closure. If `retain_reserves` is set to `true`, this
 	/// will try to send all spendable onchain funds, i.e.,
-	/// [`BalanceDetails::spendable_onchain_balance_sats`].
+	/// [`BalanceDetails::spendable_onchain_balance_sats`]. Frozen outputs are never drained.
+	///
+	/// If `utxos_to_spend` is set, only the given outputs will be drained, which may include frozen
+	/// ones. If `retain_reserves` is set to `true`, the call will fail if the remaining funds would
+	/// not suffice to cover the on-chain reserve.
+	///
+	/// If `fee_rate_sat_per_vb` is set, the transaction will pay the given fee rate, in satoshis
+	/// per virtual byte. Otherwise, the fee rate is estimated based on the current chain state.
 	///
 	/// [`BalanceDetails::spendable_onchain_balance_sats`]: crate::balance::BalanceDetails::spendable_onchain_balance_sats
 	pub fn send_all_to_address(
 		&self, address: &bitcoin::Address, retain_reserves: bool,
+		utxos_to_spend: Option<Vec<OutPoint>>, fee_rate_sat_per_vb: Option<u64>,
 	) -> Result<Txid, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
 
-		let send_amount = if retain_reserves {
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+		let send_amount = self.all_send_amount(retain_reserves);
+		self.wallet.send_to_address(address, send_amount, utxos_to_spend.as_deref(), fee_rate)
+	}
+
+	/// Returns the fee and size of the transaction [`OnchainPayment::send_all_to_address`] would
+	/// broadcast when called with the same parameters, without broadcasting anything.
+	///
+	/// As coin selection and fee estimation depend on the current state of the wallet and the
+	/// chain, the actual transaction may slightly differ if the wallet state changes in between.
+	pub fn preview_send_all_to_address(
+		&self, address: &bitcoin::Address, retain_reserves: bool,
+		utxos_to_spend: Option<Vec<OutPoint>>, fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<OnchainFeePreview, Error> {
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+		let send_amount = self.all_send_amount(retain_reserves);
+		let (fee, vsize) =
+			self.wallet.preview_send(address, &send_amount, utxos_to_spend.as_deref(), fee_rate)?;
+		Ok(OnchainFeePreview { fee_sats: fee.to_sat(), vsize })
+	}
+
+	fn exact_send_amount(&self, amount_sats: u64) -> OnchainSendAmount {
+		let cur_anchor_reserve_sats =
+			crate::total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		OnchainSendAmount::ExactRetainingReserve { amount_sats, cur_anchor_reserve_sats }
+	}
+
+	fn all_send_amount(&self, retain_reserves: bool) -> OnchainSendAmount {
+		if retain_reserves {
 			let cur_anchor_reserve_sats =
 				crate::total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
 			OnchainSendAmount::AllRetainingReserve { cur_anchor_reserve_sats }
 		} else {
 			OnchainSendAmount::AllDrainingReserve
+		}
+	}
+
+	/// Bumps the fee of the given unconfirmed transaction via [BIP 125] replace-by-fee.
+	///
+	/// The transaction is replaced by one paying the given fee rate, in satoshis per virtual byte,
+	/// while still paying the same recipients. This requires the transaction to have been created
+	/// by our on-chain wallet, which always signals replaceability. Transactions spending funds
+	/// received on addresses of other than the configured address type can't be replaced. Any
+	/// payments carried by the original transaction will be updated to reference the replacement.
+	///
+	/// Channel funding transactions can't be replaced as this would invalidate the channel. Please
+	/// use [`OnchainPayment::bump_fee_cpfp`] for these instead.
+	///
+	/// This will respect any on-chain reserve we need to keep, i.e., won't allow to cut into
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`].
+	///
+	/// Returns the [`Txid`] of the replacement transaction.
+	///
+	/// [BIP 125]: https://github.com/bitcoin/bips/blob/master/bip-0125.mediawiki
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`]: crate::BalanceDetails::total_anchor_channels_reserve_sats
+	pub fn bump_fee_rbf(&self, txid: Txid, fee_rate_sat_per_vb: u64) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let fee_rate = fee_rate_from_sat_per_vb(fee_rate_sat_per_vb)?;
+
+		let is_funding_tx = self
+			.channel_manager
+			.list_channels()
+			.iter()
+			.any(|c| c.funding_txo.map_or(false, |funding_txo| funding_txo.txid == txid));
+		if is_funding_tx {
+			log_error!(self.logger, "Refusing to replace channel funding transaction {}", txid);
+			return Err(Error::OnchainTxCreationFailed);
+		}
+
+		let cur_anchor_reserve_sats =
+			crate::total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		self.wallet.bump_fee_rbf(txid, fee_rate, cur_anchor_reserve_sats)
+	}
+
+	/// Bumps the fee of the given unconfirmed transaction via child-pays-for-parent.
+	///
+	/// This spends our outputs of the given transaction in a new child transaction paying enough
+	/// fees for both transactions together to reach the given fee rate, in satoshis per virtual
+	/// byte. It therefore also works for transactions paying us, non-replaceable transactions, and
+	/// channel funding transactions, as long as they have an unspent output belonging to our
+	/// on-chain wallet.
+	///
+	/// This will respect any on-chain reserve we need to keep, i.e., won't allow to cut into
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`].
+	///
+	/// Returns the [`Txid`] of the child transaction.
+	///
+	/// [`BalanceDetails::total_anchor_channels_reserve_sats`]: crate::BalanceDetails::total_anchor_channels_reserve_sats
+	pub fn bump_fee_cpfp(&self, txid: Txid, fee_rate_sat_per_vb: u64) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		if rt_lock.is_none() {
+			return Err(Error::NotRunning);
+		}
+
+		let fee_rate = fee_rate_from_sat_per_vb(fee_rate_sat_per_vb)?;
+		let cur_anchor_reserve_sats =
+			crate::total_anchor_channels_reserve_sats(&self.channel_manager, &self.config);
+		self.wallet.bump_fee_cpfp(txid, fee_rate, cur_anchor_reserve_sats)
+	}
+
+	/// Send an on-chain payment to the given address via [BIP 78] payjoin, using the given
+	/// payjoin endpoint.
+	///
+	/// If the endpoint refers to a [BIP 77] payjoin directory, the request is sent via the OHTTP
+	/// relay configured in [`Config::payjoin_directory_config`].
+	///
+	/// If the receiver fails to respond with a valid payjoin proposal, the original transaction
+	/// paying the receiver is broadcast instead.
+	///
+	/// [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+	/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+	pub(crate) fn send_to_address_with_payjoin(
+		&self, address: &bitcoin::Address, amount_sats: u64, endpoint_url: &str,
+		fee_rate: Option<FeeRate>,
+	) -> Result<Txid, Error> {
+		let rt_lock = self.runtime.read().unwrap();
+		let runtime = rt_lock.as_ref().ok_or(Error::NotRunning)?;
+
+		let send_amount = self.exact_send_amount(amount_sats);
+		let original_psbt =
+			self.wallet.create_signed_psbt(address, &send_amount, None, fee_rate)?;
+		let original_tx = original_psbt.clone().extract_tx().map_err(|e| {
+			log_error!(self.logger, "Failed to extract transaction: {}", e);
+			e
+		})?;
+
+		let payee_script = address.script_pubkey();
+		let tx = match self.payjoin(runtime, &original_psbt, &payee_script, endpoint_url) {
+			Ok(tx) => {
+				log_info!(
+					self.logger,
+					"Created payjoin transaction {} sending {}sats to address {}",
+					tx.compute_txid(),
+					amount_sats,
+					address
+				);
+				tx
+			},
+			Err(e) => {
+				log_error!(
+					self.logger,
+					"Failed to negotiate payjoin: {}. Falling back to broadcasting the original transaction {}.",
+					e,
+					original_tx.compute_txid()
+				);
+				original_tx
+			},
 		};
 
-		self.wallet.send_to_address(address, send_amount)
+		Ok(self.wallet.broadcast_payment(&tx, Some(amount_sats)))
+	}
+
+	fn payjoin(
+		&self, runtime: &tokio::runtime::Runtime, original_psbt: &Psbt, payee_script: &Script,
+		endpoint_url: &str,
+	) -> Result<Transaction, String> {
+		let is_mine = |script_pubkey: &Script| self.wallet.is_mine(script_pubkey);
+		let params = payjoin::sender_params(original_psbt, payee_script, &is_mine)?;
+
+		let mut proposal = match DirectoryEndpoint::from_url(endpoint_url) {
+			Some(directory_endpoint) => {
+				let relay_url = self
+					.config
+					.payjoin_directory_config
+					.as_ref()
+					.and_then(|config| payjoin::parse_endpoint_url(&config.ohttp_relay_url))
+					.ok_or("no OHTTP relay configured to reach the payjoin directory")?;
+				tokio::task::block_in_place(|| {
+					runtime.block_on(directory::request_proposal(
+						&relay_url,
+						&directory_endpoint,
+						original_psbt,
+						&params,
+					))
+				})?
+			},
+			None => {
+				let endpoint_url =
+					payjoin::parse_endpoint_url(endpoint_url).ok_or("invalid payjoin endpoint")?;
+				tokio::task::block_in_place(|| {
+					runtime.block_on(payjoin::request_proposal(
+						endpoint_url,
+						original_psbt,
+						&params,
+					))
+				})?
+			},
+		};
+		payjoin::check_proposal(original_psbt, &proposal, &params, &is_mine)?;
+
+		payjoin::restore_sender_inputs(original_psbt, &mut proposal);
+		match self.wallet.sign_owned_inputs(&mut proposal) {
+			Ok(true) => {},
+			Ok(false) => return Err("payjoin proposal could not be finalized".to_string()),
+			Err(e) => return Err(e.to_string()),
+		}
+		payjoin::check_fee_rate(&proposal, params.min_fee_rate)?;
+
+		Ok(proposal.extract_tx_unchecked_fee_rate())
 	}
 }
@@ -0,0 +1,539 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for exchanging payjoin messages asynchronously via a [BIP 77] payjoin directory.
+//!
+//! The directory stores end-to-end encrypted messages in mailboxes identified by the public keys
+//! of their recipients. All requests to the directory are made via an OHTTP relay, which keeps the
+//! directory from learning our IP address while the relay can't read the requests.
+//!
+//! [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+
+use super::hpke::{self, N_T};
+use super::ohttp::{self, KeyConfig};
+use super::{parse_endpoint_url, parse_proposal, unix_time_secs, PayjoinParams};
+use crate::config::{
+	PayjoinDirectoryConfig, PAYJOIN_DIRECTORY_POLL_INTERVAL,
+	PAYJOIN_DIRECTORY_RESPONSE_TIMEOUT_SECS, PAYJOIN_REQUEST_TIMEOUT_SECS,
+};
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bitcoin::bech32::primitives::decode::CheckedHrpstring;
+use bitcoin::bech32::{self, ByteIterExt, Fe32, Hrp, NoChecksum};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::psbt::Psbt;
+use bitcoin::secp256k1::ellswift::ElligatorSwift;
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use reqwest::Url;
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// The size every message stored at the directory is padded to, so that messages don't reveal
+// the size of their content to the directory.
+const PADDED_MESSAGE_BYTES: usize = 7168;
+
+// The size of the ElligatorSwift encoding of the ephemeral key preceding every message.
+const ELLSWIFT_BYTES: usize = 64;
+
+// The size of the reply key the sender includes in its request.
+const UNCOMPRESSED_PUBLIC_KEY_BYTES: usize = 65;
+
+const PADDED_PLAINTEXT_A_BYTES: usize =
+	PADDED_MESSAGE_BYTES - (ELLSWIFT_BYTES + UNCOMPRESSED_PUBLIC_KEY_BYTES + N_T);
+const PADDED_PLAINTEXT_B_BYTES: usize = PADDED_MESSAGE_BYTES - (ELLSWIFT_BYTES + N_T);
+
+const INFO_A: &[u8] = b"PjV2MsgA";
+const INFO_B: &[u8] = b"PjV2MsgB";
+
+// The path under which the directory serves the key configuration of its OHTTP gateway.
+const OHTTP_GATEWAY_PATH: &str = "/.well-known/ohttp-gateway";
+
+// The human-readable parts of the parameters included in the fragment of the endpoint URL.
+const EXPIRATION_HRP: &str = "EX";
+const OHTTP_KEYS_HRP: &str = "OH";
+const RECEIVER_KEY_HRP: &str = "RK";
+
+/// A mailbox at a payjoin directory we can send payjoin requests to, as given via the `pj`
+/// parameter of a payjoin-enabled URI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct DirectoryEndpoint {
+	/// The URL of the receiver's mailbox.
+	pub mailbox_url: Url,
+	/// The key the receiver's mailbox is identified by, to which we encrypt our request.
+	pub receiver_key: PublicKey,
+	/// The key configuration of the directory's OHTTP gateway.
+	pub ohttp_keys: KeyConfig,
+	/// The time the receiver stops accepting requests, in seconds since the UNIX epoch.
+	pub expires_at_secs: u64,
+}
+
+impl DirectoryEndpoint {
+	/// Parses the given endpoint URL, returning `None` if it doesn't refer to a payjoin directory,
+	/// i.e., doesn't carry the receiver key and OHTTP keys in its fragment.
+	pub(crate) fn from_url(url: &str) -> Option<Self> {
+		let (url, fragment) = url.split_once('#')?;
+		let mailbox_url = parse_endpoint_url(url)?;
+
+		let mut receiver_key = None;
+		let mut ohttp_keys = None;
+		let mut expires_at_secs = None;
+		for param in fragment.split(['-', '+']) {
+			let param = match CheckedHrpstring::new::<NoChecksum>(param) {
+				Ok(param) => param,
+				Err(_) => continue,
+			};
+			let data = param.byte_iter().collect::<Vec<u8>>();
+			if param.hrp() == Hrp::parse_unchecked(RECEIVER_KEY_HRP) {
+				receiver_key = PublicKey::from_slice(&data).ok();
+			} else if param.hrp() == Hrp::parse_unchecked(OHTTP_KEYS_HRP) {
+				ohttp_keys = data.split_first().and_then(|(key_id, public_key)| {
+					let public_key = PublicKey::from_slice(public_key).ok()?;
+					Some(KeyConfig { key_id: *key_id, public_key })
+				});
+			} else if param.hrp() == Hrp::parse_unchecked(EXPIRATION_HRP) {
+				let expiration = <[u8; 4]>::try_from(&data[..]).ok()?;
+				expires_at_secs = Some(u32::from_be_bytes(expiration) as u64);
+			}
+		}
+
+		Some(Self {
+			mailbox_url,
+			receiver_key: receiver_key?,
+			ohttp_keys: ohttp_keys?,
+			expires_at_secs: expires_at_secs?,
+		})
+	}
+
+	/// Returns the endpoint URL to include in payjoin-enabled URIs.
+	pub(crate) fn to_url(&self) -> String {
+		let expiration = u32::try_from(self.expires_at_secs).unwrap_or(u32::MAX).to_be_bytes();
+		let ohttp_keys =
+			[&[self.ohttp_keys.key_id][..], &self.ohttp_keys.public_key.serialize()].concat();
+		// The parameters are ordered by their human-readable part.
+		let fragment = [
+			encode_param(EXPIRATION_HRP, &expiration),
+			encode_param(OHTTP_KEYS_HRP, &ohttp_keys),
+			encode_param(RECEIVER_KEY_HRP, &self.receiver_key.serialize()),
+		]
+		.join("-");
+		format!("{}#{}", self.mailbox_url, fragment)
+	}
+}
+
+/// A client of a payjoin directory, which it reaches via an OHTTP relay.
+pub(crate) struct DirectoryClient {
+	directory_url: Url,
+	relay_url: Url,
+	// The key configuration of the directory's OHTTP gateway, once we retrieved it.
+	ohttp_keys: Mutex<Option<KeyConfig>>,
+}
+
+impl DirectoryClient {
+	pub(crate) fn new(config: &PayjoinDirectoryConfig) -> Option<Self> {
+		let mut directory_url = parse_endpoint_url(&config.directory_url)?;
+		// Make sure mailbox identifiers are appended to the directory URL's path.
+		if !directory_url.path().ends_with('/') {
+			let path = format!("{}/", directory_url.path());
+			directory_url.set_path(&path);
+		}
+		let relay_url = parse_endpoint_url(&config.ohttp_relay_url)?;
+		Some(Self { directory_url, relay_url, ohttp_keys: Mutex::new(None) })
+	}
+
+	pub(crate) fn ohttp_keys(&self) -> Option<KeyConfig> {
+		*self.ohttp_keys.lock().unwrap()
+	}
+
+	/// Retrieves the key configuration of the directory's OHTTP gateway.
+	///
+	/// The request is tunneled through the relay to keep the directory from learning our IP
+	/// address.
+	pub(crate) async fn refresh_ohttp_keys(&self) -> Result<(), String> {
+		let url = self.directory_url.join(OHTTP_GATEWAY_PATH).map_err(|e| e.to_string())?;
+		let proxy = reqwest::Proxy::all(self.relay_url.as_str()).map_err(|e| e.to_string())?;
+		let client = reqwest::Client::builder()
+			.proxy(proxy)
+			.timeout(Duration::from_secs(PAYJOIN_REQUEST_TIMEOUT_SECS))
+			.build()
+			.map_err(|e| e.to_string())?;
+		let response = client
+			.get(url)
+			.header("Accept", "application/ohttp-keys")
+			.send()
+			.await
+			.map_err(|e| e.to_string())?;
+		let status = response.status();
+		if !status.is_success() {
+			return Err(format!("directory responded with status {}", status));
+		}
+		let body = response.bytes().await.map_err(|e| e.to_string())?;
+		let ohttp_keys =
+			KeyConfig::from_ohttp_keys(&body).ok_or("directory served no supported OHTTP keys")?;
+		*self.ohttp_keys.lock().unwrap() = Some(ohttp_keys);
+		Ok(())
+	}
+
+	/// Returns the endpoint under which senders can reach the mailbox of the given receiver key,
+	/// if we already retrieved the directory's OHTTP keys.
+	pub(crate) fn endpoint(
+		&self, receiver_key: PublicKey, expires_at_secs: u64,
+	) -> Option<DirectoryEndpoint> {
+		let ohttp_keys = self.ohttp_keys()?;
+		let mailbox_url = mailbox_url(&self.directory_url, &receiver_key)?;
+		Some(DirectoryEndpoint { mailbox_url, receiver_key, ohttp_keys, expires_at_secs })
+	}
+
+	/// Checks the mailbox of the given receiver key, returning the request stored in it, if any.
+	pub(crate) async fn poll_request(
+		&self, receiver_key: &PublicKey,
+	) -> Result<Option<Vec<u8>>, String> {
+		let ohttp_keys = self.ohttp_keys().ok_or("missing OHTTP keys")?;
+		let mailbox_url = mailbox_url(&self.directory_url, receiver_key).ok_or("invalid URL")?;
+		poll_mailbox(&self.relay_url, &ohttp_keys, &mailbox_url).await
+	}
+
+	/// Encrypts the given response to the sender's reply key and stores it in its mailbox.
+	pub(crate) async fn respond(
+		&self, receiver_sk: &SecretKey, reply_key: &PublicKey, response: &[u8],
+	) -> Result<(), String> {
+		let ohttp_keys = self.ohttp_keys().ok_or("missing OHTTP keys")?;
+		let mailbox_url = mailbox_url(&self.directory_url, reply_key).ok_or("invalid URL")?;
+		let message = encrypt_message_b(response, receiver_sk, reply_key)?;
+		post_message(&self.relay_url, &ohttp_keys, &mailbox_url, &message).await
+	}
+}
+
+/// Sends the given original PSBT to the receiver's mailbox at the directory, returning the
+/// proposal the receiver responds with.
+///
+/// As the receiver may be offline, we keep checking our reply mailbox for a limited time only.
+pub(crate) async fn request_proposal(
+	relay_url: &Url, endpoint: &DirectoryEndpoint, original_psbt: &Psbt, params: &PayjoinParams,
+) -> Result<Psbt, String> {
+	if endpoint.expires_at_secs <= unix_time_secs() {
+		return Err("payjoin endpoint expired".to_string());
+	}
+
+	let reply_sk = hpke::random_secret_key();
+	let reply_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &reply_sk);
+	let query = params
+		.to_query_pairs(2)
+		.into_iter()
+		.map(|(key, value)| format!("{}={}", key, value))
+		.collect::<Vec<_>>()
+		.join("&");
+	let request = format!("{}\n{}", BASE64_STANDARD.encode(original_psbt.serialize()), query);
+	let message = encrypt_message_a(request.as_bytes(), &reply_key, &endpoint.receiver_key)?;
+	post_message(relay_url, &endpoint.ohttp_keys, &endpoint.mailbox_url, &message).await?;
+
+	let reply_mailbox_url =
+		endpoint.mailbox_url.join(&short_id(&reply_key)).map_err(|e| e.to_string())?;
+	let deadline = Instant::now() + Duration::from_secs(PAYJOIN_DIRECTORY_RESPONSE_TIMEOUT_SECS);
+	loop {
+		if let Some(message) =
+			poll_mailbox(relay_url, &endpoint.ohttp_keys, &reply_mailbox_url).await?
+		{
+			let response = decrypt_message_b(&message, &reply_sk, &endpoint.receiver_key)
+				.ok_or("failed to decrypt receiver response")?;
+			let response = std::str::from_utf8(&response).map_err(|e| e.to_string())?;
+			return parse_proposal(response);
+		}
+		if Instant::now() + PAYJOIN_DIRECTORY_POLL_INTERVAL > deadline {
+			return Err("receiver didn't respond in time".to_string());
+		}
+		tokio::time::sleep(PAYJOIN_DIRECTORY_POLL_INTERVAL).await;
+	}
+}
+
+/// Splits a decrypted payjoin request into the original PSBT and the query holding the sender's
+/// parameters.
+pub(crate) fn split_request(request: &[u8]) -> Option<(&[u8], &str)> {
+	let request = std::str::from_utf8(request).ok()?;
+	let (original_psbt, query) = request.split_once('\n')?;
+	Some((original_psbt.as_bytes(), query.trim_start_matches('?')))
+}
+
+/// Encrypts a payjoin request to the receiver's key, including the key the receiver should
+/// encrypt its response to.
+fn encrypt_message_a(
+	request: &[u8], reply_key: &PublicKey, receiver_key: &PublicKey,
+) -> Result<Vec<u8>, String> {
+	if request.len() > PADDED_PLAINTEXT_A_BYTES {
+		return Err("payjoin request is too large".to_string());
+	}
+	let mut plaintext = reply_key.serialize_uncompressed().to_vec();
+	plaintext.extend_from_slice(request);
+	plaintext.resize(UNCOMPRESSED_PUBLIC_KEY_BYTES + PADDED_PLAINTEXT_A_BYTES, 0);
+
+	let (ephemeral_key, mut ctx) = hpke::setup_base_sender(receiver_key, INFO_A);
+	let ciphertext = ctx.seal(b"", &plaintext);
+	Ok([&ElligatorSwift::from_pubkey(ephemeral_key).to_array()[..], &ciphertext].concat())
+}
+
+/// Decrypts a payjoin request sent to our mailbox, returning the request along with the key the
+/// sender expects us to encrypt our response to.
+pub(crate) fn decrypt_message_a(
+	message: &[u8], receiver_sk: &SecretKey,
+) -> Option<(Vec<u8>, PublicKey)> {
+	let mut ctx = decrypt_setup(message, |ephemeral_key| {
+		hpke::setup_base_receiver(ephemeral_key, receiver_sk, INFO_A)
+	})?;
+	let plaintext = ctx.open(b"", &message[ELLSWIFT_BYTES..])?;
+	if plaintext.len() < UNCOMPRESSED_PUBLIC_KEY_BYTES {
+		return None;
+	}
+	let (reply_key, request) = plaintext.split_at(UNCOMPRESSED_PUBLIC_KEY_BYTES);
+	let reply_key = PublicKey::from_slice(reply_key).ok()?;
+	Some((strip_padding(request), reply_key))
+}
+
+/// Encrypts a response to the sender's reply key, authenticated by our receiver key.
+fn encrypt_message_b(
+	response: &[u8], receiver_sk: &SecretKey, reply_key: &PublicKey,
+) -> Result<Vec<u8>, String> {
+	if response.len() > PADDED_PLAINTEXT_B_BYTES {
+		return Err("payjoin response is too large".to_string());
+	}
+	let mut plaintext = response.to_vec();
+	plaintext.resize(PADDED_PLAINTEXT_B_BYTES, 0);
+
+	let (ephemeral_key, mut ctx) = hpke::setup_auth_sender(reply_key, receiver_sk, INFO_B);
+	let ciphertext = ctx.seal(b"", &plaintext);
+	Ok([&ElligatorSwift::from_pubkey(ephemeral_key).to_array()[..], &ciphertext].concat())
+}
+
+/// Decrypts the receiver's response, checking it was sent by the holder of the receiver key.
+fn decrypt_message_b(
+	message: &[u8], reply_sk: &SecretKey, receiver_key: &PublicKey,
+) -> Option<Vec<u8>> {
+	let mut ctx = decrypt_setup(message, |ephemeral_key| {
+		hpke::setup_auth_receiver(ephemeral_key, reply_sk, receiver_key, INFO_B)
+	})?;
+	let plaintext = ctx.open(b"", &message[ELLSWIFT_BYTES..])?;
+	Some(strip_padding(&plaintext))
+}
+
+// Sets up the context to decrypt the given message with, using the ephemeral key it starts with.
+fn decrypt_setup<F: FnOnce(&PublicKey) -> hpke::Context>(
+	message: &[u8], setup: F,
+) -> Option<hpke::Context> {
+	let ellswift = <[u8; ELLSWIFT_BYTES]>::try_from(message.get(..ELLSWIFT_BYTES)?).ok()?;
+	let ephemeral_key = PublicKey::from_ellswift(ElligatorSwift::from_array(ellswift));
+	Some(setup(&ephemeral_key))
+}
+
+fn strip_padding(plaintext: &[u8]) -> Vec<u8> {
+	let len = plaintext.iter().rposition(|b| *b != 0).map_or(0, |pos| pos + 1);
+	plaintext[..len].to_vec()
+}
+
+async fn post_message(
+	relay_url: &Url, ohttp_keys: &KeyConfig, mailbox_url: &Url, message: &[u8],
+) -> Result<(), String> {
+	let (status, _) = ohttp_request(relay_url, ohttp_keys, "POST", mailbox_url, message).await?;
+	if !(200..300).contains(&status) {
+		return Err(format!("directory responded with status {}", status));
+	}
+	Ok(())
+}
+
+// Returns the message stored in the given mailbox, or `None` if the directory has none for us.
+async fn poll_mailbox(
+	relay_url: &Url, ohttp_keys: &KeyConfig, mailbox_url: &Url,
+) -> Result<Option<Vec<u8>>, String> {
+	let (status, body) = ohttp_request(relay_url, ohttp_keys, "GET", mailbox_url, &[]).await?;
+	match status {
+		200 => Ok(Some(body)),
+		202 => Ok(None),
+		_ => Err(format!("directory responded with status {}", status)),
+	}
+}
+
+async fn ohttp_request(
+	relay_url: &Url, ohttp_keys: &KeyConfig, method: &str, url: &Url, body: &[u8],
+) -> Result<(u16, Vec<u8>), String> {
+	let (request, response_context) = ohttp::encapsulate_request(ohttp_keys, method, url, body);
+	let client = reqwest::Client::builder()
+		.timeout(Duration::from_secs(PAYJOIN_REQUEST_TIMEOUT_SECS))
+		.build()
+		.map_err(|e| e.to_string())?;
+	let response = client
+		.post(relay_url.clone())
+		.header("Content-Type", "message/ohttp-req")
+		.body(request)
+		.send()
+		.await
+		.map_err(|e| e.to_string())?;
+	let status = response.status();
+	if !status.is_success() {
+		return Err(format!("OHTTP relay responded with status {}", status));
+	}
+	let response = response.bytes().await.map_err(|e| e.to_string())?;
+	response_context.decapsulate_response(&response).ok_or_else(|| "invalid OHTTP response".into())
+}
+
+fn mailbox_url(directory_url: &Url, key: &PublicKey) -> Option<Url> {
+	directory_url.join(&short_id(key)).ok()
+}
+
+// The identifier of the mailbox of the given key, i.e., the first 8 bytes of the hash of the key,
+// encoded as uppercase bech32 without a checksum.
+fn short_id(key: &PublicKey) -> String {
+	let hash = sha256::Hash::hash(&key.serialize());
+	hash[..8].iter().copied().bytes_to_fes().map(Fe32::to_char).collect::<String>().to_uppercase()
+}
+
+fn encode_param(hrp: &str, data: &[u8]) -> String {
+	bech32::encode_upper::<NoChecksum>(Hrp::parse_unchecked(hrp), data)
+		.expect("parameters are short enough")
+}
+
+/// A stand-in for a payjoin directory along with its OHTTP gateway and an OHTTP relay, serving
+/// both the relay and the directory on the same port.
+#[cfg(test)]
+pub(crate) mod stand_in {
+	use super::super::{http_response, read_http_request};
+	use super::*;
+
+	use tokio::io::AsyncWriteExt;
+	use tokio::net::TcpListener;
+
+	use std::collections::HashMap;
+	use std::sync::Arc;
+
+	pub(crate) struct StandIn {
+		pub url: Url,
+	}
+
+	impl StandIn {
+		pub(crate) async fn start() -> Self {
+			let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+			let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+
+			let gateway_sk = hpke::random_secret_key();
+			let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &gateway_sk);
+			let ohttp_keys = KeyConfig { key_id: 1, public_key };
+			let mailboxes = Arc::new(Mutex::new(HashMap::<String, Vec<u8>>::new()));
+
+			tokio::spawn(async move {
+				loop {
+					let (mut stream, _) = listener.accept().await.unwrap();
+					let mailboxes = Arc::clone(&mailboxes);
+					tokio::spawn(async move {
+						let request = read_http_request(&mut stream).await.unwrap();
+						let response = if request.method == "GET"
+							&& request.target.ends_with(OHTTP_GATEWAY_PATH)
+						{
+							let body = ohttp_keys.to_ohttp_keys();
+							http_response("200 OK", "application/ohttp-keys", &body)
+						} else {
+							let (request, response_context) = ohttp::gateway::decapsulate_request(
+								&ohttp_keys,
+								&gateway_sk,
+								&request.body,
+							)
+							.unwrap();
+							let mailbox = request.path.trim_start_matches('/').to_string();
+							let mut locked_mailboxes = mailboxes.lock().unwrap();
+							let (status, body) = match request.method.as_str() {
+								"POST" => {
+									locked_mailboxes.insert(mailbox, request.body);
+									(200, Vec::new())
+								},
+								_ => match locked_mailboxes.get(&mailbox) {
+									Some(message) => (200, message.clone()),
+									None => (202, Vec::new()),
+								},
+							};
+							let body = response_context.encapsulate_response(status, &body);
+							http_response("200 OK", "message/ohttp-res", &body)
+						};
+						stream.write_all(&response).await.unwrap();
+					});
+				}
+			});
+
+			Self { url }
+		}
+
+		pub(crate) fn config(&self) -> PayjoinDirectoryConfig {
+			PayjoinDirectoryConfig {
+				directory_url: self.url.to_string(),
+				ohttp_relay_url: self.url.to_string(),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn endpoints_round_trip() {
+		let receiver_sk = hpke::random_secret_key();
+		let receiver_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &receiver_sk);
+		let ohttp_key = hpke::random_secret_key();
+		let ohttp_keys = KeyConfig {
+			key_id: 1,
+			public_key: PublicKey::from_secret_key(&Secp256k1::signing_only(), &ohttp_key),
+		};
+		let directory_url = Url::parse("https://payjo.in/").unwrap();
+		let mailbox_url = mailbox_url(&directory_url, &receiver_key).unwrap();
+		assert_eq!(mailbox_url.path().len(), 14);
+		assert!(mailbox_url.path()[1..]
+			.chars()
+			.all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()));
+
+		let endpoint = DirectoryEndpoint {
+			mailbox_url,
+			receiver_key,
+			ohttp_keys,
+			expires_at_secs: 1_720_547_781,
+		};
+		let url = endpoint.to_url();
+		assert!(url.contains("#EX1"));
+		assert_eq!(DirectoryEndpoint::from_url(&url), Some(endpoint.clone()));
+		assert_eq!(DirectoryEndpoint::from_url(&url.replace('-', "+")), Some(endpoint));
+
+		// Regular BIP 78 endpoints don't refer to a directory.
+		assert!(DirectoryEndpoint::from_url("https://example.com/payjoin").is_none());
+		assert!(DirectoryEndpoint::from_url("https://example.com/payjoin#foo").is_none());
+	}
+
+	#[test]
+	fn messages_are_padded_and_encrypted() {
+		let secp_ctx = Secp256k1::signing_only();
+		let receiver_sk = hpke::random_secret_key();
+		let receiver_key = PublicKey::from_secret_key(&secp_ctx, &receiver_sk);
+		let reply_sk = hpke::random_secret_key();
+		let reply_key = PublicKey::from_secret_key(&secp_ctx, &reply_sk);
+
+		let message_a = encrypt_message_a(b"psbt\nv=2", &reply_key, &receiver_key).unwrap();
+		assert_eq!(message_a.len(), PADDED_MESSAGE_BYTES);
+		assert_eq!(
+			decrypt_message_a(&message_a, &receiver_sk),
+			Some((b"psbt\nv=2".to_vec(), reply_key))
+		);
+		assert!(decrypt_message_a(&message_a, &reply_sk).is_none());
+		assert_eq!(split_request(b"psbt\nv=2"), Some((&b"psbt"[..], "v=2")));
+
+		let message_b = encrypt_message_b(b"proposal", &receiver_sk, &reply_key).unwrap();
+		assert_eq!(message_b.len(), PADDED_MESSAGE_BYTES);
+		assert_eq!(
+			decrypt_message_b(&message_b, &reply_sk, &receiver_key),
+			Some(b"proposal".to_vec())
+		);
+		// Responses not sent by the receiver are rejected.
+		let forged = encrypt_message_b(b"proposal", &reply_sk, &reply_key).unwrap();
+		assert!(decrypt_message_b(&forged, &reply_sk, &receiver_key).is_none());
+
+		let too_large = vec![1u8; PADDED_PLAINTEXT_A_BYTES + 1];
+		assert!(encrypt_message_a(&too_large, &reply_key, &receiver_key).is_err());
+	}
+}
@@ -0,0 +1,321 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Hybrid public key encryption as specified by [RFC 9180], using the DHKEM(secp256k1,
+//! HKDF-SHA256), HKDF-SHA256, and ChaCha20Poly1305 cipher suite employed by [BIP 77].
+//!
+//! [RFC 9180]: https://www.rfc-editor.org/rfc/rfc9180.html
+//! [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::secp256k1::{ecdh, PublicKey, Secp256k1, SecretKey};
+use rand::{thread_rng, RngCore};
+use ring::aead;
+
+pub(crate) const KEM_ID: u16 = 0x0016;
+pub(crate) const KDF_ID: u16 = 0x0001;
+pub(crate) const AEAD_ID: u16 = 0x0003;
+
+// The length of the AEAD key.
+pub(crate) const N_K: usize = 32;
+// The length of the AEAD nonce.
+pub(crate) const N_N: usize = 12;
+// The length of the AEAD authentication tag.
+pub(crate) const N_T: usize = 16;
+// The length of the KEM shared secret, which is also the length of the KDF output.
+const N_SECRET: usize = 32;
+
+const MODE_BASE: u8 = 0x00;
+const MODE_AUTH: u8 = 0x02;
+
+/// The context established between the sender and the receiver, used to encrypt and decrypt
+/// messages and to derive secrets.
+pub(crate) struct Context {
+	key: [u8; N_K],
+	base_nonce: [u8; N_N],
+	exporter_secret: [u8; N_SECRET],
+	seq: u64,
+}
+
+impl Context {
+	pub(crate) fn seal(&mut self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+		let ciphertext = aead_seal(&self.key, self.next_nonce(), aad, plaintext);
+		self.seq += 1;
+		ciphertext
+	}
+
+	pub(crate) fn open(&mut self, aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+		let plaintext = aead_open(&self.key, self.next_nonce(), aad, ciphertext)?;
+		self.seq += 1;
+		Some(plaintext)
+	}
+
+	pub(crate) fn export(&self, exporter_context: &[u8], len: usize) -> Vec<u8> {
+		labeled_expand(&hpke_suite_id(), &self.exporter_secret, b"sec", exporter_context, len)
+	}
+
+	fn next_nonce(&self) -> [u8; N_N] {
+		let mut nonce = self.base_nonce;
+		for (byte, seq_byte) in nonce[N_N - 8..].iter_mut().zip(self.seq.to_be_bytes()) {
+			*byte ^= seq_byte;
+		}
+		nonce
+	}
+}
+
+/// Sets up a context to encrypt messages to the given receiver, returning the ephemeral public
+/// key the receiver needs to set up its context along with ours.
+pub(crate) fn setup_base_sender(receiver_pk: &PublicKey, info: &[u8]) -> (PublicKey, Context) {
+	let ephemeral_sk = random_secret_key();
+	let ephemeral_pk = PublicKey::from_secret_key(&Secp256k1::signing_only(), &ephemeral_sk);
+	let dh = diffie_hellman(&ephemeral_sk, receiver_pk);
+	let kem_context =
+		[ephemeral_pk.serialize_uncompressed(), receiver_pk.serialize_uncompressed()].concat();
+	let shared_secret = extract_and_expand(&dh, &kem_context);
+	(ephemeral_pk, key_schedule(MODE_BASE, &shared_secret, info))
+}
+
+/// Sets up a context to decrypt messages sent to us using the given ephemeral public key.
+pub(crate) fn setup_base_receiver(
+	ephemeral_pk: &PublicKey, receiver_sk: &SecretKey, info: &[u8],
+) -> Context {
+	let receiver_pk = PublicKey::from_secret_key(&Secp256k1::signing_only(), receiver_sk);
+	let dh = diffie_hellman(receiver_sk, ephemeral_pk);
+	let kem_context =
+		[ephemeral_pk.serialize_uncompressed(), receiver_pk.serialize_uncompressed()].concat();
+	let shared_secret = extract_and_expand(&dh, &kem_context);
+	key_schedule(MODE_BASE, &shared_secret, info)
+}
+
+/// Sets up a context to encrypt messages to the given receiver, authenticated as coming from the
+/// holder of the given secret key.
+pub(crate) fn setup_auth_sender(
+	receiver_pk: &PublicKey, sender_sk: &SecretKey, info: &[u8],
+) -> (PublicKey, Context) {
+	let secp_ctx = Secp256k1::signing_only();
+	let ephemeral_sk = random_secret_key();
+	let ephemeral_pk = PublicKey::from_secret_key(&secp_ctx, &ephemeral_sk);
+	let sender_pk = PublicKey::from_secret_key(&secp_ctx, sender_sk);
+	let dh = [diffie_hellman(&ephemeral_sk, receiver_pk), diffie_hellman(sender_sk, receiver_pk)]
+		.concat();
+	let kem_context = [
+		ephemeral_pk.serialize_uncompressed(),
+		receiver_pk.serialize_uncompressed(),
+		sender_pk.serialize_uncompressed(),
+	]
+	.concat();
+	let shared_secret = extract_and_expand(&dh, &kem_context);
+	(ephemeral_pk, key_schedule(MODE_AUTH, &shared_secret, info))
+}
+
+/// Sets up a context to decrypt messages sent to us by the holder of the secret key belonging to
+/// the given sender public key.
+pub(crate) fn setup_auth_receiver(
+	ephemeral_pk: &PublicKey, receiver_sk: &SecretKey, sender_pk: &PublicKey, info: &[u8],
+) -> Context {
+	let receiver_pk = PublicKey::from_secret_key(&Secp256k1::signing_only(), receiver_sk);
+	let dh = [diffie_hellman(receiver_sk, ephemeral_pk), diffie_hellman(receiver_sk, sender_pk)]
+		.concat();
+	let kem_context = [
+		ephemeral_pk.serialize_uncompressed(),
+		receiver_pk.serialize_uncompressed(),
+		sender_pk.serialize_uncompressed(),
+	]
+	.concat();
+	let shared_secret = extract_and_expand(&dh, &kem_context);
+	key_schedule(MODE_AUTH, &shared_secret, info)
+}
+
+pub(crate) fn random_secret_key() -> SecretKey {
+	loop {
+		let mut bytes = [0u8; 32];
+		thread_rng().fill_bytes(&mut bytes);
+		if let Ok(secret_key) = SecretKey::from_slice(&bytes) {
+			return secret_key;
+		}
+	}
+}
+
+pub(crate) fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
+	let mut engine = HmacEngine::<sha256::Hash>::new(salt);
+	engine.input(ikm);
+	Hmac::from_engine(engine).to_byte_array()
+}
+
+pub(crate) fn hkdf_expand(prk: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+	let mut okm = Vec::with_capacity(len);
+	let mut block = Vec::new();
+	let mut counter = 1u8;
+	while okm.len() < len {
+		let mut engine = HmacEngine::<sha256::Hash>::new(prk);
+		engine.input(&block);
+		engine.input(info);
+		engine.input(&[counter]);
+		block = Hmac::from_engine(engine).to_byte_array().to_vec();
+		okm.extend_from_slice(&block);
+		counter += 1;
+	}
+	okm.truncate(len);
+	okm
+}
+
+pub(crate) fn aead_seal(key: &[u8], nonce: [u8; N_N], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+	let key = aead::LessSafeKey::new(
+		aead::UnboundKey::new(&aead::CHACHA20_POLY1305, key).expect("valid key length"),
+	);
+	let mut in_out = plaintext.to_vec();
+	key.seal_in_place_append_tag(
+		aead::Nonce::assume_unique_for_key(nonce),
+		aead::Aad::from(aad),
+		&mut in_out,
+	)
+	.expect("message is small enough");
+	in_out
+}
+
+pub(crate) fn aead_open(
+	key: &[u8], nonce: [u8; N_N], aad: &[u8], ciphertext: &[u8],
+) -> Option<Vec<u8>> {
+	let key = aead::LessSafeKey::new(aead::UnboundKey::new(&aead::CHACHA20_POLY1305, key).ok()?);
+	let mut in_out = ciphertext.to_vec();
+	let plaintext_len = key
+		.open_in_place(aead::Nonce::assume_unique_for_key(nonce), aead::Aad::from(aad), &mut in_out)
+		.ok()?
+		.len();
+	in_out.truncate(plaintext_len);
+	Some(in_out)
+}
+
+// Returns the x-coordinate of the shared point, as specified for DHKEM over secp256k1.
+fn diffie_hellman(sk: &SecretKey, pk: &PublicKey) -> [u8; 32] {
+	let point = ecdh::shared_secret_point(pk, sk);
+	let mut x = [0u8; 32];
+	x.copy_from_slice(&point[..32]);
+	x
+}
+
+fn kem_suite_id() -> Vec<u8> {
+	[&b"KEM"[..], &KEM_ID.to_be_bytes()].concat()
+}
+
+fn hpke_suite_id() -> Vec<u8> {
+	[&b"HPKE"[..], &KEM_ID.to_be_bytes(), &KDF_ID.to_be_bytes(), &AEAD_ID.to_be_bytes()].concat()
+}
+
+fn labeled_extract(suite_id: &[u8], salt: &[u8], label: &[u8], ikm: &[u8]) -> [u8; 32] {
+	hkdf_extract(salt, &[&b"HPKE-v1"[..], suite_id, label, ikm].concat())
+}
+
+fn labeled_expand(suite_id: &[u8], prk: &[u8], label: &[u8], info: &[u8], len: usize) -> Vec<u8> {
+	let labeled_info =
+		[&(len as u16).to_be_bytes()[..], &b"HPKE-v1"[..], suite_id, label, info].concat();
+	hkdf_expand(prk, &labeled_info, len)
+}
+
+fn extract_and_expand(dh: &[u8], kem_context: &[u8]) -> Vec<u8> {
+	let suite_id = kem_suite_id();
+	let eae_prk = labeled_extract(&suite_id, b"", b"eae_prk", dh);
+	labeled_expand(&suite_id, &eae_prk, b"shared_secret", kem_context, N_SECRET)
+}
+
+// The key schedule for the modes we support, none of which use a pre-shared key.
+fn key_schedule(mode: u8, shared_secret: &[u8], info: &[u8]) -> Context {
+	let suite_id = hpke_suite_id();
+	let psk_id_hash = labeled_extract(&suite_id, b"", b"psk_id_hash", b"");
+	let info_hash = labeled_extract(&suite_id, b"", b"info_hash", info);
+	let key_schedule_context = [&[mode][..], &psk_id_hash, &info_hash].concat();
+
+	let secret = labeled_extract(&suite_id, shared_secret, b"secret", b"");
+	let mut key = [0u8; N_K];
+	key.copy_from_slice(&labeled_expand(&suite_id, &secret, b"key", &key_schedule_context, N_K));
+	let mut base_nonce = [0u8; N_N];
+	base_nonce.copy_from_slice(&labeled_expand(
+		&suite_id,
+		&secret,
+		b"base_nonce",
+		&key_schedule_context,
+		N_N,
+	));
+	let mut exporter_secret = [0u8; N_SECRET];
+	exporter_secret.copy_from_slice(&labeled_expand(
+		&suite_id,
+		&secret,
+		b"exp",
+		&key_schedule_context,
+		N_SECRET,
+	));
+
+	Context { key, base_nonce, exporter_secret, seq: 0 }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::hex::FromHex;
+
+	fn hex_bytes(hex: &str) -> Vec<u8> {
+		Vec::from_hex(hex).unwrap()
+	}
+
+	#[test]
+	fn hkdf_matches_rfc_5869() {
+		// Test case 1 of RFC 5869.
+		let ikm = [0x0bu8; 22];
+		let salt = (0x00u8..=0x0c).collect::<Vec<_>>();
+		let info = (0xf0u8..=0xf9).collect::<Vec<_>>();
+		let prk = hkdf_extract(&salt, &ikm);
+		assert_eq!(
+			prk.to_vec(),
+			hex_bytes("077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5")
+		);
+		assert_eq!(
+			hkdf_expand(&prk, &info, 42),
+			hex_bytes(
+				"3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+			)
+		);
+	}
+
+	#[test]
+	fn messages_are_encrypted_and_authenticated() {
+		let receiver_sk = random_secret_key();
+		let receiver_pk = PublicKey::from_secret_key(&Secp256k1::signing_only(), &receiver_sk);
+
+		let (ephemeral_pk, mut sender_ctx) = setup_base_sender(&receiver_pk, b"info");
+		let ciphertext = sender_ctx.seal(b"aad", b"hello");
+		assert_eq!(ciphertext.len(), 5 + N_T);
+		let mut receiver_ctx = setup_base_receiver(&ephemeral_pk, &receiver_sk, b"info");
+		assert_eq!(receiver_ctx.open(b"aad", &ciphertext), Some(b"hello".to_vec()));
+		assert_eq!(sender_ctx.export(b"context", 32), receiver_ctx.export(b"context", 32));
+
+		// Each message uses a new nonce.
+		let second_ciphertext = sender_ctx.seal(b"aad", b"hello");
+		assert_ne!(ciphertext, second_ciphertext);
+		assert_eq!(receiver_ctx.open(b"aad", &second_ciphertext), Some(b"hello".to_vec()));
+
+		// Tampered messages, or ones meant for a different context, are rejected.
+		let mut receiver_ctx = setup_base_receiver(&ephemeral_pk, &receiver_sk, b"info");
+		let mut tampered = ciphertext.clone();
+		tampered[0] ^= 1;
+		assert!(receiver_ctx.open(b"aad", &tampered).is_none());
+		let mut other_ctx = setup_base_receiver(&ephemeral_pk, &receiver_sk, b"other info");
+		assert!(other_ctx.open(b"aad", &ciphertext).is_none());
+
+		// In auth mode, the receiver also learns who sent the message.
+		let sender_sk = random_secret_key();
+		let sender_pk = PublicKey::from_secret_key(&Secp256k1::signing_only(), &sender_sk);
+		let (ephemeral_pk, mut sender_ctx) = setup_auth_sender(&receiver_pk, &sender_sk, b"info");
+		let ciphertext = sender_ctx.seal(b"", b"hello");
+		let mut receiver_ctx =
+			setup_auth_receiver(&ephemeral_pk, &receiver_sk, &sender_pk, b"info");
+		assert_eq!(receiver_ctx.open(b"", &ciphertext), Some(b"hello".to_vec()));
+		let mut impersonated_ctx =
+			setup_auth_receiver(&ephemeral_pk, &receiver_sk, &receiver_pk, b"info");
+		assert!(impersonated_ctx.open(b"", &ciphertext).is_none());
+	}
+}
@@ -0,0 +1,1424 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects for sending and receiving on-chain payments via [BIP 78] payjoin, either directly or
+//! asynchronously via a [BIP 77] payjoin directory.
+//!
+//! [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+//! [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+
+pub(crate) mod directory;
+mod hpke;
+mod ohttp;
+
+use crate::chain::ChainSource;
+use crate::config::{
+	PayjoinConfig, PayjoinDirectoryConfig, PAYJOIN_DIRECTORY_POLL_INTERVAL,
+	PAYJOIN_MAX_CONCURRENT_REQUESTS, PAYJOIN_MAX_EXPECTED_PAYMENTS,
+	PAYJOIN_ORIGINAL_TX_BROADCAST_DELAY, PAYJOIN_REQUEST_TIMEOUT_SECS,
+};
+use crate::logger::{log_error, log_info, log_trace, FilesystemLogger, Logger};
+use crate::types::Wallet;
+use directory::DirectoryClient;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::psbt::{Input as PsbtInput, Psbt};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use bitcoin::{FeeRate, Script, ScriptBuf, Transaction, TxIn, Txid, Witness};
+use rand::{thread_rng, Rng};
+use reqwest::Url;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use std::collections::{HashMap, HashSet};
+use std::net::ToSocketAddrs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The weight of a P2WPKH input, assuming a 72-byte signature.
+const P2WPKH_INPUT_WEIGHT: u64 = 272;
+
+// The weight of a P2TR key-path input.
+const P2TR_INPUT_WEIGHT: u64 = 230;
+
+// The maximum size of a payjoin request we're willing to read.
+const MAX_REQUEST_SIZE: usize = 100_000;
+
+/// The parameters a payjoin sender passes to the receiver along with the original PSBT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PayjoinParams {
+	/// The index of the sender's output the receiver may deduct fees for its inputs from.
+	pub additional_fee_output_index: Option<usize>,
+	/// The maximum amount, in satoshis, the receiver may deduct from the fee output.
+	pub max_additional_fee_contribution_sats: u64,
+	/// Whether the receiver is forbidden from substituting its output.
+	pub disable_output_substitution: bool,
+	/// The minimum fee rate the payjoin transaction needs to pay.
+	pub min_fee_rate: FeeRate,
+}
+
+impl PayjoinParams {
+	fn to_query_pairs(&self, version: u8) -> Vec<(&'static str, String)> {
+		let mut pairs = vec![("v", version.to_string())];
+		if let Some(index) = self.additional_fee_output_index {
+			pairs.push(("additionalfeeoutputindex", index.to_string()));
+			pairs.push((
+				"maxadditionalfeecontribution",
+				self.max_additional_fee_contribution_sats.to_string(),
+			));
+		}
+		if self.disable_output_substitution {
+			pairs.push(("disableoutputsubstitution", "true".to_string()));
+		}
+		let min_fee_rate_sat_per_vb = self.min_fee_rate.to_sat_per_kwu() as f64 / 250.0;
+		pairs.push(("minfeerate", format!("{:.3}", min_fee_rate_sat_per_vb)));
+		pairs
+	}
+
+	fn from_query(query: &str) -> Result<Self, ReceiveError> {
+		let mut version = None;
+		let mut additional_fee_output_index = None;
+		let mut max_additional_fee_contribution_sats = None;
+		let mut disable_output_substitution = false;
+		let mut min_fee_rate = FeeRate::ZERO;
+
+		for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+			let invalid = || ReceiveError::OriginalPsbtRejected(format!("invalid {}", key));
+			match key {
+				"v" => version = Some(value.to_string()),
+				"additionalfeeoutputindex" => {
+					additional_fee_output_index = Some(value.parse().map_err(|_| invalid())?)
+				},
+				"maxadditionalfeecontribution" => {
+					max_additional_fee_contribution_sats =
+						Some(value.parse().map_err(|_| invalid())?)
+				},
+				"disableoutputsubstitution" => disable_output_substitution = value == "true",
+				"minfeerate" => {
+					let sat_per_vb: f64 = value.parse().map_err(|_| invalid())?;
+					if !sat_per_vb.is_finite() || sat_per_vb < 0.0 {
+						return Err(invalid());
+					}
+					min_fee_rate = FeeRate::from_sat_per_kwu((sat_per_vb * 250.0).ceil() as u64);
+				},
+				_ => {},
+			}
+		}
+
+		// Requests received via a payjoin directory are of version 2, but otherwise the same.
+		if version.as_deref() != Some("1") && version.as_deref() != Some("2") {
+			return Err(ReceiveError::VersionUnsupported);
+		}
+
+		// The fee output index is only meaningful along with the maximum contribution.
+		let (additional_fee_output_index, max_additional_fee_contribution_sats) =
+			match (additional_fee_output_index, max_additional_fee_contribution_sats) {
+				(Some(index), Some(max)) => (Some(index), max),
+				_ => (None, 0),
+			};
+
+		Ok(Self {
+			additional_fee_output_index,
+			max_additional_fee_contribution_sats,
+			disable_output_substitution,
+			min_fee_rate,
+		})
+	}
+}
+
+/// The errors a payjoin receiver may respond with, as defined by [BIP 78].
+///
+/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki#receivers-well-known-errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ReceiveError {
+	Unavailable,
+	NotEnoughMoney,
+	VersionUnsupported,
+	OriginalPsbtRejected(String),
+}
+
+impl ReceiveError {
+	fn error_code(&self) -> &'static str {
+		match self {
+			Self::Unavailable => "unavailable",
+			Self::NotEnoughMoney => "not-enough-money",
+			Self::VersionUnsupported => "version-unsupported",
+			Self::OriginalPsbtRejected(_) => "original-psbt-rejected",
+		}
+	}
+
+	fn to_json(&self) -> String {
+		serde_json::json!({
+			"errorCode": self.error_code(),
+			"message": self.message(),
+		})
+		.to_string()
+	}
+
+	fn message(&self) -> String {
+		match self {
+			Self::Unavailable => "The payjoin endpoint is not available for now.".to_string(),
+			Self::NotEnoughMoney => {
+				"The receiver added some inputs but could not bump the fee of the payjoin proposal."
+					.to_string()
+			},
+			Self::VersionUnsupported => "This version of payjoin is not supported.".to_string(),
+			Self::OriginalPsbtRejected(reason) => {
+				format!("The receiver rejected the original PSBT: {}", reason)
+			},
+		}
+	}
+}
+
+// A payment we expect to receive via payjoin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ExpectedPayment {
+	amount_sats: u64,
+	// The time the URI requesting the payment expires, in seconds since the UNIX epoch.
+	expires_at_secs: u64,
+	// The mailbox at the payjoin directory we expect the request for the payment in, if any.
+	directory_session: Option<DirectorySession>,
+}
+
+// A mailbox at the payjoin directory we check for payjoin requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DirectorySession {
+	// The key identifying the mailbox, which senders encrypt their requests to.
+	secret_key: SecretKey,
+	// The hash of the last request we handled, so that we don't handle requests twice while they
+	// remain in the mailbox.
+	last_request_hash: Option<sha256::Hash>,
+}
+
+// The payments we expect to receive via payjoin, keyed by the script they pay to.
+#[derive(Default)]
+struct ExpectedPayments {
+	payments: HashMap<ScriptBuf, ExpectedPayment>,
+}
+
+impl ExpectedPayments {
+	fn insert(&mut self, script_pubkey: ScriptBuf, payment: ExpectedPayment) {
+		if self.payments.len() >= PAYJOIN_MAX_EXPECTED_PAYMENTS
+			&& !self.payments.contains_key(&script_pubkey)
+		{
+			let first_expiring = self
+				.payments
+				.iter()
+				.min_by_key(|(_, payment)| payment.expires_at_secs)
+				.map(|(script_pubkey, _)| script_pubkey.clone());
+			if let Some(first_expiring) = first_expiring {
+				self.payments.remove(&first_expiring);
+			}
+		}
+		self.payments.insert(script_pubkey, payment);
+	}
+
+	// Stops expecting the payments whose URI expired, or that were already made via a regular
+	// transaction.
+	fn prune(&mut self, now_secs: u64, paid_scripts: &HashSet<ScriptBuf>) {
+		self.payments.retain(|script_pubkey, payment| {
+			payment.expires_at_secs > now_secs && !paid_scripts.contains(script_pubkey)
+		});
+	}
+
+	// Stops expecting the payment made by the given transaction, returning the script it pays to
+	// along with the payment.
+	fn take_paid_by(&mut self, tx: &Transaction) -> Option<(ScriptBuf, ExpectedPayment)> {
+		let script_pubkey = tx
+			.output
+			.iter()
+			.map(|o| &o.script_pubkey)
+			.find(|script_pubkey| self.payments.contains_key(*script_pubkey))?
+			.clone();
+		let payment = self.payments.remove(&script_pubkey)?;
+		Some((script_pubkey, payment))
+	}
+}
+
+/// Serves payjoin requests for the on-chain addresses we handed out in payjoin-enabled URIs.
+///
+/// Requests are either received directly via our [BIP 78] endpoint, or retrieved from our
+/// mailboxes at a [BIP 77] payjoin directory.
+///
+/// [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+pub(crate) struct PayjoinReceiver {
+	config: Option<PayjoinConfig>,
+	endpoint_url: Option<Url>,
+	directory: Option<DirectoryClient>,
+	wallet: Arc<Wallet>,
+	chain_source: Arc<ChainSource>,
+	expected_payments: Mutex<ExpectedPayments>,
+	// Limits the number of payjoin requests we serve concurrently.
+	request_limiter: Arc<Semaphore>,
+	logger: Arc<FilesystemLogger>,
+}
+
+impl PayjoinReceiver {
+	pub(crate) fn new(
+		config: Option<PayjoinConfig>, directory_config: Option<PayjoinDirectoryConfig>,
+		wallet: Arc<Wallet>, chain_source: Arc<ChainSource>, logger: Arc<FilesystemLogger>,
+	) -> Option<Self> {
+		let endpoint_url = match &config {
+			Some(config) => Some(parse_endpoint_url(&config.endpoint_url)?),
+			None => None,
+		};
+		let directory = match &directory_config {
+			Some(directory_config) => Some(DirectoryClient::new(directory_config)?),
+			None => None,
+		};
+		let expected_payments = Mutex::new(ExpectedPayments::default());
+		let request_limiter = Arc::new(Semaphore::new(PAYJOIN_MAX_CONCURRENT_REQUESTS));
+		Some(Self {
+			config,
+			endpoint_url,
+			directory,
+			wallet,
+			chain_source,
+			expected_payments,
+			request_limiter,
+			logger,
+		})
+	}
+
+	/// Registers the given script to be paid the given amount via payjoin, until the URI
+	/// requesting the payment expires after the given number of seconds.
+	///
+	/// Returns the endpoint to include in the URI's `pj` parameter. If we're configured to use a
+	/// payjoin directory, this is a new mailbox at the directory. Otherwise, or if we couldn't
+	/// retrieve the directory's OHTTP keys yet, this is our [BIP 78] endpoint, if any.
+	///
+	/// We stop expecting the payment once the script is paid via a regular transaction.
+	///
+	/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+	pub(crate) fn expect_payment(
+		&self, script_pubkey: ScriptBuf, amount_sats: u64, expiry_secs: u32,
+	) -> Option<String> {
+		let expires_at_secs = unix_time_secs().saturating_add(expiry_secs as u64);
+
+		let mut directory_session = None;
+		let mut endpoint = self.endpoint_url.as_ref().map(|url| url.to_string());
+		if let Some(directory) = &self.directory {
+			let secret_key = hpke::random_secret_key();
+			let receiver_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key);
+			match directory.endpoint(receiver_key, expires_at_secs) {
+				Some(directory_endpoint) => {
+					endpoint = Some(directory_endpoint.to_url());
+					directory_session =
+						Some(DirectorySession { secret_key, last_request_hash: None });
+				},
+				None => {
+					log_error!(
+						self.logger,
+						"Unable to receive via the payjoin directory as we didn't retrieve its OHTTP keys yet."
+					);
+				},
+			}
+		}
+		let endpoint = endpoint?;
+
+		let payment = ExpectedPayment { amount_sats, expires_at_secs, directory_session };
+		let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+		self.prune_expected_payments(&mut locked_expected_payments);
+		locked_expected_payments.insert(script_pubkey, payment);
+		Some(endpoint)
+	}
+
+	fn prune_expected_payments(&self, expected_payments: &mut ExpectedPayments) {
+		let paid_scripts = self.wallet.paid_scripts(expected_payments.payments.keys());
+		expected_payments.prune(unix_time_secs(), &paid_scripts);
+	}
+
+	pub(crate) async fn continuously_serve(
+		self: Arc<Self>, mut stop_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let config = match &self.config {
+			Some(config) => config,
+			None => return,
+		};
+
+		let bind_addrs = match config.listening_address.to_socket_addrs() {
+			Ok(addrs) => addrs.collect::<Vec<_>>(),
+			Err(e) => {
+				log_error!(
+					self.logger,
+					"Unable to resolve payjoin listening address {}: {}",
+					config.listening_address,
+					e
+				);
+				return;
+			},
+		};
+
+		let listener = match tokio::net::TcpListener::bind(&*bind_addrs).await {
+			Ok(listener) => listener,
+			Err(e) => {
+				log_error!(
+					self.logger,
+					"Failed to bind to payjoin listening address {}: {}",
+					config.listening_address,
+					e
+				);
+				return;
+			},
+		};
+
+		loop {
+			tokio::select! {
+				_ = stop_receiver.changed() => {
+					log_trace!(
+						self.logger,
+						"Stopping serving payjoin requests.",
+					);
+					return;
+				}
+				res = listener.accept() => {
+					let stream = match res {
+						Ok((stream, _)) => stream,
+						Err(e) => {
+							log_error!(self.logger, "Failed to accept payjoin connection: {}", e);
+							continue;
+						},
+					};
+					// If we're busy serving other requests already, we drop the connection, which
+					// has the sender fall back to broadcasting the original transaction.
+					let permit = match Arc::clone(&self.request_limiter).try_acquire_owned() {
+						Ok(permit) => permit,
+						Err(_) => {
+							log_error!(
+								self.logger,
+								"Dropping payjoin connection as we're serving too many requests."
+							);
+							continue;
+						},
+					};
+					let receiver = Arc::clone(&self);
+					let stop_receiver = stop_receiver.clone();
+					tokio::spawn(async move {
+						receiver.handle_connection(stream, stop_receiver).await;
+						drop(permit);
+					});
+				}
+			}
+		}
+	}
+
+	async fn handle_connection(
+		&self, mut stream: TcpStream, stop_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let timeout = Duration::from_secs(PAYJOIN_REQUEST_TIMEOUT_SECS);
+		let request = match tokio::time::timeout(timeout, read_http_request(&mut stream)).await {
+			Ok(Ok(request)) => request,
+			Ok(Err(e)) => {
+				log_error!(self.logger, "Failed to read payjoin request: {}", e);
+				return;
+			},
+			Err(_) => {
+				log_error!(self.logger, "Failed to read payjoin request: timed out");
+				return;
+			},
+		};
+
+		let mut proposal = None;
+		let response = if request.method != "POST" {
+			http_response("405 Method Not Allowed", "text/plain", b"")
+		} else {
+			let query = request.target.split_once('?').map_or("", |(_, query)| query);
+			match self.handle_request(query, &request.body).await {
+				Ok(res) => {
+					let body = BASE64_STANDARD.encode(res.psbt.serialize());
+					proposal = Some(res);
+					http_response("200 OK", "text/plain", body.as_bytes())
+				},
+				Err(e) => {
+					log_error!(self.logger, "Rejected payjoin request: {}", e.message());
+					let body = e.to_json();
+					http_response("400 Bad Request", "application/json", body.as_bytes())
+				},
+			}
+		};
+
+		if let Err(e) = stream.write_all(&response).await {
+			log_error!(self.logger, "Failed to respond to payjoin request: {}", e);
+			// The sender never learned about our proposal, so we may still be paid via payjoin.
+			if let Some(proposal) = proposal {
+				let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+				locked_expected_payments
+					.insert(proposal.receiver_script, proposal.expected_payment);
+			}
+			return;
+		}
+		let _ = stream.shutdown().await;
+
+		if let Some(proposal) = proposal {
+			let payjoin_txid = proposal.psbt.unsigned_tx.compute_txid();
+			log_info!(
+				self.logger,
+				"Responded to payjoin request with proposal for transaction {}",
+				payjoin_txid
+			);
+			self.schedule_original_tx_broadcast(payjoin_txid, proposal.original_tx, stop_receiver);
+		}
+	}
+
+	pub(crate) async fn continuously_poll_directory(
+		self: Arc<Self>, mut stop_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let directory = match &self.directory {
+			Some(directory) => directory,
+			None => return,
+		};
+
+		let mut interval = tokio::time::interval(PAYJOIN_DIRECTORY_POLL_INTERVAL);
+		interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+		loop {
+			tokio::select! {
+				_ = stop_receiver.changed() => {
+					log_trace!(
+						self.logger,
+						"Stopping polling the payjoin directory.",
+					);
+					return;
+				}
+				_ = interval.tick() => {}
+			}
+
+			if directory.ohttp_keys().is_none() {
+				if let Err(e) = directory.refresh_ohttp_keys().await {
+					log_error!(
+						self.logger,
+						"Failed to retrieve payjoin directory OHTTP keys: {}",
+						e
+					);
+					continue;
+				}
+			}
+
+			let sessions = {
+				let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+				self.prune_expected_payments(&mut locked_expected_payments);
+				locked_expected_payments
+					.payments
+					.iter()
+					.filter_map(|(script_pubkey, payment)| {
+						Some((script_pubkey.clone(), payment.directory_session.clone()?))
+					})
+					.collect::<Vec<_>>()
+			};
+
+			// We wait for all mailboxes to be checked before checking them again, so that we
+			// never handle the same request concurrently.
+			let mut polls = JoinSet::new();
+			for (script_pubkey, session) in sessions {
+				let permit = match Arc::clone(&self.request_limiter).acquire_owned().await {
+					Ok(permit) => permit,
+					Err(_) => return,
+				};
+				let receiver = Arc::clone(&self);
+				let stop_receiver = stop_receiver.clone();
+				polls.spawn(async move {
+					receiver.poll_directory_session(script_pubkey, session, stop_receiver).await;
+					drop(permit);
+				});
+			}
+			tokio::select! {
+				_ = stop_receiver.changed() => {
+					log_trace!(
+						self.logger,
+						"Stopping polling the payjoin directory.",
+					);
+					return;
+				}
+				_ = async { while polls.join_next().await.is_some() {} } => {}
+			}
+		}
+	}
+
+	async fn poll_directory_session(
+		&self, script_pubkey: ScriptBuf, session: DirectorySession,
+		stop_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let directory = match &self.directory {
+			Some(directory) => directory,
+			None => return,
+		};
+		let receiver_key =
+			PublicKey::from_secret_key(&Secp256k1::signing_only(), &session.secret_key);
+		let message = match directory.poll_request(&receiver_key).await {
+			Ok(Some(message)) => message,
+			Ok(None) => return,
+			Err(e) => {
+				log_error!(self.logger, "Failed to poll payjoin directory: {}", e);
+				return;
+			},
+		};
+
+		let request_hash = sha256::Hash::hash(&message);
+		if session.last_request_hash == Some(request_hash) {
+			return;
+		}
+		let mark_handled = || {
+			let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+			let session = locked_expected_payments
+				.payments
+				.get_mut(&script_pubkey)
+				.and_then(|payment| payment.directory_session.as_mut());
+			if let Some(session) = session {
+				session.last_request_hash = Some(request_hash);
+			}
+		};
+
+		let (request, reply_key) = match directory::decrypt_message_a(&message, &session.secret_key)
+		{
+			Some(res) => res,
+			None => {
+				log_error!(self.logger, "Failed to decrypt payjoin request from directory.");
+				mark_handled();
+				return;
+			},
+		};
+		let res = match directory::split_request(&request) {
+			Some((original_psbt, query)) => self.handle_request(query, original_psbt).await,
+			None => Err(ReceiveError::OriginalPsbtRejected("invalid request".to_string())),
+		};
+
+		match res {
+			Ok(proposal) => {
+				let response = BASE64_STANDARD.encode(proposal.psbt.serialize());
+				match directory.respond(&session.secret_key, &reply_key, response.as_bytes()).await
+				{
+					Ok(()) => {
+						let payjoin_txid = proposal.psbt.unsigned_tx.compute_txid();
+						log_info!(
+							self.logger,
+							"Responded to payjoin request via directory with proposal for transaction {}",
+							payjoin_txid
+						);
+						self.schedule_original_tx_broadcast(
+							payjoin_txid,
+							proposal.original_tx,
+							stop_receiver,
+						);
+					},
+					Err(e) => {
+						log_error!(self.logger, "Failed to respond to payjoin request: {}", e);
+						// The sender never learned about our proposal, so we may still be paid
+						// via payjoin and retry responding once we poll the mailbox again.
+						let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+						locked_expected_payments
+							.insert(proposal.receiver_script, proposal.expected_payment);
+					},
+				}
+			},
+			Err(e) => {
+				log_error!(self.logger, "Rejected payjoin request: {}", e.message());
+				mark_handled();
+				let response = e.to_json();
+				if let Err(e) =
+					directory.respond(&session.secret_key, &reply_key, response.as_bytes()).await
+				{
+					log_error!(self.logger, "Failed to respond to payjoin request: {}", e);
+				}
+			},
+		}
+	}
+
+	// If the sender doesn't broadcast the payjoin transaction after all, we broadcast the original
+	// transaction they sent us to make sure we get paid.
+	fn schedule_original_tx_broadcast(
+		&self, payjoin_txid: Txid, original_tx: Transaction,
+		mut stop_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let wallet = Arc::clone(&self.wallet);
+		let logger = Arc::clone(&self.logger);
+		tokio::spawn(async move {
+			tokio::select! {
+				_ = stop_receiver.changed() => {
+					log_info!(
+						logger,
+						"Stopped waiting for payjoin transaction {}, not broadcasting original transaction {}.",
+						payjoin_txid,
+						original_tx.compute_txid()
+					);
+				}
+				_ = tokio::time::sleep(PAYJOIN_ORIGINAL_TX_BROADCAST_DELAY) => {
+					if !wallet.is_transaction_known(payjoin_txid) {
+						log_info!(
+							logger,
+							"Broadcasting original transaction {} as payjoin transaction {} is unknown.",
+							original_tx.compute_txid(),
+							payjoin_txid
+						);
+						wallet.broadcast_transaction(&original_tx);
+					}
+				}
+			}
+		});
+	}
+
+	async fn handle_request(
+		&self, query: &str, body: &[u8],
+	) -> Result<PayjoinProposal, ReceiveError> {
+		let params = PayjoinParams::from_query(query)?;
+		let body = std::str::from_utf8(body)
+			.map_err(|_| ReceiveError::OriginalPsbtRejected("invalid encoding".to_string()))?;
+		let original_psbt = BASE64_STANDARD
+			.decode(body.trim())
+			.ok()
+			.and_then(|bytes| Psbt::deserialize(&bytes).ok())
+			.ok_or_else(|| ReceiveError::OriginalPsbtRejected("invalid PSBT".to_string()))?;
+
+		let (original_tx, original_fee_sats) = self.check_original_psbt(&original_psbt)?;
+
+		// Make sure the sender can't have us reveal our UTXOs via original transactions that
+		// could never be broadcast.
+		match self.chain_source.test_mempool_accept(&original_tx).await {
+			Ok(true) => {},
+			Ok(false) => {
+				return Err(ReceiveError::OriginalPsbtRejected(
+					"original transaction can't be broadcast".to_string(),
+				))
+			},
+			Err(_) => return Err(ReceiveError::Unavailable),
+		}
+
+		// Every address only receives a single payjoin, so we stop expecting the payment while
+		// handling the request and only start expecting it again if the request fails.
+		let (receiver_script, expected_payment) = {
+			let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+			self.prune_expected_payments(&mut locked_expected_payments);
+			locked_expected_payments.take_paid_by(&original_tx).ok_or_else(|| {
+				ReceiveError::OriginalPsbtRejected("no payjoin-enabled output found".to_string())
+			})?
+		};
+
+		match self.create_proposal(
+			&original_psbt,
+			&original_tx,
+			original_fee_sats,
+			&receiver_script,
+			expected_payment.amount_sats,
+			&params,
+		) {
+			Ok(psbt) => {
+				Ok(PayjoinProposal { psbt, original_tx, receiver_script, expected_payment })
+			},
+			Err(e) => {
+				let mut locked_expected_payments = self.expected_payments.lock().unwrap();
+				locked_expected_payments.insert(receiver_script, expected_payment);
+				Err(e)
+			},
+		}
+	}
+
+	// Checks the original PSBT is finalized and doesn't spend our own coins, returning the
+	// original transaction along with the fee it pays.
+	fn check_original_psbt(
+		&self, original_psbt: &Psbt,
+	) -> Result<(Transaction, u64), ReceiveError> {
+		let reject = |reason: &str| ReceiveError::OriginalPsbtRejected(reason.to_string());
+
+		if original_psbt.inputs.is_empty() {
+			return Err(reject("no inputs"));
+		}
+
+		let mut input_value_sats = 0u64;
+		for (index, input) in original_psbt.inputs.iter().enumerate() {
+			if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+				return Err(reject("inputs need to be finalized"));
+			}
+			let utxo = original_psbt
+				.spend_utxo(index)
+				.map_err(|_| reject("inputs need to have UTXO information"))?;
+			// Refuse to respond to requests spending our own coins, which may be used to probe
+			// our UTXOs.
+			if self.wallet.is_mine(&utxo.script_pubkey) {
+				return Err(reject("inputs must not belong to the receiver"));
+			}
+			input_value_sats = input_value_sats.saturating_add(utxo.value.to_sat());
+		}
+
+		let original_tx = original_psbt.clone().extract_tx_unchecked_fee_rate();
+		let output_value_sats = original_tx.output.iter().map(|o| o.value.to_sat()).sum::<u64>();
+		let original_fee_sats = input_value_sats
+			.checked_sub(output_value_sats)
+			.ok_or_else(|| reject("negative fee"))?;
+
+		Ok((original_tx, original_fee_sats))
+	}
+
+	fn create_proposal(
+		&self, original_psbt: &Psbt, original_tx: &Transaction, original_fee_sats: u64,
+		receiver_script: &Script, expected_amount_sats: u64, params: &PayjoinParams,
+	) -> Result<Psbt, ReceiveError> {
+		let reject = |reason: &str| ReceiveError::OriginalPsbtRejected(reason.to_string());
+
+		let receiver_output_index = original_tx
+			.output
+			.iter()
+			.position(|o| o.script_pubkey.as_script() == receiver_script)
+			.ok_or_else(|| reject("no payjoin-enabled output found"))?;
+		let receiver_output = original_tx.output[receiver_output_index].clone();
+		if receiver_output.value.to_sat() < expected_amount_sats {
+			return Err(reject("output value is lower than expected"));
+		}
+
+		// We only contribute inputs of the same type as the sender's to not make the payjoin
+		// transaction stand out.
+		let sender_script = &original_psbt.spend_utxo(0).expect("checked above").script_pubkey;
+		let (outpoint, txout, prev_tx) =
+			self.wallet.select_payjoin_input(sender_script).ok_or(ReceiveError::Unavailable)?;
+		let input_weight = input_weight(&txout.script_pubkey).ok_or(ReceiveError::Unavailable)?;
+
+		// Figure out how much fee we need to pay for our input, and how much of that the sender
+		// allowed us to deduct from their fee output.
+		let original_weight = original_tx.weight().to_wu();
+		let original_fee_rate_sat_per_kwu =
+			original_fee_sats.saturating_mul(1000) / original_weight.max(1);
+		let fee_rate_sat_per_kwu =
+			original_fee_rate_sat_per_kwu.max(params.min_fee_rate.to_sat_per_kwu());
+		let required_fee_sats =
+			(fee_rate_sat_per_kwu.saturating_mul(original_weight + input_weight) + 999) / 1000;
+		let additional_fee_sats = required_fee_sats.saturating_sub(original_fee_sats);
+
+		let sender_contribution_sats = match params.additional_fee_output_index {
+			Some(index) if index != receiver_output_index => {
+				let fee_output = original_tx
+					.output
+					.get(index)
+					.ok_or_else(|| reject("additional fee output index is out of bounds"))?;
+				let available_sats = fee_output
+					.value
+					.to_sat()
+					.saturating_sub(fee_output.script_pubkey.minimal_non_dust().to_sat());
+				additional_fee_sats
+					.min(params.max_additional_fee_contribution_sats)
+					.min(available_sats)
+			},
+			Some(_) => return Err(reject("additional fee output must not be the receiver's")),
+			None => 0,
+		};
+		let receiver_contribution_sats = additional_fee_sats - sender_contribution_sats;
+
+		let receiver_output_value_sats = receiver_output
+			.value
+			.to_sat()
+			.saturating_add(txout.value.to_sat())
+			.checked_sub(receiver_contribution_sats)
+			.filter(|v| *v >= receiver_output.script_pubkey.minimal_non_dust().to_sat())
+			.ok_or(ReceiveError::NotEnoughMoney)?;
+
+		let mut proposal = original_psbt.clone();
+		proposal.unsigned_tx.output[receiver_output_index].value =
+			bitcoin::Amount::from_sat(receiver_output_value_sats);
+		if let Some(index) = params.additional_fee_output_index {
+			let fee_output = &mut proposal.unsigned_tx.output[index];
+			fee_output.value -= bitcoin::Amount::from_sat(sender_contribution_sats);
+		}
+
+		let receiver_input_index = thread_rng().gen_range(0..=proposal.inputs.len());
+		let txin = TxIn {
+			previous_output: outpoint,
+			script_sig: ScriptBuf::new(),
+			sequence: original_tx.input[0].sequence,
+			witness: Witness::new(),
+		};
+		proposal.unsigned_tx.input.insert(receiver_input_index, txin);
+		let psbt_input = PsbtInput {
+			witness_utxo: Some(txout),
+			non_witness_utxo: Some(prev_tx),
+			..Default::default()
+		};
+		proposal.inputs.insert(receiver_input_index, psbt_input);
+
+		match self.wallet.sign_owned_inputs(&mut proposal) {
+			Ok(true) => {},
+			Ok(false) | Err(_) => {
+				log_error!(self.logger, "Failed to sign payjoin proposal.");
+				return Err(ReceiveError::Unavailable);
+			},
+		}
+
+		// Strip everything the sender doesn't need to know or needs to fill in themselves.
+		proposal.xpub.clear();
+		for (index, input) in proposal.inputs.iter_mut().enumerate() {
+			input.bip32_derivation.clear();
+			input.tap_key_origins.clear();
+			if index != receiver_input_index {
+				input.final_script_sig = None;
+				input.final_script_witness = None;
+				input.witness_utxo = None;
+				input.non_witness_utxo = None;
+			}
+		}
+		for output in proposal.outputs.iter_mut() {
+			output.bip32_derivation.clear();
+			output.tap_key_origins.clear();
+		}
+
+		Ok(proposal)
+	}
+}
+
+// A payjoin proposal we responded to a sender's request with.
+struct PayjoinProposal {
+	psbt: Psbt,
+	original_tx: Transaction,
+	receiver_script: ScriptBuf,
+	expected_payment: ExpectedPayment,
+}
+
+/// Parses the given payjoin endpoint, checking it uses HTTPS or refers to an onion service as
+/// required by BIP 78.
+///
+/// Plain HTTP is also allowed for loopback hosts as the traffic never leaves the machine.
+pub(crate) fn parse_endpoint_url(url: &str) -> Option<Url> {
+	let url = Url::parse(url).ok()?;
+	let host = url.host_str()?;
+	let is_loopback = host == "localhost"
+		|| host
+			.trim_start_matches('[')
+			.trim_end_matches(']')
+			.parse::<std::net::IpAddr>()
+			.map_or(false, |ip| ip.is_loopback());
+	match url.scheme() {
+		"https" => Some(url),
+		"http" if host.ends_with(".onion") || is_loopback => Some(url),
+		_ => None,
+	}
+}
+
+/// Determines the parameters we pass to the receiver along with the given original PSBT paying
+/// to the given script.
+pub(crate) fn sender_params<F: Fn(&Script) -> bool>(
+	original_psbt: &Psbt, payee_script: &Script, is_mine: &F,
+) -> Result<PayjoinParams, String> {
+	let original_fee_sats = psbt_fee_sats(original_psbt)?;
+	let original_weight = original_psbt.clone().extract_tx_unchecked_fee_rate().weight().to_wu();
+	let fee_rate = FeeRate::from_sat_per_kwu(original_fee_sats * 1000 / original_weight.max(1));
+
+	// We allow the receiver to deduct the fee for a single input of our type from our change.
+	let sender_script =
+		&original_psbt.spend_utxo(0).map_err(|_| "missing UTXO information")?.script_pubkey;
+	let input_weight = input_weight(sender_script).ok_or("unsupported input type")?;
+	let additional_fee_output_index = original_psbt
+		.unsigned_tx
+		.output
+		.iter()
+		.position(|o| o.script_pubkey.as_script() != payee_script && is_mine(&o.script_pubkey));
+	let max_additional_fee_contribution_sats = if additional_fee_output_index.is_some() {
+		(original_fee_sats * input_weight + original_weight - 1) / original_weight.max(1)
+	} else {
+		0
+	};
+
+	Ok(PayjoinParams {
+		additional_fee_output_index,
+		max_additional_fee_contribution_sats,
+		// We never allow the receiver to substitute their output as we couldn't verify it.
+		disable_output_substitution: true,
+		min_fee_rate: fee_rate,
+	})
+}
+
+/// Sends the given original PSBT to the receiver's endpoint, returning the proposal it responds
+/// with.
+pub(crate) async fn request_proposal(
+	endpoint_url: Url, original_psbt: &Psbt, params: &PayjoinParams,
+) -> Result<Psbt, String> {
+	let mut url = endpoint_url;
+	// Append our parameters to any the receiver may have included in the endpoint URL.
+	url.query_pairs_mut().extend_pairs(params.to_query_pairs(1));
+
+	let client = reqwest::Client::builder()
+		.timeout(Duration::from_secs(PAYJOIN_REQUEST_TIMEOUT_SECS))
+		.build()
+		.map_err(|e| e.to_string())?;
+	let response = client
+		.post(url)
+		.header("Content-Type", "text/plain")
+		.body(BASE64_STANDARD.encode(original_psbt.serialize()))
+		.send()
+		.await
+		.map_err(|e| e.to_string())?;
+
+	let status = response.status();
+	let body = response.text().await.map_err(|e| e.to_string())?;
+	if !status.is_success() {
+		return Err(format!("receiver responded with status {}: {}", status, body));
+	}
+
+	parse_proposal(&body)
+}
+
+// Parses the receiver's response to our request, which is either the proposal PSBT or an error.
+fn parse_proposal(response: &str) -> Result<Psbt, String> {
+	match BASE64_STANDARD.decode(response.trim()) {
+		Ok(bytes) => Psbt::deserialize(&bytes).map_err(|e| e.to_string()),
+		Err(_) => Err(format!("receiver responded with error: {}", response)),
+	}
+}
+
+/// Checks the receiver's proposal against the original PSBT we sent, making sure it doesn't
+/// make us pay more than we allowed for.
+pub(crate) fn check_proposal<F: Fn(&Script) -> bool>(
+	original_psbt: &Psbt, proposal: &Psbt, params: &PayjoinParams, is_mine: &F,
+) -> Result<(), String> {
+	let original_tx = &original_psbt.unsigned_tx;
+	let proposal_tx = &proposal.unsigned_tx;
+
+	if proposal_tx.version != original_tx.version || proposal_tx.lock_time != original_tx.lock_time
+	{
+		return Err("version or lock time was changed".to_string());
+	}
+	if proposal_tx.input.len() != proposal.inputs.len()
+		|| proposal_tx.output.len() != proposal.outputs.len()
+	{
+		return Err("malformed PSBT".to_string());
+	}
+
+	let sender_script =
+		&original_psbt.spend_utxo(0).map_err(|_| "missing UTXO information")?.script_pubkey;
+	let mut original_inputs_found = 0;
+	for (index, (txin, input)) in proposal_tx.input.iter().zip(proposal.inputs.iter()).enumerate() {
+		match original_tx.input.iter().find(|i| i.previous_output == txin.previous_output) {
+			Some(original_txin) => {
+				original_inputs_found += 1;
+				if txin.sequence != original_txin.sequence {
+					return Err("sequence of our input was changed".to_string());
+				}
+				if !input.bip32_derivation.is_empty()
+					|| !input.tap_key_origins.is_empty()
+					|| !input.partial_sigs.is_empty()
+					|| input.tap_key_sig.is_some()
+				{
+					return Err("our inputs must not have key paths or signatures".to_string());
+				}
+			},
+			None => {
+				if input.final_script_sig.is_none() && input.final_script_witness.is_none() {
+					return Err("receiver inputs need to be finalized".to_string());
+				}
+				let utxo = proposal
+					.spend_utxo(index)
+					.map_err(|_| "receiver inputs need to have UTXO information")?;
+				if is_mine(&utxo.script_pubkey) {
+					return Err("receiver must not spend our coins".to_string());
+				}
+				if input_weight(&utxo.script_pubkey) != input_weight(sender_script) {
+					return Err("receiver inputs need to be of the same type as ours".to_string());
+				}
+				if txin.sequence != original_tx.input[0].sequence {
+					return Err(
+						"receiver inputs need to have the same sequence as ours".to_string()
+					);
+				}
+			},
+		}
+	}
+	if original_inputs_found != original_tx.input.len() {
+		return Err("some of our inputs are missing".to_string());
+	}
+
+	for (index, original_output) in original_tx.output.iter().enumerate() {
+		let output = proposal_tx
+			.output
+			.iter()
+			.find(|o| o.script_pubkey == original_output.script_pubkey)
+			.ok_or("some of the original outputs are missing")?;
+		let min_value = if Some(index) == params.additional_fee_output_index {
+			original_output
+				.value
+				.to_sat()
+				.saturating_sub(params.max_additional_fee_contribution_sats)
+		} else {
+			original_output.value.to_sat()
+		};
+		if output.value.to_sat() < min_value {
+			return Err("value of an original output was reduced".to_string());
+		}
+	}
+
+	// Finally make sure we don't spend more than we allowed for.
+	let original_input_sats = (0..original_psbt.inputs.len())
+		.map(|i| original_psbt.spend_utxo(i).map(|u| u.value.to_sat()))
+		.sum::<Result<u64, _>>()
+		.map_err(|_| "missing UTXO information")?;
+	let our_output_sats = |tx: &Transaction| {
+		tx.output
+			.iter()
+			.filter(|o| is_mine(&o.script_pubkey))
+			.map(|o| o.value.to_sat())
+			.sum::<u64>()
+	};
+	let original_spent_sats = original_input_sats.saturating_sub(our_output_sats(original_tx));
+	let proposal_spent_sats = original_input_sats.saturating_sub(our_output_sats(proposal_tx));
+	if proposal_spent_sats
+		> original_spent_sats.saturating_add(params.max_additional_fee_contribution_sats)
+	{
+		return Err("fee contribution exceeds the allowed maximum".to_string());
+	}
+
+	Ok(())
+}
+
+/// Fills in the UTXO information of our inputs the receiver stripped from its proposal.
+pub(crate) fn restore_sender_inputs(original_psbt: &Psbt, proposal: &mut Psbt) {
+	for (txin, input) in proposal.unsigned_tx.input.iter().zip(proposal.inputs.iter_mut()) {
+		let original_index = original_psbt
+			.unsigned_tx
+			.input
+			.iter()
+			.position(|i| i.previous_output == txin.previous_output);
+		if let Some(original_index) = original_index {
+			let original_input = &original_psbt.inputs[original_index];
+			input.witness_utxo = original_input.witness_utxo.clone();
+			input.non_witness_utxo = original_input.non_witness_utxo.clone();
+		}
+	}
+}
+
+/// Checks the fully signed payjoin transaction pays at least the given fee rate.
+pub(crate) fn check_fee_rate(signed_proposal: &Psbt, min_fee_rate: FeeRate) -> Result<(), String> {
+	let fee_sats = psbt_fee_sats(signed_proposal)?;
+	let weight = signed_proposal.clone().extract_tx_unchecked_fee_rate().weight().to_wu();
+	if fee_sats.saturating_mul(1000) < min_fee_rate.to_sat_per_kwu().saturating_mul(weight) {
+		return Err("fee rate of the payjoin transaction is too low".to_string());
+	}
+	Ok(())
+}
+
+fn psbt_fee_sats(psbt: &Psbt) -> Result<u64, String> {
+	let input_sats = (0..psbt.inputs.len())
+		.map(|i| psbt.spend_utxo(i).map(|u| u.value.to_sat()))
+		.sum::<Result<u64, _>>()
+		.map_err(|_| "missing UTXO information")?;
+	let output_sats = psbt.unsigned_tx.output.iter().map(|o| o.value.to_sat()).sum::<u64>();
+	input_sats.checked_sub(output_sats).ok_or_else(|| "negative fee".to_string())
+}
+
+fn input_weight(script_pubkey: &Script) -> Option<u64> {
+	if script_pubkey.is_p2wpkh() {
+		Some(P2WPKH_INPUT_WEIGHT)
+	} else if script_pubkey.is_p2tr() {
+		Some(P2TR_INPUT_WEIGHT)
+	} else {
+		None
+	}
+}
+
+struct HttpRequest {
+	method: String,
+	target: String,
+	body: Vec<u8>,
+}
+
+async fn read_http_request(stream: &mut TcpStream) -> Result<HttpRequest, std::io::Error> {
+	let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+	let mut buf = Vec::new();
+	let mut chunk = [0u8; 4096];
+	let header_len = loop {
+		if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+			break pos + 4;
+		}
+		if buf.len() > MAX_REQUEST_SIZE {
+			return Err(invalid("request too large"));
+		}
+		let read = stream.read(&mut chunk).await?;
+		if read == 0 {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+		buf.extend_from_slice(&chunk[..read]);
+	};
+
+	let head = std::str::from_utf8(&buf[..header_len]).map_err(|_| invalid("invalid header"))?;
+	let mut lines = head.split("\r\n");
+	let mut request_line = lines.next().unwrap_or_default().split(' ');
+	let method = request_line.next().unwrap_or_default().to_string();
+	let target = request_line.next().ok_or_else(|| invalid("invalid request line"))?.to_string();
+
+	let mut content_length = 0;
+	for (name, value) in lines.filter_map(|line| line.split_once(':')) {
+		if name.trim().eq_ignore_ascii_case("content-length") {
+			content_length = value.trim().parse().map_err(|_| invalid("invalid length"))?;
+		}
+	}
+	if content_length > MAX_REQUEST_SIZE {
+		return Err(invalid("request too large"));
+	}
+
+	let mut body = buf.split_off(header_len);
+	while body.len() < content_length {
+		let read = stream.read(&mut chunk).await?;
+		if read == 0 {
+			return Err(std::io::ErrorKind::UnexpectedEof.into());
+		}
+		body.extend_from_slice(&chunk[..read]);
+	}
+	body.truncate(content_length);
+
+	Ok(HttpRequest { method, target, body })
+}
+
+fn unix_time_secs() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)).as_secs()
+}
+
+fn http_response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+	let head = format!(
+		"HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+		status,
+		content_type,
+		body.len(),
+	);
+	[head.as_bytes(), body].concat()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::absolute::LockTime;
+	use bitcoin::hashes::Hash;
+	use bitcoin::transaction::Version;
+	use bitcoin::{Amount, OutPoint, Sequence, TxOut, Txid, WPubkeyHash};
+
+	fn p2wpkh_script(byte: u8) -> ScriptBuf {
+		ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([byte; 20]))
+	}
+
+	fn txin(byte: u8) -> TxIn {
+		TxIn {
+			previous_output: OutPoint { txid: Txid::from_byte_array([byte; 32]), vout: 0 },
+			script_sig: ScriptBuf::new(),
+			sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+			witness: Witness::new(),
+		}
+	}
+
+	fn finalized_input(script_pubkey: ScriptBuf, value_sats: u64) -> PsbtInput {
+		let mut witness = Witness::new();
+		witness.push([1u8; 72]);
+		witness.push([2u8; 33]);
+		PsbtInput {
+			witness_utxo: Some(TxOut { value: Amount::from_sat(value_sats), script_pubkey }),
+			final_script_witness: Some(witness),
+			..Default::default()
+		}
+	}
+
+	// Returns an original PSBT spending a 100_000 sat UTXO of ours to pay 50_000 sats to the
+	// receiver, with 49_000 sats going back to our change output.
+	fn original_psbt() -> Psbt {
+		let tx = Transaction {
+			version: Version::TWO,
+			lock_time: LockTime::ZERO,
+			input: vec![txin(1)],
+			output: vec![
+				TxOut { value: Amount::from_sat(50_000), script_pubkey: p2wpkh_script(2) },
+				TxOut { value: Amount::from_sat(49_000), script_pubkey: p2wpkh_script(1) },
+			],
+		};
+		let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+		psbt.inputs[0] = finalized_input(p2wpkh_script(1), 100_000);
+		psbt
+	}
+
+	// Returns a proposal adding a 20_000 sat receiver input to the given original PSBT, with the
+	// receiver output increased by the given amount and our change reduced by the given amount.
+	fn proposal(original_psbt: &Psbt, receiver_output_sats: u64, change_sats: u64) -> Psbt {
+		let mut proposal = original_psbt.clone();
+		proposal.unsigned_tx.input.push(txin(3));
+		proposal.inputs.push(finalized_input(p2wpkh_script(3), 20_000));
+		proposal.unsigned_tx.output[0].value = Amount::from_sat(receiver_output_sats);
+		proposal.unsigned_tx.output[1].value = Amount::from_sat(change_sats);
+		proposal.inputs[0] = PsbtInput::default();
+		proposal
+	}
+
+	#[test]
+	fn expected_payments_are_bounded() {
+		let mut expected_payments = ExpectedPayments::default();
+		let script = |i: usize| ScriptBuf::new_p2wpkh(&WPubkeyHash::hash(&i.to_be_bytes()));
+		for i in 0..PAYJOIN_MAX_EXPECTED_PAYMENTS {
+			let payment = ExpectedPayment {
+				amount_sats: 1_000,
+				expires_at_secs: 1_000 + i as u64,
+				directory_session: None,
+			};
+			expected_payments.insert(script(i), payment);
+		}
+
+		// Once full, we stop expecting the payment expiring first.
+		let payment =
+			ExpectedPayment { amount_sats: 1_000, expires_at_secs: 5_000, directory_session: None };
+		expected_payments.insert(script(PAYJOIN_MAX_EXPECTED_PAYMENTS), payment.clone());
+		assert_eq!(expected_payments.payments.len(), PAYJOIN_MAX_EXPECTED_PAYMENTS);
+		assert!(!expected_payments.payments.contains_key(&script(0)));
+		assert!(expected_payments.payments.contains_key(&script(1)));
+
+		// Payments expire, and are dropped once paid via a regular transaction.
+		let paid_scripts = [script(PAYJOIN_MAX_EXPECTED_PAYMENTS)].into_iter().collect();
+		expected_payments.prune(1_500, &paid_scripts);
+		assert_eq!(expected_payments.payments.len(), PAYJOIN_MAX_EXPECTED_PAYMENTS - 501);
+		assert!(expected_payments
+			.payments
+			.keys()
+			.all(|s| { expected_payments.payments[s].expires_at_secs > 1_500 }));
+
+		// The payment is taken once we receive a request for it.
+		let tx = Transaction {
+			version: Version::TWO,
+			lock_time: LockTime::ZERO,
+			input: vec![txin(1)],
+			output: vec![TxOut { value: Amount::from_sat(1_000), script_pubkey: script(600) }],
+		};
+		let (script_pubkey, _) = expected_payments.take_paid_by(&tx).unwrap();
+		assert_eq!(script_pubkey, script(600));
+		assert!(expected_payments.take_paid_by(&tx).is_none());
+	}
+
+	#[test]
+	fn params_are_passed_to_receiver() {
+		let params = PayjoinParams {
+			additional_fee_output_index: Some(1),
+			max_additional_fee_contribution_sats: 182,
+			disable_output_substitution: true,
+			min_fee_rate: FeeRate::from_sat_per_vb(2).unwrap(),
+		};
+		let mut url = parse_endpoint_url("https://example.com/payjoin?foo=bar").unwrap();
+		url.query_pairs_mut().extend_pairs(params.to_query_pairs(1));
+		assert_eq!(PayjoinParams::from_query(url.query().unwrap()), Ok(params));
+
+		assert_eq!(
+			PayjoinParams::from_query("v=3&minfeerate=1.000"),
+			Err(ReceiveError::VersionUnsupported)
+		);
+
+		assert!(parse_endpoint_url("http://example.com/payjoin").is_none());
+		assert!(parse_endpoint_url("http://example.onion/payjoin").is_some());
+		assert!(parse_endpoint_url("http://127.0.0.1:8080/payjoin").is_some());
+		assert!(parse_endpoint_url("ftp://example.com/payjoin").is_none());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn proposals_are_exchanged_via_directory() {
+		let stand_in = directory::stand_in::StandIn::start().await;
+		let directory = DirectoryClient::new(&stand_in.config()).unwrap();
+		directory.refresh_ohttp_keys().await.unwrap();
+
+		// The receiver hands out the endpoint of a new mailbox.
+		let receiver_sk = hpke::random_secret_key();
+		let receiver_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &receiver_sk);
+		let endpoint = directory.endpoint(receiver_key, unix_time_secs() + 600).unwrap();
+		let endpoint = directory::DirectoryEndpoint::from_url(&endpoint.to_url()).unwrap();
+
+		let is_mine = |spk: &Script| spk == p2wpkh_script(1).as_script();
+		let original_psbt = original_psbt();
+		let params = sender_params(&original_psbt, &p2wpkh_script(2), &is_mine).unwrap();
+		let relay_url = stand_in.url.clone();
+		let sender = {
+			let (original_psbt, params) = (original_psbt.clone(), params.clone());
+			tokio::spawn(async move {
+				directory::request_proposal(&relay_url, &endpoint, &original_psbt, &params).await
+			})
+		};
+
+		// The receiver picks up the request from its mailbox, and responds with its proposal.
+		let request = loop {
+			if let Some(request) = directory.poll_request(&receiver_key).await.unwrap() {
+				break request;
+			}
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		};
+		let (request, reply_key) = directory::decrypt_message_a(&request, &receiver_sk).unwrap();
+		let (request_psbt, query) = directory::split_request(&request).unwrap();
+		assert_eq!(PayjoinParams::from_query(query), Ok(params.clone()));
+		assert_eq!(BASE64_STANDARD.decode(request_psbt).unwrap(), original_psbt.serialize());
+
+		let max_contribution_sats = params.max_additional_fee_contribution_sats;
+		let proposal = proposal(&original_psbt, 70_000, 49_000 - max_contribution_sats);
+		let response = BASE64_STANDARD.encode(proposal.serialize());
+		directory.respond(&receiver_sk, &reply_key, response.as_bytes()).await.unwrap();
+		assert_eq!(sender.await.unwrap(), Ok(proposal));
+
+		// Errors the receiver responds with are passed on to the sender.
+		let endpoint = directory.endpoint(receiver_key, unix_time_secs() + 600).unwrap();
+		let relay_url = stand_in.url.clone();
+		let sender = {
+			let (original_psbt, params) = (original_psbt.clone(), params.clone());
+			tokio::spawn(async move {
+				directory::request_proposal(&relay_url, &endpoint, &original_psbt, &params).await
+			})
+		};
+		let request = loop {
+			let request = directory.poll_request(&receiver_key).await.unwrap().unwrap();
+			if directory::decrypt_message_a(&request, &receiver_sk).unwrap().1 != reply_key {
+				break request;
+			}
+			tokio::time::sleep(Duration::from_millis(100)).await;
+		};
+		let (_, reply_key) = directory::decrypt_message_a(&request, &receiver_sk).unwrap();
+		let response = ReceiveError::Unavailable.to_json();
+		directory.respond(&receiver_sk, &reply_key, response.as_bytes()).await.unwrap();
+		assert!(sender.await.unwrap().unwrap_err().contains("unavailable"));
+
+		// Expired endpoints aren't even tried.
+		let endpoint = directory.endpoint(receiver_key, unix_time_secs() - 1).unwrap();
+		let res =
+			directory::request_proposal(&stand_in.url, &endpoint, &original_psbt, &params).await;
+		assert_eq!(res, Err("payjoin endpoint expired".to_string()));
+	}
+
+	#[test]
+	fn proposals_are_checked() {
+		let is_mine = |spk: &Script| spk == p2wpkh_script(1).as_script();
+		let original_psbt = original_psbt();
+		let params = sender_params(&original_psbt, &p2wpkh_script(2), &is_mine).unwrap();
+		assert_eq!(params.additional_fee_output_index, Some(1));
+		assert!(params.max_additional_fee_contribution_sats > 0);
+		let max_contribution_sats = params.max_additional_fee_contribution_sats;
+
+		// The receiver may deduct up to the maximum contribution from our change.
+		let valid = proposal(&original_psbt, 70_000, 49_000 - max_contribution_sats);
+		assert_eq!(check_proposal(&original_psbt, &valid, &params, &is_mine), Ok(()));
+
+		let mut restored = valid.clone();
+		restore_sender_inputs(&original_psbt, &mut restored);
+		assert_eq!(restored.inputs[0].witness_utxo, original_psbt.inputs[0].witness_utxo);
+
+		// But not more than that.
+		let too_expensive = proposal(&original_psbt, 70_000, 49_000 - max_contribution_sats - 1);
+		assert!(check_proposal(&original_psbt, &too_expensive, &params, &is_mine).is_err());
+
+		// The receiver must not reduce its own output as we disabled output substitution.
+		let reduced_payment = proposal(&original_psbt, 49_999, 49_000);
+		assert!(check_proposal(&original_psbt, &reduced_payment, &params, &is_mine).is_err());
+
+		// The receiver must not sign for our inputs.
+		let mut signed_for_us = valid.clone();
+		signed_for_us.inputs[0] = finalized_input(p2wpkh_script(1), 100_000);
+		signed_for_us.inputs[0].final_script_witness = None;
+		signed_for_us.inputs[0].tap_key_sig =
+			Some(bitcoin::taproot::Signature::from_slice(&[1u8; 64]).unwrap());
+		assert!(check_proposal(&original_psbt, &signed_for_us, &params, &is_mine).is_err());
+
+		// The receiver must not spend our coins.
+		let mut spending_ours = valid.clone();
+		spending_ours.inputs[1] = finalized_input(p2wpkh_script(1), 20_000);
+		assert!(check_proposal(&original_psbt, &spending_ours, &params, &is_mine).is_err());
+
+		// The receiver inputs need to be finalized.
+		let mut unfinalized = valid;
+		unfinalized.inputs[1].final_script_witness = None;
+		assert!(check_proposal(&original_psbt, &unfinalized, &params, &is_mine).is_err());
+	}
+}
@@ -0,0 +1,378 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Oblivious HTTP as specified by [RFC 9458], which [BIP 77] uses to keep payjoin directories
+//! from learning the IP addresses of their clients. The encapsulated requests and responses are
+//! encoded as known-length [RFC 9292] binary HTTP messages.
+//!
+//! [RFC 9458]: https://www.rfc-editor.org/rfc/rfc9458.html
+//! [RFC 9292]: https://www.rfc-editor.org/rfc/rfc9292.html
+//! [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+
+use super::hpke::{self, AEAD_ID, KDF_ID, KEM_ID, N_K, N_N, N_T};
+
+use bitcoin::secp256k1::PublicKey;
+use reqwest::Url;
+
+// The size every encapsulated request is padded to, so that requests don't reveal the size of
+// their content to the relay.
+const ENCAPSULATED_MESSAGE_BYTES: usize = 8192;
+
+// The size of the header identifying the key configuration and algorithms used.
+const REQUEST_HEADER_BYTES: usize = 7;
+
+// The size of an uncompressed public key, which is how the encapsulated key is serialized.
+const UNCOMPRESSED_PUBLIC_KEY_BYTES: usize = 65;
+
+// The size of the nonce preceding encapsulated responses.
+const RESPONSE_NONCE_BYTES: usize = if N_K > N_N { N_K } else { N_N };
+
+const REQUEST_LABEL: &[u8] = b"message/bhttp request";
+const RESPONSE_LABEL: &[u8] = b"message/bhttp response";
+
+// The framing indicators of known-length binary HTTP messages.
+const KNOWN_LENGTH_REQUEST: u64 = 0;
+const KNOWN_LENGTH_RESPONSE: u64 = 1;
+
+/// The key configuration of an OHTTP gateway, i.e., the key we encrypt requests to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KeyConfig {
+	pub key_id: u8,
+	pub public_key: PublicKey,
+}
+
+impl KeyConfig {
+	/// Parses the given `application/ohttp-keys` response, returning the first key configuration
+	/// supporting the cipher suite we use.
+	pub(crate) fn from_ohttp_keys(bytes: &[u8]) -> Option<Self> {
+		let mut reader = Reader(bytes);
+		let mut key_configs = Vec::new();
+		while !reader.0.is_empty() {
+			let len = match reader.read_u16() {
+				Some(len) => len as usize,
+				None => break,
+			};
+			match reader.read_bytes(len) {
+				Some(key_config) => key_configs.push(key_config),
+				None => break,
+			}
+		}
+		if !reader.0.is_empty() {
+			// Some gateways serve their only key configuration without a length prefix.
+			return Self::from_slice(bytes);
+		}
+		key_configs.into_iter().find_map(Self::from_slice)
+	}
+
+	fn from_slice(bytes: &[u8]) -> Option<Self> {
+		let mut reader = Reader(bytes);
+		let key_id = reader.read_bytes(1)?[0];
+		if reader.read_u16()? != KEM_ID {
+			return None;
+		}
+		let public_key =
+			PublicKey::from_slice(reader.read_bytes(UNCOMPRESSED_PUBLIC_KEY_BYTES)?).ok()?;
+		let algorithms_len = reader.read_u16()? as usize;
+		let mut algorithms = Reader(reader.read_bytes(algorithms_len)?);
+		while let (Some(kdf_id), Some(aead_id)) = (algorithms.read_u16(), algorithms.read_u16()) {
+			if kdf_id == KDF_ID && aead_id == AEAD_ID {
+				return Some(Self { key_id, public_key });
+			}
+		}
+		None
+	}
+
+	/// Serializes the key configuration as an `application/ohttp-keys` response.
+	#[cfg(test)]
+	pub(crate) fn to_ohttp_keys(self) -> Vec<u8> {
+		let mut key_config = vec![self.key_id];
+		key_config.extend_from_slice(&KEM_ID.to_be_bytes());
+		key_config.extend_from_slice(&self.public_key.serialize_uncompressed());
+		key_config.extend_from_slice(&4u16.to_be_bytes());
+		key_config.extend_from_slice(&KDF_ID.to_be_bytes());
+		key_config.extend_from_slice(&AEAD_ID.to_be_bytes());
+		[&(key_config.len() as u16).to_be_bytes()[..], &key_config].concat()
+	}
+
+	fn request_header(&self) -> [u8; REQUEST_HEADER_BYTES] {
+		let mut header = [self.key_id, 0, 0, 0, 0, 0, 0];
+		header[1..3].copy_from_slice(&KEM_ID.to_be_bytes());
+		header[3..5].copy_from_slice(&KDF_ID.to_be_bytes());
+		header[5..7].copy_from_slice(&AEAD_ID.to_be_bytes());
+		header
+	}
+}
+
+/// The state we need to decapsulate the response to a request we encapsulated.
+pub(crate) struct ResponseContext {
+	enc: [u8; UNCOMPRESSED_PUBLIC_KEY_BYTES],
+	secret: Vec<u8>,
+}
+
+impl ResponseContext {
+	/// Decapsulates the given response, returning its status code and body.
+	pub(crate) fn decapsulate_response(&self, bytes: &[u8]) -> Option<(u16, Vec<u8>)> {
+		if bytes.len() < RESPONSE_NONCE_BYTES + N_T {
+			return None;
+		}
+		let (response_nonce, ciphertext) = bytes.split_at(RESPONSE_NONCE_BYTES);
+		let (key, nonce) = response_key_and_nonce(&self.enc, &self.secret, response_nonce);
+		let bhttp = hpke::aead_open(&key, nonce, b"", ciphertext)?;
+		decode_bhttp_response(&bhttp)
+	}
+}
+
+/// Encapsulates an HTTP request with the given method, URL, and body to the gateway with the
+/// given key configuration, returning the encapsulated request along with the context needed to
+/// decapsulate the response.
+pub(crate) fn encapsulate_request(
+	key_config: &KeyConfig, method: &str, url: &Url, body: &[u8],
+) -> (Vec<u8>, ResponseContext) {
+	let header = key_config.request_header();
+	let info = [REQUEST_LABEL, &[0u8], &header].concat();
+	let (ephemeral_pk, mut ctx) = hpke::setup_base_sender(&key_config.public_key, &info);
+	let enc = ephemeral_pk.serialize_uncompressed();
+
+	let mut bhttp = encode_bhttp_request(method, url, body);
+	let padded_len =
+		ENCAPSULATED_MESSAGE_BYTES - (REQUEST_HEADER_BYTES + UNCOMPRESSED_PUBLIC_KEY_BYTES + N_T);
+	if bhttp.len() < padded_len {
+		bhttp.resize(padded_len, 0);
+	}
+	let ciphertext = ctx.seal(b"", &bhttp);
+
+	let secret = ctx.export(RESPONSE_LABEL, RESPONSE_NONCE_BYTES);
+	let request = [&header[..], &enc, &ciphertext].concat();
+	(request, ResponseContext { enc, secret })
+}
+
+fn response_key_and_nonce(
+	enc: &[u8], secret: &[u8], response_nonce: &[u8],
+) -> ([u8; N_K], [u8; N_N]) {
+	let salt = [enc, response_nonce].concat();
+	let prk = hpke::hkdf_extract(&salt, secret);
+	let mut key = [0u8; N_K];
+	key.copy_from_slice(&hpke::hkdf_expand(&prk, b"key", N_K));
+	let mut nonce = [0u8; N_N];
+	nonce.copy_from_slice(&hpke::hkdf_expand(&prk, b"nonce", N_N));
+	(key, nonce)
+}
+
+fn encode_bhttp_request(method: &str, url: &Url, body: &[u8]) -> Vec<u8> {
+	let authority = match url.port() {
+		Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+		None => url.host_str().unwrap_or_default().to_string(),
+	};
+	let path = match url.query() {
+		Some(query) => format!("{}?{}", url.path(), query),
+		None => url.path().to_string(),
+	};
+
+	let mut bhttp = Vec::new();
+	write_varint(&mut bhttp, KNOWN_LENGTH_REQUEST);
+	for field in [method, url.scheme(), &authority, &path] {
+		write_length_prefixed(&mut bhttp, field.as_bytes());
+	}
+	// We don't send any header fields.
+	write_varint(&mut bhttp, 0);
+	write_length_prefixed(&mut bhttp, body);
+	// Nor any trailer fields.
+	write_varint(&mut bhttp, 0);
+	bhttp
+}
+
+fn decode_bhttp_response(bytes: &[u8]) -> Option<(u16, Vec<u8>)> {
+	let mut reader = Reader(bytes);
+	if reader.read_varint()? != KNOWN_LENGTH_RESPONSE {
+		return None;
+	}
+	loop {
+		let status = u16::try_from(reader.read_varint()?).ok()?;
+		// Skip the header fields.
+		reader.read_length_prefixed()?;
+		// Informational responses precede the final response.
+		if !(100..200).contains(&status) {
+			let body = reader.read_length_prefixed()?.to_vec();
+			return Some((status, body));
+		}
+	}
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: u64) {
+	if value < 1 << 6 {
+		buf.push(value as u8);
+	} else if value < 1 << 14 {
+		buf.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes());
+	} else if value < 1 << 30 {
+		buf.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes());
+	} else {
+		buf.extend_from_slice(&(value | 0xc000_0000_0000_0000).to_be_bytes());
+	}
+}
+
+fn write_length_prefixed(buf: &mut Vec<u8>, bytes: &[u8]) {
+	write_varint(buf, bytes.len() as u64);
+	buf.extend_from_slice(bytes);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+	fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+		if self.0.len() < len {
+			return None;
+		}
+		let (bytes, rest) = self.0.split_at(len);
+		self.0 = rest;
+		Some(bytes)
+	}
+
+	fn read_u16(&mut self) -> Option<u16> {
+		let bytes = self.read_bytes(2)?;
+		Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+	}
+
+	fn read_varint(&mut self) -> Option<u64> {
+		let first = *self.0.first()?;
+		let len = 1 << (first >> 6);
+		let bytes = self.read_bytes(len)?;
+		let mut value = (first & 0x3f) as u64;
+		for byte in &bytes[1..] {
+			value = (value << 8) | *byte as u64;
+		}
+		Some(value)
+	}
+
+	fn read_length_prefixed(&mut self) -> Option<&'a [u8]> {
+		let len = usize::try_from(self.read_varint()?).ok()?;
+		self.read_bytes(len)
+	}
+}
+
+/// The gateway side of OHTTP, which we only need to stand in for payjoin directories in tests.
+#[cfg(test)]
+pub(crate) mod gateway {
+	use super::*;
+	use bitcoin::secp256k1::SecretKey;
+	use rand::{thread_rng, RngCore};
+
+	// The size every encapsulated response is padded to.
+	const PADDED_BHTTP_RESPONSE_BYTES: usize =
+		ENCAPSULATED_MESSAGE_BYTES - (RESPONSE_NONCE_BYTES + N_T);
+
+	/// A request decapsulated by the gateway.
+	pub(crate) struct Request {
+		pub method: String,
+		pub path: String,
+		pub body: Vec<u8>,
+	}
+
+	/// The state the gateway needs to encapsulate the response to a request.
+	pub(crate) struct ResponseContext(super::ResponseContext);
+
+	impl ResponseContext {
+		pub(crate) fn encapsulate_response(&self, status: u16, body: &[u8]) -> Vec<u8> {
+			let mut bhttp = Vec::new();
+			write_varint(&mut bhttp, KNOWN_LENGTH_RESPONSE);
+			write_varint(&mut bhttp, status as u64);
+			write_varint(&mut bhttp, 0);
+			write_length_prefixed(&mut bhttp, body);
+			write_varint(&mut bhttp, 0);
+			if bhttp.len() < PADDED_BHTTP_RESPONSE_BYTES {
+				bhttp.resize(PADDED_BHTTP_RESPONSE_BYTES, 0);
+			}
+
+			let mut response_nonce = [0u8; RESPONSE_NONCE_BYTES];
+			thread_rng().fill_bytes(&mut response_nonce);
+			let (key, nonce) = response_key_and_nonce(&self.0.enc, &self.0.secret, &response_nonce);
+			[&response_nonce[..], &hpke::aead_seal(&key, nonce, b"", &bhttp)].concat()
+		}
+	}
+
+	pub(crate) fn decapsulate_request(
+		key_config: &KeyConfig, secret_key: &SecretKey, bytes: &[u8],
+	) -> Option<(Request, ResponseContext)> {
+		let header = key_config.request_header();
+		if bytes.len() < REQUEST_HEADER_BYTES + UNCOMPRESSED_PUBLIC_KEY_BYTES
+			|| bytes[..REQUEST_HEADER_BYTES] != header
+		{
+			return None;
+		}
+		let (enc, ciphertext) =
+			bytes[REQUEST_HEADER_BYTES..].split_at(UNCOMPRESSED_PUBLIC_KEY_BYTES);
+		let ephemeral_pk = PublicKey::from_slice(enc).ok()?;
+		let info = [REQUEST_LABEL, &[0u8], &header].concat();
+		let mut ctx = hpke::setup_base_receiver(&ephemeral_pk, secret_key, &info);
+		let bhttp = ctx.open(b"", ciphertext)?;
+
+		let mut reader = Reader(&bhttp);
+		if reader.read_varint()? != KNOWN_LENGTH_REQUEST {
+			return None;
+		}
+		let method = String::from_utf8(reader.read_length_prefixed()?.to_vec()).ok()?;
+		let _scheme = reader.read_length_prefixed()?;
+		let _authority = reader.read_length_prefixed()?;
+		let path = String::from_utf8(reader.read_length_prefixed()?.to_vec()).ok()?;
+		reader.read_length_prefixed()?;
+		let body = reader.read_length_prefixed()?.to_vec();
+
+		let secret = ctx.export(RESPONSE_LABEL, RESPONSE_NONCE_BYTES);
+		let enc = ephemeral_pk.serialize_uncompressed();
+		let response_context = ResponseContext(super::ResponseContext { enc, secret });
+		Some((Request { method, path, body }, response_context))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bitcoin::secp256k1::Secp256k1;
+
+	#[test]
+	fn varints_round_trip() {
+		for value in [0, 37, 63, 64, 15_293, 16_383, 16_384, 494_878_333, 1 << 30, u64::MAX >> 2] {
+			let mut buf = Vec::new();
+			write_varint(&mut buf, value);
+			let mut reader = Reader(&buf);
+			assert_eq!(reader.read_varint(), Some(value));
+			assert!(reader.0.is_empty());
+		}
+
+		// Examples given in RFC 9000, Appendix A.1.
+		let mut buf = Vec::new();
+		write_varint(&mut buf, 15_293);
+		assert_eq!(buf, vec![0x7b, 0xbd]);
+		assert_eq!(Reader(&[0x9d, 0x7f, 0x3e, 0x7d]).read_varint(), Some(494_878_333));
+	}
+
+	#[test]
+	fn requests_and_responses_are_encapsulated() {
+		let secret_key = hpke::random_secret_key();
+		let public_key = PublicKey::from_secret_key(&Secp256k1::signing_only(), &secret_key);
+		let key_config = KeyConfig { key_id: 1, public_key };
+		assert_eq!(KeyConfig::from_ohttp_keys(&key_config.to_ohttp_keys()), Some(key_config));
+		assert_eq!(KeyConfig::from_ohttp_keys(&key_config.to_ohttp_keys()[2..]), Some(key_config));
+
+		let url = Url::parse("https://directory.example:8443/ABCDEF?x=1").unwrap();
+		let (request, response_context) = encapsulate_request(&key_config, "POST", &url, b"body");
+		assert_eq!(request.len(), ENCAPSULATED_MESSAGE_BYTES);
+
+		let (decapsulated, gateway_context) =
+			gateway::decapsulate_request(&key_config, &secret_key, &request).unwrap();
+		assert_eq!(decapsulated.method, "POST");
+		assert_eq!(decapsulated.path, "/ABCDEF?x=1");
+		assert_eq!(decapsulated.body, b"body".to_vec());
+
+		let response = gateway_context.encapsulate_response(202, b"");
+		assert_eq!(response.len(), ENCAPSULATED_MESSAGE_BYTES);
+		assert_eq!(response_context.decapsulate_response(&response), Some((202, Vec::new())));
+
+		// Requests encapsulated to another key can't be decapsulated by the gateway.
+		let other_key = hpke::random_secret_key();
+		assert!(gateway::decapsulate_request(&key_config, &other_key, &request).is_none());
+	}
+}
@@ -0,0 +1,227 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+use crate::hex_utils;
+use crate::io::{
+	PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+	PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, Logger};
+use crate::payment::store::{PaymentStatus, PaymentStore};
+use crate::types::{CustomTlvRecord, DynStore};
+use crate::Error;
+
+use lightning::ln::channelmanager::PaymentId;
+use lightning::util::ser::Writeable;
+use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
+
+use bitcoin::secp256k1::PublicKey;
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+
+/// The information required to re-send an outbound payment that is not part of its
+/// [`PaymentDetails`].
+///
+/// [`PaymentDetails`]: crate::payment::PaymentDetails
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PaymentRetryInfo {
+	pub id: PaymentId,
+	pub target: PaymentRetryTarget,
+}
+
+impl_writeable_tlv_based!(PaymentRetryInfo, {
+	(0, id, required),
+	(2, target, required),
+});
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PaymentRetryTarget {
+	Bolt11 {
+		/// The string representation of the invoice that was paid.
+		invoice: String,
+	},
+	Bolt12Offer {
+		/// The string representation of the offer that was paid.
+		offer: String,
+		/// The amount explicitly given when sending, if any.
+		amount_msat: Option<u64>,
+	},
+	Spontaneous {
+		node_id: PublicKey,
+		custom_tlvs: Vec<CustomTlvRecord>,
+		/// Whether the payment may be split across multiple paths.
+		allow_mpp: bool,
+	},
+}
+
+impl_writeable_tlv_based_enum!(PaymentRetryTarget,
+	(0, Bolt11) => {
+		(0, invoice, required),
+	},
+	(2, Bolt12Offer) => {
+		(0, offer, required),
+		(2, amount_msat, option),
+	},
+	(4, Spontaneous) => {
+		(0, node_id, required),
+		(1, allow_mpp, (default_value, false)),
+		(2, custom_tlvs, optional_vec),
+	}
+);
+
+pub(crate) struct PaymentRetryStore<L: Deref>
+where
+	L::Target: Logger,
+{
+	retry_infos: Mutex<HashMap<PaymentId, PaymentRetryInfo>>,
+	kv_store: Arc<DynStore>,
+	logger: L,
+}
+
+impl<L: Deref> PaymentRetryStore<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		retry_infos: Vec<PaymentRetryInfo>, kv_store: Arc<DynStore>, logger: L,
+	) -> Self {
+		let retry_infos =
+			Mutex::new(HashMap::from_iter(retry_infos.into_iter().map(|info| (info.id, info))));
+		Self { retry_infos, kv_store, logger }
+	}
+
+	pub(crate) fn insert(&self, retry_info: PaymentRetryInfo) -> Result<(), Error> {
+		let mut locked_retry_infos = self.retry_infos.lock().unwrap();
+
+		let store_key = hex_utils::to_string(&retry_info.id.0);
+		let data = retry_info.encode();
+		self.kv_store
+			.write(
+				PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+
+		locked_retry_infos.insert(retry_info.id, retry_info);
+		Ok(())
+	}
+
+	/// Persists the retry info of a payment that was just accepted for sending.
+	///
+	/// As the payment may have already succeeded in the meantime, we check its status only after
+	/// persisting and drop the info again if it won't be needed. Failing to persist is only logged,
+	/// as the payment is in flight at this point and merely can't be retried.
+	pub(crate) fn insert_for_sent_payment(
+		&self, retry_info: PaymentRetryInfo, payment_store: &PaymentStore<L>,
+	) {
+		let id = retry_info.id;
+		if self.insert(retry_info).is_err() {
+			return;
+		}
+
+		if payment_store.get(&id).map_or(false, |p| p.status == PaymentStatus::Succeeded) {
+			let _ = self.remove(&id);
+		}
+	}
+
+	pub(crate) fn get(&self, id: &PaymentId) -> Option<PaymentRetryInfo> {
+		self.retry_infos.lock().unwrap().get(id).cloned()
+	}
+
+	pub(crate) fn remove(&self, id: &PaymentId) -> Result<(), Error> {
+		let mut locked_retry_infos = self.retry_infos.lock().unwrap();
+		if locked_retry_infos.remove(id).is_none() {
+			return Ok(());
+		}
+
+		let store_key = hex_utils::to_string(&id.0);
+		self.kv_store
+			.remove(
+				PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				false,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Removing payment retry data for key {}/{}/{} failed due to: {}",
+					PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+					PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::util::ser::Readable;
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	use std::io::Cursor;
+	use std::str::FromStr;
+
+	#[test]
+	fn retry_info_persistence_round_trip() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let retry_store = PaymentRetryStore::new(Vec::new(), Arc::clone(&store), logger);
+
+		let id = PaymentId([42u8; 32]);
+		let node_id = PublicKey::from_str(
+			"02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+		)
+		.unwrap();
+		let custom_tlvs = vec![CustomTlvRecord { type_num: 65537, value: vec![1, 2, 3] }];
+		let target = PaymentRetryTarget::Spontaneous { node_id, custom_tlvs, allow_mpp: true };
+		let retry_info = PaymentRetryInfo { id, target };
+
+		assert!(retry_store.get(&id).is_none());
+		retry_store.insert(retry_info.clone()).unwrap();
+		assert_eq!(retry_store.get(&id), Some(retry_info.clone()));
+
+		let store_key = hex_utils::to_string(&id.0);
+		let data = store
+			.read(
+				PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+			)
+			.unwrap();
+		let read_info = PaymentRetryInfo::read(&mut Cursor::new(data)).unwrap();
+		assert_eq!(read_info, retry_info);
+
+		retry_store.remove(&id).unwrap();
+		assert!(retry_store.get(&id).is_none());
+		assert!(store
+			.read(
+				PAYMENT_RETRY_INFO_PERSISTENCE_PRIMARY_NAMESPACE,
+				PAYMENT_RETRY_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+			)
+			.is_err());
+	}
+}
@@ -7,28 +7,27 @@
 
 //! Holds a payment handler allowing to send spontaneous ("keysend") payments.
 
-use crate::config::{Config, LDK_PAYMENT_RETRY_TIMEOUT};
+use crate::config::{Config, LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA};
 use crate::error::Error;
 use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::payment::retry::{PaymentRetryInfo, PaymentRetryStore, PaymentRetryTarget};
 use crate::payment::store::{
 	PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus, PaymentStore,
 };
-use crate::payment::SendingParameters;
-use crate::types::{ChannelManager, CustomTlvRecord, KeysManager};
+use crate::payment::{Boostagram, KeysendMessage, SendingParameters};
+use crate::types::{ChannelManager, CustomTlvRecord, Graph, KeysManager};
 
-use lightning::ln::channelmanager::{PaymentId, RecipientOnionFields, Retry, RetryableSendFailure};
+use lightning::ln::channelmanager::{PaymentId, RecipientOnionFields, RetryableSendFailure};
+use lightning::routing::gossip::NodeId;
 use lightning::routing::router::{PaymentParameters, RouteParameters};
 use lightning::sign::EntropySource;
 
-use lightning_types::payment::{PaymentHash, PaymentPreimage};
+use lightning_types::payment::{PaymentHash, PaymentPreimage, PaymentSecret};
 
 use bitcoin::secp256k1::PublicKey;
 
 use std::sync::{Arc, RwLock};
 
-// The default `final_cltv_expiry_delta` we apply when not set.
-const LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA: u32 = 144;
-
 /// A payment handler allowing to send spontaneous ("keysend") payments.
 ///
 /// Should be retrieved by calling [`Node::spontaneous_payment`].
@@ -38,7 +37,9 @@ pub struct SpontaneousPayment {
 	runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
 	channel_manager: Arc<ChannelManager>,
 	keys_manager: Arc<KeysManager>,
+	network_graph: Arc<Graph>,
 	payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+	payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>,
 	config: Arc<Config>,
 	logger: Arc<FilesystemLogger>,
 }
@@ -47,10 +48,20 @@ impl SpontaneousPayment {
 	pub(crate) fn new(
 		runtime: Arc<RwLock<Option<Arc<tokio::runtime::Runtime>>>>,
 		channel_manager: Arc<ChannelManager>, keys_manager: Arc<KeysManager>,
-		payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>, config: Arc<Config>,
+		network_graph: Arc<Graph>, payment_store: Arc<PaymentStore<Arc<FilesystemLogger>>>,
+		payment_retry_store: Arc<PaymentRetryStore<Arc<FilesystemLogger>>>, config: Arc<Config>,
 		logger: Arc<FilesystemLogger>,
 	) -> Self {
-		Self { runtime, channel_manager, keys_manager, payment_store, config, logger }
+		Self {
+			runtime,
+			channel_manager,
+			keys_manager,
+			network_graph,
+			payment_store,
+			payment_retry_store,
+			config,
+			logger,
+		}
 	}
 
 	/// Send a spontaneous aka. "keysend", payment.
@@ -60,7 +71,7 @@ impl SpontaneousPayment {
 	pub fn send(
 		&self, amount_msat: u64, node_id: PublicKey, sending_parameters: Option<SendingParameters>,
 	) -> Result<PaymentId, Error> {
-		self.send_inner(amount_msat, node_id, sending_parameters, None)
+		self.send_inner(amount_msat, node_id, sending_parameters, None, None, false)
 	}
 
 	/// Send a spontaneous payment including a list of custom TLVs.
@@ -68,19 +79,106 @@ impl SpontaneousPayment {
 		&self, amount_msat: u64, node_id: PublicKey, sending_parameters: Option<SendingParameters>,
 		custom_tlvs: Vec<CustomTlvRecord>,
 	) -> Result<PaymentId, Error> {
-		self.send_inner(amount_msat, node_id, sending_parameters, Some(custom_tlvs))
+		self.send_inner(amount_msat, node_id, sending_parameters, Some(custom_tlvs), None, false)
+	}
+
+	/// Send a spontaneous payment carrying the given text message.
+	///
+	/// The message is attached via the custom TLV type used by Lightning chat applications and
+	/// will be available as [`Event::PaymentReceived::received_message`] on the recipient's side if
+	/// it runs LDK Node.
+	///
+	/// The message must be non-empty and must not exceed 800 bytes when UTF-8 encoded.
+	///
+	/// [`Event::PaymentReceived::received_message`]: crate::Event::PaymentReceived::received_message
+	pub fn send_message(
+		&self, node_id: PublicKey, amount_msat: u64, text: &str,
+	) -> Result<PaymentId, Error> {
+		let message = KeysendMessage::Text { text: text.to_string() };
+		self.send_keysend_message(node_id, amount_msat, message)
+	}
+
+	/// Send a spontaneous payment carrying the given [bLIP 10] boostagram.
+	///
+	/// The encoded boostagram must not exceed 800 bytes.
+	///
+	/// [bLIP 10]: https://github.com/lightning/blips/blob/master/blip-0010.md
+	pub fn send_boostagram(
+		&self, node_id: PublicKey, amount_msat: u64, boostagram: Boostagram,
+	) -> Result<PaymentId, Error> {
+		let message = KeysendMessage::Boostagram { boostagram };
+		self.send_keysend_message(node_id, amount_msat, message)
+	}
+
+	fn send_keysend_message(
+		&self, node_id: PublicKey, amount_msat: u64, message: KeysendMessage,
+	) -> Result<PaymentId, Error> {
+		let record = message.to_custom_record().map_err(|e| {
+			log_error!(self.logger, "Failed to send payment with invalid message: {:?}", message);
+			e
+		})?;
+		self.send_inner(amount_msat, node_id, None, Some(vec![record]), None, false)
+	}
+
+	/// Send a spontaneous payment that may be split across multiple paths.
+	///
+	/// If the recipient advertises support for multi-path payments, the payment will include a
+	/// payment secret and the total amount, allowing it to be split across multiple paths just as
+	/// [BOLT 11] payments are. This allows to send amounts exceeding what any single channel could
+	/// carry. Otherwise, this falls back to a regular single-path spontaneous payment.
+	///
+	/// If non-empty, the given `custom_tlvs` will be included in every part of the payment.
+	///
+	/// If `sending_parameters` are provided they will override the default as well as the
+	/// node-wide parameters configured via [`Config::sending_parameters`] on a per-field basis.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	pub fn send_mpp(
+		&self, amount_msat: u64, node_id: PublicKey, sending_parameters: Option<SendingParameters>,
+		custom_tlvs: Vec<CustomTlvRecord>,
+	) -> Result<PaymentId, Error> {
+		let allow_mpp = self.recipient_supports_mpp(&node_id);
+		if !allow_mpp {
+			log_info!(
+				self.logger,
+				"Falling back to single-path payment as {} doesn't advertise multi-path support.",
+				node_id
+			);
+		}
+		let custom_tlvs = if custom_tlvs.is_empty() { None } else { Some(custom_tlvs) };
+		self.send_inner(amount_msat, node_id, sending_parameters, custom_tlvs, None, allow_mpp)
+	}
+
+	/// Re-sends the given failed payment, reusing its pre-image and hence its [`PaymentId`].
+	pub(crate) fn retry(
+		&self, payment: &PaymentDetails, node_id: PublicKey, custom_tlvs: Vec<CustomTlvRecord>,
+		allow_mpp: bool,
+	) -> Result<(), Error> {
+		let payment_preimage = match payment.kind {
+			PaymentKind::Spontaneous { preimage: Some(preimage), .. } => preimage,
+			_ => {
+				log_error!(self.logger, "Failed to retry payment as its pre-image is unknown.");
+				return Err(Error::InvalidPaymentId);
+			},
+		};
+		let amount_msat = payment.amount_msat.ok_or(Error::InvalidAmount)?;
+		let custom_tlvs = if custom_tlvs.is_empty() { None } else { Some(custom_tlvs) };
+		self.send_inner(amount_msat, node_id, None, custom_tlvs, Some(payment_preimage), allow_mpp)
+			.map(|_| ())
 	}
 
 	fn send_inner(
 		&self, amount_msat: u64, node_id: PublicKey, sending_parameters: Option<SendingParameters>,
-		custom_tlvs: Option<Vec<CustomTlvRecord>>,
+		custom_tlvs: Option<Vec<CustomTlvRecord>>, payment_preimage: Option<PaymentPreimage>,
+		allow_mpp: bool,
 	) -> Result<PaymentId, Error> {
 		let rt_lock = self.runtime.read().unwrap();
 		if rt_lock.is_none() {
 			return Err(Error::NotRunning);
 		}
 
-		let payment_preimage = PaymentPreimage(self.keys_manager.get_secure_random_bytes());
+		let payment_preimage = payment_preimage
+			.unwrap_or_else(|| PaymentPreimage(self.keys_manager.get_secure_random_bytes()));
 		let payment_hash = PaymentHash::from(payment_preimage);
 		let payment_id = PaymentId(payment_hash.0);
 
@@ -94,7 +192,7 @@ impl SpontaneousPayment {
 		}
 
 		let mut route_params = RouteParameters::from_payment_params_and_value(
-			PaymentParameters::from_node_id(node_id, LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA),
+			PaymentParameters::for_keysend(node_id, LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA, allow_mpp),
 			amount_msat,
 		);
 
@@ -113,22 +211,37 @@ impl SpontaneousPayment {
 				.map(|s| route_params.payment_params.max_channel_saturation_power_of_half = s);
 		};
 
+		let retry_strategy =
+			override_params.and_then(|p| p.retry_strategy).unwrap_or_default().into();
+
+		// Multi-path payments require a payment secret for the recipient to tie the parts together.
+		let base_recipient_fields = if allow_mpp {
+			let payment_secret = PaymentSecret(self.keys_manager.get_secure_random_bytes());
+			RecipientOnionFields::secret_only(payment_secret)
+		} else {
+			RecipientOnionFields::spontaneous_empty()
+		};
+
 		let recipient_fields = match custom_tlvs {
-			Some(tlvs) => RecipientOnionFields::spontaneous_empty()
-				.with_custom_tlvs(tlvs.into_iter().map(|tlv| (tlv.type_num, tlv.value)).collect())
+			Some(ref tlvs) => base_recipient_fields
+				.with_custom_tlvs(
+					tlvs.iter().map(|tlv| (tlv.type_num, tlv.value.clone())).collect(),
+				)
 				.map_err(|e| {
 					log_error!(self.logger, "Failed to send payment with custom TLVs: {:?}", e);
 					Error::InvalidCustomTlvs
 				})?,
-			None => RecipientOnionFields::spontaneous_empty(),
+			None => base_recipient_fields,
 		};
 
+		let message = custom_tlvs.as_deref().and_then(KeysendMessage::from_custom_records);
+
 		match self.channel_manager.send_spontaneous_payment(
 			Some(payment_preimage),
 			recipient_fields,
 			PaymentId(payment_hash.0),
 			route_params,
-			Retry::Timeout(LDK_PAYMENT_RETRY_TIMEOUT),
+			retry_strategy,
 		) {
 			Ok(_hash) => {
 				log_info!(self.logger, "Initiated sending {}msat to {}.", amount_msat, node_id);
@@ -136,6 +249,7 @@ impl SpontaneousPayment {
 				let kind = PaymentKind::Spontaneous {
 					hash: payment_hash,
 					preimage: Some(payment_preimage),
+					message,
 				};
 				let payment = PaymentDetails::new(
 					payment_id,
@@ -146,6 +260,14 @@ impl SpontaneousPayment {
 				);
 				self.payment_store.insert(payment)?;
 
+				let target = PaymentRetryTarget::Spontaneous {
+					node_id,
+					custom_tlvs: custom_tlvs.unwrap_or_default(),
+					allow_mpp,
+				};
+				let retry_info = PaymentRetryInfo { id: payment_id, target };
+				self.payment_retry_store.insert_for_sent_payment(retry_info, &self.payment_store);
+
 				Ok(payment_id)
 			},
 			Err(e) => {
@@ -157,6 +279,7 @@ impl SpontaneousPayment {
 						let kind = PaymentKind::Spontaneous {
 							hash: payment_hash,
 							preimage: Some(payment_preimage),
+							message,
 						};
 						let payment = PaymentDetails::new(
 							payment_id,
@@ -202,4 +325,21 @@ impl SpontaneousPayment {
 
 		Ok(())
 	}
+
+	fn recipient_supports_mpp(&self, node_id: &PublicKey) -> bool {
+		// Check the features the recipient announced to the network, falling back to the ones it
+		// sent us on connection if we have a channel with it.
+		let announced_features = self
+			.network_graph
+			.read_only()
+			.node(&NodeId::from_pubkey(node_id))
+			.and_then(|node| node.announcement_info.as_ref())
+			.map(|info| info.features().supports_basic_mpp());
+		announced_features.unwrap_or_else(|| {
+			self.channel_manager
+				.list_channels_with_counterparty(node_id)
+				.iter()
+				.any(|channel| channel.counterparty.features.supports_basic_mpp())
+		})
+	}
 }
@@ -10,6 +10,7 @@ use crate::io::{
 	PAYMENT_INFO_PERSISTENCE_PRIMARY_NAMESPACE, PAYMENT_INFO_PERSISTENCE_SECONDARY_NAMESPACE,
 };
 use crate::logger::{log_error, Logger};
+use crate::payment::KeysendMessage;
 use crate::types::DynStore;
 use crate::Error;
 
@@ -25,6 +26,8 @@ use lightning::{
 
 use lightning_types::payment::{PaymentHash, PaymentPreimage, PaymentSecret};
 
+use bitcoin::Txid;
+
 use std::collections::HashMap;
 use std::iter::FromIterator;
 use std::ops::Deref;
@@ -46,6 +49,13 @@ pub struct PaymentDetails {
 	pub status: PaymentStatus,
 	/// The timestamp, in seconds since start of the UNIX epoch, when this entry was last updated.
 	pub latest_update_timestamp: u64,
+	/// The label assigned to the payment via [`Node::set_label`], if any.
+	///
+	/// For on-chain payments without a label of their own, this is the label of the transaction
+	/// carrying the payment.
+	///
+	/// [`Node::set_label`]: crate::Node::set_label
+	pub label: Option<String>,
 }
 
 impl PaymentDetails {
@@ -57,7 +67,7 @@ impl PaymentDetails {
 			.duration_since(UNIX_EPOCH)
 			.unwrap_or(Duration::from_secs(0))
 			.as_secs();
-		Self { id, kind, amount_msat, direction, status, latest_update_timestamp }
+		Self { id, kind, amount_msat, direction, status, latest_update_timestamp, label: None }
 	}
 }
 
@@ -132,11 +142,22 @@ impl Readable for PaymentDetails {
 					PaymentKind::Bolt11 { hash, preimage, secret }
 				}
 			} else {
-				PaymentKind::Spontaneous { hash, preimage }
+				PaymentKind::Spontaneous { hash, preimage, message: None }
 			}
 		};
 
-		Ok(PaymentDetails { id, kind, amount_msat, direction, status, latest_update_timestamp })
+		// Labels are persisted separately and only added when the payment is retrieved.
+		let label = None;
+
+		Ok(PaymentDetails {
+			id,
+			kind,
+			amount_msat,
+			direction,
+			status,
+			latest_update_timestamp,
+			label,
+		})
 	}
 }
 
@@ -175,7 +196,29 @@ impl_writeable_tlv_based_enum!(PaymentStatus,
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum PaymentKind {
 	/// An on-chain payment.
-	Onchain,
+	Onchain {
+		/// The identifier of the transaction carrying the payment.
+		///
+		/// Will be updated if the transaction is replaced, e.g., via
+		/// [`OnchainPayment::bump_fee_rbf`].
+		///
+		/// [`OnchainPayment::bump_fee_rbf`]: crate::payment::OnchainPayment::bump_fee_rbf
+		txid: Txid,
+		/// The identifiers of the transactions previously carrying the payment that were since
+		/// replaced, in the order they were replaced in.
+		///
+		/// If one of these transactions confirms rather than its replacement, it becomes the
+		/// transaction carrying the payment, while the replacement is listed here instead.
+		replaced_txids: Vec<Txid>,
+		/// The fee, in satoshis, paid by the transaction carrying the payment, if known.
+		///
+		/// Note the transaction may carry other payments, too.
+		///
+		/// This will be `None` if the fee can't be determined as the transaction spends outputs
+		/// our wallet doesn't know about, e.g., if an externally signed PSBT or a payjoin
+		/// transaction adds inputs of other parties.
+		fee_sats: Option<u64>,
+	},
 	/// A [BOLT 11] payment.
 	///
 	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
@@ -260,11 +303,19 @@ pub enum PaymentKind {
 		hash: PaymentHash,
 		/// The pre-image used by the payment.
 		preimage: Option<PaymentPreimage>,
+		/// The message attached to the payment, if any.
+		///
+		/// This will be `None` for payments serialized by LDK Node v0.4.2 and prior.
+		message: Option<KeysendMessage>,
 	},
 }
 
 impl_writeable_tlv_based_enum!(PaymentKind,
-	(0, Onchain) => {},
+	(0, Onchain) => {
+		(0, txid, required),
+		(2, replaced_txids, optional_vec),
+		(4, fee_sats, option),
+	},
 	(2, Bolt11) => {
 		(0, hash, required),
 		(2, preimage, option),
@@ -286,6 +337,7 @@ impl_writeable_tlv_based_enum!(PaymentKind,
 	},
 	(8, Spontaneous) => {
 		(0, hash, required),
+		(1, message, option),
 		(2, preimage, option),
 	},
 	(10, Bolt12Refund) => {
@@ -326,6 +378,8 @@ pub(crate) struct PaymentDetailsUpdate {
 	pub amount_msat: Option<Option<u64>>,
 	pub direction: Option<PaymentDirection>,
 	pub status: Option<PaymentStatus>,
+	pub txid: Option<Txid>,
+	pub fee_sats: Option<Option<u64>>,
 }
 
 impl PaymentDetailsUpdate {
@@ -338,6 +392,8 @@ impl PaymentDetailsUpdate {
 			amount_msat: None,
 			direction: None,
 			status: None,
+			txid: None,
+			fee_sats: None,
 		}
 	}
 }
@@ -440,6 +496,25 @@ where
 				}
 			}
 
+			if let Some(new_txid) = update.txid {
+				if let PaymentKind::Onchain { ref mut txid, ref mut replaced_txids, .. } =
+					payment.kind
+				{
+					if *txid != new_txid {
+						// A previously replaced transaction may confirm after all.
+						replaced_txids.retain(|replaced_txid| *replaced_txid != new_txid);
+						replaced_txids.push(*txid);
+						*txid = new_txid;
+					}
+				}
+			}
+
+			if let Some(fee_opt) = update.fee_sats {
+				if let PaymentKind::Onchain { ref mut fee_sats, .. } = payment.kind {
+					*fee_sats = fee_opt;
+				}
+			}
+
 			if let Some(amount_opt) = update.amount_msat {
 				payment.amount_msat = amount_opt;
 			}
@@ -500,6 +575,7 @@ where
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use bitcoin::hashes::Hash;
 	use bitcoin::io::Cursor;
 	use lightning::util::{
 		ser::Readable,
@@ -574,6 +650,62 @@ mod tests {
 		assert_eq!(PaymentStatus::Succeeded, payment_store.get(&id).unwrap().status);
 	}
 
+	#[test]
+	fn onchain_payment_replacements_are_tracked() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let payment_store = PaymentStore::new(Vec::new(), Arc::clone(&store), logger);
+
+		let original_txid = Txid::from_byte_array([42u8; 32]);
+		let replacement_txid = Txid::from_byte_array([43u8; 32]);
+		let id = PaymentId(original_txid.to_byte_array());
+		let kind = PaymentKind::Onchain {
+			txid: original_txid,
+			replaced_txids: Vec::new(),
+			fee_sats: Some(1_000),
+		};
+		let payment = PaymentDetails::new(
+			id,
+			kind,
+			Some(100_000_000),
+			PaymentDirection::Outbound,
+			PaymentStatus::Pending,
+		);
+		assert_eq!(Ok(false), payment_store.insert(payment));
+
+		let update = PaymentDetailsUpdate {
+			txid: Some(replacement_txid),
+			fee_sats: Some(Some(2_000)),
+			..PaymentDetailsUpdate::new(id)
+		};
+		assert_eq!(Ok(true), payment_store.update(&update));
+
+		let payment = payment_store.get(&id).unwrap();
+		let expected_kind = PaymentKind::Onchain {
+			txid: replacement_txid,
+			replaced_txids: vec![original_txid],
+			fee_sats: Some(2_000),
+		};
+		assert_eq!(payment.kind, expected_kind);
+		assert_eq!(payment, PaymentDetails::read(&mut Cursor::new(payment.encode())).unwrap());
+
+		// If the original transaction confirms after all, the payment switches back to it.
+		let update = PaymentDetailsUpdate {
+			txid: Some(original_txid),
+			fee_sats: Some(Some(1_000)),
+			..PaymentDetailsUpdate::new(id)
+		};
+		assert_eq!(Ok(true), payment_store.update(&update));
+
+		let payment = payment_store.get(&id).unwrap();
+		let expected_kind = PaymentKind::Onchain {
+			txid: original_txid,
+			replaced_txids: vec![replacement_txid],
+			fee_sats: Some(1_000),
+		};
+		assert_eq!(payment.kind, expected_kind);
+	}
+
 	#[test]
 	fn old_payment_details_deser_compat() {
 		// We refactored `PaymentDetails` to hold a payment id and moved some required fields into
@@ -692,9 +824,10 @@ mod tests {
 			);
 
 			match spontaneous_decoded.kind {
-				PaymentKind::Spontaneous { hash: h, preimage: p } => {
+				PaymentKind::Spontaneous { hash: h, preimage: p, message: m } => {
 					assert_eq!(hash, h);
 					assert_eq!(preimage, p);
+					assert_eq!(m, None);
 				},
 				_ => {
 					panic!("Unexpected kind!");
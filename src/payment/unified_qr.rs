@@ -12,18 +12,21 @@
 //! [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
 //! [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
 use crate::error::Error;
-use crate::logger::{log_error, FilesystemLogger, Logger};
+use crate::fee_estimator::fee_rate_from_sat_per_vb;
+use crate::logger::{log_error, log_info, FilesystemLogger, Logger};
+use crate::payment::payjoin::PayjoinReceiver;
 use crate::payment::{Bolt11Payment, Bolt12Payment, OnchainPayment};
+use crate::types::ChannelManager;
 use crate::Config;
 
 use lightning::ln::channelmanager::PaymentId;
 use lightning::offers::offer::Offer;
 use lightning_invoice::Bolt11Invoice;
 
-use bip21::de::ParamKind;
+use bip21::de::{DeserializationState as _, ParamKind};
 use bip21::{DeserializationError, DeserializeParams, Param, SerializeParams};
 use bitcoin::address::{NetworkChecked, NetworkUnchecked};
-use bitcoin::{Amount, Txid};
+use bitcoin::{Address, Amount, Denomination, FeeRate, Network, Txid};
 
 use std::sync::Arc;
 use std::vec::IntoIter;
@@ -31,9 +34,10 @@ use std::vec::IntoIter;
 type Uri<'a> = bip21::Uri<'a, NetworkChecked, Extras>;
 
 #[derive(Debug, Clone)]
-struct Extras {
-	bolt11_invoice: Option<Bolt11Invoice>,
-	bolt12_offer: Option<Offer>,
+pub(crate) struct Extras {
+	pub(crate) bolt11_invoice: Option<Bolt11Invoice>,
+	pub(crate) bolt12_offer: Option<Offer>,
+	pub(crate) payjoin_endpoint: Option<String>,
 }
 
 /// A payment handler allowing to create [BIP 21] URIs with an on-chain, [BOLT 11], and [BOLT 12] payment
@@ -49,6 +53,8 @@ pub struct UnifiedQrPayment {
 	onchain_payment: Arc<OnchainPayment>,
 	bolt11_invoice: Arc<Bolt11Payment>,
 	bolt12_payment: Arc<Bolt12Payment>,
+	payjoin_receiver: Option<Arc<PayjoinReceiver>>,
+	channel_manager: Arc<ChannelManager>,
 	config: Arc<Config>,
 	logger: Arc<FilesystemLogger>,
 }
@@ -56,9 +62,18 @@ pub struct UnifiedQrPayment {
 impl UnifiedQrPayment {
 	pub(crate) fn new(
 		onchain_payment: Arc<OnchainPayment>, bolt11_invoice: Arc<Bolt11Payment>,
-		bolt12_payment: Arc<Bolt12Payment>, config: Arc<Config>, logger: Arc<FilesystemLogger>,
+		bolt12_payment: Arc<Bolt12Payment>, payjoin_receiver: Option<Arc<PayjoinReceiver>>,
+		channel_manager: Arc<ChannelManager>, config: Arc<Config>, logger: Arc<FilesystemLogger>,
 	) -> Self {
-		Self { onchain_payment, bolt11_invoice, bolt12_payment, config, logger }
+		Self {
+			onchain_payment,
+			bolt11_invoice,
+			bolt12_payment,
+			payjoin_receiver,
+			channel_manager,
+			config,
+			logger,
+		}
 	}
 
 	/// Generates a URI with an on-chain address, [BOLT 11] invoice and [BOLT 12] offer.
@@ -68,6 +83,16 @@ impl UnifiedQrPayment {
 	/// can always pay using the provided on-chain address, while newer wallets will
 	/// typically opt to use the provided BOLT11 invoice or BOLT12 offer.
 	///
+	/// Which Lightning payment options are included is determined by
+	/// [`Config::unified_payment_policy`]. Use [`Self::receive_with_policy`] to override it for a
+	/// single URI.
+	///
+	/// If our usable channels lack the inbound capacity to receive the given amount and an [LSPS2]
+	/// liquidity source is configured, the [BOLT 11] invoice will be created via
+	/// [`Bolt11Payment::receive_via_jit_channel`] so that the payment can be received over a
+	/// just-in-time channel. In this case no [BOLT 12] offer will be included, as it couldn't be
+	/// paid.
+	///
 	/// # Parameters
 	/// - `amount_sats`: The amount to be received, specified in satoshis.
 	/// - `description`: A description or note associated with the payment.
@@ -79,90 +104,470 @@ impl UnifiedQrPayment {
 	/// address issues, `Error::InvoiceCreationFailed` for BOLT11 invoice issues, or
 	/// `Error::OfferCreationFailed` for BOLT12 offer issues.
 	///
+	/// If [`Config::payjoin_config`] or [`Config::payjoin_directory_config`] is set, the URI will
+	/// also include a `pj` parameter allowing the sender to pay the on-chain address via [BIP 78]
+	/// payjoin, or asynchronously via a [BIP 77] payjoin directory, respectively.
+	///
 	/// The generated URI can then be given to a QR code library.
 	///
+	/// [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+	/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
 	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
 	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	/// [LSPS2]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS2/README.md
 	pub fn receive(
 		&self, amount_sats: u64, description: &str, expiry_sec: u32,
 	) -> Result<String, Error> {
-		let onchain_address = self.onchain_payment.new_address()?;
+		let policy = self.config.unified_payment_policy.clone();
+		self.receive_with_policy(amount_sats, description, expiry_sec, policy)
+	}
+
+	/// Generates a URI as [`Self::receive`] does, but using the given [`UnifiedPaymentPolicy`]
+	/// rather than [`Config::unified_payment_policy`].
+	pub fn receive_with_policy(
+		&self, amount_sats: u64, description: &str, expiry_sec: u32, policy: UnifiedPaymentPolicy,
+	) -> Result<String, Error> {
+		self.receive_inner(Some(amount_sats), description, expiry_sec, &policy)
+	}
 
-		let amount_msats = amount_sats * 1_000;
+	/// Generates a URI as [`Self::receive`] does, but leaving the amount to be determined by the
+	/// sender.
+	///
+	/// If we don't have any inbound liquidity, the [BOLT 11] invoice will be created via
+	/// [`Bolt11Payment::receive_variable_amount_via_jit_channel`] if an [LSPS2] liquidity source is
+	/// configured.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	/// [LSPS2]: https://github.com/BitcoinAndLightningLayerSpecs/lsp/blob/main/LSPS2/README.md
+	pub fn receive_variable_amount(
+		&self, description: &str, expiry_sec: u32,
+	) -> Result<String, Error> {
+		self.receive_inner(None, description, expiry_sec, &self.config.unified_payment_policy)
+	}
 
-		let bolt12_offer = match self.bolt12_payment.receive(amount_msats, description, None, None)
-		{
-			Ok(offer) => Some(offer),
-			Err(e) => {
-				log_error!(self.logger, "Failed to create offer: {}", e);
-				return Err(Error::OfferCreationFailed);
-			},
+	fn receive_inner(
+		&self, amount_sats: Option<u64>, description: &str, expiry_sec: u32,
+		policy: &UnifiedPaymentPolicy,
+	) -> Result<String, Error> {
+		let onchain_address = self.onchain_payment.new_address(None)?;
+
+		let amount_msats = amount_sats.map(|amount_sats| amount_sats * 1_000);
+
+		// Amounts above the threshold are only to be paid on-chain.
+		let include_lightning = match (policy.prefer_onchain_above_sats, amount_sats) {
+			(Some(threshold), Some(amount_sats)) => amount_sats <= threshold,
+			_ => true,
+		};
+
+		// If we lack the inbound liquidity to receive the payment, we have a JIT channel opened to
+		// us instead. As an offer couldn't be paid in this case, we don't include any.
+		let use_jit_channel = include_lightning
+			&& policy.include_bolt11_invoice
+			&& self.bolt11_invoice.has_liquidity_source()
+			&& !self.has_inbound_liquidity(amount_msats);
+
+		let bolt12_offer = if include_lightning && policy.include_bolt12_offer && !use_jit_channel {
+			let res = match amount_msats {
+				Some(amount_msats) => {
+					self.bolt12_payment.receive(amount_msats, description, None, None)
+				},
+				None => self.bolt12_payment.receive_variable_amount(description, None),
+			};
+			match res {
+				Ok(offer) => Some(offer),
+				Err(e) => {
+					log_error!(self.logger, "Failed to create offer: {}", e);
+					return Err(Error::OfferCreationFailed);
+				},
+			}
+		} else {
+			None
 		};
 
-		let bolt11_invoice =
-			match self.bolt11_invoice.receive(amount_msats, description, expiry_sec) {
+		let bolt11_invoice = if include_lightning && policy.include_bolt11_invoice {
+			let res = match (amount_msats, use_jit_channel) {
+				(Some(amount_msats), false) => {
+					self.bolt11_invoice.receive(amount_msats, description, expiry_sec)
+				},
+				(None, false) => {
+					self.bolt11_invoice.receive_variable_amount(description, expiry_sec)
+				},
+				(Some(amount_msats), true) => self.bolt11_invoice.receive_via_jit_channel(
+					amount_msats,
+					description,
+					expiry_sec,
+					policy.max_total_lsp_fee_limit_msat,
+				),
+				(None, true) => self.bolt11_invoice.receive_variable_amount_via_jit_channel(
+					description,
+					expiry_sec,
+					policy.max_proportional_lsp_fee_limit_ppm_msat,
+				),
+			};
+			match res {
 				Ok(invoice) => Some(invoice),
 				Err(e) => {
 					log_error!(self.logger, "Failed to create invoice {}", e);
 					return Err(Error::InvoiceCreationFailed);
 				},
-			};
+			}
+		} else {
+			None
+		};
+
+		let payjoin_endpoint = self.payjoin_receiver.as_ref().and_then(|payjoin_receiver| {
+			payjoin_receiver.expect_payment(
+				onchain_address.script_pubkey(),
+				amount_sats.unwrap_or(0),
+				expiry_sec,
+			)
+		});
 
-		let extras = Extras { bolt11_invoice, bolt12_offer };
+		let extras = Extras { bolt11_invoice, bolt12_offer, payjoin_endpoint };
 
 		let mut uri = Uri::with_extras(onchain_address, extras);
-		uri.amount = Some(Amount::from_sat(amount_sats));
+		uri.amount = amount_sats.map(Amount::from_sat);
 		uri.message = Some(description.into());
 
 		Ok(format_uri(uri))
 	}
 
+	// Returns whether our usable channels have enough inbound capacity to receive the given
+	// amount, or any inbound capacity at all if no amount is given.
+	fn has_inbound_liquidity(&self, amount_msat: Option<u64>) -> bool {
+		let inbound_capacity_msat = self
+			.channel_manager
+			.list_usable_channels()
+			.iter()
+			.map(|c| c.inbound_capacity_msat)
+			.sum::<u64>();
+		inbound_capacity_msat > 0 && inbound_capacity_msat >= amount_msat.unwrap_or(0)
+	}
+
 	/// Sends a payment given a [BIP 21] URI.
 	///
-	/// This method parses the provided URI string and attempts to send the payment. If the URI
-	/// has an offer and or invoice, it will try to pay the offer first followed by the invoice.
-	/// If they both fail, the on-chain payment will be paid.
+	/// This method parses the provided URI string and attempts to send the payment. The order in
+	/// which the offer, invoice, and on-chain address contained in the URI are tried is determined
+	/// by [`Config::unified_payment_policy`], which by default tries the offer first, followed by
+	/// the invoice, and finally the on-chain address. If the URI includes a `pj` parameter, the
+	/// on-chain payment will be made via [BIP 78] payjoin, falling back to a regular transaction
+	/// if the receiver doesn't respond with a valid payjoin proposal. Paying a `pj` parameter
+	/// referring to a [BIP 77] payjoin directory requires [`Config::payjoin_directory_config`] to
+	/// be set.
+	///
+	/// Returns a `QrPaymentResult` indicating the outcome of the payment, including any payment
+	/// methods that were attempted and failed before. If all attempts fail, the `Error` of the last
+	/// attempt is returned.
+	///
+	/// If `fee_rate_sat_per_vb` is set, an on-chain payment will pay the given fee rate, in
+	/// satoshis per virtual byte. Otherwise, the fee rate is estimated based on the current chain
+	/// state.
 	///
-	/// Returns a `QrPaymentResult` indicating the outcome of the payment. If an error
-	/// occurs, an `Error` is returned detailing the issue encountered.
+	/// URIs lacking an on-chain address, e.g., `bitcoin:?lno=lno1...` as commonly resolved from
+	/// [BIP 353] names, are supported, in which case only the Lightning payment options are tried.
 	///
 	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
-	pub fn send(&self, uri_str: &str) -> Result<QrPaymentResult, Error> {
-		let uri: bip21::Uri<NetworkUnchecked, Extras> =
-			uri_str.parse().map_err(|_| Error::InvalidUri)?;
+	/// [BIP 77]: https://github.com/bitcoin/bips/blob/master/bip-0077.md
+	/// [BIP 78]: https://github.com/bitcoin/bips/blob/master/bip-0078.mediawiki
+	/// [BIP 353]: https://github.com/bitcoin/bips/blob/master/bip-0353.mediawiki
+	pub fn send(
+		&self, uri_str: &str, fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<QrPaymentResult, Error> {
+		self.send_inner(uri_str, None, &self.config.unified_payment_policy, fee_rate_sat_per_vb)
+	}
 
-		let uri_network_checked =
-			uri.clone().require_network(self.config.network).map_err(|_| Error::InvalidNetwork)?;
+	/// Sends a payment given a [BIP 21] URI as [`Self::send`] does, but using the given
+	/// [`UnifiedPaymentPolicy`] rather than [`Config::unified_payment_policy`].
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	pub fn send_with_policy(
+		&self, uri_str: &str, policy: UnifiedPaymentPolicy, fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<QrPaymentResult, Error> {
+		self.send_inner(uri_str, None, &policy, fee_rate_sat_per_vb)
+	}
 
-		if let Some(offer) = uri_network_checked.extras.bolt12_offer {
-			match self.bolt12_payment.send(&offer, None, None) {
-				Ok(payment_id) => return Ok(QrPaymentResult::Bolt12 { payment_id }),
-				Err(e) => log_error!(self.logger, "Failed to send BOLT12 offer: {:?}. This is part of a unified QR code payment. Falling back to the BOLT11 invoice.", e),
+	/// Sends a payment given a [BIP 21] URI, paying the given amount rather than the one
+	/// specified in the URI, if any.
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	pub(crate) fn send_inner(
+		&self, uri_str: &str, amount_msat: Option<u64>, policy: &UnifiedPaymentPolicy,
+		fee_rate_sat_per_vb: Option<u64>,
+	) -> Result<QrPaymentResult, Error> {
+		let uri = PaymentUri::parse(uri_str, self.config.network)?;
+		let fee_rate = fee_rate_sat_per_vb.map(fee_rate_from_sat_per_vb).transpose()?;
+
+		// Lightning payments are only made using an explicitly given amount as invoices and offers
+		// carry their own, while the URI's amount is what we pay on-chain.
+		let onchain_amount_msat = amount_msat.or(uri.amount.map(|amount| amount.to_sat() * 1000));
+		let methods = self.payment_methods(policy, onchain_amount_msat);
+
+		let mut failed_attempts: Vec<QrPaymentAttemptFailure> = Vec::new();
+		for method in methods {
+			match method {
+				UnifiedPaymentMethod::Bolt12 => {
+					let offer = match uri.extras.bolt12_offer {
+						Some(ref offer) => offer,
+						None => continue,
+					};
+					let res = if let Some(amount_msat) = amount_msat {
+						self.bolt12_payment.send_using_amount(offer, amount_msat, None, None)
+					} else {
+						self.bolt12_payment.send(offer, None, None)
+					};
+					match res {
+						Ok(payment_id) => {
+							return Ok(QrPaymentResult::Bolt12 { payment_id, failed_attempts })
+						},
+						Err(error) => {
+							log_error!(self.logger, "Failed to send BOLT12 offer: {:?}. This is part of a unified QR code payment.", error);
+							failed_attempts.push(QrPaymentAttemptFailure { method, error });
+						},
+					}
+				},
+				UnifiedPaymentMethod::Bolt11 => {
+					let invoice = match uri.extras.bolt11_invoice {
+						Some(ref invoice) => invoice,
+						None => continue,
+					};
+					let res = if let Some(amount_msat) = amount_msat {
+						self.bolt11_invoice.send_using_amount(invoice, amount_msat, None)
+					} else {
+						self.bolt11_invoice.send(invoice, None)
+					};
+					match res {
+						Ok(payment_id) => {
+							return Ok(QrPaymentResult::Bolt11 { payment_id, failed_attempts })
+						},
+						Err(error) => {
+							log_error!(self.logger, "Failed to send BOLT11 invoice: {:?}. This is part of a unified QR code payment.", error);
+							failed_attempts.push(QrPaymentAttemptFailure { method, error });
+						},
+					}
+				},
+				UnifiedPaymentMethod::Onchain => {
+					let address = match uri.address {
+						Some(ref address) => address,
+						None => continue,
+					};
+					if !policy.allow_onchain_fallback && !failed_attempts.is_empty() {
+						log_error!(
+							self.logger,
+							"Not falling back to the on-chain transaction as disallowed by the payment policy."
+						);
+						continue;
+					}
+
+					let res = match onchain_amount_msat {
+						Some(amount_msat) if amount_msat % 1000 != 0 => {
+							log_error!(
+								self.logger,
+								"Failed to pay on-chain as {}msat is not a whole number of satoshis.",
+								amount_msat
+							);
+							Err(Error::InvalidAmount)
+						},
+						Some(amount_msat) => {
+							let payjoin_endpoint = uri.extras.payjoin_endpoint.as_deref();
+							self.send_onchain(
+								address,
+								payjoin_endpoint,
+								amount_msat / 1000,
+								fee_rate,
+							)
+						},
+						None => {
+							log_error!(
+								self.logger,
+								"No amount specified in the URI. Aborting the payment."
+							);
+							Err(Error::InvalidAmount)
+						},
+					};
+					match res {
+						Ok(txid) => return Ok(QrPaymentResult::Onchain { txid, failed_attempts }),
+						Err(error) => {
+							failed_attempts.push(QrPaymentAttemptFailure { method, error });
+						},
+					}
+				},
 			}
 		}
 
-		if let Some(invoice) = uri_network_checked.extras.bolt11_invoice {
-			match self.bolt11_invoice.send(&invoice, None) {
-				Ok(payment_id) => return Ok(QrPaymentResult::Bolt11 { payment_id }),
-				Err(e) => log_error!(self.logger, "Failed to send BOLT11 invoice: {:?}. This is part of a unified QR code payment. Falling back to the on-chain transaction.", e),
-			}
+		match failed_attempts.pop() {
+			Some(last_attempt) => Err(last_attempt.error),
+			None => {
+				log_error!(
+					self.logger,
+					"The URI doesn't contain any payment method allowed by the payment policy."
+				);
+				Err(Error::PaymentSendingFailed)
+			},
 		}
+	}
 
-		let amount = match uri_network_checked.amount {
-			Some(amount) => amount,
+	fn send_onchain(
+		&self, address: &Address, payjoin_endpoint: Option<&str>, amount_sats: u64,
+		fee_rate: Option<FeeRate>,
+	) -> Result<Txid, Error> {
+		match payjoin_endpoint {
+			Some(endpoint_url) => self.onchain_payment.send_to_address_with_payjoin(
+				address,
+				amount_sats,
+				endpoint_url,
+				fee_rate,
+			),
 			None => {
-				log_error!(self.logger, "No amount specified in the URI. Aborting the payment.");
-				return Err(Error::InvalidAmount);
+				self.onchain_payment.send_to_address_inner(address, amount_sats, None, fee_rate)
 			},
+		}
+	}
+
+	// Returns the payment methods to attempt, in order, for a payment of the given amount.
+	fn payment_methods(
+		&self, policy: &UnifiedPaymentPolicy, amount_msat: Option<u64>,
+	) -> Vec<UnifiedPaymentMethod> {
+		let mut methods = Vec::with_capacity(policy.send_order.len());
+		for method in policy.send_order.iter() {
+			if !methods.contains(method) {
+				methods.push(*method);
+			}
+		}
+
+		let prefer_onchain = match (policy.prefer_onchain_above_sats, amount_msat) {
+			(Some(threshold_sats), Some(amount_msat)) => amount_msat > threshold_sats * 1000,
+			_ => false,
 		};
+		if prefer_onchain && methods.contains(&UnifiedPaymentMethod::Onchain) {
+			methods.retain(|m| *m != UnifiedPaymentMethod::Onchain);
+			methods.insert(0, UnifiedPaymentMethod::Onchain);
+		}
 
-		let txid =
-			self.onchain_payment.send_to_address(&uri_network_checked.address, amount.to_sat())?;
+		if policy.skip_lightning_without_liquidity {
+			let outbound_liquidity_msat = self
+				.channel_manager
+				.list_usable_channels()
+				.iter()
+				.map(|c| c.next_outbound_htlc_limit_msat)
+				.sum::<u64>();
+			let has_liquidity =
+				outbound_liquidity_msat > 0 && outbound_liquidity_msat >= amount_msat.unwrap_or(0);
+			if !has_liquidity {
+				log_info!(
+					self.logger,
+					"Skipping Lightning payment methods due to insufficient outbound liquidity."
+				);
+				methods.retain(|m| *m == UnifiedPaymentMethod::Onchain);
+			}
+		}
 
-		Ok(QrPaymentResult::Onchain { txid })
+		methods
 	}
 }
 
+/// A method of paying a [BIP 21] URI.
+///
+/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum UnifiedPaymentMethod {
+	/// Paying the URI's on-chain address.
+	Onchain,
+	/// Paying the URI's [BOLT 11] invoice.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	Bolt11,
+	/// Paying the URI's [BOLT 12] offer.
+	///
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	Bolt12,
+}
+
+/// Rules determining which payment methods are used when sending and receiving payments via
+/// [`UnifiedQrPayment`].
+///
+/// The default policy tries the [BOLT 12] offer first, followed by the [BOLT 11] invoice and the
+/// on-chain address, and includes all of them in generated URIs.
+///
+/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnifiedPaymentPolicy {
+	/// The order in which payment methods are attempted when sending.
+	///
+	/// Methods that are not listed will never be attempted.
+	pub send_order: Vec<UnifiedPaymentMethod>,
+	/// The amount above which on-chain payments are preferred over Lightning.
+	///
+	/// When sending amounts above this threshold, the on-chain address will be attempted first if
+	/// it's part of [`UnifiedPaymentPolicy::send_order`]. When receiving amounts above this
+	/// threshold, generated URIs won't include any Lightning payment options.
+	pub prefer_onchain_above_sats: Option<u64>,
+	/// Whether to skip Lightning payment methods when sending if our usable channels don't have
+	/// enough outbound liquidity to cover the payment amount.
+	pub skip_lightning_without_liquidity: bool,
+	/// Whether to fall back to the on-chain address when sending after a Lightning payment method
+	/// failed.
+	///
+	/// If set to `false`, the on-chain address will only be paid if no Lightning payment method was
+	/// attempted beforehand.
+	pub allow_onchain_fallback: bool,
+	/// Whether to include a [BOLT 11] invoice in generated URIs.
+	///
+	/// [BOLT 11]: https://github.com/lightning/bolts/blob/master/11-payment-encoding.md
+	pub include_bolt11_invoice: bool,
+	/// Whether to include a [BOLT 12] offer in generated URIs.
+	///
+	/// Note that generated URIs always include an on-chain address as it's mandatory for [BIP 21]
+	/// URIs.
+	///
+	/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+	/// [BOLT 12]: https://github.com/lightning/bolts/blob/master/12-offer-encoding.md
+	pub include_bolt12_offer: bool,
+	/// The maximum total fee, in millisatoshis, we allow the LSP to take for opening a
+	/// just-in-time channel when receiving a fixed amount without sufficient inbound liquidity.
+	///
+	/// We'll use the LSP's cheapest offer if unset. See
+	/// [`Bolt11Payment::receive_via_jit_channel`] for more information.
+	pub max_total_lsp_fee_limit_msat: Option<u64>,
+	/// The maximum proportional fee, in parts-per-million millisatoshis, we allow the LSP to take
+	/// for opening a just-in-time channel when receiving a variable amount without any inbound
+	/// liquidity.
+	///
+	/// We'll use the LSP's cheapest offer if unset. See
+	/// [`Bolt11Payment::receive_variable_amount_via_jit_channel`] for more information.
+	pub max_proportional_lsp_fee_limit_ppm_msat: Option<u64>,
+}
+
+impl Default for UnifiedPaymentPolicy {
+	fn default() -> Self {
+		Self {
+			send_order: vec![
+				UnifiedPaymentMethod::Bolt12,
+				UnifiedPaymentMethod::Bolt11,
+				UnifiedPaymentMethod::Onchain,
+			],
+			prefer_onchain_above_sats: None,
+			skip_lightning_without_liquidity: false,
+			allow_onchain_fallback: true,
+			include_bolt11_invoice: true,
+			include_bolt12_offer: true,
+			max_total_lsp_fee_limit_msat: None,
+			max_proportional_lsp_fee_limit_ppm_msat: None,
+		}
+	}
+}
+
+/// A payment method that was attempted and failed while paying a [BIP 21] URI.
+///
+/// [BIP 21]: https://github.com/bitcoin/bips/blob/master/bip-0021.mediawiki
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QrPaymentAttemptFailure {
+	/// The payment method that was attempted.
+	pub method: UnifiedPaymentMethod,
+	/// The error the attempt failed with.
+	pub error: Error,
+}
+
 /// Represents the result of a payment made using a [BIP 21] QR code.
 ///
 /// After a successful on-chain transaction, the transaction ID ([`Txid`]) is returned.
@@ -176,6 +581,8 @@ pub enum QrPaymentResult {
 	Onchain {
 		/// The transaction ID (txid) of the on-chain payment.
 		txid: Txid,
+		/// The payment methods that were attempted and failed before.
+		failed_attempts: Vec<QrPaymentAttemptFailure>,
 	},
 	/// A [BOLT 11] payment.
 	///
@@ -183,6 +590,8 @@ pub enum QrPaymentResult {
 	Bolt11 {
 		/// The payment ID for the BOLT11 invoice.
 		payment_id: PaymentId,
+		/// The payment methods that were attempted and failed before.
+		failed_attempts: Vec<QrPaymentAttemptFailure>,
 	},
 	/// A [BOLT 12] offer payment, i.e., a payment for an [`Offer`].
 	///
@@ -191,9 +600,110 @@ pub enum QrPaymentResult {
 	Bolt12 {
 		/// The payment ID for the BOLT12 offer.
 		payment_id: PaymentId,
+		/// The payment methods that were attempted and failed before.
+		failed_attempts: Vec<QrPaymentAttemptFailure>,
 	},
 }
 
+// A BIP 21 URI to pay.
+//
+// Unlike `bip21::Uri`, this allows the on-chain address to be omitted, as is common for URIs
+// resolved from BIP 353 names, e.g., `bitcoin:?lno=lno1...`.
+pub(crate) struct PaymentUri {
+	pub(crate) address: Option<Address>,
+	pub(crate) amount: Option<Amount>,
+	pub(crate) label: Option<String>,
+	pub(crate) message: Option<String>,
+	pub(crate) extras: Extras,
+}
+
+impl PaymentUri {
+	pub(crate) fn parse(uri_str: &str, network: Network) -> Result<Self, Error> {
+		if let Some(query) = addressless_uri_query(uri_str) {
+			return Self::parse_addressless(query);
+		}
+
+		let uri: bip21::Uri<NetworkUnchecked, Extras> =
+			uri_str.parse().map_err(|_| Error::InvalidUri)?;
+		let uri = uri.require_network(network).map_err(|_| Error::InvalidNetwork)?;
+		Ok(Self {
+			address: Some(uri.address),
+			amount: uri.amount,
+			label: uri.label.and_then(|label| String::try_from(label).ok()),
+			message: uri.message.and_then(|message| String::try_from(message).ok()),
+			extras: uri.extras,
+		})
+	}
+
+	fn parse_addressless(query: &str) -> Result<Self, Error> {
+		let mut amount = None;
+		let mut label = None;
+		let mut message = None;
+		let mut state = DeserializationState::default();
+		for param in query.split('&').filter(|param| !param.is_empty()) {
+			let (key, value) = param.split_once('=').ok_or(Error::InvalidUri)?;
+			if key == "amount" {
+				let parsed_amount = Amount::from_str_in(value, Denomination::Bitcoin)
+					.map_err(|_| Error::InvalidUri)?;
+				amount = Some(parsed_amount);
+				continue;
+			}
+
+			let value = percent_decode_param(value)?;
+			match key {
+				"label" => {
+					label = Some(value);
+					continue;
+				},
+				"message" => {
+					message = Some(value);
+					continue;
+				},
+				_ => {},
+			}
+			let kind =
+				state.deserialize_temp(key, Param::from(value)).map_err(|_| Error::InvalidUri)?;
+			// As per BIP 21, URIs with unknown required parameters must be rejected.
+			if matches!(kind, ParamKind::Unknown) && key.starts_with("req-") {
+				return Err(Error::InvalidUri);
+			}
+		}
+
+		let extras = state.finalize()?;
+		Ok(Self { address: None, amount, label, message, extras })
+	}
+}
+
+// Returns the query of the given URI if it's a `bitcoin:` URI lacking an on-chain address.
+fn addressless_uri_query(uri_str: &str) -> Option<&str> {
+	let scheme = "bitcoin:";
+	if !uri_str.get(..scheme.len())?.eq_ignore_ascii_case(scheme) {
+		return None;
+	}
+	uri_str[scheme.len()..].strip_prefix('?')
+}
+
+// Decodes a percent-encoded URI parameter value.
+fn percent_decode_param(value: &str) -> Result<String, Error> {
+	let bytes = value.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'%' {
+			let hex = value.get(i + 1..i + 3).ok_or(Error::InvalidUri)?;
+			if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+				return Err(Error::InvalidUri);
+			}
+			decoded.push(u8::from_str_radix(hex, 16).map_err(|_| Error::InvalidUri)?);
+			i += 3;
+		} else {
+			decoded.push(bytes[i]);
+			i += 1;
+		}
+	}
+	String::from_utf8(decoded).map_err(|_| Error::InvalidUri)
+}
+
 fn format_uri(uri: bip21::Uri<NetworkChecked, Extras>) -> String {
 	let mut uri = format!("{:#}", uri);
 
@@ -227,26 +737,43 @@ impl<'a> SerializeParams for &'a Extras {
 		if let Some(bolt12_offer) = &self.bolt12_offer {
 			params.push(("lno", bolt12_offer.to_string()));
 		}
+		if let Some(payjoin_endpoint) = &self.payjoin_endpoint {
+			params.push(("pj", percent_encode_param(payjoin_endpoint)));
+		}
 
 		params.into_iter()
 	}
 }
 
+// `bip21` leaves encoding extra parameters to us, so we need to make sure any URL reserved
+// characters the payjoin endpoint may contain don't clash with the URI's own.
+fn percent_encode_param(value: &str) -> String {
+	let mut encoded = String::with_capacity(value.len());
+	for c in value.chars() {
+		match c {
+			'%' | '&' | '?' | '=' | '#' | ' ' => encoded.push_str(&format!("%{:02X}", c as u8)),
+			_ => encoded.push(c),
+		}
+	}
+	encoded
+}
+
 impl<'a> DeserializeParams<'a> for Extras {
 	type DeserializationState = DeserializationState;
 }
 
 #[derive(Default)]
-struct DeserializationState {
+pub(crate) struct DeserializationState {
 	bolt11_invoice: Option<Bolt11Invoice>,
 	bolt12_offer: Option<Offer>,
+	payjoin_endpoint: Option<String>,
 }
 
 impl<'a> bip21::de::DeserializationState<'a> for DeserializationState {
 	type Value = Extras;
 
 	fn is_param_known(&self, key: &str) -> bool {
-		key == "lightning" || key == "lno"
+		key == "lightning" || key == "lno" || key == "pj"
 	}
 
 	fn deserialize_temp(
@@ -270,12 +797,22 @@ impl<'a> bip21::de::DeserializationState<'a> for DeserializationState {
 				self.bolt12_offer = Some(offer);
 				Ok(bip21::de::ParamKind::Known)
 			},
+			"pj" => {
+				let payjoin_endpoint =
+					String::try_from(value).map_err(|_| Error::UriParameterParsingFailed)?;
+				self.payjoin_endpoint = Some(payjoin_endpoint);
+				Ok(bip21::de::ParamKind::Known)
+			},
 			_ => Ok(bip21::de::ParamKind::Unknown),
 		}
 	}
 
 	fn finalize(self) -> Result<Self::Value, <Self::Value as DeserializationError>::Error> {
-		Ok(Extras { bolt11_invoice: self.bolt11_invoice, bolt12_offer: self.bolt12_offer })
+		Ok(Extras {
+			bolt11_invoice: self.bolt11_invoice,
+			bolt12_offer: self.bolt12_offer,
+			payjoin_endpoint: self.payjoin_endpoint,
+		})
 	}
 }
 
@@ -414,4 +951,43 @@ mod tests {
 			panic!("No invoice found");
 		}
 	}
+
+	#[test]
+	fn parse_addressless_uri() {
+		let invoice_str = "lnbc1pn8g249pp5f6ytj32ty90jhvw69enf30hwfgdhyymjewywcmfjevflg6s4z86qdqqcqzzgxqyz5vqrzjqwnvuc0u4txn35cafc7w94gxvq5p3cu9dd95f7hlrh0fvs46wpvhdfjjzh2j9f7ye5qqqqryqqqqthqqpysp5mm832athgcal3m7h35sc29j63lmgzvwc5smfjh2es65elc2ns7dq9qrsgqu2xcje2gsnjp0wn97aknyd3h58an7sjj6nhcrm40846jxphv47958c6th76whmec8ttr2wmg6sxwchvxmsc00kqrzqcga6lvsf9jtqgqy5yexa";
+		let invoice = Bolt11Invoice::from_str(invoice_str).unwrap();
+
+		let uri_str = format!(
+			"BITCOIN:?amount=0.0001&lightning={}&pj=https%3A%2F%2Fexample.com%3Fa%3D1&foo=bar",
+			invoice_str
+		);
+		let uri = PaymentUri::parse(&uri_str, Network::Bitcoin).unwrap();
+		assert_eq!(uri.address, None);
+		assert_eq!(uri.amount, Some(Amount::from_sat(10_000)));
+		assert_eq!(uri.extras.bolt11_invoice, Some(invoice));
+		assert_eq!(uri.extras.bolt12_offer, None);
+		assert_eq!(uri.extras.payjoin_endpoint, Some("https://example.com?a=1".to_string()));
+
+		let uri_str = format!("bitcoin:?lightning={}&req-foo=bar", invoice_str);
+		assert!(matches!(PaymentUri::parse(&uri_str, Network::Bitcoin), Err(Error::InvalidUri)));
+		assert!(matches!(
+			PaymentUri::parse("bitcoin:?lightning=lnbc1invalid", Network::Bitcoin),
+			Err(Error::InvalidUri)
+		));
+		assert!(matches!(
+			PaymentUri::parse("bitcoin:?pj=%zz", Network::Bitcoin),
+			Err(Error::InvalidUri)
+		));
+
+		// URIs including an address are still parsed as before.
+		let address_str = "bc1qwe94y974pjl9kg5afg8tmsc0nz4hct04u78hdhukxvnnphgu48hs9lx3k5";
+		let uri_str = format!("bitcoin:{}?lightning={}", address_str, invoice_str);
+		let uri = PaymentUri::parse(&uri_str, Network::Bitcoin).unwrap();
+		let address = Address::from_str(address_str).unwrap().assume_checked();
+		assert_eq!(uri.address, Some(address));
+		assert!(matches!(
+			PaymentUri::parse(&uri_str, Network::Testnet),
+			Err(Error::InvalidNetwork)
+		));
+	}
 }
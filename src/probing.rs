@@ -0,0 +1,388 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+use crate::config::{
+	ProbingConfig, LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA, PROBING_HIGH_CAPACITY_NODES_COUNT,
+	PROBING_INTERVAL_MINIMUM_SECS,
+};
+use crate::logger::{log_debug, log_error, log_info, log_trace, Logger};
+use crate::types::{ChannelManager, Graph, Router};
+use crate::Error;
+
+use lightning::ln::channelmanager::PaymentId;
+use lightning::routing::router::{Path, PaymentParameters, RouteHop, RouteParameters, Router as _};
+
+use bitcoin::secp256k1::PublicKey;
+
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// The outcome of a payment probe sent via [`Node::probe`].
+///
+/// [`Node::probe`]: crate::Node::probe
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProbeResult {
+	/// The probe reached the destination, i.e., the path had sufficient liquidity.
+	Succeeded {
+		/// The hops of the successfully probed path.
+		path: Vec<ProbeHop>,
+		/// The total routing fee, in millisatoshis, a payment along the path would incur.
+		fee_msat: u64,
+	},
+	/// The probe failed before reaching the destination.
+	Failed {
+		/// The short channel id of the channel at which the probe failed, if known.
+		short_channel_id: Option<u64>,
+	},
+}
+
+/// A hop of a successfully probed path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProbeHop {
+	/// The node id of the node at the end of this hop.
+	pub node_id: PublicKey,
+	/// The short channel id of the channel used for this hop.
+	pub short_channel_id: u64,
+	/// The fee, in millisatoshis, charged by the node at the end of this hop.
+	///
+	/// For the last hop this is the amount that was probed.
+	pub fee_msat: u64,
+	/// The CLTV expiry delta required by the node at the end of this hop.
+	pub cltv_expiry_delta: u32,
+}
+
+impl From<&RouteHop> for ProbeHop {
+	fn from(value: &RouteHop) -> Self {
+		Self {
+			node_id: value.pubkey,
+			short_channel_id: value.short_channel_id,
+			fee_msat: value.fee_msat,
+			cltv_expiry_delta: value.cltv_expiry_delta,
+		}
+	}
+}
+
+pub(crate) struct Prober<L: Deref + Clone + Sync + Send>
+where
+	L::Target: Logger,
+{
+	probes_in_flight: Mutex<HashMap<PaymentId, u64>>,
+	pending_probes: Mutex<HashMap<PaymentId, tokio::sync::oneshot::Sender<ProbeResult>>>,
+	channel_manager: Arc<ChannelManager>,
+	router: Arc<Router>,
+	network_graph: Arc<Graph>,
+	logger: L,
+}
+
+impl<L: Deref + Clone + Sync + Send> Prober<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		channel_manager: Arc<ChannelManager>, router: Arc<Router>, network_graph: Arc<Graph>,
+		logger: L,
+	) -> Self {
+		let probes_in_flight = Mutex::new(HashMap::new());
+		let pending_probes = Mutex::new(HashMap::new());
+		Self { probes_in_flight, pending_probes, channel_manager, router, network_graph, logger }
+	}
+
+	pub(crate) async fn continuously_probe(
+		&self, probing_config: &ProbingConfig,
+		mut stop_probing_receiver: tokio::sync::watch::Receiver<()>,
+	) {
+		let probing_interval_secs = probing_config.interval_secs.max(PROBING_INTERVAL_MINIMUM_SECS);
+		let mut probing_interval =
+			tokio::time::interval(Duration::from_secs(probing_interval_secs));
+		probing_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+		// Don't probe right away on startup, but give us a chance to connect to our peers first.
+		probing_interval.reset();
+
+		loop {
+			tokio::select! {
+				_ = stop_probing_receiver.changed() => {
+					log_trace!(
+						self.logger,
+						"Stopping background probing.",
+					);
+					return;
+				}
+				_ = probing_interval.tick() => {
+					let _ = self.probe_random_destination(probing_config);
+				}
+			}
+		}
+	}
+
+	/// Sends a probe of a random amount to a destination picked according to the given config.
+	pub(crate) fn probe_random_destination(
+		&self, probing_config: &ProbingConfig,
+	) -> Result<(), Error> {
+		let destination = match self.select_destination(probing_config) {
+			Some(destination) => destination,
+			None => {
+				log_debug!(self.logger, "Skipping background probing as no destination was found.");
+				return Ok(());
+			},
+		};
+
+		let amount_msat = random_probe_amount_msat(probing_config);
+
+		let locked_msat = self.locked_msat();
+		if exceeds_locked_limit(locked_msat, amount_msat, probing_config) {
+			log_debug!(
+				self.logger,
+				"Skipping background probing as {}msat are already locked in probes in flight.",
+				locked_msat
+			);
+			return Ok(());
+		}
+
+		let path =
+			self.find_probe_path(destination, amount_msat, Some(probing_config.max_fee_msat))?;
+		let path_amount_msat = path.final_value_msat().saturating_add(path.fee_msat());
+
+		let mut locked_probes = self.probes_in_flight.lock().unwrap();
+		let locked_msat = locked_probes.values().sum::<u64>();
+		if exceeds_locked_limit(locked_msat, path_amount_msat, probing_config) {
+			return Ok(());
+		}
+
+		let payment_id = self.send_probe_along_path(path)?;
+		log_debug!(self.logger, "Sent background probe of {}msat to {}", amount_msat, destination);
+		locked_probes.insert(payment_id, path_amount_msat);
+		Ok(())
+	}
+
+	/// Sends a probe of the given amount to the given destination and waits for its outcome.
+	///
+	/// Returns [`Error::ProbeTimeout`] if the outcome isn't known before the given timeout.
+	pub(crate) async fn probe(
+		&self, destination: PublicKey, amount_msat: u64, timeout: Duration,
+	) -> Result<ProbeResult, Error> {
+		let path = self.find_probe_path(destination, amount_msat, None)?;
+
+		let (payment_id, result_receiver) = {
+			// We hold the lock while sending to make sure we register before the outcome is
+			// handled.
+			let mut pending_probes_lock = self.pending_probes.lock().unwrap();
+			let payment_id = self.send_probe_along_path(path)?;
+			let (tx, rx) = tokio::sync::oneshot::channel();
+			pending_probes_lock.insert(payment_id, tx);
+			(payment_id, rx)
+		};
+
+		log_info!(self.logger, "Sent probe of {}msat to {}", amount_msat, destination);
+
+		match tokio::time::timeout(timeout, result_receiver).await {
+			Ok(res) => res.map_err(|e| {
+				debug_assert!(false, "Failed to receive probe result: {:?}", e);
+				log_error!(self.logger, "Failed to receive probe result: {:?}", e);
+				Error::ProbeSendingFailed
+			}),
+			Err(e) => {
+				// Stop waiting for the outcome, which might never arrive.
+				self.pending_probes.lock().unwrap().remove(&payment_id);
+				log_error!(self.logger, "Probing {} timed out: {}", destination, e);
+				Err(Error::ProbeTimeout)
+			},
+		}
+	}
+
+	/// Handles the outcome of a successful probe with the given id.
+	pub(crate) fn handle_probe_successful(&self, payment_id: &PaymentId, path: &Path) {
+		self.handle_probe_outcome(payment_id);
+		let res = ProbeResult::Succeeded {
+			path: path.hops.iter().map(|hop| hop.into()).collect(),
+			fee_msat: path.fee_msat(),
+		};
+		self.propagate_result_to_subscriber(payment_id, res);
+	}
+
+	/// Handles the outcome of a failed probe with the given id.
+	pub(crate) fn handle_probe_failed(
+		&self, payment_id: &PaymentId, short_channel_id: Option<u64>,
+	) {
+		self.handle_probe_outcome(payment_id);
+		self.propagate_result_to_subscriber(payment_id, ProbeResult::Failed { short_channel_id });
+	}
+
+	fn propagate_result_to_subscriber(&self, payment_id: &PaymentId, res: ProbeResult) {
+		if let Some(sender) = self.pending_probes.lock().unwrap().remove(payment_id) {
+			// The receiver might be gone already if the caller timed out, which is fine.
+			let _ = sender.send(res);
+		}
+	}
+
+	fn find_probe_path(
+		&self, destination: PublicKey, amount_msat: u64, max_fee_msat: Option<u64>,
+	) -> Result<Path, Error> {
+		let payment_params =
+			PaymentParameters::from_node_id(destination, LDK_DEFAULT_FINAL_CLTV_EXPIRY_DELTA);
+		let mut route_params =
+			RouteParameters::from_payment_params_and_value(payment_params, amount_msat);
+		route_params.payment_params.max_path_count = 1;
+		if let Some(max_fee_msat) = max_fee_msat {
+			route_params.max_total_routing_fee_msat = Some(max_fee_msat);
+		}
+
+		let payer = self.channel_manager.get_our_node_id();
+		let usable_channels = self.channel_manager.list_usable_channels();
+		let first_hops = usable_channels.iter().collect::<Vec<_>>();
+		let inflight_htlcs = self.channel_manager.compute_inflight_htlcs();
+
+		let route = self
+			.router
+			.find_route(&payer, &route_params, Some(&first_hops), inflight_htlcs)
+			.map_err(|e| {
+				log_debug!(
+					self.logger,
+					"Failed to find route for probing {} with {}msat: {:?}",
+					destination,
+					amount_msat,
+					e
+				);
+				Error::ProbeSendingFailed
+			})?;
+
+		let path = route.paths.into_iter().next().ok_or(Error::ProbeSendingFailed)?;
+
+		if path.hops.len() < 2 {
+			log_debug!(
+				self.logger,
+				"Refusing to probe {} as the path has less than two hops.",
+				destination
+			);
+			return Err(Error::ProbeSendingFailed);
+		}
+
+		Ok(path)
+	}
+
+	fn send_probe_along_path(&self, path: Path) -> Result<PaymentId, Error> {
+		self.channel_manager.send_probe(path).map(|(_, payment_id)| payment_id).map_err(|e| {
+			log_error!(self.logger, "Failed to send probe: {:?}", e);
+			Error::ProbeSendingFailed
+		})
+	}
+
+	// Releases the liquidity locked by the background probe with the given id once it resolved.
+	fn handle_probe_outcome(&self, payment_id: &PaymentId) {
+		self.probes_in_flight.lock().unwrap().remove(payment_id);
+	}
+
+	fn locked_msat(&self) -> u64 {
+		self.probes_in_flight.lock().unwrap().values().sum()
+	}
+
+	fn select_destination(&self, probing_config: &ProbingConfig) -> Option<PublicKey> {
+		if !probing_config.destinations.is_empty() {
+			return probing_config.destinations.choose(&mut thread_rng()).copied();
+		}
+
+		let our_node_id = self.channel_manager.get_our_node_id();
+		let graph = self.network_graph.read_only();
+		let node_capacities = graph.nodes().unordered_iter().filter_map(|(node_id, node_info)| {
+			let capacity_sats = node_info
+				.channels
+				.iter()
+				.filter_map(|scid| graph.channels().get(scid))
+				.filter_map(|channel| channel.capacity_sats)
+				.sum::<u64>();
+			node_id.as_pubkey().ok().map(|pubkey| (pubkey, capacity_sats))
+		});
+
+		highest_capacity_nodes(node_capacities, &our_node_id).choose(&mut thread_rng()).copied()
+	}
+}
+
+// Picks a random probe amount within the configured bounds.
+fn random_probe_amount_msat(probing_config: &ProbingConfig) -> u64 {
+	let min_amount_msat = probing_config.min_amount_msat;
+	let max_amount_msat = probing_config.max_amount_msat.max(min_amount_msat);
+	thread_rng().gen_range(min_amount_msat..=max_amount_msat)
+}
+
+// Returns whether locking another `amount_msat` in probes would exceed the configured limit.
+fn exceeds_locked_limit(
+	locked_msat: u64, amount_msat: u64, probing_config: &ProbingConfig,
+) -> bool {
+	locked_msat.saturating_add(amount_msat) > probing_config.max_locked_msat
+}
+
+// Returns the nodes with the highest total channel capacity we pick background probing
+// destinations from, never including ourselves.
+fn highest_capacity_nodes(
+	node_capacities: impl Iterator<Item = (PublicKey, u64)>, our_node_id: &PublicKey,
+) -> Vec<PublicKey> {
+	let mut nodes_by_capacity =
+		node_capacities.filter(|(pubkey, _)| pubkey != our_node_id).collect::<Vec<_>>();
+	nodes_by_capacity.sort_unstable_by_key(|(_, capacity_sats)| std::cmp::Reverse(*capacity_sats));
+	nodes_by_capacity.truncate(PROBING_HIGH_CAPACITY_NODES_COUNT);
+	nodes_by_capacity.into_iter().map(|(pubkey, _)| pubkey).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	use bitcoin::secp256k1::{Secp256k1, SecretKey};
+
+	fn pubkey(byte: u8) -> PublicKey {
+		let secp_ctx = Secp256k1::new();
+		PublicKey::from_secret_key(&secp_ctx, &SecretKey::from_slice(&[byte; 32]).unwrap())
+	}
+
+	#[test]
+	fn probe_amount_is_within_bounds() {
+		let mut probing_config = ProbingConfig::default();
+		probing_config.min_amount_msat = 1_000;
+		probing_config.max_amount_msat = 5_000;
+		for _ in 0..100 {
+			let amount_msat = random_probe_amount_msat(&probing_config);
+			assert!((1_000..=5_000).contains(&amount_msat));
+		}
+
+		// A maximum below the minimum is ignored.
+		probing_config.max_amount_msat = 10;
+		assert_eq!(random_probe_amount_msat(&probing_config), 1_000);
+	}
+
+	#[test]
+	fn locked_limit_is_enforced() {
+		let mut probing_config = ProbingConfig::default();
+		probing_config.max_locked_msat = 10_000;
+		assert!(!exceeds_locked_limit(0, 10_000, &probing_config));
+		assert!(!exceeds_locked_limit(4_000, 6_000, &probing_config));
+		assert!(exceeds_locked_limit(4_000, 6_001, &probing_config));
+		assert!(exceeds_locked_limit(u64::MAX, 1, &probing_config));
+	}
+
+	#[test]
+	fn destinations_are_highest_capacity_nodes() {
+		let our_node_id = pubkey(1);
+		let nodes =
+			vec![(pubkey(2), 100), (our_node_id, 1_000), (pubkey(3), 300), (pubkey(4), 200)];
+		assert_eq!(
+			highest_capacity_nodes(nodes.into_iter(), &our_node_id),
+			vec![pubkey(3), pubkey(4), pubkey(2)]
+		);
+
+		let many_nodes = (0..PROBING_HIGH_CAPACITY_NODES_COUNT as u64 + 10)
+			.map(|i| (pubkey(i as u8 + 2), i))
+			.collect::<Vec<_>>();
+		let destinations = highest_capacity_nodes(many_nodes.into_iter(), &our_node_id);
+		assert_eq!(destinations.len(), PROBING_HIGH_CAPACITY_NODES_COUNT);
+		assert!(!destinations.contains(&pubkey(2)));
+		assert_eq!(destinations[0], pubkey(PROBING_HIGH_CAPACITY_NODES_COUNT as u8 + 11));
+	}
+}
@@ -0,0 +1,635 @@
+// This file is Copyright its original authors, visible in version control history.
+//
+// This file is licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
+// accordance with one or both of these licenses.
+
+//! Objects related to scheduling recurring payments.
+
+use crate::config::{
+	ScheduledPaymentSpendingLimit, SCHEDULED_PAYMENTS_CHECK_INTERVAL,
+	SCHEDULED_PAYMENT_MAX_RECENT_EXECUTIONS,
+};
+use crate::event::{Event, EventQueue};
+use crate::hex_utils;
+use crate::io::{
+	SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+	SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE, SCHEDULED_PAYMENT_SPENDING_KEY,
+	SCHEDULED_PAYMENT_SPENDING_PRIMARY_NAMESPACE, SCHEDULED_PAYMENT_SPENDING_SECONDARY_NAMESPACE,
+};
+use crate::logger::{log_error, log_info, log_trace, Logger};
+use crate::payment::PaymentStatus;
+use crate::types::DynStore;
+use crate::Error;
+
+use lightning::ln::channelmanager::PaymentId;
+use lightning::offers::offer::Offer;
+use lightning::util::ser::{Readable, Writeable, Writer};
+use lightning::{impl_writeable_tlv_based, impl_writeable_tlv_based_enum};
+
+use bitcoin::secp256k1::PublicKey;
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A local identifier of a recurring payment scheduled via [`Node::schedule_payment`].
+///
+/// [`Node::schedule_payment`]: crate::Node::schedule_payment
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ScheduledPaymentId(pub [u8; 32]);
+
+impl Writeable for ScheduledPaymentId {
+	fn write<W: Writer>(&self, writer: &mut W) -> Result<(), lightning::io::Error> {
+		self.0.write(writer)
+	}
+}
+
+impl Readable for ScheduledPaymentId {
+	fn read<R: lightning::io::Read>(
+		reader: &mut R,
+	) -> Result<Self, lightning::ln::msgs::DecodeError> {
+		Ok(Self(Readable::read(reader)?))
+	}
+}
+
+/// The recipient of a recurring payment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduledPaymentTarget {
+	/// A BOLT12 offer that is paid on every execution.
+	Bolt12Offer {
+		/// The offer to pay.
+		offer: Offer,
+	},
+	/// A node that is sent a spontaneous ("keysend") payment on every execution.
+	Spontaneous {
+		/// The node id of the recipient.
+		node_id: PublicKey,
+	},
+}
+
+impl_writeable_tlv_based_enum!(ScheduledPaymentTarget,
+	(0, Bolt12Offer) => {
+		(0, offer, required),
+	},
+	(2, Spontaneous) => {
+		(0, node_id, required),
+	}
+);
+
+/// A payment made as part of a recurring payment schedule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledPaymentExecution {
+	/// The id of the payment that was initiated.
+	pub payment_id: PaymentId,
+	/// The amount, in millisatoshis, that was sent.
+	pub amount_msat: u64,
+	/// The timestamp, in seconds since start of the UNIX epoch, when the payment was initiated.
+	pub executed_at: u64,
+	/// The status of the payment.
+	pub status: PaymentStatus,
+}
+
+impl_writeable_tlv_based!(ScheduledPaymentExecution, {
+	(0, payment_id, required),
+	(2, amount_msat, required),
+	(4, executed_at, required),
+	(6, status, required),
+});
+
+/// Details of a recurring payment scheduled via [`Node::schedule_payment`].
+///
+/// [`Node::schedule_payment`]: crate::Node::schedule_payment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledPaymentDetails {
+	/// The identifier of the schedule.
+	pub id: ScheduledPaymentId,
+	/// The recipient of the payments.
+	pub target: ScheduledPaymentTarget,
+	/// The amount, in millisatoshis, sent on every execution.
+	pub amount_msat: u64,
+	/// The time in-between executions, in seconds.
+	pub interval_secs: u64,
+	/// The timestamp, in seconds since start of the UNIX epoch, of the next execution.
+	///
+	/// Will be `None` if the schedule completed, i.e., [`Self::max_count`] payments were made.
+	pub next_execution_at: Option<u64>,
+	/// The maximum number of payments to make, if any.
+	pub max_count: Option<u64>,
+	/// The number of payments made so far.
+	pub execution_count: u64,
+	/// The most recent payments made, oldest first.
+	///
+	/// Only the last 100 payments are kept.
+	pub executions: Vec<ScheduledPaymentExecution>,
+}
+
+impl_writeable_tlv_based!(ScheduledPaymentDetails, {
+	(0, id, required),
+	(2, target, required),
+	(4, amount_msat, required),
+	(6, interval_secs, required),
+	(8, next_execution_at, option),
+	(10, max_count, option),
+	(12, executions, optional_vec),
+	(14, execution_count, required),
+});
+
+/// The reason why a scheduled payment could not be made.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduledPaymentFailureReason {
+	/// The payment would have exceeded the configured [`ScheduledPaymentSpendingLimit`].
+	///
+	/// [`ScheduledPaymentSpendingLimit`]: crate::config::ScheduledPaymentSpendingLimit
+	SpendingLimitExceeded,
+	/// We failed to initiate the payment.
+	SendingFailed,
+}
+
+impl_writeable_tlv_based_enum!(ScheduledPaymentFailureReason,
+	(0, SpendingLimitExceeded) => {},
+	(2, SendingFailed) => {}
+);
+
+/// The amount spent by scheduled payments within the spending limit period starting at
+/// `period_start`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct ScheduledPaymentSpending {
+	period_start: u64,
+	spent_msat: u64,
+}
+
+impl_writeable_tlv_based!(ScheduledPaymentSpending, {
+	(0, period_start, required),
+	(2, spent_msat, required),
+});
+
+pub(crate) struct PaymentScheduler<L: Deref + Clone + Sync + Send>
+where
+	L::Target: Logger,
+{
+	schedules: Mutex<HashMap<ScheduledPaymentId, ScheduledPaymentDetails>>,
+	spending_limit: Option<ScheduledPaymentSpendingLimit>,
+	spending: Mutex<ScheduledPaymentSpending>,
+	event_queue: Arc<EventQueue<L>>,
+	kv_store: Arc<DynStore>,
+	logger: L,
+}
+
+impl<L: Deref + Clone + Sync + Send> PaymentScheduler<L>
+where
+	L::Target: Logger,
+{
+	pub(crate) fn new(
+		schedules: Vec<ScheduledPaymentDetails>,
+		spending_limit: Option<ScheduledPaymentSpendingLimit>, spending: ScheduledPaymentSpending,
+		event_queue: Arc<EventQueue<L>>, kv_store: Arc<DynStore>, logger: L,
+	) -> Self {
+		let schedules = Mutex::new(HashMap::from_iter(schedules.into_iter().map(|s| (s.id, s))));
+		let spending = Mutex::new(spending);
+		Self { schedules, spending_limit, spending, event_queue, kv_store, logger }
+	}
+
+	pub(crate) fn insert(&self, schedule: ScheduledPaymentDetails) -> Result<(), Error> {
+		let mut locked_schedules = self.schedules.lock().unwrap();
+		self.persist_info(&schedule)?;
+		locked_schedules.insert(schedule.id, schedule);
+		Ok(())
+	}
+
+	pub(crate) fn remove(&self, id: &ScheduledPaymentId) -> Result<(), Error> {
+		let mut locked_schedules = self.schedules.lock().unwrap();
+		if !locked_schedules.contains_key(id) {
+			return Err(Error::InvalidScheduledPaymentId);
+		}
+
+		let store_key = hex_utils::to_string(&id.0);
+		self.kv_store
+			.remove(
+				SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				false,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Removing scheduled payment data for key {}/{}/{} failed due to: {}",
+					SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+					SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})?;
+
+		locked_schedules.remove(id);
+		Ok(())
+	}
+
+	pub(crate) fn list(&self) -> Vec<ScheduledPaymentDetails> {
+		self.schedules.lock().unwrap().values().cloned().collect::<Vec<ScheduledPaymentDetails>>()
+	}
+
+	pub(crate) async fn continuously_execute<F>(
+		&self, execute: F, mut stop_receiver: tokio::sync::watch::Receiver<()>,
+	) where
+		F: Fn(&ScheduledPaymentTarget, u64) -> Result<PaymentId, Error>,
+	{
+		let mut interval = tokio::time::interval(SCHEDULED_PAYMENTS_CHECK_INTERVAL);
+		interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+		// Give us a chance to connect to our peers before we try to make any payments.
+		interval.reset();
+
+		loop {
+			tokio::select! {
+				_ = stop_receiver.changed() => {
+					log_trace!(
+						self.logger,
+						"Stopping executing scheduled payments.",
+					);
+					return;
+				}
+				_ = interval.tick() => {
+					let now = SystemTime::now()
+						.duration_since(UNIX_EPOCH)
+						.expect("system time must be after Unix epoch")
+						.as_secs();
+					self.execute_due_payments(&execute, now);
+				}
+			}
+		}
+	}
+
+	/// Makes all payments due at the given time, i.e., at most one payment per schedule.
+	pub(crate) fn execute_due_payments<F>(&self, execute: &F, now: u64)
+	where
+		F: Fn(&ScheduledPaymentTarget, u64) -> Result<PaymentId, Error>,
+	{
+		let due_schedules = self
+			.schedules
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|s| s.next_execution_at.map_or(false, |t| t <= now))
+			.cloned()
+			.collect::<Vec<_>>();
+
+		for schedule in due_schedules {
+			let res = if !self.reserve_spending(schedule.amount_msat, now) {
+				log_error!(
+					self.logger,
+					"Skipping scheduled payment {} as it would exceed the spending limit.",
+					hex_utils::to_string(&schedule.id.0)
+				);
+				Err(ScheduledPaymentFailureReason::SpendingLimitExceeded)
+			} else {
+				execute(&schedule.target, schedule.amount_msat).map_err(|e| {
+					log_error!(
+						self.logger,
+						"Failed to execute scheduled payment {}: {}",
+						hex_utils::to_string(&schedule.id.0),
+						e
+					);
+					self.release_spending(schedule.amount_msat, now);
+					ScheduledPaymentFailureReason::SendingFailed
+				})
+			};
+
+			let execution = res.ok().map(|payment_id| ScheduledPaymentExecution {
+				payment_id,
+				amount_msat: schedule.amount_msat,
+				executed_at: now,
+				status: PaymentStatus::Pending,
+			});
+
+			if let Err(e) = self.record_execution(&schedule.id, execution, now) {
+				log_error!(self.logger, "Failed to update scheduled payment: {}", e);
+			}
+
+			let event = match res {
+				Ok(payment_id) => {
+					log_info!(
+						self.logger,
+						"Executed scheduled payment {} with payment id {}.",
+						hex_utils::to_string(&schedule.id.0),
+						payment_id
+					);
+					Event::ScheduledPaymentExecuted {
+						schedule_id: schedule.id,
+						payment_id,
+						amount_msat: schedule.amount_msat,
+					}
+				},
+				Err(reason) => Event::ScheduledPaymentFailed { schedule_id: schedule.id, reason },
+			};
+
+			if let Err(e) = self.event_queue.add_event(event) {
+				log_error!(self.logger, "Failed to push to event queue: {}", e);
+			}
+		}
+	}
+
+	/// Updates the status of the scheduled payment with the given id, if any, once it succeeded or
+	/// failed.
+	///
+	/// Failed payments no longer count toward the spending limit.
+	pub(crate) fn update_payment_status(
+		&self, payment_id: &PaymentId, status: PaymentStatus,
+	) -> Result<(), Error> {
+		let mut locked_schedules = self.schedules.lock().unwrap();
+		for schedule in locked_schedules.values_mut() {
+			let execution = match schedule
+				.executions
+				.iter()
+				.position(|e| &e.payment_id == payment_id && e.status == PaymentStatus::Pending)
+			{
+				Some(idx) => idx,
+				None => continue,
+			};
+
+			let mut updated_schedule = schedule.clone();
+			updated_schedule.executions[execution].status = status;
+			self.persist_info(&updated_schedule)?;
+			*schedule = updated_schedule;
+
+			if status == PaymentStatus::Failed {
+				let execution = &schedule.executions[execution];
+				self.release_spending(execution.amount_msat, execution.executed_at);
+			}
+			break;
+		}
+		Ok(())
+	}
+
+	// Adds the given amount to the total spent within the current period, returning `false` if
+	// this would exceed the spending limit.
+	fn reserve_spending(&self, amount_msat: u64, now: u64) -> bool {
+		let spending_limit = match self.spending_limit.as_ref() {
+			Some(spending_limit) => spending_limit,
+			None => return true,
+		};
+
+		let period_start = now - now % spending_limit.period_secs.max(1);
+		let mut locked_spending = self.spending.lock().unwrap();
+		let spent_msat = if locked_spending.period_start == period_start {
+			locked_spending.spent_msat
+		} else {
+			0
+		};
+		let updated_spent_msat = spent_msat.saturating_add(amount_msat);
+		if updated_spent_msat > spending_limit.max_amount_msat {
+			return false;
+		}
+
+		let updated_spending =
+			ScheduledPaymentSpending { period_start, spent_msat: updated_spent_msat };
+		// Better to fail the payment than to lose track of what we spent.
+		if self.persist_spending(&updated_spending).is_err() {
+			return false;
+		}
+		*locked_spending = updated_spending;
+		true
+	}
+
+	// Deducts the amount of a payment initiated at `executed_at` from the total spent within the
+	// current period, if it was counted toward it.
+	fn release_spending(&self, amount_msat: u64, executed_at: u64) {
+		if self.spending_limit.is_none() {
+			return;
+		}
+
+		let mut locked_spending = self.spending.lock().unwrap();
+		if executed_at < locked_spending.period_start {
+			return;
+		}
+
+		let updated_spending = ScheduledPaymentSpending {
+			period_start: locked_spending.period_start,
+			spent_msat: locked_spending.spent_msat.saturating_sub(amount_msat),
+		};
+		if let Err(e) = self.persist_spending(&updated_spending) {
+			log_error!(self.logger, "Failed to update scheduled payment spending: {}", e);
+		}
+		*locked_spending = updated_spending;
+	}
+
+	// Records the outcome of the execution of a schedule and advances it to the next interval
+	// after `now`.
+	fn record_execution(
+		&self, id: &ScheduledPaymentId, execution: Option<ScheduledPaymentExecution>, now: u64,
+	) -> Result<(), Error> {
+		let mut locked_schedules = self.schedules.lock().unwrap();
+		let schedule = match locked_schedules.get_mut(id) {
+			Some(schedule) => schedule,
+			// The schedule was cancelled in the meantime.
+			None => return Ok(()),
+		};
+
+		let mut updated_schedule = schedule.clone();
+		if let Some(execution) = execution {
+			updated_schedule.execution_count += 1;
+			if updated_schedule.executions.len() >= SCHEDULED_PAYMENT_MAX_RECENT_EXECUTIONS {
+				updated_schedule.executions.remove(0);
+			}
+			updated_schedule.executions.push(execution);
+		}
+
+		let completed = updated_schedule
+			.max_count
+			.map_or(false, |max_count| updated_schedule.execution_count >= max_count);
+		updated_schedule.next_execution_at = if completed {
+			None
+		} else {
+			// If we missed some executions, e.g., as we were offline, we don't try to catch up on
+			// them but continue with the next interval in the future.
+			updated_schedule.next_execution_at.map(|next_execution_at| {
+				let interval_secs = updated_schedule.interval_secs.max(1);
+				let missed_intervals = (now.saturating_sub(next_execution_at) / interval_secs) + 1;
+				next_execution_at.saturating_add(missed_intervals.saturating_mul(interval_secs))
+			})
+		};
+
+		self.persist_info(&updated_schedule)?;
+		*schedule = updated_schedule;
+		Ok(())
+	}
+
+	fn persist_info(&self, schedule: &ScheduledPaymentDetails) -> Result<(), Error> {
+		let store_key = hex_utils::to_string(&schedule.id.0);
+		let data = schedule.encode();
+		self.kv_store
+			.write(
+				SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+				SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+				&store_key,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					SCHEDULED_PAYMENT_PERSISTENCE_PRIMARY_NAMESPACE,
+					SCHEDULED_PAYMENT_PERSISTENCE_SECONDARY_NAMESPACE,
+					store_key,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+
+	fn persist_spending(&self, spending: &ScheduledPaymentSpending) -> Result<(), Error> {
+		let data = spending.encode();
+		self.kv_store
+			.write(
+				SCHEDULED_PAYMENT_SPENDING_PRIMARY_NAMESPACE,
+				SCHEDULED_PAYMENT_SPENDING_SECONDARY_NAMESPACE,
+				SCHEDULED_PAYMENT_SPENDING_KEY,
+				&data,
+			)
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Write for key {}/{}/{} failed due to: {}",
+					SCHEDULED_PAYMENT_SPENDING_PRIMARY_NAMESPACE,
+					SCHEDULED_PAYMENT_SPENDING_SECONDARY_NAMESPACE,
+					SCHEDULED_PAYMENT_SPENDING_KEY,
+					e
+				);
+				Error::PersistenceFailed
+			})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use lightning::util::test_utils::{TestLogger, TestStore};
+
+	use std::str::FromStr;
+
+	fn test_schedule(id: ScheduledPaymentId, max_count: Option<u64>) -> ScheduledPaymentDetails {
+		let node_id = PublicKey::from_str(
+			"02eec7245d6b7d2ccb30380bfbe2a3648cd7a942653f5aa340edcea1f283686619",
+		)
+		.unwrap();
+		ScheduledPaymentDetails {
+			id,
+			target: ScheduledPaymentTarget::Spontaneous { node_id },
+			amount_msat: 1000,
+			interval_secs: 100,
+			next_execution_at: Some(1000),
+			max_count,
+			execution_count: 0,
+			executions: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn scheduled_payments_respect_count_and_spending_limit() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let event_queue = Arc::new(EventQueue::new(Arc::clone(&store), Arc::clone(&logger)));
+		let spending_limit =
+			ScheduledPaymentSpendingLimit { max_amount_msat: 1500, period_secs: 500 };
+		let scheduler = PaymentScheduler::new(
+			Vec::new(),
+			Some(spending_limit),
+			ScheduledPaymentSpending::default(),
+			Arc::clone(&event_queue),
+			store,
+			Arc::clone(&logger),
+		);
+
+		let id = ScheduledPaymentId([42u8; 32]);
+		scheduler.insert(test_schedule(id, Some(3))).unwrap();
+
+		let payment_count = Mutex::new(0u8);
+		let execute = |_: &ScheduledPaymentTarget, _: u64| {
+			let mut payment_count = payment_count.lock().unwrap();
+			*payment_count += 1;
+			Ok(PaymentId([*payment_count; 32]))
+		};
+
+		// Nothing is due yet.
+		scheduler.execute_due_payments(&execute, 999);
+		assert!(scheduler.list()[0].executions.is_empty());
+
+		// Missed executions are skipped.
+		scheduler.execute_due_payments(&execute, 1250);
+		assert_eq!(scheduler.list()[0].execution_count, 1);
+		assert_eq!(scheduler.list()[0].next_execution_at, Some(1300));
+		match event_queue.next_event() {
+			Some(Event::ScheduledPaymentExecuted { schedule_id, amount_msat, .. }) => {
+				assert_eq!(schedule_id, id);
+				assert_eq!(amount_msat, 1000);
+			},
+			e => panic!("Unexpected event: {:?}", e),
+		}
+		event_queue.event_handled().unwrap();
+
+		// The second payment would exceed the spending limit.
+		scheduler.execute_due_payments(&execute, 1300);
+		assert_eq!(scheduler.list()[0].execution_count, 1);
+		assert_eq!(scheduler.list()[0].next_execution_at, Some(1400));
+		assert_eq!(
+			event_queue.next_event(),
+			Some(Event::ScheduledPaymentFailed {
+				schedule_id: id,
+				reason: ScheduledPaymentFailureReason::SpendingLimitExceeded
+			})
+		);
+		event_queue.event_handled().unwrap();
+
+		// Failed payments don't count toward the spending limit.
+		scheduler.update_payment_status(&PaymentId([1u8; 32]), PaymentStatus::Failed).unwrap();
+		assert_eq!(scheduler.list()[0].executions[0].status, PaymentStatus::Failed);
+		scheduler.execute_due_payments(&execute, 1400);
+		assert_eq!(scheduler.list()[0].execution_count, 2);
+		scheduler.update_payment_status(&PaymentId([2u8; 32]), PaymentStatus::Succeeded).unwrap();
+		assert_eq!(scheduler.list()[0].executions[1].status, PaymentStatus::Succeeded);
+
+		// Once the period passed, the last payment is made and the schedule completes.
+		scheduler.execute_due_payments(&execute, 1500);
+		assert_eq!(scheduler.list()[0].execution_count, 3);
+		assert_eq!(scheduler.list()[0].executions.len(), 3);
+		assert_eq!(scheduler.list()[0].next_execution_at, None);
+
+		scheduler.remove(&id).unwrap();
+		assert!(scheduler.list().is_empty());
+		assert_eq!(scheduler.remove(&id), Err(Error::InvalidScheduledPaymentId));
+	}
+
+	#[test]
+	fn scheduled_payments_keep_bounded_history() {
+		let store: Arc<DynStore> = Arc::new(TestStore::new(false));
+		let logger = Arc::new(TestLogger::new());
+		let event_queue = Arc::new(EventQueue::new(Arc::clone(&store), Arc::clone(&logger)));
+		let scheduler = PaymentScheduler::new(
+			Vec::new(),
+			None,
+			ScheduledPaymentSpending::default(),
+			Arc::clone(&event_queue),
+			store,
+			Arc::clone(&logger),
+		);
+
+		let id = ScheduledPaymentId([42u8; 32]);
+		scheduler.insert(test_schedule(id, None)).unwrap();
+
+		let execute = |_: &ScheduledPaymentTarget, _: u64| Ok(PaymentId([1u8; 32]));
+		let execution_count = SCHEDULED_PAYMENT_MAX_RECENT_EXECUTIONS as u64 + 10;
+		for i in 0..execution_count {
+			scheduler.execute_due_payments(&execute, 1000 + i * 100);
+		}
+
+		let schedule = &scheduler.list()[0];
+		assert_eq!(schedule.execution_count, execution_count);
+		assert_eq!(schedule.executions.len(), SCHEDULED_PAYMENT_MAX_RECENT_EXECUTIONS);
+		assert_eq!(schedule.executions[0].executed_at, 2000);
+		assert_eq!(schedule.next_execution_at, Some(1000 + execution_count * 100));
+	}
+}
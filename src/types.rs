@@ -8,16 +8,20 @@
 use crate::chain::ChainSource;
 use crate::config::ChannelConfig;
 use crate::fee_estimator::OnchainFeeEstimator;
+use crate::hrn_resolver::HrnResolver;
 use crate::logger::FilesystemLogger;
-use crate::message_handler::NodeCustomMessageHandler;
+use crate::message_handler::{NodeCustomMessageHandler, NodeOffersMessageHandler};
 
+use lightning::blinded_path::message::OffersContext;
 use lightning::chain::chainmonitor;
 use lightning::impl_writeable_tlv_based;
 use lightning::ln::channel_state::ChannelDetails as LdkChannelDetails;
+use lightning::ln::channelmanager::PaymentId;
 use lightning::ln::msgs::RoutingMessageHandler;
 use lightning::ln::msgs::SocketAddress;
 use lightning::ln::peer_handler::IgnoringMessageHandler;
 use lightning::ln::types::ChannelId;
+use lightning::offers::invoice::Bolt12Invoice;
 use lightning::routing::gossip;
 use lightning::routing::router::DefaultRouter;
 use lightning::routing::scoring::{ProbabilisticScorer, ProbabilisticScoringFeeParameters};
@@ -30,6 +34,7 @@ use lightning_net_tokio::SocketDescriptor;
 use bitcoin::secp256k1::PublicKey;
 use bitcoin::OutPoint;
 
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
 
 pub(crate) type DynStore = dyn KVStore + Sync + Send;
@@ -112,12 +117,16 @@ pub(crate) type OnionMessenger = lightning::onion_message::messenger::OnionMesse
 	Arc<FilesystemLogger>,
 	Arc<ChannelManager>,
 	Arc<MessageRouter>,
-	Arc<ChannelManager>,
-	IgnoringMessageHandler,
+	Arc<NodeOffersMessageHandler<Arc<FilesystemLogger>>>,
 	IgnoringMessageHandler,
+	Arc<HrnResolver<Arc<KeysManager>, Arc<FilesystemLogger>>>,
 	IgnoringMessageHandler,
 >;
 
+/// BOLT12 invoices received for our outbound payments that are awaiting manual approval.
+pub(crate) type PendingBolt12Invoices =
+	Mutex<HashMap<PaymentId, (Bolt12Invoice, Option<OffersContext>)>>;
+
 pub(crate) type MessageRouter = lightning::onion_message::messenger::DefaultMessageRouter<
 	Arc<Graph>,
 	Arc<FilesystemLogger>,
@@ -352,6 +361,29 @@ pub struct PeerDetails {
 	pub is_connected: bool,
 }
 
+/// A request to open a channel as part of a batch opened via [`Node::open_channels_batch`].
+///
+/// [`Node::open_channels_batch`]: crate::Node::open_channels_batch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChannelOpenRequest {
+	/// The node ID of the counterparty.
+	pub node_id: PublicKey,
+	/// The network address of the counterparty.
+	pub address: SocketAddress,
+	/// The value of the channel, in satoshis.
+	pub channel_amount_sats: u64,
+	/// The amount to push to the counterparty on channel open, if any.
+	pub push_to_counterparty_msat: Option<u64>,
+	/// The configuration of the channel. If unset, the default configuration is used.
+	pub channel_config: Option<ChannelConfig>,
+	/// Whether the channel should be announced to the network.
+	///
+	/// See [`Node::open_announced_channel`] for the requirements this entails.
+	///
+	/// [`Node::open_announced_channel`]: crate::Node::open_announced_channel
+	pub announce_for_forwarding: bool,
+}
+
 /// Custom TLV entry.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CustomTlvRecord {
@@ -11,11 +11,19 @@
 // Make sure to add any re-exported items that need to be used in uniffi below.
 
 pub use crate::config::{
-	default_config, AnchorChannelsConfig, EsploraSyncConfig, MaxDustHTLCExposure,
+	default_config, AddressType, AnchorChannelsConfig, EsploraSyncConfig, HoldInvoiceConfig,
+	MaxDustHTLCExposure, PayjoinConfig, PayjoinDirectoryConfig, ProbingConfig,
+	ScheduledPaymentSpendingLimit,
 };
 pub use crate::graph::{ChannelInfo, ChannelUpdateInfo, NodeAnnouncementInfo, NodeInfo};
 pub use crate::payment::store::{LSPFeeLimits, PaymentDirection, PaymentKind, PaymentStatus};
-pub use crate::payment::{MaxTotalRoutingFeeLimit, QrPaymentResult, SendingParameters};
+pub use crate::payment::{
+	Boostagram, HeldPaymentDetails, KeysendMessage, LnurlPayRequest, LnurlPayResult,
+	LnurlSuccessAction, LnurlWithdrawRequest, MaxTotalRoutingFeeLimit, OfferDetails, OfferStats,
+	OnchainFeePreview, OnchainRecipient, PaymentInstructions, PaymentRetryStrategy,
+	QrPaymentAttemptFailure, QrPaymentResult, SendingParameters, SpendableUtxo,
+	UnifiedPaymentMethod, UnifiedPaymentPolicy,
+};
 
 pub use lightning::chain::channelmonitor::BalanceSource;
 pub use lightning::events::{ClosureReason, PaymentFailureReason};
@@ -30,7 +38,9 @@ pub use lightning_types::payment::{PaymentHash, PaymentPreimage, PaymentSecret};
 
 pub use lightning_invoice::Bolt11Invoice;
 
-pub use bitcoin::{Address, BlockHash, Network, OutPoint, Txid};
+pub use bitcoin::bip32::{Fingerprint, Xpub};
+pub use bitcoin::psbt::Psbt;
+pub use bitcoin::{Address, BlockHash, Network, OutPoint, ScriptBuf, Transaction, Txid};
 
 pub use bip39::Mnemonic;
 
@@ -41,8 +51,10 @@ use crate::UniffiCustomTypeConverter;
 use crate::builder::sanitize_alias;
 use crate::error::Error;
 use crate::hex_utils;
-use crate::{SocketAddress, UserChannelId};
+use crate::{ScheduledPaymentId, SocketAddress, UserChannelId};
 
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
 use bitcoin::hashes::sha256::Hash as Sha256;
 use bitcoin::hashes::Hash;
 use bitcoin::secp256k1::PublicKey;
@@ -279,6 +291,24 @@ impl UniffiCustomTypeConverter for UserChannelId {
 	}
 }
 
+impl UniffiCustomTypeConverter for ScheduledPaymentId {
+	type Builtin = String;
+
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		if let Some(bytes_vec) = hex_utils::to_vec(&val) {
+			let bytes_res = bytes_vec.try_into();
+			if let Ok(bytes) = bytes_res {
+				return Ok(ScheduledPaymentId(bytes));
+			}
+		}
+		Err(Error::InvalidScheduledPaymentId.into())
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		hex_utils::to_string(&obj.0)
+	}
+}
+
 impl UniffiCustomTypeConverter for Txid {
 	type Builtin = String;
 	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
@@ -312,6 +342,62 @@ impl UniffiCustomTypeConverter for Mnemonic {
 	}
 }
 
+impl UniffiCustomTypeConverter for Psbt {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		let bytes = BASE64_STANDARD.decode(val).map_err(|_| Error::InvalidPsbt)?;
+		Ok(Psbt::deserialize(&bytes).map_err(|_| Error::InvalidPsbt)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		BASE64_STANDARD.encode(obj.serialize())
+	}
+}
+
+impl UniffiCustomTypeConverter for Xpub {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(Xpub::from_str(&val).map_err(|_| Error::InvalidPublicKey)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
+
+impl UniffiCustomTypeConverter for ScriptBuf {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(ScriptBuf::from_hex(&val).map_err(|_| Error::InvalidFundingTransaction)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_hex_string()
+	}
+}
+
+impl UniffiCustomTypeConverter for Transaction {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(deserialize_hex(&val).map_err(|_| Error::InvalidFundingTransaction)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		serialize_hex(&obj)
+	}
+}
+
+impl UniffiCustomTypeConverter for Fingerprint {
+	type Builtin = String;
+	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
+		Ok(Fingerprint::from_str(&val).map_err(|_| Error::InvalidPublicKey)?)
+	}
+
+	fn from_custom(obj: Self) -> Self::Builtin {
+		obj.to_string()
+	}
+}
+
 impl UniffiCustomTypeConverter for SocketAddress {
 	type Builtin = String;
 	fn into_custom(val: Self::Builtin) -> uniffi::Result<Self> {
@@ -7,43 +7,73 @@
 
 use persist::KVStoreWalletPersister;
 
-use crate::logger::{log_debug, log_error, log_info, log_trace, Logger};
+use crate::logger::{log_debug, log_error, log_info, log_trace, FilesystemLogger, Logger};
 
+use crate::balance::WatchOnlyBalance;
+use crate::config::{AddressType, BDK_WATCH_ONLY_SYNC_LOOKAHEAD};
 use crate::fee_estimator::{ConfirmationTarget, FeeEstimator};
+use crate::io::utils::{write_frozen_utxos, write_watch_only_descriptors};
+use crate::payment::store::{PaymentDetailsUpdate, PaymentStore};
+use crate::payment::{
+	OnchainRecipient, PaymentDetails, PaymentDirection, PaymentKind, PaymentStatus, SpendableUtxo,
+};
+use crate::types::DynStore;
 use crate::Error;
 
 use lightning::chain::chaininterface::BroadcasterInterface;
 use lightning::chain::{BestBlock, Listen};
 
 use lightning::events::bump_transaction::{Utxo, WalletSource};
+use lightning::ln::channelmanager::PaymentId;
 use lightning::ln::inbound_payment::ExpandedKey;
 use lightning::ln::msgs::{DecodeError, UnsignedGossipMessage};
 use lightning::ln::script::ShutdownScript;
+use lightning::ln::types::ChannelId;
 use lightning::sign::{
 	ChangeDestinationSource, EntropySource, InMemorySigner, KeysManager, NodeSigner, OutputSpender,
 	Recipient, SignerProvider, SpendableOutputDescriptor,
 };
 
+use lightning::impl_writeable_tlv_based;
 use lightning::util::message_signing;
 use lightning_invoice::RawBolt11Invoice;
 
-use bdk_chain::spk_client::{FullScanRequest, SyncRequest};
-use bdk_wallet::{Balance, KeychainKind, PersistedWallet, SignOptions, Update};
+use bdk_chain::miniscript::{Descriptor, DescriptorPublicKey};
+use bdk_chain::spk_client::{FullScanRequest, FullScanResponse, SyncRequest, SyncResponse};
+use bdk_chain::{
+	BlockId, CheckPoint, ConfirmationBlockTime, DescriptorExt, DescriptorId, TxUpdate,
+};
+use bdk_wallet::coin_selection::{
+	CoinSelectionAlgorithm, CoinSelectionResult, DefaultCoinSelectionAlgorithm, InsufficientFunds,
+};
+use bdk_wallet::descriptor::ExtendedDescriptor;
+use bdk_wallet::keys::KeyMap;
+use bdk_wallet::{
+	Balance, KeychainKind, LocalOutput, PersistedWallet, SignOptions, TxBuilder, Update,
+	Wallet as BdkWallet, WeightedUtxo,
+};
 
 use bitcoin::blockdata::constants::WITNESS_SCALE_FACTOR;
 use bitcoin::blockdata::locktime::absolute::LockTime;
-use bitcoin::hashes::Hash;
+use bitcoin::hashes::{sha256, Hash};
 use bitcoin::key::XOnlyPublicKey;
 use bitcoin::psbt::Psbt;
 use bitcoin::secp256k1::ecdh::SharedSecret;
 use bitcoin::secp256k1::ecdsa::{RecoverableSignature, Signature};
 use bitcoin::secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, Signing};
 use bitcoin::{
-	Amount, ScriptBuf, Transaction, TxOut, Txid, WPubkeyHash, WitnessProgram, WitnessVersion,
+	Amount, Block, FeeRate, Network, OutPoint, Script, ScriptBuf, Transaction, TxOut, Txid,
+	WPubkeyHash, Weight, WitnessProgram, WitnessVersion,
 };
 
+use rand::seq::SliceRandom;
+use rand::{thread_rng, RngCore};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::ops::Deref;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub(crate) enum OnchainSendAmount {
 	ExactRetainingReserve { amount_sats: u64, cur_anchor_reserve_sats: u64 },
@@ -51,18 +81,382 @@ pub(crate) enum OnchainSendAmount {
 	AllDrainingReserve,
 }
 
+// The persisted set of outputs excluded from coin selection.
+pub(crate) struct FrozenUtxos {
+	pub(crate) outpoints: Vec<OutPoint>,
+}
+
+impl_writeable_tlv_based!(FrozenUtxos, {
+	(0, outpoints, required_vec),
+});
+
+// A public descriptor imported by the user, which we watch without being able to spend from it.
+#[derive(Clone)]
+pub(crate) struct WatchOnlyDescriptor {
+	pub(crate) descriptor: String,
+	// The block height from which on the descriptor may have been used.
+	pub(crate) birthday_height: u32,
+	// Whether we still need to scan the chain for the descriptor's history.
+	pub(crate) pending_scan: bool,
+	// The height of the next block to rescan for the descriptor's history, if we already rescanned
+	// some blocks when syncing via Bitcoin Core RPC.
+	pub(crate) rescan_height: Option<u32>,
+}
+
+impl_writeable_tlv_based!(WatchOnlyDescriptor, {
+	(0, descriptor, required),
+	(2, birthday_height, required),
+	(4, pending_scan, required),
+	(6, rescan_height, option),
+});
+
+// The persisted set of descriptors imported by the user.
+pub(crate) struct WatchOnlyDescriptors {
+	pub(crate) descriptors: Vec<WatchOnlyDescriptor>,
+}
+
+impl_writeable_tlv_based!(WatchOnlyDescriptors, {
+	(0, descriptors, required_vec),
+});
+
+// The parameters used to fund a channel we opened.
+#[derive(Default)]
+pub(crate) struct FundingOptions {
+	pub(crate) utxos_to_spend: Option<Vec<OutPoint>>,
+	pub(crate) fee_rate: Option<FeeRate>,
+}
+
+// The funding output of a channel we opened, once the counterparty accepted the channel.
+#[derive(Clone)]
+pub(crate) struct ChannelFundingOutput {
+	pub(crate) temporary_channel_id: ChannelId,
+	pub(crate) counterparty_node_id: PublicKey,
+	pub(crate) output_script: ScriptBuf,
+	pub(crate) amount: Amount,
+}
+
+// A set of channels we opened which are to be funded by a single transaction.
+pub(crate) struct FundingBatch {
+	// The user channel ids of all channels in the batch.
+	pub(crate) user_channel_ids: Vec<u128>,
+	// The funding outputs of the channels that are ready to be funded.
+	pub(crate) outputs: Vec<ChannelFundingOutput>,
+}
+
+// A channel we opened which awaits a funding transaction constructed by the user.
+pub(crate) struct ExternalFunding {
+	pub(crate) output: ChannelFundingOutput,
+	// The time, in seconds since the UNIX epoch, after which the channel open is aborted.
+	pub(crate) expires_at_secs: u64,
+}
+
 pub(crate) mod persist;
 pub(crate) mod ser;
 
+fn unix_time_secs() -> u64 {
+	SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::from_secs(0)).as_secs()
+}
+
+// Payments of batch transactions are identified by the hash of the txid and their output index, as
+// the txid alone isn't unique among the recipients.
+fn batch_payment_id(txid: Txid, vout: u32) -> PaymentId {
+	let mut preimage = txid.to_byte_array().to_vec();
+	preimage.extend_from_slice(&vout.to_be_bytes());
+	PaymentId(sha256::Hash::hash(&preimage).to_byte_array())
+}
+
+fn total_amount_sats(recipients: &[OnchainRecipient]) -> Result<u64, Error> {
+	recipients
+		.iter()
+		.try_fold(0u64, |acc, r| acc.checked_add(r.amount_sats))
+		.ok_or(Error::InvalidAmount)
+}
+
+// Returns the weight the given unsigned transaction spending our outputs will have once all of
+// its inputs are satisfied, based on the maximum satisfaction weight of our descriptors.
+fn predict_signed_weight(
+	wallet: &PersistedWallet<KVStoreWalletPersister>, additional_wallets: &[AdditionalWallet],
+	tx: &Transaction,
+) -> Result<Weight, Error> {
+	let mut weight = tx.weight();
+	let mut has_witness = false;
+	for txin in &tx.input {
+		let (wallet, utxo) = std::iter::once(wallet)
+			.chain(additional_wallets.iter().map(|w| &w.inner))
+			.find_map(|w| w.get_utxo(txin.previous_output).map(|u| (w, u)))
+			.ok_or(Error::OnchainTxCreationFailed)?;
+		let descriptor = wallet.public_descriptor(utxo.keychain);
+		weight += descriptor.max_weight_to_satisfy().map_err(|_| Error::OnchainTxCreationFailed)?;
+		has_witness |= descriptor.desc_type().segwit_version().is_some();
+	}
+	if has_witness {
+		// Account for the segwit marker and flag.
+		weight += Weight::from_wu(2);
+	}
+	Ok(weight)
+}
+
+// Inserts the unspent outputs of the wallets of other address types into the graph of the primary
+// wallet, which it requires to determine the fees of transactions spending them.
+fn insert_additional_txouts(
+	wallet: &mut PersistedWallet<KVStoreWalletPersister>, additional_wallets: &[AdditionalWallet],
+) {
+	for additional_wallet in additional_wallets {
+		for utxo in additional_wallet.inner.list_unspent() {
+			wallet.insert_txout(utxo.outpoint, utxo.txout);
+		}
+	}
+}
+
+// Returns the sum of the balances of the primary wallet and the wallets of other address types.
+fn combined_balance(
+	wallet: &PersistedWallet<KVStoreWalletPersister>, additional_wallets: &[AdditionalWallet],
+) -> Balance {
+	additional_wallets.iter().fold(wallet.balance(), |balance, w| balance + w.inner.balance())
+}
+
+// Returns whether the given transaction spends an output that was already spent by a confirmed
+// transaction, meaning it can never confirm.
+fn is_conflicted_by_confirmed_tx(
+	wallet: &PersistedWallet<KVStoreWalletPersister>, txid: Txid,
+) -> bool {
+	let tx = match wallet.tx_graph().get_tx(txid) {
+		Some(tx) => tx,
+		None => return false,
+	};
+	let is_conflicted = wallet.tx_graph().direct_conflicts(&tx).any(|(_, conflicting_txid)| {
+		wallet
+			.get_tx(conflicting_txid)
+			.map_or(false, |wallet_tx| wallet_tx.chain_position.is_confirmed())
+	});
+	is_conflicted
+}
+
+// Parses the given public descriptor into the descriptors of the external and, for multipath
+// descriptors, the internal keychain.
+fn parse_watch_only_descriptor(
+	descriptor: &str,
+) -> Result<(Descriptor<DescriptorPublicKey>, Option<Descriptor<DescriptorPublicKey>>), Error> {
+	let descriptor = Descriptor::<DescriptorPublicKey>::from_str(descriptor)
+		.map_err(|_| Error::InvalidDescriptor)?;
+	if !descriptor.is_multipath() {
+		return Ok((descriptor, None));
+	}
+
+	let mut descriptors =
+		descriptor.into_single_descriptors().map_err(|_| Error::InvalidDescriptor)?.into_iter();
+	match (descriptors.next(), descriptors.next(), descriptors.next()) {
+		(Some(external), Some(internal), None) => Ok((external, Some(internal))),
+		_ => Err(Error::InvalidDescriptor),
+	}
+}
+
+/// Loads the BDK wallet watching the given public descriptor, or creates it if it doesn't exist
+/// yet.
+///
+/// Returns [`Error::InvalidDescriptor`] if the descriptor can't be watched on the given network.
+pub(crate) fn setup_watch_only_wallet(
+	descriptor: &str, network: Network, kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
+) -> Result<(PersistedWallet<KVStoreWalletPersister>, KVStoreWalletPersister), Error> {
+	let (external, internal) = parse_watch_only_descriptor(descriptor)?;
+	let mut persister = KVStoreWalletPersister::new_watch_only(
+		external.descriptor_id(),
+		kv_store,
+		Arc::clone(&logger),
+	);
+	let external = external.to_string();
+	let internal = internal.map(|d| d.to_string());
+
+	let wallet_opt = BdkWallet::load()
+		.descriptor(KeychainKind::External, Some(external.clone()))
+		.descriptor(KeychainKind::Internal, internal.clone())
+		.check_network(network)
+		.load_wallet(&mut persister)
+		.map_err(|e| {
+			log_error!(logger, "Failed to load watch-only wallet: {}", e);
+			Error::WalletOperationFailed
+		})?;
+	let wallet = match wallet_opt {
+		Some(wallet) => wallet,
+		None => {
+			let create_params = match internal {
+				Some(internal) => BdkWallet::create(external, internal),
+				None => BdkWallet::create_single(external),
+			};
+			create_params.network(network).create_wallet(&mut persister).map_err(|e| {
+				log_error!(logger, "Failed to set up watch-only wallet: {}", e);
+				Error::InvalidDescriptor
+			})?
+		},
+	};
+	Ok((wallet, persister))
+}
+
+// Identifies one of our BDK wallets in the requests and responses of chain syncing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum WalletId {
+	// The wallet of the given address type.
+	Owned(AddressType),
+	// The wallet watching the imported descriptor with the given id.
+	WatchOnly(DescriptorId),
+}
+
+// The descriptor of a BDK wallet, along with its private keys.
+pub(crate) type WalletDescriptor = (ExtendedDescriptor, KeyMap);
+
+// A BDK on-chain wallet of an address type other than the configured one, e.g., the one used
+// before the address type was changed. Its funds are spent alongside the primary wallet's.
+struct AdditionalWallet {
+	address_type: AddressType,
+	inner: PersistedWallet<KVStoreWalletPersister>,
+	persister: KVStoreWalletPersister,
+}
+
+impl AdditionalWallet {
+	fn persist(&mut self) -> Result<bool, std::io::Error> {
+		self.inner.persist(&mut self.persister)
+	}
+}
+
+// The wallet of an address type we didn't use so far, which is only created once we hand out the
+// first address of that type.
+pub(crate) struct UnusedWallet {
+	pub(crate) address_type: AddressType,
+	pub(crate) descriptor: WalletDescriptor,
+	pub(crate) change_descriptor: WalletDescriptor,
+	pub(crate) persister: KVStoreWalletPersister,
+}
+
+// Coin selection preferring the outputs of the primary wallet, which only spends the given outputs
+// of the wallets of other address types if the former don't suffice.
+#[derive(Debug)]
+struct PrimaryFirstCoinSelection {
+	additional_outpoints: HashSet<OutPoint>,
+}
+
+impl CoinSelectionAlgorithm for PrimaryFirstCoinSelection {
+	fn coin_select<R: RngCore>(
+		&self, required_utxos: Vec<WeightedUtxo>, optional_utxos: Vec<WeightedUtxo>,
+		fee_rate: FeeRate, target_amount: Amount, drain_script: &Script, rand: &mut R,
+	) -> Result<CoinSelectionResult, InsufficientFunds> {
+		// BDK always spends the outputs of other wallets, so we make them optional again.
+		let (additional_utxos, required_utxos): (Vec<_>, Vec<_>) = required_utxos
+			.into_iter()
+			.partition(|u| self.additional_outpoints.contains(&u.utxo.outpoint()));
+		let coin_selection = DefaultCoinSelectionAlgorithm::default();
+		coin_selection
+			.coin_select(
+				required_utxos.clone(),
+				optional_utxos.clone(),
+				fee_rate,
+				target_amount,
+				drain_script,
+				rand,
+			)
+			.or_else(|_| {
+				let optional_utxos = optional_utxos.into_iter().chain(additional_utxos).collect();
+				coin_selection.coin_select(
+					required_utxos,
+					optional_utxos,
+					fee_rate,
+					target_amount,
+					drain_script,
+					rand,
+				)
+			})
+	}
+}
+
+// A BDK wallet watching a descriptor imported by the user. As we can't spend its funds, they
+// aren't accounted for in our balances.
+struct WatchOnlyWallet {
+	id: DescriptorId,
+	descriptor: WatchOnlyDescriptor,
+	inner: PersistedWallet<KVStoreWalletPersister>,
+	persister: KVStoreWalletPersister,
+}
+
+impl WatchOnlyWallet {
+	fn persist(&mut self) -> Result<bool, std::io::Error> {
+		self.inner.persist(&mut self.persister)
+	}
+}
+
+/// An update for all of our on-chain wallets, retrieved via the requests returned by
+/// [`Wallet::get_full_scan_request`] or [`Wallet::get_incremental_sync_request`].
+pub(crate) struct WalletUpdate {
+	last_active_indices: BTreeMap<(WalletId, KeychainKind), u32>,
+	tx_update: TxUpdate<ConfirmationBlockTime>,
+	chain: Option<CheckPoint>,
+}
+
+impl WalletUpdate {
+	// Returns the part of the update relevant to the given wallet.
+	//
+	// Note we hand every wallet the full transaction and chain data, which keeps the chains of all
+	// wallets identical. Transactions unrelated to a wallet don't affect its UTXOs or balance.
+	fn for_wallet(&self, wallet_id: WalletId) -> Update {
+		let last_active_indices = self
+			.last_active_indices
+			.iter()
+			.filter(|((id, _), _)| *id == wallet_id)
+			.map(|((_, keychain), index)| (*keychain, *index))
+			.collect();
+		Update { last_active_indices, tx_update: self.tx_update.clone(), chain: self.chain.clone() }
+	}
+}
+
+impl From<FullScanResponse<(WalletId, KeychainKind)>> for WalletUpdate {
+	fn from(value: FullScanResponse<(WalletId, KeychainKind)>) -> Self {
+		Self {
+			last_active_indices: value.last_active_indices,
+			tx_update: value.tx_update,
+			chain: value.chain_update,
+		}
+	}
+}
+
+impl From<SyncResponse> for WalletUpdate {
+	fn from(value: SyncResponse) -> Self {
+		Self {
+			last_active_indices: BTreeMap::new(),
+			tx_update: value.tx_update,
+			chain: value.chain_update,
+		}
+	}
+}
+
 pub(crate) struct Wallet<B: Deref, E: Deref, L: Deref>
 where
 	B::Target: BroadcasterInterface,
 	E::Target: FeeEstimator,
 	L::Target: Logger,
 {
+	// The address type of the primary wallet.
+	address_type: AddressType,
 	// A BDK on-chain wallet.
 	inner: Mutex<PersistedWallet<KVStoreWalletPersister>>,
 	persister: Mutex<KVStoreWalletPersister>,
+	// The wallets of the other address types we used, which we keep in sync with the primary one.
+	additional_wallets: Mutex<Vec<AdditionalWallet>>,
+	// The wallets of the address types we didn't use so far.
+	unused_wallets: Mutex<Vec<UnusedWallet>>,
+	// The wallets watching the descriptors imported by the user.
+	watch_only_wallets: Mutex<Vec<WatchOnlyWallet>>,
+	// The outpoints the user asked us to exclude from automatic coin selection.
+	frozen_utxos: Mutex<HashSet<OutPoint>>,
+	// The outpoints spent by the PSBTs we handed out for external signing, which we exclude from
+	// coin selection until the PSBT is either broadcast or cancelled.
+	reserved_utxos: Mutex<HashSet<OutPoint>>,
+	// The options to use when funding pending channel opens, by user channel id.
+	pending_funding_options: Mutex<HashMap<u128, FundingOptions>>,
+	// The channel opens waiting to be funded as part of a batch.
+	pending_funding_batches: Mutex<Vec<FundingBatch>>,
+	// The channel opens to be funded by the user, by user channel id. The funding details are
+	// only known once the counterparty accepted the channel.
+	pending_external_fundings: Mutex<HashMap<u128, Option<ExternalFunding>>>,
+	payment_store: Arc<PaymentStore<L>>,
+	kv_store: Arc<DynStore>,
 	broadcaster: B,
 	fee_estimator: E,
 	logger: L,
@@ -75,20 +469,284 @@ where
 	L::Target: Logger,
 {
 	pub(crate) fn new(
-		wallet: bdk_wallet::PersistedWallet<KVStoreWalletPersister>,
-		wallet_persister: KVStoreWalletPersister, broadcaster: B, fee_estimator: E, logger: L,
+		address_type: AddressType, wallet: bdk_wallet::PersistedWallet<KVStoreWalletPersister>,
+		wallet_persister: KVStoreWalletPersister,
+		additional_wallets: Vec<(
+			AddressType,
+			bdk_wallet::PersistedWallet<KVStoreWalletPersister>,
+			KVStoreWalletPersister,
+		)>,
+		unused_wallets: Vec<UnusedWallet>,
+		watch_only_wallets: Vec<(
+			WatchOnlyDescriptor,
+			bdk_wallet::PersistedWallet<KVStoreWalletPersister>,
+			KVStoreWalletPersister,
+		)>,
+		frozen_utxos: HashSet<OutPoint>, payment_store: Arc<PaymentStore<L>>,
+		kv_store: Arc<DynStore>, broadcaster: B, fee_estimator: E, logger: L,
 	) -> Self {
+		let additional_wallets = additional_wallets
+			.into_iter()
+			.map(|(address_type, inner, persister)| AdditionalWallet {
+				address_type,
+				inner,
+				persister,
+			})
+			.collect::<Vec<_>>();
+		let mut wallet = wallet;
+		insert_additional_txouts(&mut wallet, &additional_wallets);
 		let inner = Mutex::new(wallet);
 		let persister = Mutex::new(wallet_persister);
-		Self { inner, persister, broadcaster, fee_estimator, logger }
+		let additional_wallets = Mutex::new(additional_wallets);
+		let unused_wallets = Mutex::new(unused_wallets);
+		let watch_only_wallets = Mutex::new(
+			watch_only_wallets
+				.into_iter()
+				.map(|(descriptor, inner, persister)| WatchOnlyWallet {
+					id: inner.public_descriptor(KeychainKind::External).descriptor_id(),
+					descriptor,
+					inner,
+					persister,
+				})
+				.collect(),
+		);
+		let frozen_utxos = Mutex::new(frozen_utxos);
+		let reserved_utxos = Mutex::new(HashSet::new());
+		let pending_funding_options = Mutex::new(HashMap::new());
+		let pending_funding_batches = Mutex::new(Vec::new());
+		let pending_external_fundings = Mutex::new(HashMap::new());
+		Self {
+			address_type,
+			inner,
+			persister,
+			additional_wallets,
+			unused_wallets,
+			watch_only_wallets,
+			frozen_utxos,
+			reserved_utxos,
+			pending_funding_options,
+			pending_funding_batches,
+			pending_external_fundings,
+			payment_store,
+			kv_store,
+			broadcaster,
+			fee_estimator,
+			logger,
+		}
+	}
+
+	pub(crate) fn get_full_scan_request(&self) -> FullScanRequest<(WalletId, KeychainKind)> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		let locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+
+		let mut request_builder =
+			FullScanRequest::builder().chain_tip(locked_wallet.latest_checkpoint());
+		let wallets = std::iter::once((WalletId::Owned(self.address_type), &**locked_wallet))
+			.chain(
+				locked_additional_wallets
+					.iter()
+					.map(|w| (WalletId::Owned(w.address_type), &*w.inner)),
+			)
+			.chain(
+				locked_watch_only_wallets.iter().map(|w| (WalletId::WatchOnly(w.id), &*w.inner)),
+			);
+		for (wallet_id, wallet) in wallets {
+			for (keychain, spks) in wallet.all_unbounded_spk_iters() {
+				request_builder = request_builder.spks_for_keychain((wallet_id, keychain), spks);
+			}
+		}
+		request_builder.build()
+	}
+
+	pub(crate) fn get_incremental_sync_request(
+		&self,
+	) -> SyncRequest<((WalletId, KeychainKind), u32)> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		let locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+
+		let mut request_builder =
+			SyncRequest::builder().chain_tip(locked_wallet.latest_checkpoint());
+		let wallets = std::iter::once((WalletId::Owned(self.address_type), &**locked_wallet))
+			.chain(
+				locked_additional_wallets
+					.iter()
+					.map(|w| (WalletId::Owned(w.address_type), &*w.inner)),
+			);
+		for (wallet_id, wallet) in wallets {
+			let spks = wallet
+				.spk_index()
+				.revealed_spks(..)
+				.map(|((keychain, index), spk)| (((wallet_id, keychain), index), spk));
+			request_builder = request_builder.spks_with_indexes(spks);
+		}
+
+		// The addresses of imported descriptors are handed out by other wallets, so we also check
+		// the scripts following the last one we saw being used.
+		for watch_only_wallet in locked_watch_only_wallets.iter() {
+			let wallet_id = WalletId::WatchOnly(watch_only_wallet.id);
+			for (keychain, spks) in watch_only_wallet.inner.all_unbounded_spk_iters() {
+				let num_spks = watch_only_wallet
+					.inner
+					.spk_index()
+					.last_revealed_index(keychain)
+					.map_or(0, |index| index + 1)
+					.saturating_add(BDK_WATCH_ONLY_SYNC_LOOKAHEAD);
+				let spks = spks
+					.take(num_spks as usize)
+					.map(move |(index, spk)| (((wallet_id, keychain), index), spk));
+				request_builder = request_builder.spks_with_indexes(spks);
+			}
+		}
+		request_builder.build()
+	}
+
+	/// Returns a request to scan for the history of the imported descriptors we didn't scan for
+	/// yet, along with their ids, or `None` if there are no such descriptors.
+	pub(crate) fn get_watch_only_full_scan_request(
+		&self,
+	) -> Option<(Vec<DescriptorId>, FullScanRequest<(WalletId, KeychainKind)>)> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+
+		let mut ids = Vec::new();
+		let mut request_builder =
+			FullScanRequest::builder().chain_tip(locked_wallet.latest_checkpoint());
+		for wallet in locked_watch_only_wallets.iter().filter(|w| w.descriptor.pending_scan) {
+			ids.push(wallet.id);
+			for (keychain, spks) in wallet.inner.all_unbounded_spk_iters() {
+				request_builder = request_builder
+					.spks_for_keychain((WalletId::WatchOnly(wallet.id), keychain), spks);
+			}
+		}
+
+		if ids.is_empty() {
+			None
+		} else {
+			Some((ids, request_builder.build()))
+		}
+	}
+
+	/// Returns the ids of the imported descriptors we didn't scan the chain for yet, along with the
+	/// heights from which on we still need to rescan the blocks.
+	pub(crate) fn get_pending_watch_only_rescans(&self) -> Vec<(DescriptorId, u32)> {
+		self.watch_only_wallets
+			.lock()
+			.unwrap()
+			.iter()
+			.filter(|w| w.descriptor.pending_scan)
+			.map(|w| (w.id, w.descriptor.rescan_height.unwrap_or(w.descriptor.birthday_height)))
+			.collect()
+	}
+
+	/// Applies a historical block to the wallet watching the descriptor with the given id.
+	///
+	/// The changes are only persisted once the progress of the rescan is recorded via
+	/// [`Wallet::record_watch_only_rescan_progress`] or the rescan is completed via
+	/// [`Wallet::complete_watch_only_scans`].
+	pub(crate) fn apply_watch_only_rescan_block(
+		&self, id: DescriptorId, block: &Block, height: u32,
+	) -> Result<(), Error> {
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+		let wallet = match locked_watch_only_wallets.iter_mut().find(|w| w.id == id) {
+			Some(wallet) => wallet,
+			None => return Ok(()),
+		};
+
+		// The wallet's chain already extends beyond the block, so we need to insert a checkpoint for
+		// it before applying the block, as BDK otherwise can't tell how it connects.
+		let block_id = BlockId { height, hash: block.block_hash() };
+		let chain = wallet.inner.latest_checkpoint().insert(block_id);
+		wallet
+			.inner
+			.apply_update(Update { chain: Some(chain), ..Default::default() })
+			.and_then(|()| wallet.inner.apply_block(block, height))
+			.map_err(|e| {
+				log_error!(
+					self.logger,
+					"Failed to apply block {} to watch-only wallet: {}",
+					block_id.hash,
+					e
+				);
+				Error::WalletOperationFailed
+			})
+	}
+
+	/// Records that the blocks below the given height were rescanned for the history of the
+	/// imported descriptor with the given id, so that a later rescan resumes from there.
+	pub(crate) fn record_watch_only_rescan_progress(
+		&self, id: DescriptorId, rescan_height: u32,
+	) -> Result<(), Error> {
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+		let wallet = match locked_watch_only_wallets.iter_mut().find(|w| w.id == id) {
+			Some(wallet) => wallet,
+			None => return Ok(()),
+		};
+		wallet.persist().map_err(|e| {
+			log_error!(self.logger, "Failed to persist wallet: {}", e);
+			Error::PersistenceFailed
+		})?;
+		wallet.descriptor.rescan_height = Some(rescan_height);
+
+		let descriptors = locked_watch_only_wallets.iter().map(|w| w.descriptor.clone()).collect();
+		write_watch_only_descriptors(descriptors, Arc::clone(&self.kv_store), &*self.logger)
 	}
 
-	pub(crate) fn get_full_scan_request(&self) -> FullScanRequest<KeychainKind> {
-		self.inner.lock().unwrap().start_full_scan().build()
+	/// Marks the chain as scanned for the history of the imported descriptors with the given ids.
+	pub(crate) fn complete_watch_only_scans(&self, ids: &[DescriptorId]) -> Result<(), Error> {
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+		for wallet in locked_watch_only_wallets.iter_mut().filter(|w| ids.contains(&w.id)) {
+			wallet.persist().map_err(|e| {
+				log_error!(self.logger, "Failed to persist wallet: {}", e);
+				Error::PersistenceFailed
+			})?;
+			wallet.descriptor.pending_scan = false;
+		}
+
+		let descriptors = locked_watch_only_wallets.iter().map(|w| w.descriptor.clone()).collect();
+		write_watch_only_descriptors(descriptors, Arc::clone(&self.kv_store), &*self.logger)
 	}
 
-	pub(crate) fn get_incremental_sync_request(&self) -> SyncRequest<(KeychainKind, u32)> {
-		self.inner.lock().unwrap().start_sync_with_revealed_spks().build()
+	/// Starts watching the given imported descriptor, whose history will be scanned for on the
+	/// next chain sync.
+	///
+	/// Importing a descriptor we already watch is a no-op.
+	pub(crate) fn add_watch_only_wallet(
+		&self, descriptor: WatchOnlyDescriptor,
+		mut wallet: PersistedWallet<KVStoreWalletPersister>, persister: KVStoreWalletPersister,
+	) -> Result<(), Error> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+
+		let id = wallet.public_descriptor(KeychainKind::External).descriptor_id();
+		if locked_watch_only_wallets.iter().any(|w| w.id == id) {
+			return Ok(());
+		}
+
+		// Keep the chain of the new wallet in line with the ones of all other wallets.
+		let chain_update =
+			Update { chain: Some(locked_wallet.latest_checkpoint()), ..Default::default() };
+		wallet.apply_update(chain_update).map_err(|e| {
+			log_error!(self.logger, "Failed to update chain of watch-only wallet: {}", e);
+			Error::WalletOperationFailed
+		})?;
+
+		let mut watch_only_wallet = WatchOnlyWallet { id, descriptor, inner: wallet, persister };
+		watch_only_wallet.persist().map_err(|e| {
+			log_error!(self.logger, "Failed to persist wallet: {}", e);
+			Error::PersistenceFailed
+		})?;
+
+		let descriptors = locked_watch_only_wallets
+			.iter()
+			.map(|w| w.descriptor.clone())
+			.chain(std::iter::once(watch_only_wallet.descriptor.clone()))
+			.collect();
+		write_watch_only_descriptors(descriptors, Arc::clone(&self.kv_store), &*self.logger)?;
+
+		locked_watch_only_wallets.push(watch_only_wallet);
+		Ok(())
 	}
 
 	pub(crate) fn current_best_block(&self) -> BestBlock {
@@ -96,9 +754,10 @@ where
 		BestBlock { block_hash: checkpoint.hash(), height: checkpoint.height() }
 	}
 
-	pub(crate) fn apply_update(&self, update: impl Into<Update>) -> Result<(), Error> {
+	pub(crate) fn apply_update(&self, update: impl Into<WalletUpdate>) -> Result<(), Error> {
+		let update = update.into();
 		let mut locked_wallet = self.inner.lock().unwrap();
-		match locked_wallet.apply_update(update) {
+		match locked_wallet.apply_update(update.for_wallet(WalletId::Owned(self.address_type))) {
 			Ok(()) => {
 				let mut locked_persister = self.persister.lock().unwrap();
 				locked_wallet.persist(&mut locked_persister).map_err(|e| {
@@ -106,20 +765,87 @@ where
 					Error::PersistenceFailed
 				})?;
 
-				Ok(())
+				self.update_payment_statuses(&locked_wallet);
 			},
 			Err(e) => {
 				log_error!(self.logger, "Sync failed due to chain connection error: {}", e);
-				Err(Error::WalletOperationFailed)
+				return Err(Error::WalletOperationFailed);
 			},
 		}
+
+		let mut locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		for additional_wallet in locked_additional_wallets.iter_mut() {
+			let address_type = additional_wallet.address_type;
+			additional_wallet
+				.inner
+				.apply_update(update.for_wallet(WalletId::Owned(address_type)))
+				.map_err(|e| {
+					log_error!(
+						self.logger,
+						"Sync of {:?} wallet failed due to chain connection error: {}",
+						address_type,
+						e
+					);
+					Error::WalletOperationFailed
+				})?;
+			additional_wallet.persist().map_err(|e| {
+				log_error!(self.logger, "Failed to persist wallet: {}", e);
+				Error::PersistenceFailed
+			})?;
+		}
+
+		if !locked_additional_wallets.is_empty() {
+			insert_additional_txouts(&mut locked_wallet, &locked_additional_wallets);
+			let mut locked_persister = self.persister.lock().unwrap();
+			locked_wallet.persist(&mut locked_persister).map_err(|e| {
+				log_error!(self.logger, "Failed to persist wallet: {}", e);
+				Error::PersistenceFailed
+			})?;
+		}
+
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+		for watch_only_wallet in locked_watch_only_wallets.iter_mut() {
+			let wallet_id = WalletId::WatchOnly(watch_only_wallet.id);
+			watch_only_wallet.inner.apply_update(update.for_wallet(wallet_id)).map_err(|e| {
+				log_error!(
+					self.logger,
+					"Sync of watch-only wallet failed due to chain connection error: {}",
+					e
+				);
+				Error::WalletOperationFailed
+			})?;
+			watch_only_wallet.persist().map_err(|e| {
+				log_error!(self.logger, "Failed to persist wallet: {}", e);
+				Error::PersistenceFailed
+			})?;
+		}
+		Ok(())
 	}
 
 	pub(crate) fn apply_unconfirmed_txs(
 		&self, unconfirmed_txs: Vec<(Transaction, u64)>,
 	) -> Result<(), Error> {
 		let mut locked_wallet = self.inner.lock().unwrap();
+		let mut locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		for additional_wallet in locked_additional_wallets.iter_mut() {
+			additional_wallet.inner.apply_unconfirmed_txs(unconfirmed_txs.clone());
+			additional_wallet.persist().map_err(|e| {
+				log_error!(self.logger, "Failed to persist wallet: {}", e);
+				Error::PersistenceFailed
+			})?;
+		}
+
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+		for watch_only_wallet in locked_watch_only_wallets.iter_mut() {
+			watch_only_wallet.inner.apply_unconfirmed_txs(unconfirmed_txs.clone());
+			watch_only_wallet.persist().map_err(|e| {
+				log_error!(self.logger, "Failed to persist wallet: {}", e);
+				Error::PersistenceFailed
+			})?;
+		}
+
 		locked_wallet.apply_unconfirmed_txs(unconfirmed_txs);
+		insert_additional_txouts(&mut locked_wallet, &locked_additional_wallets);
 
 		let mut locked_persister = self.persister.lock().unwrap();
 		locked_wallet.persist(&mut locked_persister).map_err(|e| {
@@ -131,15 +857,22 @@ where
 	}
 
 	pub(crate) fn create_funding_transaction(
-		&self, output_script: ScriptBuf, amount: Amount, confirmation_target: ConfirmationTarget,
-		locktime: LockTime,
+		&self, outputs: Vec<(ScriptBuf, Amount)>, confirmation_target: ConfirmationTarget,
+		locktime: LockTime, options: Option<FundingOptions>,
 	) -> Result<Transaction, Error> {
-		let fee_rate = self.fee_estimator.estimate_fee_rate(confirmation_target);
+		let options = options.unwrap_or_default();
+		let fee_rate = options
+			.fee_rate
+			.unwrap_or_else(|| self.fee_estimator.estimate_fee_rate(confirmation_target));
+		let utxos_to_spend = options.utxos_to_spend.as_deref();
 
 		let mut locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
 		let mut tx_builder = locked_wallet.build_tx();
 
-		tx_builder.add_recipient(output_script, amount).fee_rate(fee_rate).nlocktime(locktime);
+		tx_builder.set_recipients(outputs).fee_rate(fee_rate).nlocktime(locktime);
+		let tx_builder =
+			self.select_utxos(tx_builder, &locked_additional_wallets, utxos_to_spend, false)?;
 
 		let mut psbt = match tx_builder.finish() {
 			Ok(psbt) => {
@@ -152,6 +885,7 @@ where
 			},
 		};
 
+		self.sign_with_additional_wallets(&locked_additional_wallets, &mut psbt)?;
 		match locked_wallet.sign(&mut psbt, SignOptions::default()) {
 			Ok(finalized) => {
 				if !finalized {
@@ -178,7 +912,105 @@ where
 		Ok(tx)
 	}
 
-	pub(crate) fn get_new_address(&self) -> Result<bitcoin::Address, Error> {
+	pub(crate) fn get_new_address(
+		&self, address_type: Option<AddressType>,
+	) -> Result<bitcoin::Address, Error> {
+		match address_type {
+			Some(address_type) if address_type != self.address_type => {
+				let (tip, network) = {
+					let locked_wallet = self.inner.lock().unwrap();
+					(locked_wallet.latest_checkpoint(), locked_wallet.network())
+				};
+				let mut locked_additional_wallets = self.additional_wallets.lock().unwrap();
+				if !locked_additional_wallets.iter().any(|w| w.address_type == address_type) {
+					let additional_wallet =
+						self.create_additional_wallet(address_type, tip, network)?;
+					locked_additional_wallets.push(additional_wallet);
+				}
+				let additional_wallet = locked_additional_wallets
+					.iter_mut()
+					.find(|w| w.address_type == address_type)
+					.expect("We just made sure the wallet exists");
+
+				let address_info =
+					additional_wallet.inner.reveal_next_address(KeychainKind::External);
+				additional_wallet.persist().map_err(|e| {
+					log_error!(self.logger, "Failed to persist wallet: {}", e);
+					Error::PersistenceFailed
+				})?;
+				Ok(address_info.address)
+			},
+			_ => self.get_new_primary_address(),
+		}
+	}
+
+	// Creates the wallet of the given address type we didn't use so far, starting at the given tip
+	// of the primary wallet as it can't have received any funds before.
+	fn create_additional_wallet(
+		&self, address_type: AddressType, tip: CheckPoint, network: Network,
+	) -> Result<AdditionalWallet, Error> {
+		let mut locked_unused_wallets = self.unused_wallets.lock().unwrap();
+		let index = locked_unused_wallets
+			.iter()
+			.position(|w| w.address_type == address_type)
+			.ok_or_else(|| {
+				log_error!(
+					self.logger,
+					"On-chain wallet doesn't support {:?} addresses",
+					address_type
+				);
+				Error::InvalidAddressType
+			})?;
+
+		let unused_wallet = &mut locked_unused_wallets[index];
+		let mut inner = BdkWallet::create(
+			unused_wallet.descriptor.clone(),
+			unused_wallet.change_descriptor.clone(),
+		)
+		.network(network)
+		.create_wallet(&mut unused_wallet.persister)
+		.map_err(|e| {
+			log_error!(self.logger, "Failed to set up {:?} wallet: {}", address_type, e);
+			Error::WalletOperationFailed
+		})?;
+		inner.apply_update(Update { chain: Some(tip), ..Default::default() }).map_err(|e| {
+			log_error!(self.logger, "Failed to set up {:?} wallet: {}", address_type, e);
+			Error::WalletOperationFailed
+		})?;
+		inner.persist(&mut unused_wallet.persister).map_err(|e| {
+			log_error!(self.logger, "Failed to persist wallet: {}", e);
+			Error::PersistenceFailed
+		})?;
+
+		let unused_wallet = locked_unused_wallets.remove(index);
+		log_info!(self.logger, "Created on-chain wallet for {:?} addresses", address_type);
+		Ok(AdditionalWallet { address_type, inner, persister: unused_wallet.persister })
+	}
+
+	// Returns a new address to close channels to. Not all peers accept closing to scripts other
+	// than native SegWit ones, so we prefer these if available.
+	fn get_new_shutdown_address(&self) -> Result<bitcoin::Address, Error> {
+		let has_native_segwit_wallet = self.address_type == AddressType::NativeSegwit
+			|| self
+				.additional_wallets
+				.lock()
+				.unwrap()
+				.iter()
+				.any(|w| w.address_type == AddressType::NativeSegwit)
+			|| self
+				.unused_wallets
+				.lock()
+				.unwrap()
+				.iter()
+				.any(|w| w.address_type == AddressType::NativeSegwit);
+		if has_native_segwit_wallet {
+			self.get_new_address(Some(AddressType::NativeSegwit))
+		} else {
+			self.get_new_primary_address()
+		}
+	}
+
+	fn get_new_primary_address(&self) -> Result<bitcoin::Address, Error> {
 		let mut locked_wallet = self.inner.lock().unwrap();
 		let mut locked_persister = self.persister.lock().unwrap();
 
@@ -205,12 +1037,17 @@ where
 	pub(crate) fn get_balances(
 		&self, total_anchor_channels_reserve_sats: u64,
 	) -> Result<(u64, u64), Error> {
-		let balance = self.inner.lock().unwrap().balance();
+		let (balance, primary_confirmed) = {
+			let locked_wallet = self.inner.lock().unwrap();
+			let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+			let primary_confirmed = locked_wallet.balance().confirmed;
+			(combined_balance(&locked_wallet, &locked_additional_wallets), primary_confirmed)
+		};
 
 		// Make sure `list_confirmed_utxos` returns at least one `Utxo` we could use to spend/bump
 		// Anchors if we have any confirmed amounts.
 		#[cfg(debug_assertions)]
-		if balance.confirmed != Amount::ZERO {
+		if primary_confirmed != Amount::ZERO {
 			debug_assert!(
 				self.list_confirmed_utxos().map_or(false, |v| !v.is_empty()),
 				"Confirmed amounts should always be available for Anchor spending"
@@ -220,6 +1057,23 @@ where
 		self.get_balances_inner(balance, total_anchor_channels_reserve_sats)
 	}
 
+	/// Returns the balances of the wallets watching the imported descriptors.
+	pub(crate) fn get_watch_only_balances(&self) -> Vec<WatchOnlyBalance> {
+		self.watch_only_wallets
+			.lock()
+			.unwrap()
+			.iter()
+			.map(|w| {
+				let balance = w.inner.balance();
+				WatchOnlyBalance {
+					descriptor: w.descriptor.descriptor.clone(),
+					total_balance_sats: balance.total().to_sat(),
+					confirmed_balance_sats: balance.confirmed.to_sat(),
+				}
+			})
+			.collect()
+	}
+
 	fn get_balances_inner(
 		&self, balance: Balance, total_anchor_channels_reserve_sats: u64,
 	) -> Result<(u64, u64), Error> {
@@ -237,177 +1091,211 @@ where
 		self.get_balances(total_anchor_channels_reserve_sats).map(|(_, s)| s)
 	}
 
-	pub(crate) fn send_to_address(
-		&self, address: &bitcoin::Address, send_amount: OnchainSendAmount,
-	) -> Result<Txid, Error> {
-		let confirmation_target = ConfirmationTarget::OnchainPayment;
-		let fee_rate = self.fee_estimator.estimate_fee_rate(confirmation_target);
+	/// Creates a fully signed PSBT sending the given amount to the given address, without
+	/// broadcasting the resulting transaction.
+	///
+	/// If `utxos_to_spend` is set, only the given outputs will be spent. If `fee_rate` is unset,
+	/// the fee rate is estimated for [`ConfirmationTarget::OnchainPayment`].
+	pub(crate) fn create_signed_psbt(
+		&self, address: &bitcoin::Address, send_amount: &OnchainSendAmount,
+		utxos_to_spend: Option<&[OutPoint]>, fee_rate: Option<FeeRate>,
+	) -> Result<Psbt, Error> {
+		let mut locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		let mut psbt = self.build_send_psbt(
+			&mut locked_wallet,
+			&locked_additional_wallets,
+			address,
+			send_amount,
+			utxos_to_spend,
+			fee_rate,
+		)?;
+		self.sign_and_persist(&mut locked_wallet, &locked_additional_wallets, &mut psbt)?;
+		Ok(psbt)
+	}
 
-		let tx = {
-			let mut locked_wallet = self.inner.lock().unwrap();
+	// Builds the unsigned PSBT sending the given amount to the given address, checking it doesn't
+	// infringe on our reserve requirements.
+	fn build_send_psbt(
+		&self, locked_wallet: &mut PersistedWallet<KVStoreWalletPersister>,
+		additional_wallets: &[AdditionalWallet], address: &bitcoin::Address,
+		send_amount: &OnchainSendAmount, utxos_to_spend: Option<&[OutPoint]>,
+		fee_rate: Option<FeeRate>,
+	) -> Result<Psbt, Error> {
+		let confirmation_target = ConfirmationTarget::OnchainPayment;
+		let fee_rate =
+			fee_rate.unwrap_or_else(|| self.fee_estimator.estimate_fee_rate(confirmation_target));
 
-			// Prepare the tx_builder. We properly check the reserve requirements (again) further down.
-			let tx_builder = match send_amount {
-				OnchainSendAmount::ExactRetainingReserve { amount_sats, .. } => {
-					let mut tx_builder = locked_wallet.build_tx();
-					let amount = Amount::from_sat(amount_sats);
-					tx_builder.add_recipient(address.script_pubkey(), amount).fee_rate(fee_rate);
-					tx_builder
-				},
-				OnchainSendAmount::AllRetainingReserve { cur_anchor_reserve_sats } => {
-					let change_address_info = locked_wallet.peek_address(KeychainKind::Internal, 0);
-					let balance = locked_wallet.balance();
-					let spendable_amount_sats = self
-						.get_balances_inner(balance, cur_anchor_reserve_sats)
-						.map(|(_, s)| s)
-						.unwrap_or(0);
-					let tmp_tx = {
-						let mut tmp_tx_builder = locked_wallet.build_tx();
-						tmp_tx_builder
-							.drain_wallet()
-							.drain_to(address.script_pubkey())
-							.add_recipient(
-								change_address_info.address.script_pubkey(),
-								Amount::from_sat(cur_anchor_reserve_sats),
-							)
-							.fee_rate(fee_rate);
-						match tmp_tx_builder.finish() {
-							Ok(psbt) => psbt.unsigned_tx,
-							Err(err) => {
-								log_error!(
-									self.logger,
-									"Failed to create temporary transaction: {}",
-									err
-								);
-								return Err(err.into());
-							},
-						}
-					};
+		// Prepare the tx_builder. We properly check the reserve requirements (again) further down.
+		let tx_builder = match *send_amount {
+			OnchainSendAmount::ExactRetainingReserve { amount_sats, .. } => {
+				let mut tx_builder = locked_wallet.build_tx();
+				let amount = Amount::from_sat(amount_sats);
+				tx_builder.add_recipient(address.script_pubkey(), amount).fee_rate(fee_rate);
+				self.select_utxos(tx_builder, additional_wallets, utxos_to_spend, false)?
+			},
+			OnchainSendAmount::AllRetainingReserve { .. } if utxos_to_spend.is_some() => {
+				// When spending explicitly selected outputs we drain them entirely and rely on the
+				// check below to ensure the remaining funds still cover the reserve.
+				let mut tx_builder = locked_wallet.build_tx();
+				tx_builder.drain_to(address.script_pubkey()).fee_rate(fee_rate);
+				self.select_utxos(tx_builder, additional_wallets, utxos_to_spend, false)?
+			},
+			OnchainSendAmount::AllRetainingReserve { cur_anchor_reserve_sats } => {
+				let change_address_info = locked_wallet.peek_address(KeychainKind::Internal, 0);
+				let balance = combined_balance(locked_wallet, additional_wallets);
+				// Frozen and reserved outputs won't be drained, so we don't consider them spendable
+				// here.
+				let unspendable_utxos = self.unspendable_utxos();
+				let unspendable_amount_sats = locked_wallet
+					.list_unspent()
+					.chain(additional_wallets.iter().flat_map(|w| w.inner.list_unspent()))
+					.filter(|u| unspendable_utxos.contains(&u.outpoint))
+					.map(|u| u.txout.value.to_sat())
+					.sum::<u64>();
+				let spendable_amount_sats = self
+					.get_balances_inner(balance, cur_anchor_reserve_sats)
+					.map(|(_, s)| s.saturating_sub(unspendable_amount_sats))
+					.unwrap_or(0);
+				let tmp_tx = {
+					let mut tmp_tx_builder = locked_wallet.build_tx();
+					tmp_tx_builder
+						.drain_to(address.script_pubkey())
+						.add_recipient(
+							change_address_info.address.script_pubkey(),
+							Amount::from_sat(cur_anchor_reserve_sats),
+						)
+						.fee_rate(fee_rate);
+					let tmp_tx_builder =
+						self.select_utxos(tmp_tx_builder, additional_wallets, None, true)?;
+					match tmp_tx_builder.finish() {
+						Ok(psbt) => psbt.unsigned_tx,
+						Err(err) => {
+							log_error!(
+								self.logger,
+								"Failed to create temporary transaction: {}",
+								err
+							);
+							return Err(err.into());
+						},
+					}
+				};
 
-					let estimated_tx_fee = locked_wallet.calculate_fee(&tmp_tx).map_err(|e| {
-						log_error!(
-							self.logger,
-							"Failed to calculate fee of temporary transaction: {}",
-							e
-						);
+				let estimated_tx_fee = locked_wallet.calculate_fee(&tmp_tx).map_err(|e| {
+					log_error!(
+						self.logger,
+						"Failed to calculate fee of temporary transaction: {}",
 						e
-					})?;
-					let estimated_spendable_amount = Amount::from_sat(
-						spendable_amount_sats.saturating_sub(estimated_tx_fee.to_sat()),
 					);
+					e
+				})?;
+				let estimated_spendable_amount = Amount::from_sat(
+					spendable_amount_sats.saturating_sub(estimated_tx_fee.to_sat()),
+				);
 
-					if estimated_spendable_amount == Amount::ZERO {
-						log_error!(self.logger,
-							"Unable to send payment without infringing on Anchor reserves. Available: {}sats, estimated fee required: {}sats.",
-							spendable_amount_sats,
-							estimated_tx_fee,
-						);
-						return Err(Error::InsufficientFunds);
-					}
+				if estimated_spendable_amount == Amount::ZERO {
+					log_error!(self.logger,
+						"Unable to send payment without infringing on Anchor reserves. Available: {}sats, estimated fee required: {}sats.",
+						spendable_amount_sats,
+						estimated_tx_fee,
+					);
+					return Err(Error::InsufficientFunds);
+				}
 
-					let mut tx_builder = locked_wallet.build_tx();
-					tx_builder
-						.add_recipient(address.script_pubkey(), estimated_spendable_amount)
-						.fee_absolute(estimated_tx_fee);
-					tx_builder
-				},
-				OnchainSendAmount::AllDrainingReserve => {
-					let mut tx_builder = locked_wallet.build_tx();
-					tx_builder.drain_wallet().drain_to(address.script_pubkey()).fee_rate(fee_rate);
-					tx_builder
-				},
-			};
+				let mut tx_builder = locked_wallet.build_tx();
+				tx_builder
+					.add_recipient(address.script_pubkey(), estimated_spendable_amount)
+					.fee_absolute(estimated_tx_fee);
+				self.select_utxos(tx_builder, additional_wallets, None, false)?
+			},
+			OnchainSendAmount::AllDrainingReserve => {
+				let mut tx_builder = locked_wallet.build_tx();
+				tx_builder.drain_to(address.script_pubkey()).fee_rate(fee_rate);
+				let drain_wallet = utxos_to_spend.is_none();
+				self.select_utxos(tx_builder, additional_wallets, utxos_to_spend, drain_wallet)?
+			},
+		};
 
-			let mut psbt = match tx_builder.finish() {
-				Ok(psbt) => {
-					log_trace!(self.logger, "Created PSBT: {:?}", psbt);
-					psbt
-				},
-				Err(err) => {
-					log_error!(self.logger, "Failed to create transaction: {}", err);
-					return Err(err.into());
-				},
-			};
+		let psbt = match tx_builder.finish() {
+			Ok(psbt) => {
+				log_trace!(self.logger, "Created PSBT: {:?}", psbt);
+				psbt
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to create transaction: {}", err);
+				return Err(err.into());
+			},
+		};
 
-			// Check the reserve requirements (again) and return an error if they aren't met.
-			match send_amount {
-				OnchainSendAmount::ExactRetainingReserve {
-					amount_sats,
-					cur_anchor_reserve_sats,
-				} => {
-					let balance = locked_wallet.balance();
-					let spendable_amount_sats = self
-						.get_balances_inner(balance, cur_anchor_reserve_sats)
-						.map(|(_, s)| s)
-						.unwrap_or(0);
-					let tx_fee_sats = locked_wallet
-						.calculate_fee(&psbt.unsigned_tx)
-						.map_err(|e| {
-							log_error!(
-								self.logger,
-								"Failed to calculate fee of candidate transaction: {}",
-								e
-							);
+		// Check the reserve requirements (again) and return an error if they aren't met.
+		match *send_amount {
+			OnchainSendAmount::ExactRetainingReserve { amount_sats, cur_anchor_reserve_sats } => {
+				let balance = combined_balance(locked_wallet, additional_wallets);
+				let spendable_amount_sats = self
+					.get_balances_inner(balance, cur_anchor_reserve_sats)
+					.map(|(_, s)| s)
+					.unwrap_or(0);
+				let tx_fee_sats = locked_wallet
+					.calculate_fee(&psbt.unsigned_tx)
+					.map_err(|e| {
+						log_error!(
+							self.logger,
+							"Failed to calculate fee of candidate transaction: {}",
 							e
-						})?
-						.to_sat();
-					if spendable_amount_sats < amount_sats.saturating_add(tx_fee_sats) {
-						log_error!(self.logger,
-							"Unable to send payment due to insufficient funds. Available: {}sats, Required: {}sats + {}sats fee",
-							spendable_amount_sats,
-							amount_sats,
-							tx_fee_sats,
-						);
-						return Err(Error::InsufficientFunds);
-					}
-				},
-				OnchainSendAmount::AllRetainingReserve { cur_anchor_reserve_sats } => {
-					let balance = locked_wallet.balance();
-					let spendable_amount_sats = self
-						.get_balances_inner(balance, cur_anchor_reserve_sats)
-						.map(|(_, s)| s)
-						.unwrap_or(0);
-					let (sent, received) = locked_wallet.sent_and_received(&psbt.unsigned_tx);
-					let drain_amount = sent - received;
-					if spendable_amount_sats < drain_amount.to_sat() {
-						log_error!(self.logger,
-							"Unable to send payment due to insufficient funds. Available: {}sats, Required: {}",
-							spendable_amount_sats,
-							drain_amount,
 						);
-						return Err(Error::InsufficientFunds);
-					}
-				},
-				_ => {},
-			}
-
-			match locked_wallet.sign(&mut psbt, SignOptions::default()) {
-				Ok(finalized) => {
-					if !finalized {
-						return Err(Error::OnchainTxCreationFailed);
-					}
-				},
-				Err(err) => {
-					log_error!(self.logger, "Failed to create transaction: {}", err);
-					return Err(err.into());
-				},
-			}
-
-			let mut locked_persister = self.persister.lock().unwrap();
-			locked_wallet.persist(&mut locked_persister).map_err(|e| {
-				log_error!(self.logger, "Failed to persist wallet: {}", e);
-				Error::PersistenceFailed
-			})?;
+						e
+					})?
+					.to_sat();
+				if spendable_amount_sats < amount_sats.saturating_add(tx_fee_sats) {
+					log_error!(self.logger,
+						"Unable to send payment due to insufficient funds. Available: {}sats, Required: {}sats + {}sats fee",
+						spendable_amount_sats,
+						amount_sats,
+						tx_fee_sats,
+					);
+					return Err(Error::InsufficientFunds);
+				}
+			},
+			OnchainSendAmount::AllRetainingReserve { cur_anchor_reserve_sats } => {
+				let balance = combined_balance(locked_wallet, additional_wallets);
+				let spendable_amount_sats = self
+					.get_balances_inner(balance, cur_anchor_reserve_sats)
+					.map(|(_, s)| s)
+					.unwrap_or(0);
+				let (sent, received) = additional_wallets.iter().fold(
+					locked_wallet.sent_and_received(&psbt.unsigned_tx),
+					|(sent, received), w| {
+						let (s, r) = w.inner.sent_and_received(&psbt.unsigned_tx);
+						(sent + s, received + r)
+					},
+				);
+				let drain_amount = sent - received;
+				if spendable_amount_sats < drain_amount.to_sat() {
+					log_error!(self.logger,
+						"Unable to send payment due to insufficient funds. Available: {}sats, Required: {}",
+						spendable_amount_sats,
+						drain_amount,
+					);
+					return Err(Error::InsufficientFunds);
+				}
+			},
+			_ => {},
+		}
 
-			psbt.extract_tx().map_err(|e| {
-				log_error!(self.logger, "Failed to extract transaction: {}", e);
-				e
-			})?
-		};
+		Ok(psbt)
+	}
 
-		self.broadcaster.broadcast_transactions(&[&tx]);
+	pub(crate) fn send_to_address(
+		&self, address: &bitcoin::Address, send_amount: OnchainSendAmount,
+		utxos_to_spend: Option<&[OutPoint]>, fee_rate: Option<FeeRate>,
+	) -> Result<Txid, Error> {
+		let psbt = self.create_signed_psbt(address, &send_amount, utxos_to_spend, fee_rate)?;
+		let tx = psbt.extract_tx().map_err(|e| {
+			log_error!(self.logger, "Failed to extract transaction: {}", e);
+			e
+		})?;
 
-		let txid = tx.compute_txid();
+		let txid = self.broadcast_payment(&tx, None);
 
 		match send_amount {
 			OnchainSendAmount::ExactRetainingReserve { amount_sats, .. } => {
@@ -440,6 +1328,1020 @@ where
 
 		Ok(txid)
 	}
+
+	// Restricts coin selection to the given outputs if any are set, and otherwise excludes any
+	// frozen or reserved outputs from it. Unless we drain the wallet, the outputs of the wallets of
+	// other address types are only spent if those of the primary wallet don't suffice.
+	fn select_utxos<'a, Cs>(
+		&self, mut tx_builder: TxBuilder<'a, Cs>, additional_wallets: &[AdditionalWallet],
+		utxos_to_spend: Option<&[OutPoint]>, drain_wallet: bool,
+	) -> Result<TxBuilder<'a, PrimaryFirstCoinSelection>, Error> {
+		let mut additional_outpoints = HashSet::new();
+		match utxos_to_spend {
+			Some(outpoints) => {
+				let locked_reserved_utxos = self.reserved_utxos.lock().unwrap();
+				if let Some(outpoint) = outpoints.iter().find(|o| locked_reserved_utxos.contains(o))
+				{
+					log_error!(
+						self.logger,
+						"Failed to select UTXO {} as it's reserved by a pending PSBT",
+						outpoint
+					);
+					return Err(Error::InvalidUtxo);
+				}
+
+				let mut primary_outpoints = Vec::new();
+				for outpoint in outpoints {
+					let additional_utxo = additional_wallets
+						.iter()
+						.find_map(|w| w.inner.get_utxo(*outpoint).map(|u| (w, u)));
+					match additional_utxo {
+						Some((wallet, utxo)) => {
+							self.add_additional_utxo(&mut tx_builder, wallet, utxo)?;
+						},
+						None => primary_outpoints.push(*outpoint),
+					}
+				}
+				tx_builder
+					.add_utxos(&primary_outpoints)
+					.map_err(|e| {
+						log_error!(self.logger, "Failed to select UTXOs to spend: {}", e);
+						e
+					})?
+					.manually_selected_only();
+			},
+			None => {
+				let unspendable_utxos = self.unspendable_utxos();
+				for additional_wallet in additional_wallets {
+					for utxo in additional_wallet.inner.list_unspent() {
+						if unspendable_utxos.contains(&utxo.outpoint) {
+							continue;
+						}
+						if !drain_wallet {
+							additional_outpoints.insert(utxo.outpoint);
+						}
+						self.add_additional_utxo(&mut tx_builder, additional_wallet, utxo)?;
+					}
+				}
+				tx_builder.unspendable(unspendable_utxos.into_iter().collect());
+				if drain_wallet {
+					tx_builder.drain_wallet();
+				}
+			},
+		}
+		Ok(tx_builder.coin_selection(PrimaryFirstCoinSelection { additional_outpoints }))
+	}
+
+	// Adds the given output of a wallet of another address type to the inputs of the transaction.
+	fn add_additional_utxo<Cs>(
+		&self, tx_builder: &mut TxBuilder<'_, Cs>, additional_wallet: &AdditionalWallet,
+		utxo: LocalOutput,
+	) -> Result<(), Error> {
+		let outpoint = utxo.outpoint;
+		let satisfaction_weight = additional_wallet
+			.inner
+			.public_descriptor(utxo.keychain)
+			.max_weight_to_satisfy()
+			.map_err(|_| Error::OnchainTxCreationFailed)?;
+		let psbt_input =
+			additional_wallet.inner.get_psbt_input(utxo, None, false).map_err(|e| {
+				log_error!(self.logger, "Failed to select UTXO {} to spend: {}", outpoint, e);
+				Error::OnchainTxCreationFailed
+			})?;
+		tx_builder.add_foreign_utxo(outpoint, psbt_input, satisfaction_weight).map_err(|e| {
+			log_error!(self.logger, "Failed to select UTXO {} to spend: {}", outpoint, e);
+			Error::InvalidUtxo
+		})?;
+		Ok(())
+	}
+
+	// Returns the outputs excluded from automatic coin selection, i.e., the frozen ones and the
+	// ones reserved by pending PSBTs.
+	fn unspendable_utxos(&self) -> HashSet<OutPoint> {
+		let mut unspendable_utxos = self.frozen_utxos.lock().unwrap().clone();
+		unspendable_utxos.extend(self.reserved_utxos.lock().unwrap().iter().copied());
+		unspendable_utxos
+	}
+
+	pub(crate) fn list_utxos(&self) -> Vec<SpendableUtxo> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		let locked_frozen_utxos = self.frozen_utxos.lock().unwrap();
+		let network = locked_wallet.network();
+		let tip_height = locked_wallet.latest_checkpoint().height();
+
+		let additional_utxos =
+			locked_additional_wallets.iter().flat_map(|w| w.inner.list_unspent());
+		let mut utxos = Vec::new();
+		for u in locked_wallet.list_unspent().chain(additional_utxos) {
+			let address = match bitcoin::Address::from_script(&u.txout.script_pubkey, network) {
+				Ok(address) => address,
+				Err(e) => {
+					log_error!(
+						self.logger,
+						"Failed to derive address of UTXO {}: {}",
+						u.outpoint,
+						e
+					);
+					continue;
+				},
+			};
+			let confirmations = u
+				.chain_position
+				.confirmation_height_upper_bound()
+				.map_or(0, |height| tip_height.saturating_sub(height) + 1);
+			utxos.push(SpendableUtxo {
+				outpoint: u.outpoint,
+				value_sats: u.txout.value.to_sat(),
+				confirmations,
+				address,
+				is_frozen: locked_frozen_utxos.contains(&u.outpoint),
+				label: None,
+			});
+		}
+		utxos
+	}
+
+	// Returns the given unspent output of the primary wallet or one of the wallets of other address
+	// types.
+	fn get_utxo(&self, outpoint: OutPoint) -> Option<LocalOutput> {
+		let locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		locked_wallet
+			.get_utxo(outpoint)
+			.or_else(|| locked_additional_wallets.iter().find_map(|w| w.inner.get_utxo(outpoint)))
+	}
+
+	/// Returns the total value of the given outputs, failing if any of them is unknown or spent.
+	pub(crate) fn utxos_value_sats(&self, outpoints: &[OutPoint]) -> Result<u64, Error> {
+		let mut total_sats = 0u64;
+		for outpoint in outpoints {
+			let utxo = self.get_utxo(*outpoint).ok_or_else(|| {
+				log_error!(self.logger, "Failed to find UTXO {} in on-chain wallet", outpoint);
+				Error::InvalidUtxo
+			})?;
+			total_sats = total_sats.saturating_add(utxo.txout.value.to_sat());
+		}
+		Ok(total_sats)
+	}
+
+	pub(crate) fn set_utxo_frozen(&self, outpoint: OutPoint, frozen: bool) -> Result<(), Error> {
+		if frozen && self.get_utxo(outpoint).is_none() {
+			log_error!(self.logger, "Failed to freeze unknown UTXO {}", outpoint);
+			return Err(Error::InvalidUtxo);
+		}
+
+		let mut locked_frozen_utxos = self.frozen_utxos.lock().unwrap();
+		let updated = if frozen {
+			locked_frozen_utxos.insert(outpoint)
+		} else {
+			locked_frozen_utxos.remove(&outpoint)
+		};
+
+		if updated {
+			write_frozen_utxos(&locked_frozen_utxos, Arc::clone(&self.kv_store), &*self.logger)
+				.map_err(|e| {
+					// Revert the change so we don't diverge from the persisted state.
+					if frozen {
+						locked_frozen_utxos.remove(&outpoint);
+					} else {
+						locked_frozen_utxos.insert(outpoint);
+					}
+					e
+				})?;
+		}
+		Ok(())
+	}
+
+	pub(crate) fn set_funding_options(&self, user_channel_id: u128, options: FundingOptions) {
+		self.pending_funding_options.lock().unwrap().insert(user_channel_id, options);
+	}
+
+	pub(crate) fn take_funding_options(&self, user_channel_id: u128) -> Option<FundingOptions> {
+		self.pending_funding_options.lock().unwrap().remove(&user_channel_id)
+	}
+
+	pub(crate) fn add_funding_batch(&self, user_channel_ids: Vec<u128>) {
+		let batch = FundingBatch { user_channel_ids, outputs: Vec::new() };
+		self.pending_funding_batches.lock().unwrap().push(batch);
+	}
+
+	pub(crate) fn is_batch_funded(&self, user_channel_id: u128) -> bool {
+		let locked_batches = self.pending_funding_batches.lock().unwrap();
+		locked_batches.iter().any(|b| b.user_channel_ids.contains(&user_channel_id))
+	}
+
+	/// Removes and returns the pending funding batch the given channel is part of, if any.
+	pub(crate) fn take_funding_batch(&self, user_channel_id: u128) -> Option<FundingBatch> {
+		let mut locked_batches = self.pending_funding_batches.lock().unwrap();
+		let idx =
+			locked_batches.iter().position(|b| b.user_channel_ids.contains(&user_channel_id))?;
+		Some(locked_batches.remove(idx))
+	}
+
+	/// Adds the funding output of the given channel to its pending funding batch, returning the
+	/// batch once all of its channels are ready to be funded.
+	pub(crate) fn add_batch_funding_output(
+		&self, user_channel_id: u128, output: ChannelFundingOutput,
+	) -> Option<FundingBatch> {
+		let mut locked_batches = self.pending_funding_batches.lock().unwrap();
+		let idx =
+			locked_batches.iter().position(|b| b.user_channel_ids.contains(&user_channel_id))?;
+		let batch = &mut locked_batches[idx];
+		batch.outputs.push(output);
+		if batch.outputs.len() == batch.user_channel_ids.len() {
+			Some(locked_batches.remove(idx))
+		} else {
+			None
+		}
+	}
+
+	pub(crate) fn add_external_funding(&self, user_channel_id: u128) {
+		self.pending_external_fundings.lock().unwrap().insert(user_channel_id, None);
+	}
+
+	pub(crate) fn is_externally_funded(&self, user_channel_id: u128) -> bool {
+		self.pending_external_fundings.lock().unwrap().contains_key(&user_channel_id)
+	}
+
+	/// Records the funding output of the given externally funded channel, which expires after the
+	/// given timeout. Returns `false` if the channel isn't (or is no longer) awaiting external
+	/// funding.
+	pub(crate) fn set_external_funding_output(
+		&self, user_channel_id: u128, output: ChannelFundingOutput, timeout_secs: u64,
+	) -> bool {
+		let expires_at_secs = unix_time_secs().saturating_add(timeout_secs);
+		let mut locked_fundings = self.pending_external_fundings.lock().unwrap();
+		match locked_fundings.get_mut(&user_channel_id) {
+			Some(funding) => {
+				*funding = Some(ExternalFunding { output, expires_at_secs });
+				true
+			},
+			None => false,
+		}
+	}
+
+	/// Returns the funding output of the given externally funded channel, if the counterparty
+	/// already accepted the channel.
+	pub(crate) fn external_funding_output(
+		&self, user_channel_id: u128,
+	) -> Option<ChannelFundingOutput> {
+		let locked_fundings = self.pending_external_fundings.lock().unwrap();
+		locked_fundings.get(&user_channel_id)?.as_ref().map(|f| f.output.clone())
+	}
+
+	pub(crate) fn remove_external_funding(&self, user_channel_id: u128) {
+		self.pending_external_fundings.lock().unwrap().remove(&user_channel_id);
+	}
+
+	/// Removes and returns the funding outputs of all externally funded channels that weren't
+	/// funded before their expiry.
+	pub(crate) fn take_expired_external_fundings(&self) -> Vec<ChannelFundingOutput> {
+		let now_secs = unix_time_secs();
+		let mut locked_fundings = self.pending_external_fundings.lock().unwrap();
+		let expired_ids = locked_fundings
+			.iter()
+			.filter_map(|(id, funding)| match funding {
+				Some(f) if f.expires_at_secs <= now_secs => Some(*id),
+				_ => None,
+			})
+			.collect::<Vec<_>>();
+		expired_ids
+			.into_iter()
+			.filter_map(|id| locked_fundings.remove(&id).flatten().map(|f| f.output))
+			.collect()
+	}
+
+	/// Returns the fee and virtual size of the transaction sending the given amount to the given
+	/// address, without broadcasting it.
+	pub(crate) fn preview_send(
+		&self, address: &bitcoin::Address, send_amount: &OnchainSendAmount,
+		utxos_to_spend: Option<&[OutPoint]>, fee_rate: Option<FeeRate>,
+	) -> Result<(Amount, u64), Error> {
+		let mut locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		let psbt = self.build_send_psbt(
+			&mut locked_wallet,
+			&locked_additional_wallets,
+			address,
+			send_amount,
+			utxos_to_spend,
+			fee_rate,
+		)?;
+		// As we never sign nor broadcast the transaction, we release the change address it uses.
+		locked_wallet.cancel_tx(&psbt.unsigned_tx);
+
+		let fee = locked_wallet.calculate_fee(&psbt.unsigned_tx).map_err(|e| {
+			log_error!(self.logger, "Failed to calculate fee of candidate transaction: {}", e);
+			e
+		})?;
+		let weight =
+			predict_signed_weight(&locked_wallet, &locked_additional_wallets, &psbt.unsigned_tx)?;
+		Ok((fee, weight.to_vbytes_ceil()))
+	}
+
+	/// Broadcasts the given transaction paying out on-chain funds, tracking it in the wallet and
+	/// recording it as a pending outbound payment.
+	///
+	/// If `amount_sats` is unset, the amount is derived from the outputs not belonging to us.
+	pub(crate) fn broadcast_payment(&self, tx: &Transaction, amount_sats: Option<u64>) -> Txid {
+		let txid = self.broadcast_and_track(tx);
+		let amount_sats = amount_sats.unwrap_or_else(|| self.foreign_output_value_sats(tx));
+		self.record_outbound_payment(PaymentId(txid.to_byte_array()), tx, amount_sats);
+		txid
+	}
+
+	/// Sends the given amounts to the given recipients in a single transaction, recording a
+	/// separate pending outbound payment per recipient.
+	pub(crate) fn send_batch(
+		&self, recipients: &[OnchainRecipient], cur_anchor_reserve_sats: u64,
+		fee_rate: Option<FeeRate>,
+	) -> Result<Txid, Error> {
+		let total_amount_sats = total_amount_sats(recipients)?;
+
+		let tx = {
+			let mut locked_wallet = self.inner.lock().unwrap();
+			let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+			let mut psbt = self.create_unsigned_psbt(
+				&mut locked_wallet,
+				&locked_additional_wallets,
+				recipients,
+				cur_anchor_reserve_sats,
+				fee_rate,
+			)?;
+			self.sign_and_persist(&mut locked_wallet, &locked_additional_wallets, &mut psbt)?;
+			psbt.extract_tx().map_err(|e| {
+				log_error!(self.logger, "Failed to extract transaction: {}", e);
+				e
+			})?
+		};
+
+		let txid = self.broadcast_and_track(&tx);
+
+		// As outputs are shuffled, we look up each recipient's output, taking care to assign each
+		// output only once if a recipient is listed multiple times.
+		let mut claimed_vouts = HashSet::new();
+		for recipient in recipients {
+			let script_pubkey = recipient.address.script_pubkey();
+			let vout = tx.output.iter().enumerate().position(|(vout, output)| {
+				!claimed_vouts.contains(&vout)
+					&& output.script_pubkey == script_pubkey
+					&& output.value.to_sat() == recipient.amount_sats
+			});
+			match vout {
+				Some(vout) => {
+					claimed_vouts.insert(vout);
+					let payment_id = batch_payment_id(txid, vout as u32);
+					self.record_outbound_payment(payment_id, &tx, recipient.amount_sats);
+				},
+				None => {
+					log_error!(
+						self.logger,
+						"Failed to find output paying {} in batch transaction {}",
+						recipient.address,
+						txid
+					);
+				},
+			}
+		}
+
+		log_info!(
+			self.logger,
+			"Created new batch transaction {} sending {}sats on-chain to {} recipients",
+			txid,
+			total_amount_sats,
+			recipients.len()
+		);
+		Ok(txid)
+	}
+
+	/// Creates an unsigned PSBT paying the given recipients, to be signed externally.
+	///
+	/// The outputs spent by the PSBT are reserved until it's either broadcast via
+	/// [`Wallet::finalize_and_broadcast`] or cancelled via [`Wallet::cancel_psbt`].
+	pub(crate) fn create_psbt(
+		&self, recipients: &[OnchainRecipient], cur_anchor_reserve_sats: u64,
+		fee_rate: Option<FeeRate>,
+	) -> Result<Psbt, Error> {
+		let mut locked_wallet = self.inner.lock().unwrap();
+		let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		let psbt = self.create_unsigned_psbt(
+			&mut locked_wallet,
+			&locked_additional_wallets,
+			recipients,
+			cur_anchor_reserve_sats,
+			fee_rate,
+		)?;
+
+		// Persist the change address we might have revealed.
+		let mut locked_persister = self.persister.lock().unwrap();
+		locked_wallet.persist(&mut locked_persister).map_err(|e| {
+			log_error!(self.logger, "Failed to persist wallet: {}", e);
+			Error::PersistenceFailed
+		})?;
+
+		let mut locked_reserved_utxos = self.reserved_utxos.lock().unwrap();
+		locked_reserved_utxos.extend(psbt.unsigned_tx.input.iter().map(|i| i.previous_output));
+
+		Ok(psbt)
+	}
+
+	/// Cancels the given PSBT created via [`Wallet::create_psbt`], releasing the outputs it spends
+	/// as well as its change address.
+	pub(crate) fn cancel_psbt(&self, psbt: &Psbt) -> Result<(), Error> {
+		let mut locked_wallet = self.inner.lock().unwrap();
+		let mut locked_reserved_utxos = self.reserved_utxos.lock().unwrap();
+		let inputs = psbt.unsigned_tx.input.iter().map(|i| i.previous_output).collect::<Vec<_>>();
+		if !inputs.iter().any(|o| locked_reserved_utxos.contains(o)) {
+			log_error!(self.logger, "Failed to cancel PSBT not spending any reserved outputs");
+			return Err(Error::InvalidPsbt);
+		}
+		for outpoint in inputs {
+			locked_reserved_utxos.remove(&outpoint);
+		}
+		locked_wallet.cancel_tx(&psbt.unsigned_tx);
+		Ok(())
+	}
+
+	/// Finalizes the given externally signed PSBT and broadcasts the resulting transaction,
+	/// recording it as a pending outbound payment.
+	pub(crate) fn finalize_and_broadcast(&self, mut psbt: Psbt) -> Result<Txid, Error> {
+		let tx = {
+			let locked_wallet = self.inner.lock().unwrap();
+			let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+
+			let spends_own_outputs = psbt.unsigned_tx.input.iter().any(|txin| {
+				locked_wallet.get_utxo(txin.previous_output).is_some()
+					|| locked_additional_wallets
+						.iter()
+						.any(|w| w.inner.get_utxo(txin.previous_output).is_some())
+			});
+			if !spends_own_outputs {
+				log_error!(self.logger, "Failed to finalize PSBT not spending any of our outputs");
+				return Err(Error::InvalidPsbt);
+			}
+
+			// Finalize the inputs spending outputs of the wallets of other address types first, as
+			// the primary wallet doesn't know their descriptors.
+			for additional_wallet in locked_additional_wallets.iter() {
+				if let Err(err) =
+					additional_wallet.inner.finalize_psbt(&mut psbt, SignOptions::default())
+				{
+					log_error!(self.logger, "Failed to finalize PSBT: {}", err);
+					return Err(Error::InvalidPsbt);
+				}
+			}
+
+			match locked_wallet.finalize_psbt(&mut psbt, SignOptions::default()) {
+				Ok(true) => {},
+				Ok(false) => {
+					log_error!(self.logger, "Failed to finalize PSBT as it isn't fully signed");
+					return Err(Error::InvalidPsbt);
+				},
+				Err(err) => {
+					log_error!(self.logger, "Failed to finalize PSBT: {}", err);
+					return Err(Error::InvalidPsbt);
+				},
+			}
+
+			psbt.extract_tx().map_err(|e| {
+				log_error!(self.logger, "Failed to extract transaction: {}", e);
+				Error::InvalidPsbt
+			})?
+		};
+
+		let txid = self.broadcast_payment(&tx, None);
+
+		// The outputs are spent now, so we don't need to reserve them any longer.
+		let mut locked_reserved_utxos = self.reserved_utxos.lock().unwrap();
+		for txin in &tx.input {
+			locked_reserved_utxos.remove(&txin.previous_output);
+		}
+
+		log_info!(self.logger, "Broadcast externally signed transaction {}", txid);
+		Ok(txid)
+	}
+
+	// Builds an unsigned transaction paying the given recipients, making sure we retain the given
+	// reserve.
+	fn create_unsigned_psbt(
+		&self, locked_wallet: &mut PersistedWallet<KVStoreWalletPersister>,
+		additional_wallets: &[AdditionalWallet], recipients: &[OnchainRecipient],
+		cur_anchor_reserve_sats: u64, fee_rate: Option<FeeRate>,
+	) -> Result<Psbt, Error> {
+		let confirmation_target = ConfirmationTarget::OnchainPayment;
+		let fee_rate =
+			fee_rate.unwrap_or_else(|| self.fee_estimator.estimate_fee_rate(confirmation_target));
+		let total_amount_sats = total_amount_sats(recipients)?;
+
+		let mut tx_builder = locked_wallet.build_tx();
+		for recipient in recipients {
+			let amount = Amount::from_sat(recipient.amount_sats);
+			tx_builder.add_recipient(recipient.address.script_pubkey(), amount);
+		}
+		tx_builder.fee_rate(fee_rate);
+		let tx_builder = self.select_utxos(tx_builder, additional_wallets, None, false)?;
+
+		let psbt = match tx_builder.finish() {
+			Ok(psbt) => {
+				log_trace!(self.logger, "Created PSBT: {:?}", psbt);
+				psbt
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to create transaction: {}", err);
+				return Err(err.into());
+			},
+		};
+
+		// Check the reserve requirements and return an error if they aren't met.
+		let balance = combined_balance(locked_wallet, additional_wallets);
+		let spendable_amount_sats =
+			self.get_balances_inner(balance, cur_anchor_reserve_sats).map(|(_, s)| s).unwrap_or(0);
+		let tx_fee_sats = locked_wallet
+			.calculate_fee(&psbt.unsigned_tx)
+			.map_err(|e| {
+				log_error!(self.logger, "Failed to calculate fee of candidate transaction: {}", e);
+				e
+			})?
+			.to_sat();
+		if spendable_amount_sats < total_amount_sats.saturating_add(tx_fee_sats) {
+			log_error!(self.logger,
+				"Unable to send payment due to insufficient funds. Available: {}sats, Required: {}sats + {}sats fee",
+				spendable_amount_sats,
+				total_amount_sats,
+				tx_fee_sats,
+			);
+			return Err(Error::InsufficientFunds);
+		}
+
+		Ok(psbt)
+	}
+
+	fn broadcast_and_track(&self, tx: &Transaction) -> Txid {
+		self.broadcaster.broadcast_transactions(&[tx]);
+
+		let txid = tx.compute_txid();
+		// We track the transaction right away, so that it can be bumped before the next sync.
+		if let Err(e) = self.apply_unconfirmed_txs(vec![(tx.clone(), unix_time_secs())]) {
+			log_error!(self.logger, "Failed to track transaction {} in wallet: {}", txid, e);
+		}
+		txid
+	}
+
+	fn record_outbound_payment(&self, payment_id: PaymentId, tx: &Transaction, amount_sats: u64) {
+		let txid = tx.compute_txid();
+		let fee_sats = self.inner.lock().unwrap().calculate_fee(tx).ok().map(|fee| fee.to_sat());
+		let kind = PaymentKind::Onchain { txid, replaced_txids: Vec::new(), fee_sats };
+		let payment = PaymentDetails::new(
+			payment_id,
+			kind,
+			Some(amount_sats * 1000),
+			PaymentDirection::Outbound,
+			PaymentStatus::Pending,
+		);
+		if let Err(e) = self.payment_store.insert(payment) {
+			log_error!(self.logger, "Failed to record payment for transaction {}: {}", txid, e);
+		}
+	}
+
+	/// Replaces the given unconfirmed transaction with one paying the given fee rate via [BIP 125]
+	/// replace-by-fee, updating any payments it carries.
+	///
+	/// [BIP 125]: https://github.com/bitcoin/bips/blob/master/bip-0125.mediawiki
+	pub(crate) fn bump_fee_rbf(
+		&self, txid: Txid, fee_rate: FeeRate, cur_anchor_reserve_sats: u64,
+	) -> Result<Txid, Error> {
+		let (tx, replacement_fee) = {
+			let mut locked_wallet = self.inner.lock().unwrap();
+			let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+
+			let original_tx = match locked_wallet.get_tx(txid) {
+				Some(wallet_tx) => wallet_tx.tx_node.tx,
+				None => {
+					log_error!(self.logger, "Failed to bump fee of unknown transaction {}", txid);
+					return Err(Error::OnchainTxCreationFailed);
+				},
+			};
+			let original_fee = locked_wallet.calculate_fee(&original_tx).map_err(|e| {
+				log_error!(self.logger, "Failed to calculate fee of transaction {}: {}", txid, e);
+				e
+			})?;
+
+			let mut tx_builder = locked_wallet.build_fee_bump(txid).map_err(|e| {
+				log_error!(self.logger, "Failed to bump fee of transaction {}: {}", txid, e);
+				Error::OnchainTxCreationFailed
+			})?;
+			tx_builder.fee_rate(fee_rate);
+			let tx_builder =
+				self.select_utxos(tx_builder, &locked_additional_wallets, None, false)?;
+
+			let mut psbt = match tx_builder.finish() {
+				Ok(psbt) => {
+					log_trace!(self.logger, "Created replacement PSBT: {:?}", psbt);
+					psbt
+				},
+				Err(err) => {
+					log_error!(self.logger, "Failed to create replacement transaction: {}", err);
+					return Err(err.into());
+				},
+			};
+
+			let balance = combined_balance(&locked_wallet, &locked_additional_wallets);
+			let spendable_amount_sats = self
+				.get_balances_inner(balance, cur_anchor_reserve_sats)
+				.map(|(_, s)| s)
+				.unwrap_or(0);
+			let replacement_fee = locked_wallet.calculate_fee(&psbt.unsigned_tx).map_err(|e| {
+				log_error!(
+					self.logger,
+					"Failed to calculate fee of replacement transaction: {}",
+					e
+				);
+				e
+			})?;
+			let additional_fee_sats =
+				replacement_fee.to_sat().saturating_sub(original_fee.to_sat());
+			if spendable_amount_sats < additional_fee_sats {
+				log_error!(self.logger,
+					"Unable to bump fee due to insufficient funds. Available: {}sats, Required: {}sats",
+					spendable_amount_sats,
+					additional_fee_sats,
+				);
+				return Err(Error::InsufficientFunds);
+			}
+
+			self.sign_and_persist(&mut locked_wallet, &locked_additional_wallets, &mut psbt)?;
+			let tx = psbt.extract_tx().map_err(|e| {
+				log_error!(self.logger, "Failed to extract transaction: {}", e);
+				e
+			})?;
+			(tx, replacement_fee)
+		};
+
+		self.broadcaster.broadcast_transactions(&[&tx]);
+		let replacement_txid = tx.compute_txid();
+		self.apply_unconfirmed_txs(vec![(tx, unix_time_secs())])?;
+
+		let replaced_payments = self.payment_store.list_filter(
+			|p| matches!(p.kind, PaymentKind::Onchain { txid: payment_txid, .. } if payment_txid == txid),
+		);
+		for payment in replaced_payments {
+			let update = PaymentDetailsUpdate {
+				txid: Some(replacement_txid),
+				fee_sats: Some(Some(replacement_fee.to_sat())),
+				..PaymentDetailsUpdate::new(payment.id)
+			};
+			self.payment_store.update(&update)?;
+		}
+
+		log_info!(
+			self.logger,
+			"Replaced transaction {} with transaction {} paying {}sat/kwu",
+			txid,
+			replacement_txid,
+			fee_rate.to_sat_per_kwu()
+		);
+		Ok(replacement_txid)
+	}
+
+	/// Spends our outputs of the given unconfirmed transaction in a child transaction, so that
+	/// both together pay the given fee rate.
+	pub(crate) fn bump_fee_cpfp(
+		&self, txid: Txid, fee_rate: FeeRate, cur_anchor_reserve_sats: u64,
+	) -> Result<Txid, Error> {
+		let tx = {
+			let mut locked_wallet = self.inner.lock().unwrap();
+			let locked_additional_wallets = self.additional_wallets.lock().unwrap();
+
+			// The parent might only pay to the wallet of another address type, so look it up in
+			// all of our wallets.
+			let wallets = || {
+				std::iter::once(&*locked_wallet)
+					.chain(locked_additional_wallets.iter().map(|w| &w.inner))
+			};
+			let parent_tx = match wallets().find_map(|w| w.get_tx(txid)) {
+				Some(wallet_tx) if wallet_tx.chain_position.is_confirmed() => {
+					log_error!(
+						self.logger,
+						"Failed to bump fee of already confirmed transaction {}",
+						txid
+					);
+					return Err(Error::OnchainTxCreationFailed);
+				},
+				Some(wallet_tx) => wallet_tx.tx_node.tx,
+				None => {
+					log_error!(self.logger, "Failed to bump fee of unknown transaction {}", txid);
+					return Err(Error::OnchainTxCreationFailed);
+				},
+			};
+
+			let mut parent_utxos = Vec::new();
+			let mut additional_parent_utxos = Vec::new();
+			for outpoint in (0..parent_tx.output.len() as u32).map(|vout| OutPoint { txid, vout }) {
+				if locked_wallet.get_utxo(outpoint).is_some() {
+					parent_utxos.push(outpoint);
+					continue;
+				}
+				for additional_wallet in locked_additional_wallets.iter() {
+					if let Some(utxo) = additional_wallet.inner.get_utxo(outpoint) {
+						additional_parent_utxos.push((additional_wallet, utxo));
+						break;
+					}
+				}
+			}
+			if parent_utxos.is_empty() && additional_parent_utxos.is_empty() {
+				log_error!(
+					self.logger,
+					"Failed to bump fee of transaction {} as it has no unspent outputs of ours",
+					txid
+				);
+				return Err(Error::OnchainTxCreationFailed);
+			}
+
+			// We might not know all inputs of transactions paying us, in which case we
+			// conservatively assume the parent doesn't pay any fee.
+			let parent_fee =
+				wallets().find_map(|w| w.calculate_fee(&parent_tx).ok()).unwrap_or(Amount::ZERO);
+			let parent_target_fee =
+				fee_rate.fee_wu(parent_tx.weight()).ok_or(Error::InvalidFeeRate)?;
+			if parent_fee >= parent_target_fee {
+				log_error!(
+					self.logger,
+					"Failed to bump fee of transaction {} as it already pays the requested fee rate",
+					txid
+				);
+				return Err(Error::InvalidFeeRate);
+			}
+
+			let change_script =
+				locked_wallet.next_unused_address(KeychainKind::Internal).address.script_pubkey();
+			let unspendable_utxos = self.unspendable_utxos().into_iter().collect::<Vec<_>>();
+
+			// Build the child at the requested fee rate first to learn its fee, and then add what
+			// is required to lift the parent to the same fee rate.
+			let child_fee = {
+				let mut tmp_tx_builder = locked_wallet.build_tx();
+				tmp_tx_builder
+					.add_utxos(&parent_utxos)?
+					.drain_to(change_script.clone())
+					.unspendable(unspendable_utxos.clone())
+					.fee_rate(fee_rate);
+				for (additional_wallet, utxo) in &additional_parent_utxos {
+					self.add_additional_utxo(&mut tmp_tx_builder, additional_wallet, utxo.clone())?;
+				}
+				let tmp_tx = match tmp_tx_builder.finish() {
+					Ok(psbt) => psbt.unsigned_tx,
+					Err(err) => {
+						log_error!(self.logger, "Failed to create temporary transaction: {}", err);
+						return Err(err.into());
+					},
+				};
+				let tmp_fee = locked_wallet.calculate_fee(&tmp_tx).map_err(|e| {
+					log_error!(
+						self.logger,
+						"Failed to calculate fee of temporary transaction: {}",
+						e
+					);
+					e
+				})?;
+				tmp_fee + (parent_target_fee - parent_fee)
+			};
+
+			let balance = combined_balance(&locked_wallet, &locked_additional_wallets);
+			let available_amount_sats =
+				balance.total().to_sat().saturating_sub(cur_anchor_reserve_sats);
+			if available_amount_sats < child_fee.to_sat() {
+				log_error!(
+					self.logger,
+					"Unable to bump fee due to insufficient funds. Available: {}sats, Required: {}",
+					available_amount_sats,
+					child_fee,
+				);
+				return Err(Error::InsufficientFunds);
+			}
+
+			let mut tx_builder = locked_wallet.build_tx();
+			tx_builder
+				.add_utxos(&parent_utxos)?
+				.drain_to(change_script)
+				.unspendable(unspendable_utxos)
+				.fee_absolute(child_fee);
+			for (additional_wallet, utxo) in additional_parent_utxos {
+				self.add_additional_utxo(&mut tx_builder, additional_wallet, utxo)?;
+			}
+			let mut psbt = match tx_builder.finish() {
+				Ok(psbt) => {
+					log_trace!(self.logger, "Created child PSBT: {:?}", psbt);
+					psbt
+				},
+				Err(err) => {
+					log_error!(self.logger, "Failed to create child transaction: {}", err);
+					return Err(err.into());
+				},
+			};
+
+			self.sign_and_persist(&mut locked_wallet, &locked_additional_wallets, &mut psbt)?;
+			psbt.extract_tx().map_err(|e| {
+				log_error!(self.logger, "Failed to extract transaction: {}", e);
+				e
+			})?
+		};
+
+		self.broadcaster.broadcast_transactions(&[&tx]);
+		let child_txid = tx.compute_txid();
+		self.apply_unconfirmed_txs(vec![(tx, unix_time_secs())])?;
+
+		log_info!(
+			self.logger,
+			"Created child transaction {} bumping the fee of transaction {} to {}sat/kwu",
+			child_txid,
+			txid,
+			fee_rate.to_sat_per_kwu()
+		);
+		Ok(child_txid)
+	}
+
+	fn sign_and_persist(
+		&self, locked_wallet: &mut PersistedWallet<KVStoreWalletPersister>,
+		additional_wallets: &[AdditionalWallet], psbt: &mut Psbt,
+	) -> Result<(), Error> {
+		self.sign_with_additional_wallets(additional_wallets, psbt)?;
+		match locked_wallet.sign(psbt, SignOptions::default()) {
+			Ok(finalized) => {
+				if !finalized {
+					return Err(Error::OnchainTxCreationFailed);
+				}
+			},
+			Err(err) => {
+				log_error!(self.logger, "Failed to create transaction: {}", err);
+				return Err(err.into());
+			},
+		}
+
+		let mut locked_persister = self.persister.lock().unwrap();
+		locked_wallet.persist(&mut locked_persister).map_err(|e| {
+			log_error!(self.logger, "Failed to persist wallet: {}", e);
+			Error::PersistenceFailed
+		})?;
+		Ok(())
+	}
+
+	// Signs the inputs of the given PSBT spending outputs of the wallets of other address types,
+	// leaving it to the primary wallet to check the PSBT is fully signed.
+	fn sign_with_additional_wallets(
+		&self, additional_wallets: &[AdditionalWallet], psbt: &mut Psbt,
+	) -> Result<(), Error> {
+		for additional_wallet in additional_wallets {
+			let spends_wallet_utxos = psbt
+				.unsigned_tx
+				.input
+				.iter()
+				.any(|txin| additional_wallet.inner.get_utxo(txin.previous_output).is_some());
+			if !spends_wallet_utxos {
+				continue;
+			}
+			additional_wallet.inner.sign(psbt, SignOptions::default()).map_err(|e| {
+				log_error!(self.logger, "Failed to sign transaction: {}", e);
+				Error::OnchainTxSigningFailed
+			})?;
+		}
+		Ok(())
+	}
+
+	// Returns the total value of the given transaction's outputs that don't belong to us.
+	fn foreign_output_value_sats(&self, tx: &Transaction) -> u64 {
+		let locked_wallet = self.inner.lock().unwrap();
+		tx.output
+			.iter()
+			.filter(|o| !locked_wallet.is_mine(o.script_pubkey.clone()))
+			.map(|o| o.value.to_sat())
+			.sum()
+	}
+
+	// Marks pending on-chain payments whose transaction got confirmed as succeeded.
+	fn update_payment_statuses(&self, locked_wallet: &PersistedWallet<KVStoreWalletPersister>) {
+		let pending_payments = self.payment_store.list_filter(|p| {
+			p.status == PaymentStatus::Pending && matches!(p.kind, PaymentKind::Onchain { .. })
+		});
+		for payment in pending_payments {
+			if let PaymentKind::Onchain { txid, ref replaced_txids, .. } = payment.kind {
+				// Any of the transactions that carried the payment may be the one that confirms.
+				let candidate_txids =
+					std::iter::once(txid).chain(replaced_txids.iter().copied()).collect::<Vec<_>>();
+				let confirmed_tx = candidate_txids.iter().find_map(|candidate_txid| {
+					locked_wallet
+						.get_tx(*candidate_txid)
+						.filter(|wallet_tx| wallet_tx.chain_position.is_confirmed())
+						.map(|wallet_tx| wallet_tx.tx_node.tx)
+				});
+
+				let update = match confirmed_tx {
+					Some(confirmed_tx) => {
+						let fee_sats =
+							locked_wallet.calculate_fee(&confirmed_tx).ok().map(|fee| fee.to_sat());
+						PaymentDetailsUpdate {
+							txid: Some(confirmed_tx.compute_txid()),
+							fee_sats: Some(fee_sats),
+							status: Some(PaymentStatus::Succeeded),
+							..PaymentDetailsUpdate::new(payment.id)
+						}
+					},
+					None if candidate_txids.iter().all(|candidate_txid| {
+						is_conflicted_by_confirmed_tx(locked_wallet, *candidate_txid)
+					}) =>
+					{
+						log_info!(
+							self.logger,
+							"Failed on-chain payment {} as transaction {} was conflicted by a confirmed transaction.",
+							payment.id,
+							txid
+						);
+						PaymentDetailsUpdate {
+							status: Some(PaymentStatus::Failed),
+							..PaymentDetailsUpdate::new(payment.id)
+						}
+					},
+					None => continue,
+				};
+
+				if let Err(e) = self.payment_store.update(&update) {
+					log_error!(
+						self.logger,
+						"Failed to update payment for transaction {}: {}",
+						txid,
+						e
+					);
+				}
+			}
+		}
+	}
+
+	pub(crate) fn broadcast_transaction(&self, tx: &Transaction) {
+		self.broadcaster.broadcast_transactions(&[tx]);
+	}
+
+	pub(crate) fn is_mine(&self, script_pubkey: &Script) -> bool {
+		self.inner.lock().unwrap().is_mine(script_pubkey.to_owned())
+	}
+
+	/// Returns those of the given scripts that are paid by any of the transactions known to us.
+	pub(crate) fn paid_scripts<'a, I: Iterator<Item = &'a ScriptBuf>>(
+		&self, scripts: I,
+	) -> HashSet<ScriptBuf> {
+		let scripts = scripts.collect::<HashSet<_>>();
+		if scripts.is_empty() {
+			return HashSet::new();
+		}
+		let locked_wallet = self.inner.lock().unwrap();
+		locked_wallet
+			.list_output()
+			.map(|o| o.txout.script_pubkey)
+			.filter(|script_pubkey| scripts.contains(script_pubkey))
+			.collect()
+	}
+
+	pub(crate) fn is_transaction_known(&self, txid: Txid) -> bool {
+		self.inner.lock().unwrap().get_tx(txid).is_some()
+	}
+
+	/// Selects a confirmed UTXO of the same type as the given script to contribute to a payjoin
+	/// transaction, returning the UTXO along with the transaction creating it.
+	///
+	/// Frozen outputs and outputs reserved by pending PSBTs are never selected.
+	pub(crate) fn select_payjoin_input(
+		&self, script_pubkey: &Script,
+	) -> Option<(OutPoint, TxOut, Transaction)> {
+		let same_type = |spk: &Script| {
+			(spk.is_p2wpkh() && script_pubkey.is_p2wpkh())
+				|| (spk.is_p2tr() && script_pubkey.is_p2tr())
+		};
+
+		let unspendable_utxos = self.unspendable_utxos();
+		let locked_wallet = self.inner.lock().unwrap();
+		let candidates = locked_wallet
+			.list_unspent()
+			.filter(|u| {
+				u.chain_position.is_confirmed()
+					&& same_type(&u.txout.script_pubkey)
+					&& !unspendable_utxos.contains(&u.outpoint)
+			})
+			.collect::<Vec<_>>();
+
+		// Picking a random UTXO avoids making our contributed inputs predictable.
+		let utxo = candidates.choose(&mut thread_rng())?;
+		let prev_tx = locked_wallet.get_tx(utxo.outpoint.txid)?.tx_node.tx;
+		Some((utxo.outpoint, utxo.txout.clone(), (*prev_tx).clone()))
+	}
+
+	/// Signs and finalizes all inputs of the given PSBT that belong to us, returning whether all
+	/// of its inputs are finalized.
+	pub(crate) fn sign_owned_inputs(&self, psbt: &mut Psbt) -> Result<bool, Error> {
+		let locked_wallet = self.inner.lock().unwrap();
+		locked_wallet.sign(psbt, SignOptions::default()).map_err(|e| {
+			log_error!(self.logger, "Failed to sign transaction: {}", e);
+			Error::OnchainTxSigningFailed
+		})
+	}
 }
 
 impl<B: Deref, E: Deref, L: Deref> Listen for Wallet<B, E, L>
@@ -493,6 +2395,46 @@ where
 				return;
 			},
 		};
+
+		self.update_payment_statuses(&locked_wallet);
+
+		let mut locked_additional_wallets = self.additional_wallets.lock().unwrap();
+		for additional_wallet in locked_additional_wallets.iter_mut() {
+			if let Err(e) = additional_wallet.inner.apply_block(block, height) {
+				log_error!(
+					self.logger,
+					"Failed to apply connected block to {:?} wallet: {}",
+					additional_wallet.address_type,
+					e
+				);
+				continue;
+			}
+			if let Err(e) = additional_wallet.persist() {
+				log_error!(self.logger, "Failed to persist on-chain wallet: {}", e);
+			}
+		}
+
+		if !locked_additional_wallets.is_empty() {
+			insert_additional_txouts(&mut locked_wallet, &locked_additional_wallets);
+			if let Err(e) = locked_wallet.persist(&mut locked_persister) {
+				log_error!(self.logger, "Failed to persist on-chain wallet: {}", e);
+			}
+		}
+
+		let mut locked_watch_only_wallets = self.watch_only_wallets.lock().unwrap();
+		for watch_only_wallet in locked_watch_only_wallets.iter_mut() {
+			if let Err(e) = watch_only_wallet.inner.apply_block(block, height) {
+				log_error!(
+					self.logger,
+					"Failed to apply connected block to watch-only wallet: {}",
+					e
+				);
+				continue;
+			}
+			if let Err(e) = watch_only_wallet.persist() {
+				log_error!(self.logger, "Failed to persist watch-only wallet: {}", e);
+			}
+		}
 	}
 
 	fn block_disconnected(&self, _header: &bitcoin::block::Header, _height: u32) {
@@ -587,6 +2529,20 @@ where
 				Some(version) => {
 					log_error!(self.logger, "Unexpected witness version: {}", version,);
 				},
+				None if script_pubkey.is_p2sh() => {
+					// Our nested SegWit outputs are P2SH-wrapped P2WPKH outputs, which are
+					// satisfied by pushing the redeem script and providing a P2WPKH witness.
+					let utxo = Utxo {
+						outpoint: u.outpoint,
+						output: TxOut { value: u.txout.value, script_pubkey },
+						satisfaction_weight: (1 /* script_sig len */ + 1 /* OP_PUSH22 */ +
+							1 /* OP_0 */ + 1 /* OP_PUSH20 */ + 20 /* pubkey hash */) *
+							WITNESS_SCALE_FACTOR as u64 +
+							1 /* witness items */ + 1 /* sig len */ + 73 /* sig */ +
+							1 /* pubkey len */ + 33, /* pubkey */
+					};
+					utxos.push(utxo);
+				},
 				None => {
 					log_error!(
 						self.logger,
@@ -782,14 +2738,14 @@ where
 	}
 
 	fn get_destination_script(&self, _channel_keys_id: [u8; 32]) -> Result<ScriptBuf, ()> {
-		let address = self.wallet.get_new_address().map_err(|e| {
+		let address = self.wallet.get_new_address(None).map_err(|e| {
 			log_error!(self.logger, "Failed to retrieve new address from wallet: {}", e);
 		})?;
 		Ok(address.script_pubkey())
 	}
 
 	fn get_shutdown_scriptpubkey(&self) -> Result<ShutdownScript, ()> {
-		let address = self.wallet.get_new_address().map_err(|e| {
+		let address = self.wallet.get_new_shutdown_address().map_err(|e| {
 			log_error!(self.logger, "Failed to retrieve new address from wallet: {}", e);
 		})?;
 
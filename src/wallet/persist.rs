@@ -5,29 +5,57 @@
 // http://opensource.org/licenses/MIT>, at your option. You may not use this file except in
 // accordance with one or both of these licenses.
 
+use crate::config::AddressType;
 use crate::io::utils::{
 	read_bdk_wallet_change_set, write_bdk_wallet_change_descriptor, write_bdk_wallet_descriptor,
 	write_bdk_wallet_indexer, write_bdk_wallet_local_chain, write_bdk_wallet_network,
 	write_bdk_wallet_tx_graph,
 };
+use crate::io::{
+	BDK_WALLET_NATIVE_SEGWIT_SECONDARY_NAMESPACE, BDK_WALLET_NESTED_SEGWIT_SECONDARY_NAMESPACE,
+	BDK_WALLET_TAPROOT_SECONDARY_NAMESPACE, BDK_WALLET_WATCH_ONLY_SECONDARY_NAMESPACE_PREFIX,
+};
 use crate::logger::{log_error, FilesystemLogger};
 use crate::types::DynStore;
 
 use lightning::util::logger::Logger;
 
-use bdk_chain::Merge;
+use bdk_chain::{DescriptorId, Merge};
 use bdk_wallet::{ChangeSet, WalletPersister};
 
 use std::sync::Arc;
 pub(crate) struct KVStoreWalletPersister {
 	latest_change_set: Option<ChangeSet>,
+	// The secondary namespace the wallet is persisted under, depending on its address type or the
+	// descriptor it watches.
+	secondary_namespace: String,
 	kv_store: Arc<DynStore>,
 	logger: Arc<FilesystemLogger>,
 }
 
 impl KVStoreWalletPersister {
-	pub(crate) fn new(kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>) -> Self {
-		Self { latest_change_set: None, kv_store, logger }
+	pub(crate) fn new(
+		address_type: AddressType, kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
+	) -> Self {
+		let secondary_namespace = match address_type {
+			AddressType::NativeSegwit => BDK_WALLET_NATIVE_SEGWIT_SECONDARY_NAMESPACE,
+			AddressType::Taproot => BDK_WALLET_TAPROOT_SECONDARY_NAMESPACE,
+			AddressType::NestedSegwit => BDK_WALLET_NESTED_SEGWIT_SECONDARY_NAMESPACE,
+		};
+		Self {
+			latest_change_set: None,
+			secondary_namespace: secondary_namespace.to_string(),
+			kv_store,
+			logger,
+		}
+	}
+
+	pub(crate) fn new_watch_only(
+		descriptor_id: DescriptorId, kv_store: Arc<DynStore>, logger: Arc<FilesystemLogger>,
+	) -> Self {
+		let secondary_namespace =
+			format!("{}{}", BDK_WALLET_WATCH_ONLY_SECONDARY_NAMESPACE_PREFIX, descriptor_id);
+		Self { latest_change_set: None, secondary_namespace, kv_store, logger }
 	}
 }
 
@@ -41,6 +69,7 @@ impl WalletPersister for KVStoreWalletPersister {
 		}
 
 		let change_set_opt = read_bdk_wallet_change_set(
+			&persister.secondary_namespace,
 			Arc::clone(&persister.kv_store),
 			Arc::clone(&persister.logger),
 		)?;
@@ -91,6 +120,7 @@ impl WalletPersister for KVStoreWalletPersister {
 				latest_change_set.descriptor = Some(descriptor.clone());
 				write_bdk_wallet_descriptor(
 					&descriptor,
+					&persister.secondary_namespace,
 					Arc::clone(&persister.kv_store),
 					Arc::clone(&persister.logger),
 				)?;
@@ -114,6 +144,7 @@ impl WalletPersister for KVStoreWalletPersister {
 				latest_change_set.change_descriptor = Some(change_descriptor.clone());
 				write_bdk_wallet_change_descriptor(
 					&change_descriptor,
+					&persister.secondary_namespace,
 					Arc::clone(&persister.kv_store),
 					Arc::clone(&persister.logger),
 				)?;
@@ -135,17 +166,18 @@ impl WalletPersister for KVStoreWalletPersister {
 				latest_change_set.network = Some(network);
 				write_bdk_wallet_network(
 					&network,
+					&persister.secondary_namespace,
 					Arc::clone(&persister.kv_store),
 					Arc::clone(&persister.logger),
 				)?;
 			}
 		}
 
+		// Note the change_descriptor is optional, as watch-only wallets tracking a single descriptor
+		// don't have one.
 		debug_assert!(
-			latest_change_set.descriptor.is_some()
-				&& latest_change_set.change_descriptor.is_some()
-				&& latest_change_set.network.is_some(),
-			"descriptor, change_descriptor, and network are mandatory ChangeSet fields"
+			latest_change_set.descriptor.is_some() && latest_change_set.network.is_some(),
+			"descriptor and network are mandatory ChangeSet fields"
 		);
 
 		// Merge and persist the sub-changesets individually if necessary.
@@ -159,6 +191,7 @@ impl WalletPersister for KVStoreWalletPersister {
 			latest_change_set.indexer.merge(change_set.indexer.clone());
 			write_bdk_wallet_indexer(
 				&latest_change_set.indexer,
+				&persister.secondary_namespace,
 				Arc::clone(&persister.kv_store),
 				Arc::clone(&persister.logger),
 			)?;
@@ -168,6 +201,7 @@ impl WalletPersister for KVStoreWalletPersister {
 			latest_change_set.tx_graph.merge(change_set.tx_graph.clone());
 			write_bdk_wallet_tx_graph(
 				&latest_change_set.tx_graph,
+				&persister.secondary_namespace,
 				Arc::clone(&persister.kv_store),
 				Arc::clone(&persister.logger),
 			)?;
@@ -177,6 +211,7 @@ impl WalletPersister for KVStoreWalletPersister {
 			latest_change_set.local_chain.merge(change_set.local_chain.clone());
 			write_bdk_wallet_local_chain(
 				&latest_change_set.local_chain,
+				&persister.secondary_namespace,
 				Arc::clone(&persister.kv_store),
 				Arc::clone(&persister.logger),
 			)?;
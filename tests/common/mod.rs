@@ -242,9 +242,9 @@ pub(crate) fn random_config(anchor_channels: bool) -> Config {
 }
 
 #[cfg(feature = "uniffi")]
-type TestNode = Arc<Node>;
+pub(crate) type TestNode = Arc<Node>;
 #[cfg(not(feature = "uniffi"))]
-type TestNode = Node;
+pub(crate) type TestNode = Node;
 
 #[derive(Clone)]
 pub(crate) enum TestChainSource<'a> {
@@ -441,6 +441,8 @@ pub fn open_channel(
 				funding_amount_sat,
 				None,
 				None,
+				None,
+				None,
 			)
 			.unwrap();
 	} else {
@@ -451,6 +453,8 @@ pub fn open_channel(
 				funding_amount_sat,
 				None,
 				None,
+				None,
+				None,
 			)
 			.unwrap();
 	}
@@ -466,8 +470,8 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 	node_a: TestNode, node_b: TestNode, bitcoind: &BitcoindClient, electrsd: &E, allow_0conf: bool,
 	expect_anchor_channel: bool, force_close: bool,
 ) {
-	let addr_a = node_a.onchain_payment().new_address().unwrap();
-	let addr_b = node_b.onchain_payment().new_address().unwrap();
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
 
 	let premine_amount_sat = if expect_anchor_channel { 2_125_000 } else { 2_100_000 };
 
@@ -496,6 +500,8 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 			funding_amount_sat,
 			Some(push_msat),
 			None,
+			None,
+			None,
 		)
 		.unwrap();
 
@@ -596,6 +602,10 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 	assert_eq!(node_b.payment(&payment_id).unwrap().direction, PaymentDirection::Inbound);
 	assert_eq!(node_b.payment(&payment_id).unwrap().amount_msat, Some(invoice_amount_1_msat));
 
+	// Assert we only allow to retry failed outbound payments.
+	assert_eq!(Err(NodeError::InvalidPaymentId), node_a.retry_payment(&payment_id));
+	assert_eq!(Err(NodeError::InvalidPaymentId), node_b.retry_payment(&payment_id));
+
 	// Test under-/overpayment
 	let invoice_amount_2_msat = 2500_000;
 	let invoice = node_b.bolt11_payment().receive(invoice_amount_2_msat, &"asdf", 9217).unwrap();
@@ -686,10 +696,16 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 		manual_payment_hash,
 		invoice_amount_3_msat
 	);
+	let held_payments = node_b.list_held_payments();
+	assert_eq!(held_payments.len(), 1);
+	assert_eq!(held_payments[0].payment_hash, manual_payment_hash);
+	assert_eq!(held_payments[0].claimable_amount_msat, claimable_amount_msat);
+	assert!(held_payments[0].claim_deadline.is_some());
 	node_b
 		.bolt11_payment()
 		.claim_for_hash(manual_payment_hash, claimable_amount_msat, manual_preimage)
 		.unwrap();
+	assert!(node_b.list_held_payments().is_empty());
 	expect_payment_received_event!(node_b, claimable_amount_msat);
 	expect_payment_successful_event!(node_a, Some(manual_payment_id), None);
 	assert_eq!(node_a.payment(&manual_payment_id).unwrap().status, PaymentStatus::Succeeded);
@@ -724,7 +740,9 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 		manual_fail_payment_hash,
 		invoice_amount_4_msat
 	);
+	assert_eq!(node_b.list_held_payments().len(), 1);
 	node_b.bolt11_payment().fail_for_hash(manual_fail_payment_hash).unwrap();
+	assert!(node_b.list_held_payments().is_empty());
 	expect_event!(node_a, PaymentFailed);
 	assert_eq!(node_a.payment(&manual_fail_payment_id).unwrap().status, PaymentStatus::Failed);
 	assert_eq!(
@@ -753,6 +771,25 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 		PaymentKind::Bolt11 { .. }
 	));
 
+	// Test retrying the failed payment, which keeps its payment id.
+	node_a.retry_payment(&manual_fail_payment_id).unwrap();
+	assert_eq!(node_a.payment(&manual_fail_payment_id).unwrap().status, PaymentStatus::Pending);
+	let claimable_amount_msat = expect_payment_claimable_event!(
+		node_b,
+		manual_fail_payment_id,
+		manual_fail_payment_hash,
+		invoice_amount_4_msat
+	);
+	node_b
+		.bolt11_payment()
+		.claim_for_hash(manual_fail_payment_hash, claimable_amount_msat, manual_fail_preimage)
+		.unwrap();
+	expect_payment_received_event!(node_b, claimable_amount_msat);
+	expect_payment_successful_event!(node_a, Some(manual_fail_payment_id), None);
+	assert_eq!(node_a.payment(&manual_fail_payment_id).unwrap().status, PaymentStatus::Succeeded);
+	assert_eq!(node_b.payment(&manual_fail_payment_id).unwrap().status, PaymentStatus::Succeeded);
+	assert_eq!(node_a.retry_payment(&manual_fail_payment_id), Err(NodeError::InvalidPaymentId));
+
 	// Test spontaneous/keysend payments
 	println!("\nA send_spontaneous_payment");
 	let keysend_amount_msat = 2500_000;
@@ -894,6 +931,7 @@ pub(crate) fn do_channel_full_cycle<E: ElectrumApi>(
 		+ invoice_amount_1_msat
 		+ overpaid_amount_msat
 		+ invoice_amount_3_msat
+		+ invoice_amount_4_msat
 		+ determined_amount_msat
 		+ keysend_amount_msat)
 		/ 1000;
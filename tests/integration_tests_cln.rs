@@ -51,7 +51,7 @@ fn test_cln() {
 	node.start().unwrap();
 
 	// Premine some funds and distribute
-	let address = node.onchain_payment().new_address().unwrap();
+	let address = node.onchain_payment().new_address(None).unwrap();
 	let premine_amount = Amount::from_sat(5_000_000);
 	common::premine_and_distribute_funds(
 		&bitcoind_client,
@@ -82,8 +82,16 @@ fn test_cln() {
 	// Open the channel
 	let funding_amount_sat = 1_000_000;
 
-	node.open_channel(cln_node_id, cln_address, funding_amount_sat, Some(500_000_000), None)
-		.unwrap();
+	node.open_channel(
+		cln_node_id,
+		cln_address,
+		funding_amount_sat,
+		Some(500_000_000),
+		None,
+		None,
+		None,
+	)
+	.unwrap();
 
 	let funding_txo = common::expect_channel_pending_event!(node, cln_node_id);
 	common::wait_for_tx(&electrs_client, funding_txo.txid);
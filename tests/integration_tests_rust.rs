@@ -8,23 +8,44 @@
 mod common;
 
 use common::{
-	do_channel_full_cycle, expect_channel_ready_event, expect_event, expect_payment_received_event,
-	expect_payment_successful_event, generate_blocks_and_wait, open_channel,
-	premine_and_distribute_funds, random_config, setup_bitcoind_and_electrsd, setup_builder,
-	setup_node, setup_two_nodes, wait_for_tx, TestChainSource, TestSyncStore,
+	do_channel_full_cycle, expect_channel_pending_event, expect_channel_ready_event, expect_event,
+	expect_payment_received_event, expect_payment_successful_event, generate_blocks_and_wait,
+	open_channel, premine_and_distribute_funds, random_config, random_port,
+	setup_bitcoind_and_electrsd, setup_builder, setup_node, setup_two_nodes, wait_for_tx,
+	TestChainSource, TestNode, TestSyncStore,
 };
 
-use ldk_node::config::EsploraSyncConfig;
-use ldk_node::payment::{PaymentKind, QrPaymentResult, SendingParameters};
-use ldk_node::{Builder, Event, NodeError};
+use ldk_node::config::{AddressType, EsploraSyncConfig, PayjoinConfig};
+use ldk_node::payment::{
+	KeysendMessage, PaymentKind, PaymentRetryStrategy, PaymentStatus, QrPaymentAttemptFailure,
+	QrPaymentResult, SendingParameters, UnifiedPaymentMethod, UnifiedPaymentPolicy,
+};
+use ldk_node::{
+	BuildError, Builder, ChannelOpenRequest, Event, LabelType, NodeError, ProbeResult,
+	ScheduledPaymentTarget, WatchOnlyBalance,
+};
 
 use lightning::ln::channelmanager::PaymentId;
+use lightning::offers::offer::OfferId;
+use lightning::routing::gossip::NodeId;
 use lightning::util::persist::KVStore;
 
 use bitcoincore_rpc::RpcApi;
 
-use bitcoin::Amount;
+use electrsd::bitcoind::BitcoinD;
+use electrsd::ElectrsD;
+use electrum_client::ElectrumApi;
+
+use bdk_wallet::template::Bip84;
+use bdk_wallet::{KeychainKind, SignOptions};
+
+use bitcoin::bip32::{DerivationPath, Xpriv, Xpub};
+use bitcoin::hashes::Hash;
+use bitcoin::secp256k1::Secp256k1;
+use bitcoin::{Address, Amount, Network, Transaction};
 
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
 
 #[test]
@@ -81,8 +102,8 @@ fn channel_open_fails_when_funds_insufficient() {
 	let chain_source = TestChainSource::Esplora(&electrsd);
 	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
 
-	let addr_a = node_a.onchain_payment().new_address().unwrap();
-	let addr_b = node_b.onchain_payment().new_address().unwrap();
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
 
 	let premine_amount_sat = 100_000;
 
@@ -106,7 +127,271 @@ fn channel_open_fails_when_funds_insufficient() {
 			120000,
 			None,
 			None,
+			None,
+			None,
+		)
+	);
+}
+
+#[test]
+fn channel_open_batch() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let node_a = setup_node(&chain_source, random_config(true), None);
+	let node_b = setup_node(&chain_source, random_config(true), None);
+	let node_c = setup_node(&chain_source, random_config(true), None);
+
+	// The counterparties need to hold the Anchor reserve to accept the channels.
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let addr_c = node_c.onchain_payment().new_address(None).unwrap();
+	let premine_amount_sat = 5_000_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a, addr_b, addr_c],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+
+	let request = |node: &TestNode, channel_amount_sats| ChannelOpenRequest {
+		node_id: node.node_id(),
+		address: node.listening_addresses().unwrap().first().unwrap().clone(),
+		channel_amount_sats,
+		push_to_counterparty_msat: None,
+		channel_config: None,
+		announce_for_forwarding: false,
+	};
+
+	assert_eq!(
+		Err(NodeError::InsufficientFunds),
+		node_a.open_channels_batch(vec![request(&node_b, 3_000_000), request(&node_c, 3_000_000)])
+	);
+
+	let user_channel_ids = node_a
+		.open_channels_batch(vec![request(&node_b, 1_000_000), request(&node_c, 2_000_000)])
+		.unwrap();
+	assert_eq!(user_channel_ids.len(), 2);
+
+	// Both channels are funded by the same transaction.
+	let mut funding_txids = Vec::new();
+	for _ in 0..2 {
+		match node_a.wait_next_event() {
+			Event::ChannelPending { funding_txo, user_channel_id, .. } => {
+				assert!(user_channel_ids.contains(&user_channel_id));
+				funding_txids.push(funding_txo.txid);
+				node_a.event_handled();
+			},
+			e => panic!("node_a got unexpected event: {:?}", e),
+		}
+	}
+	assert_eq!(funding_txids[0], funding_txids[1]);
+	expect_channel_pending_event!(node_b, node_a.node_id());
+	expect_channel_pending_event!(node_c, node_a.node_id());
+	wait_for_tx(&electrsd.client, funding_txids[0]);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	node_c.sync_wallets().unwrap();
+
+	let channels = node_a.list_channels();
+	assert_eq!(channels.len(), 2);
+	assert!(channels.iter().all(|c| c.funding_txo.unwrap().txid == funding_txids[0]));
+}
+
+#[test]
+fn channel_open_batch_rejected() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let node_a = setup_node(&chain_source, random_config(true), None);
+	let node_b = setup_node(&chain_source, random_config(true), None);
+	let node_c = setup_node(&chain_source, random_config(true), None);
+
+	// Node C doesn't hold the Anchor reserve, so it will reject its channel.
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let premine_amount_sat = 5_000_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a, addr_b],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	let request = |node: &TestNode, channel_amount_sats| ChannelOpenRequest {
+		node_id: node.node_id(),
+		address: node.listening_addresses().unwrap().first().unwrap().clone(),
+		channel_amount_sats,
+		push_to_counterparty_msat: None,
+		channel_config: None,
+		announce_for_forwarding: false,
+	};
+	let user_channel_ids = node_a
+		.open_channels_batch(vec![request(&node_b, 1_000_000), request(&node_c, 2_000_000)])
+		.unwrap();
+
+	// Both channels of the batch are closed, including the one node B accepted.
+	let mut closed_channel_ids = Vec::new();
+	for _ in 0..2 {
+		match node_a.wait_next_event() {
+			Event::ChannelClosed { user_channel_id, .. } => {
+				assert!(user_channel_ids.contains(&user_channel_id));
+				assert!(!closed_channel_ids.contains(&user_channel_id));
+				closed_channel_ids.push(user_channel_id);
+				node_a.event_handled();
+			},
+			e => panic!("node_a got unexpected event: {:?}", e),
+		}
+	}
+	assert!(node_a.list_channels().is_empty());
+
+	// No funding transaction was broadcast.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+	assert!(bitcoind.client.get_raw_mempool().unwrap().is_empty());
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node_a.sync_wallets().unwrap();
+	assert_eq!(node_a.list_balances().total_onchain_balance_sats, premine_amount_sat);
+	assert!(node_b.list_channels().is_empty());
+	assert!(node_c.list_channels().is_empty());
+}
+
+#[test]
+fn channel_open_with_external_funding() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let node_a = setup_node(&chain_source, random_config(true), None);
+	let node_b = setup_node(&chain_source, random_config(true), None);
+
+	// The nodes only hold the anchor reserve, while the channel is funded by bitcoind's wallet.
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a, addr_b],
+		Amount::from_sat(100_000),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	let channel_amount_sats = 1_000_000;
+	let user_channel_id = node_a
+		.open_channel_with_external_funding(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			channel_amount_sats,
+			None,
+			None,
+			false,
 		)
+		.unwrap();
+
+	let (output_script, amount_sats) = match node_a.wait_next_event() {
+		Event::FundingTransactionRequired {
+			user_channel_id: id,
+			counterparty_node_id,
+			output_script,
+			amount_sats,
+		} => {
+			assert_eq!(id, user_channel_id);
+			assert_eq!(counterparty_node_id, node_b.node_id());
+			node_a.event_handled();
+			(output_script, amount_sats)
+		},
+		e => panic!("node_a got unexpected event: {:?}", e),
+	};
+	assert_eq!(amount_sats, channel_amount_sats);
+
+	let funding_address = Address::from_script(&output_script, Network::Regtest).unwrap();
+	let mut outputs = HashMap::new();
+	outputs.insert(funding_address.to_string(), Amount::from_sat(amount_sats));
+	let raw_tx = bitcoind.client.create_raw_transaction_hex(&[], &outputs, None, None).unwrap();
+	let funded_tx = bitcoind.client.fund_raw_transaction(raw_tx, None, None).unwrap();
+
+	// An unsigned transaction is rejected without aborting the channel open.
+	let unsigned_tx: Transaction = bitcoin::consensus::deserialize(&funded_tx.hex).unwrap();
+	assert_eq!(
+		Err(NodeError::InvalidFundingTransaction),
+		node_a.provide_funding_transaction(&user_channel_id, unsigned_tx)
+	);
+
+	let signed_tx =
+		bitcoind.client.sign_raw_transaction_with_wallet(&funded_tx.hex, None, None).unwrap();
+	let funding_tx = signed_tx.transaction().unwrap();
+	let funding_txid = funding_tx.compute_txid();
+	node_a.provide_funding_transaction(&user_channel_id, funding_tx.clone()).unwrap();
+	assert_eq!(
+		Err(NodeError::InvalidChannelId),
+		node_a.provide_funding_transaction(&user_channel_id, funding_tx)
+	);
+
+	let funding_txo = expect_channel_pending_event!(node_a, node_b.node_id());
+	assert_eq!(funding_txo.txid, funding_txid);
+	expect_channel_pending_event!(node_b, node_a.node_id());
+	wait_for_tx(&electrsd.client, funding_txid);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// The channel value wasn't taken from node A's wallet.
+	assert_eq!(node_a.list_balances().total_onchain_balance_sats, 100_000);
+}
+
+#[test]
+fn channel_open_with_external_funding_timeout() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let mut config_a = random_config(false);
+	config_a.external_funding_timeout_secs = 1;
+	let node_a = setup_node(&chain_source, config_a, None);
+	let node_b = setup_node(&chain_source, random_config(false), None);
+
+	let user_channel_id = node_a
+		.open_channel_with_external_funding(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			1_000_000,
+			None,
+			None,
+			false,
+		)
+		.unwrap();
+
+	match node_a.wait_next_event() {
+		Event::FundingTransactionRequired { user_channel_id: id, .. } => {
+			assert_eq!(id, user_channel_id);
+			node_a.event_handled();
+		},
+		e => panic!("node_a got unexpected event: {:?}", e),
+	}
+
+	// The channel open is aborted once the funding transaction wasn't provided in time.
+	match node_a.wait_next_event() {
+		Event::ChannelClosed { user_channel_id: id, .. } => {
+			assert_eq!(id, user_channel_id);
+			node_a.event_handled();
+		},
+		e => panic!("node_a got unexpected event: {:?}", e),
+	}
+	assert!(node_a.list_channels().is_empty());
+
+	let tx = Transaction {
+		version: bitcoin::transaction::Version::TWO,
+		lock_time: bitcoin::absolute::LockTime::ZERO,
+		input: Vec::new(),
+		output: Vec::new(),
+	};
+	assert_eq!(
+		Err(NodeError::InvalidChannelId),
+		node_a.provide_funding_transaction(&user_channel_id, tx)
 	);
 }
 
@@ -129,7 +414,7 @@ fn multi_hop_sending() {
 		nodes.push(node);
 	}
 
-	let addresses = nodes.iter().map(|n| n.onchain_payment().new_address().unwrap()).collect();
+	let addresses = nodes.iter().map(|n| n.onchain_payment().new_address(None).unwrap()).collect();
 	let premine_amount_sat = 5_000_000;
 	premine_and_distribute_funds(
 		&bitcoind.client,
@@ -182,11 +467,22 @@ fn multi_hop_sending() {
 	// Sleep a bit for gossip to propagate.
 	std::thread::sleep(std::time::Duration::from_secs(1));
 
+	match nodes[0].probe(nodes[4].node_id(), 2_500_000, 30).unwrap() {
+		ProbeResult::Succeeded { path, .. } => {
+			assert_eq!(path.len(), 3);
+			assert_eq!(path.last().unwrap().node_id, nodes[4].node_id());
+		},
+		ProbeResult::Failed { short_channel_id } => {
+			panic!("Unexpected probe failure at channel {:?}", short_channel_id);
+		},
+	}
+
 	let sending_params = SendingParameters {
 		max_total_routing_fee_msat: Some(Some(75_000).into()),
 		max_total_cltv_expiry_delta: Some(1000),
 		max_path_count: Some(10),
 		max_channel_saturation_power_of_half: Some(2),
+		retry_strategy: Some(PaymentRetryStrategy::Attempts { max_attempts: 3 }),
 	};
 
 	let invoice = nodes[4].bolt11_payment().receive(2_500_000, &"asdf", 9217).unwrap();
@@ -198,6 +494,86 @@ fn multi_hop_sending() {
 	let payment_id = expect_payment_received_event!(&nodes[4], 2_500_000);
 	let fee_paid_msat = Some(2000);
 	expect_payment_successful_event!(nodes[0], payment_id, Some(fee_paid_msat));
+
+	// Check we can send multi-path spontaneous payments. As the amount exceeds the outbound
+	// capacity of any of node 1's channels, it needs to be split across the paths via node 2 and
+	// node 3.
+	let mpp_amount_msat = 1_500_000_000;
+	assert!(nodes[1].list_channels().iter().all(|c| c.outbound_capacity_msat < mpp_amount_msat));
+	let mpp_sending_params = SendingParameters {
+		max_total_routing_fee_msat: None,
+		max_total_cltv_expiry_delta: None,
+		max_path_count: Some(2),
+		max_channel_saturation_power_of_half: Some(0),
+		retry_strategy: None,
+	};
+	// Node 4 signals support for multi-path payments in its node announcement, so wait for it to
+	// reach node 1.
+	let node_id_4 = NodeId::from_pubkey(&nodes[4].node_id());
+	while nodes[1].network_graph().node(&node_id_4).map_or(true, |n| n.announcement_info.is_none())
+	{
+		std::thread::sleep(std::time::Duration::from_millis(100));
+	}
+	let keysend_payment_id = nodes[1]
+		.spontaneous_payment()
+		.send_mpp(mpp_amount_msat, nodes[4].node_id(), Some(mpp_sending_params), Vec::new())
+		.unwrap();
+	expect_event!(nodes[2], PaymentForwarded);
+	expect_event!(nodes[3], PaymentForwarded);
+	let received_payment_id = expect_payment_received_event!(&nodes[4], mpp_amount_msat);
+	assert_eq!(received_payment_id, Some(keysend_payment_id));
+	// Each of the two paths pays the base fee of a single hop.
+	expect_payment_successful_event!(nodes[1], Some(keysend_payment_id), Some(Some(2000)));
+
+	// Check we can attach messages to spontaneous payments.
+	let message_payment_id =
+		nodes[1].spontaneous_payment().send_message(nodes[2].node_id(), 10_000, "Hi!").unwrap();
+	let expected_message = Some(KeysendMessage::Text { text: "Hi!".to_string() });
+	match nodes[2].wait_next_event() {
+		ref e @ Event::PaymentReceived { payment_id, ref received_message, .. } => {
+			println!("{} got event {:?}", nodes[2].node_id(), e);
+			assert_eq!(payment_id, Some(message_payment_id));
+			assert_eq!(*received_message, expected_message);
+			nodes[2].event_handled();
+		},
+		ref e => panic!("{} got unexpected event!: {:?}", std::stringify!(nodes[2]), e),
+	}
+	expect_payment_successful_event!(nodes[1], Some(message_payment_id), Some(Some(0)));
+	match nodes[2].payment(&message_payment_id).unwrap().kind {
+		PaymentKind::Spontaneous { message, .. } => assert_eq!(message, expected_message),
+		kind => panic!("Unexpected payment kind: {:?}", kind),
+	}
+	assert_eq!(
+		nodes[1].spontaneous_payment().send_message(nodes[2].node_id(), 10_000, ""),
+		Err(NodeError::InvalidKeysendMessage)
+	);
+
+	// Check we can schedule recurring payments.
+	let target = ScheduledPaymentTarget::Spontaneous { node_id: nodes[2].node_id() };
+	assert_eq!(
+		nodes[1].schedule_payment(target.clone(), 10_000, 1, None, None),
+		Err(NodeError::InvalidPaymentSchedule)
+	);
+	let schedule_id = nodes[1].schedule_payment(target, 10_000, 3600, None, Some(1)).unwrap();
+	let scheduled_payment_id = match nodes[1].wait_next_event() {
+		ref e @ Event::ScheduledPaymentExecuted { schedule_id: id, payment_id, amount_msat } => {
+			println!("{} got event {:?}", nodes[1].node_id(), e);
+			assert_eq!(id, schedule_id);
+			assert_eq!(amount_msat, 10_000);
+			nodes[1].event_handled();
+			payment_id
+		},
+		ref e => panic!("{} got unexpected event!: {:?}", std::stringify!(nodes[1]), e),
+	};
+	expect_payment_successful_event!(nodes[1], Some(scheduled_payment_id), Some(Some(0)));
+	let received_payment_id = expect_payment_received_event!(&nodes[2], 10_000);
+	assert_eq!(received_payment_id, Some(scheduled_payment_id));
+	let schedules = nodes[1].list_scheduled_payments();
+	assert_eq!(schedules.len(), 1);
+	assert_eq!(schedules[0].executions.len(), 1);
+	assert_eq!(schedules[0].next_execution_at, None);
+	nodes[1].cancel_scheduled_payment(&schedule_id).unwrap();
+	assert!(nodes[1].list_scheduled_payments().is_empty());
 }
 
 #[test]
@@ -222,7 +598,7 @@ fn start_stop_reinit() {
 	let expected_node_id = node.node_id();
 	assert_eq!(node.start(), Err(NodeError::AlreadyRunning));
 
-	let funding_address = node.onchain_payment().new_address().unwrap();
+	let funding_address = node.onchain_payment().new_address(None).unwrap();
 
 	assert_eq!(node.list_balances().total_onchain_balance_sats, 0);
 
@@ -277,8 +653,8 @@ fn onchain_spend_receive() {
 	let chain_source = TestChainSource::Esplora(&electrsd);
 	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
 
-	let addr_a = node_a.onchain_payment().new_address().unwrap();
-	let addr_b = node_b.onchain_payment().new_address().unwrap();
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
 
 	let premine_amount_sat = 1_100_000;
 	premine_and_distribute_funds(
@@ -315,11 +691,12 @@ fn onchain_spend_receive() {
 
 	assert_eq!(
 		Err(NodeError::InsufficientFunds),
-		node_a.onchain_payment().send_to_address(&addr_b, expected_node_a_balance + 1)
+		node_a.onchain_payment().send_to_address(&addr_b, expected_node_a_balance + 1, None, None)
 	);
 
 	let amount_to_send_sats = 1000;
-	let txid = node_b.onchain_payment().send_to_address(&addr_a, amount_to_send_sats).unwrap();
+	let txid =
+		node_b.onchain_payment().send_to_address(&addr_a, amount_to_send_sats, None, None).unwrap();
 	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
 	wait_for_tx(&electrsd.client, txid);
 
@@ -333,8 +710,8 @@ fn onchain_spend_receive() {
 	assert!(node_b.list_balances().spendable_onchain_balance_sats > expected_node_b_balance_lower);
 	assert!(node_b.list_balances().spendable_onchain_balance_sats < expected_node_b_balance_upper);
 
-	let addr_b = node_b.onchain_payment().new_address().unwrap();
-	let txid = node_a.onchain_payment().send_all_to_address(&addr_b, true).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let txid = node_a.onchain_payment().send_all_to_address(&addr_b, true, None, None).unwrap();
 	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
 	wait_for_tx(&electrsd.client, txid);
 
@@ -349,8 +726,8 @@ fn onchain_spend_receive() {
 	assert!(node_b.list_balances().spendable_onchain_balance_sats > expected_node_b_balance_lower);
 	assert!(node_b.list_balances().spendable_onchain_balance_sats < expected_node_b_balance_upper);
 
-	let addr_b = node_b.onchain_payment().new_address().unwrap();
-	let txid = node_a.onchain_payment().send_all_to_address(&addr_b, false).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let txid = node_a.onchain_payment().send_all_to_address(&addr_b, false, None, None).unwrap();
 	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
 	wait_for_tx(&electrsd.client, txid);
 
@@ -367,6 +744,634 @@ fn onchain_spend_receive() {
 	assert!(node_b.list_balances().spendable_onchain_balance_sats < expected_node_b_balance_upper);
 }
 
+#[test]
+fn onchain_coin_control() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
+
+	let addr_a_1 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_a_2 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let premine_amount_sat = 100_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a_1.clone(), addr_a_2],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+
+	let utxos = node_a.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 2);
+	assert!(utxos.iter().all(|u| u.value_sats == premine_amount_sat && u.confirmations >= 1));
+	assert!(utxos.iter().all(|u| !u.is_frozen));
+
+	// Freeze one of the outputs and check draining the wallet leaves it untouched.
+	let frozen_utxo = utxos.iter().find(|u| u.address == addr_a_1).unwrap().outpoint;
+	node_a.onchain_payment().freeze_utxo(frozen_utxo).unwrap();
+	assert!(node_a.onchain_payment().list_utxos().iter().any(|u| u.is_frozen));
+
+	let txid = node_a.onchain_payment().send_all_to_address(&addr_b, false, None, None).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+
+	let utxos = node_a.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 1);
+	assert_eq!(utxos[0].outpoint, frozen_utxo);
+	assert!(utxos[0].is_frozen);
+
+	// Frozen outputs won't be selected automatically, but may be spent explicitly.
+	assert_eq!(
+		Err(NodeError::InsufficientFunds),
+		node_a.onchain_payment().send_to_address(&addr_b, 10_000, None, None)
+	);
+	let txid = node_a
+		.onchain_payment()
+		.send_to_address(&addr_b, 10_000, Some(vec![frozen_utxo]), None)
+		.unwrap();
+	wait_for_tx(&electrsd.client, txid);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+
+	let utxos = node_a.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 1);
+	assert_ne!(utxos[0].outpoint, frozen_utxo);
+	assert!(!utxos[0].is_frozen);
+	assert_eq!(Err(NodeError::InvalidUtxo), node_a.onchain_payment().freeze_utxo(frozen_utxo));
+}
+
+#[test]
+fn channel_funding_coin_control() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+
+	println!("== Node A ==");
+	let config_a = random_config(true);
+	let node_a = setup_node(&chain_source, config_a.clone(), None);
+
+	println!("\n== Node B ==");
+	let config_b = random_config(true);
+	let node_b = setup_node(&chain_source, config_b, None);
+
+	let addr_a_1 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_a_2 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_a_3 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let premine_amount_sat = 1_000_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a_1.clone(), addr_a_2.clone(), addr_a_3, addr_b],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	let utxos = node_a.onchain_payment().list_utxos();
+	let frozen_utxo = utxos.iter().find(|u| u.address == addr_a_1).unwrap().outpoint;
+	let selected_utxo = utxos.iter().find(|u| u.address == addr_a_2).unwrap().outpoint;
+	node_a.onchain_payment().freeze_utxo(frozen_utxo).unwrap();
+
+	// Frozen outputs stay frozen across restarts.
+	node_a.stop().unwrap();
+	drop(node_a);
+	let node_a = setup_node(&chain_source, config_a, None);
+	node_a.sync_wallets().unwrap();
+	let utxos = node_a.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 3);
+	assert!(utxos.iter().all(|u| u.is_frozen == (u.outpoint == frozen_utxo)));
+
+	// Channels may be funded from explicitly selected outputs only.
+	node_a
+		.open_channel(
+			node_b.node_id(),
+			node_b.listening_addresses().unwrap().first().unwrap().clone(),
+			400_000,
+			None,
+			None,
+			Some(vec![selected_utxo]),
+			None,
+		)
+		.unwrap();
+	let funding_txo_a = expect_channel_pending_event!(node_a, node_b.node_id());
+	let funding_txo_b = expect_channel_pending_event!(node_b, node_a.node_id());
+	assert_eq!(funding_txo_a, funding_txo_b);
+	wait_for_tx(&electrsd.client, funding_txo_a.txid);
+	let funding_tx = electrsd.client.transaction_get(&funding_txo_a.txid).unwrap();
+	let funding_inputs =
+		funding_tx.input.iter().map(|txin| txin.previous_output).collect::<Vec<_>>();
+	assert_eq!(funding_inputs, vec![selected_utxo]);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// Automatically funded channels never spend frozen outputs, even if that requires spending
+	// all others.
+	open_channel(&node_a, &node_b, 1_400_000, false, &electrsd);
+	let funding_txo = node_a
+		.list_channels()
+		.into_iter()
+		.filter_map(|c| c.funding_txo)
+		.find(|txo| txo.txid != funding_txo_a.txid)
+		.unwrap();
+	let funding_tx = electrsd.client.transaction_get(&funding_txo.txid).unwrap();
+	assert_eq!(funding_tx.input.len(), 2);
+	assert!(funding_tx.input.iter().all(|txin| txin.previous_output != frozen_utxo));
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	let utxos = node_a.onchain_payment().list_utxos();
+	assert!(utxos.iter().any(|u| u.outpoint == frozen_utxo && u.is_frozen));
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
+}
+
+#[test]
+fn onchain_wallet_labels() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
+
+	let addr_a_1 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_a_2 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_a_3 = node_a.onchain_payment().new_address(None).unwrap();
+	let premine_amount_sat = 100_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a_1.clone(), addr_a_2.clone(), addr_a_3.clone()],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node_a.sync_wallets().unwrap();
+
+	// Make an on-chain payment that doesn't spend the outputs we're about to label.
+	let utxo_3 =
+		node_a.onchain_payment().list_utxos().into_iter().find(|u| u.address == addr_a_3).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	let payment_txid = node_a
+		.onchain_payment()
+		.send_to_address(&addr_b, 10_000, Some(vec![utxo_3.outpoint]), None)
+		.unwrap();
+	wait_for_tx(&electrsd.client, payment_txid);
+
+	// Labels are validated against their type.
+	assert_eq!(
+		Err(NodeError::InvalidLabel),
+		node_a.set_label(LabelType::Transaction, addr_a_1.to_string(), "Savings".to_string())
+	);
+	assert_eq!(
+		Err(NodeError::InvalidLabel),
+		node_a.set_label(LabelType::Address, addr_a_1.to_string(), String::new())
+	);
+
+	node_a.set_label(LabelType::Address, addr_a_1.to_string(), "Savings".to_string()).unwrap();
+	node_a.set_label(LabelType::Address, addr_a_2.to_string(), "Donations".to_string()).unwrap();
+
+	let utxos = node_a.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 3);
+	let utxo_1 = utxos.iter().find(|u| u.address == addr_a_1).unwrap().clone();
+	let utxo_2 = utxos.iter().find(|u| u.address == addr_a_2).unwrap().clone();
+	assert_eq!(utxo_1.label, Some("Savings".to_string()));
+	assert_eq!(utxo_2.label, Some("Donations".to_string()));
+
+	// Output labels take precedence over address labels.
+	node_a
+		.set_label(LabelType::Output, utxo_1.outpoint.to_string(), "Cold storage".to_string())
+		.unwrap();
+	let utxo_1_label = node_a
+		.onchain_payment()
+		.list_utxos()
+		.into_iter()
+		.find(|u| u.outpoint == utxo_1.outpoint)
+		.unwrap()
+		.label;
+	assert_eq!(utxo_1_label, Some("Cold storage".to_string()));
+
+	// On-chain payments are labeled with the label of their transaction, unless labeled
+	// themselves.
+	let payment = node_a
+		.list_payments()
+		.into_iter()
+		.find(|p| match p.kind {
+			PaymentKind::Onchain { txid, .. } => txid == payment_txid,
+			_ => false,
+		})
+		.unwrap();
+	assert_eq!(payment.label, None);
+	node_a.set_label(LabelType::Transaction, payment_txid.to_string(), "Rent".to_string()).unwrap();
+	assert_eq!(node_a.payment(&payment.id).unwrap().label, Some("Rent".to_string()));
+	let payment_id_hex = payment.id.0.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+	node_a
+		.set_label(LabelType::Payment, payment_id_hex.clone(), "Office rent".to_string())
+		.unwrap();
+	assert_eq!(node_a.payment(&payment.id).unwrap().label, Some("Office rent".to_string()));
+	node_a.remove_label(LabelType::Payment, payment_id_hex).unwrap();
+	assert_eq!(node_a.payment(&payment.id).unwrap().label, Some("Rent".to_string()));
+	assert_eq!(node_a.list_labels().len(), 4);
+
+	// Frozen outputs are exported as not spendable.
+	node_a.onchain_payment().freeze_utxo(utxo_2.outpoint).unwrap();
+	let export = node_a.export_labels();
+	let records: Vec<serde_json::Value> =
+		export.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+	assert!(records.iter().any(|r| r["type"] == "addr"
+		&& r["ref"] == addr_a_1.to_string()
+		&& r["label"] == "Savings"));
+	assert!(records.iter().any(|r| r["type"] == "output"
+		&& r["ref"] == utxo_1.outpoint.to_string()
+		&& r["spendable"] == true));
+	assert!(records.iter().any(|r| r["type"] == "output"
+		&& r["ref"] == utxo_2.outpoint.to_string()
+		&& r["spendable"] == false));
+
+	// Labels carry over to another wallet.
+	node_b.import_labels(export).unwrap();
+	let labels_a = node_a.list_labels();
+	assert_eq!(node_b.list_labels(), labels_a);
+
+	// Importing records of spendability updates our outputs, and invalid exports are rejected.
+	let unfreeze =
+		format!("{{\"type\":\"output\",\"ref\":\"{}\",\"spendable\":true}}\n", utxo_2.outpoint);
+	node_a.import_labels(unfreeze).unwrap();
+	assert!(node_a.onchain_payment().list_utxos().iter().all(|u| !u.is_frozen));
+	assert_eq!(node_a.list_labels(), labels_a);
+	assert_eq!(Err(NodeError::InvalidLabel), node_a.import_labels("not json".to_string()));
+	assert_eq!(
+		Err(NodeError::InvalidLabel),
+		node_a.import_labels("{\"type\":\"tx\",\"ref\":\"abc\",\"label\":\"x\"}".to_string())
+	);
+}
+
+#[test]
+fn onchain_wallet_labels_import_on_other_nodes() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let seed_bytes = vec![43u8; 64];
+	let node_a = setup_node(&chain_source, random_config(true), Some(seed_bytes.clone()));
+	let node_b = setup_node(&chain_source, random_config(true), None);
+
+	let addr_a_1 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_a_2 = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a_1.clone(), addr_a_2, addr_b],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	// Label and freeze an output, so that it isn't used to fund the channel below.
+	let frozen_utxo =
+		node_a.onchain_payment().list_utxos().into_iter().find(|u| u.address == addr_a_1).unwrap();
+	node_a.onchain_payment().freeze_utxo(frozen_utxo.outpoint).unwrap();
+	node_a
+		.set_label(LabelType::Output, frozen_utxo.outpoint.to_string(), "Cold storage".to_string())
+		.unwrap();
+
+	// Pay node B via a channel, so that both nodes know the payment.
+	open_channel(&node_a, &node_b, 500_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	let invoice = node_b.bolt11_payment().receive(100_000_000, "asdf", 9217).unwrap();
+	let payment_id = node_a.bolt11_payment().send(&invoice, None).unwrap();
+	expect_payment_received_event!(node_b, 100_000_000);
+	expect_payment_successful_event!(node_a, Some(payment_id), None);
+
+	let payment_id_hex = payment_id.0.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+	node_a.set_label(LabelType::Payment, payment_id_hex, "Coffee".to_string()).unwrap();
+	let labels_a = node_a.list_labels();
+	let export = node_a.export_labels();
+
+	// The payee picks up the label of the payment, but ignores the records of outputs that aren't
+	// its own.
+	node_b.import_labels(export.clone()).unwrap();
+	let payment = node_b.list_payments().into_iter().find(|p| p.id == payment_id).unwrap();
+	assert_eq!(payment.label, Some("Coffee".to_string()));
+	assert!(node_b.onchain_payment().list_utxos().iter().all(|u| !u.is_frozen));
+
+	// A node restored from the same seed picks up both the labels and the frozen state of the
+	// outputs.
+	node_a.stop().unwrap();
+	drop(node_a);
+	let node_c = setup_node(&chain_source, random_config(true), Some(seed_bytes));
+	node_c.sync_wallets().unwrap();
+	let utxos = node_c.onchain_payment().list_utxos();
+	assert!(utxos.iter().any(|u| u.outpoint == frozen_utxo.outpoint));
+	assert!(utxos.iter().all(|u| !u.is_frozen && u.label.is_none()));
+
+	node_c.import_labels(export).unwrap();
+	assert_eq!(node_c.list_labels(), labels_a);
+	let utxo = node_c
+		.onchain_payment()
+		.list_utxos()
+		.into_iter()
+		.find(|u| u.outpoint == frozen_utxo.outpoint)
+		.unwrap();
+	assert!(utxo.is_frozen);
+	assert_eq!(utxo.label, Some("Cold storage".to_string()));
+	assert!(node_c
+		.onchain_payment()
+		.list_utxos()
+		.iter()
+		.all(|u| u.is_frozen == (u.outpoint == frozen_utxo.outpoint)));
+
+	node_b.stop().unwrap();
+	node_c.stop().unwrap();
+}
+
+#[test]
+fn onchain_fee_bumping() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
+
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	let txid = node_a.onchain_payment().send_to_address(&addr_b, 100_000, None, None).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	let payment_id = PaymentId(txid.to_byte_array());
+	let payment = node_a.payment(&payment_id).unwrap();
+	let original_fee_sats = match payment.kind {
+		PaymentKind::Onchain { txid: payment_txid, replaced_txids, fee_sats: Some(fee_sats) } => {
+			assert_eq!(payment_txid, txid);
+			assert!(replaced_txids.is_empty());
+			fee_sats
+		},
+		kind => panic!("Unexpected payment kind {:?}", kind),
+	};
+	assert_eq!(payment.amount_msat, Some(100_000_000));
+	assert_eq!(payment.status, PaymentStatus::Pending);
+
+	// Replace the transaction and check the payment now references the replacement.
+	let replacement_txid = node_a.onchain_payment().bump_fee_rbf(txid, 20).unwrap();
+	assert_ne!(replacement_txid, txid);
+	wait_for_tx(&electrsd.client, replacement_txid);
+
+	let payment = node_a.payment(&payment_id).unwrap();
+	match payment.kind {
+		PaymentKind::Onchain { txid: payment_txid, replaced_txids, fee_sats: Some(fee_sats) } => {
+			assert_eq!(payment_txid, replacement_txid);
+			assert_eq!(replaced_txids, vec![txid]);
+			assert!(fee_sats > original_fee_sats);
+		},
+		kind => panic!("Unexpected payment kind {:?}", kind),
+	}
+	assert_eq!(
+		Err(NodeError::InvalidFeeRate),
+		node_a.onchain_payment().bump_fee_rbf(replacement_txid, 10)
+	);
+
+	// Have the recipient bump the incoming transaction further.
+	node_b.sync_wallets().unwrap();
+	let child_txid = node_b.onchain_payment().bump_fee_cpfp(replacement_txid, 50).unwrap();
+	wait_for_tx(&electrsd.client, child_txid);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	assert_eq!(node_a.payment(&payment_id).unwrap().status, PaymentStatus::Succeeded);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats > 90_000);
+	assert!(node_b.list_balances().spendable_onchain_balance_sats < 100_000);
+
+	// If the original transaction confirms after all, e.g., as the miner never saw the
+	// replacement, the payment is switched back to it.
+	let txid = node_a.onchain_payment().send_to_address(&addr_b, 100_000, None, None).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+	let original_tx = electrsd.client.transaction_get(&txid).unwrap();
+	let payment_id = PaymentId(txid.to_byte_array());
+	let original_fee_sats = match node_a.payment(&payment_id).unwrap().kind {
+		PaymentKind::Onchain { fee_sats: Some(fee_sats), .. } => fee_sats,
+		kind => panic!("Unexpected payment kind {:?}", kind),
+	};
+
+	let replacement_txid = node_a.onchain_payment().bump_fee_rbf(txid, 20).unwrap();
+	wait_for_tx(&electrsd.client, replacement_txid);
+
+	let mining_address = bitcoind.client.get_new_address(None, None).unwrap().assume_checked();
+	let original_tx_hex = bitcoin::consensus::encode::serialize_hex(&original_tx);
+	bitcoind
+		.client
+		.call::<serde_json::Value>(
+			"generateblock",
+			&[serde_json::json!(mining_address.to_string()), serde_json::json!([original_tx_hex])],
+		)
+		.unwrap();
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 5);
+	node_a.sync_wallets().unwrap();
+
+	let payment = node_a.payment(&payment_id).unwrap();
+	assert_eq!(payment.status, PaymentStatus::Succeeded);
+	let expected_kind = PaymentKind::Onchain {
+		txid,
+		replaced_txids: vec![replacement_txid],
+		fee_sats: Some(original_fee_sats),
+	};
+	assert_eq!(payment.kind, expected_kind);
+}
+
+#[test]
+fn onchain_fee_rates_and_preview() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
+
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	assert_eq!(
+		Err(NodeError::InvalidFeeRate),
+		node_a.onchain_payment().send_to_address(&addr_b, 100_000, None, Some(0))
+	);
+
+	let preview =
+		node_a.onchain_payment().preview_send_to_address(&addr_b, 100_000, None, Some(25)).unwrap();
+	assert!(preview.vsize > 0);
+	assert!(preview.fee_sats >= preview.vsize * 25);
+
+	let txid = node_a.onchain_payment().send_to_address(&addr_b, 100_000, None, Some(25)).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	let tx = electrsd.client.transaction_get(&txid).unwrap();
+	let sent_sats = tx.output.iter().map(|o| o.value.to_sat()).sum::<u64>();
+	assert_eq!(1_000_000 - sent_sats, preview.fee_sats);
+	// The preview predicts the maximum size of the signatures, so it's an upper bound.
+	assert!(tx.vsize() as u64 <= preview.vsize);
+	assert!(tx.vsize() as u64 + 2 >= preview.vsize);
+
+	let preview_all =
+		node_a.onchain_payment().preview_send_all_to_address(&addr_b, false, None, None).unwrap();
+	assert!(preview_all.fee_sats > 0);
+	assert_eq!(node_a.list_payments().len(), 1);
+}
+
+#[test]
+fn onchain_send_batch() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
+
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+
+	let addr_b_1 = node_b.onchain_payment().new_address(None).unwrap();
+	let addr_b_2 = node_b.onchain_payment().new_address(None).unwrap();
+
+	let too_large_batch =
+		vec![(addr_b_1.clone(), 600_000).into(), (addr_b_2.clone(), 600_000).into()];
+	assert_eq!(
+		Err(NodeError::InsufficientFunds),
+		node_a.onchain_payment().send_batch(too_large_batch, None)
+	);
+
+	// Paying the same address twice results in two distinct payments.
+	let recipients = vec![
+		(addr_b_1.clone(), 100_000).into(),
+		(addr_b_2, 200_000).into(),
+		(addr_b_1, 100_000).into(),
+	];
+	let txid = node_a.onchain_payment().send_batch(recipients, Some(5)).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	let payments = node_a.list_payments();
+	assert_eq!(payments.len(), 3);
+	assert!(payments
+		.iter()
+		.all(|p| matches!(p.kind, PaymentKind::Onchain { txid: payment_txid, ref replaced_txids, fee_sats: Some(_) } if payment_txid == txid && replaced_txids.is_empty())));
+	let payment_ids = payments.iter().map(|p| p.id).collect::<HashSet<_>>();
+	assert_eq!(payment_ids.len(), 3);
+	let total_amount_msat = payments.iter().map(|p| p.amount_msat.unwrap()).sum::<u64>();
+	assert_eq!(total_amount_msat, 400_000_000);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	assert!(node_a.list_payments().iter().all(|p| p.status == PaymentStatus::Succeeded));
+	assert_eq!(node_b.list_balances().spendable_onchain_balance_sats, 400_000);
+}
+
+#[test]
+fn onchain_psbt_external_signing() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let node_b = setup_node(&chain_source, random_config(true), None);
+
+	// Derive the watch-only wallet's account key from a seed only known to the external signer.
+	let secp = Secp256k1::new();
+	let signer_xprv = Xpriv::new_master(Network::Regtest, &[42u8; 64]).unwrap();
+	let account_path = DerivationPath::from_str("m/84'/1'/0'").unwrap();
+	let account_xpub =
+		Xpub::from_priv(&secp, &signer_xprv.derive_priv(&secp, &account_path).unwrap());
+	let master_fingerprint = signer_xprv.fingerprint(&secp);
+
+	// Anchor channels aren't supported by watch-only wallets.
+	let config = random_config(true);
+	setup_builder!(builder, config);
+	builder.set_onchain_wallet_watch_only(account_xpub, master_fingerprint);
+	assert!(matches!(builder.build(), Err(BuildError::WalletSetupFailed)));
+
+	let config = random_config(false);
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	setup_builder!(builder, config);
+	builder.set_chain_source_esplora(esplora_url, None);
+	builder.set_onchain_wallet_watch_only(account_xpub, master_fingerprint);
+	let node_a = builder.build().unwrap();
+	node_a.start().unwrap();
+
+	let addr_a = node_a.onchain_payment().new_address(None).unwrap();
+	let addr_b = node_b.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_a],
+		Amount::from_sat(1_000_000),
+	);
+	node_a.sync_wallets().unwrap();
+	assert_eq!(node_a.list_balances().spendable_onchain_balance_sats, 1_000_000);
+
+	// The watch-only wallet can't sign by itself.
+	assert_eq!(
+		Err(NodeError::OnchainTxCreationFailed),
+		node_a.onchain_payment().send_to_address(&addr_b, 100_000, None, None)
+	);
+
+	// The output spent by a pending PSBT is reserved until the PSBT is cancelled.
+	let recipients = vec![(addr_b.clone(), 100_000).into()];
+	let psbt = node_a.onchain_payment().create_psbt(recipients.clone(), Some(5)).unwrap();
+	assert_eq!(
+		Err(NodeError::InsufficientFunds),
+		node_a.onchain_payment().create_psbt(recipients.clone(), Some(5))
+	);
+	node_a.onchain_payment().cancel_psbt(psbt.clone()).unwrap();
+	assert_eq!(Err(NodeError::InvalidPsbt), node_a.onchain_payment().cancel_psbt(psbt));
+
+	let mut psbt = node_a.onchain_payment().create_psbt(recipients, Some(5)).unwrap();
+	assert_eq!(
+		Err(NodeError::InvalidPsbt),
+		node_a.onchain_payment().finalize_and_broadcast(psbt.clone())
+	);
+
+	let signer_wallet = bdk_wallet::Wallet::create(
+		Bip84(signer_xprv, KeychainKind::External),
+		Bip84(signer_xprv, KeychainKind::Internal),
+	)
+	.network(Network::Regtest)
+	.create_wallet_no_persist()
+	.unwrap();
+	let sign_options = SignOptions { try_finalize: false, ..Default::default() };
+	signer_wallet.sign(&mut psbt, sign_options).unwrap();
+
+	let txid = node_a.onchain_payment().finalize_and_broadcast(psbt).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	let payment = node_a.payment(&PaymentId(txid.to_byte_array())).unwrap();
+	assert_eq!(payment.amount_msat, Some(100_000_000));
+	assert!(matches!(
+		payment.kind,
+		PaymentKind::Onchain { txid: payment_txid, ref replaced_txids, fee_sats: Some(_) }
+			if payment_txid == txid && replaced_txids.is_empty()
+	));
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node_b.sync_wallets().unwrap();
+	assert_eq!(node_b.list_balances().spendable_onchain_balance_sats, 100_000);
+}
+
 #[test]
 fn onchain_wallet_recovery() {
 	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
@@ -380,24 +1385,186 @@ fn onchain_wallet_recovery() {
 
 	let premine_amount_sat = 100_000;
 
-	let addr_1 = original_node.onchain_payment().new_address().unwrap();
+	let addr_1 = original_node.onchain_payment().new_address(None).unwrap();
+
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![addr_1],
+		Amount::from_sat(premine_amount_sat),
+	);
+	original_node.sync_wallets().unwrap();
+	assert_eq!(original_node.list_balances().spendable_onchain_balance_sats, premine_amount_sat);
+
+	let addr_2 = original_node.onchain_payment().new_address(None).unwrap();
+
+	let txid = bitcoind
+		.client
+		.send_to_address(
+			&addr_2,
+			Amount::from_sat(premine_amount_sat),
+			None,
+			None,
+			None,
+			None,
+			None,
+			None,
+		)
+		.unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+
+	original_node.sync_wallets().unwrap();
+	assert_eq!(
+		original_node.list_balances().spendable_onchain_balance_sats,
+		premine_amount_sat * 2
+	);
+
+	original_node.stop().unwrap();
+	drop(original_node);
+
+	// Now we start from scratch, only the seed remains the same.
+	let recovered_config = random_config(true);
+	let recovered_node = setup_node(&chain_source, recovered_config, Some(seed_bytes));
+
+	recovered_node.sync_wallets().unwrap();
+	assert_eq!(
+		recovered_node.list_balances().spendable_onchain_balance_sats,
+		premine_amount_sat * 2
+	);
+
+	// Check we sync even when skipping some addresses.
+	let _addr_3 = recovered_node.onchain_payment().new_address(None).unwrap();
+	let _addr_4 = recovered_node.onchain_payment().new_address(None).unwrap();
+	let _addr_5 = recovered_node.onchain_payment().new_address(None).unwrap();
+	let addr_6 = recovered_node.onchain_payment().new_address(None).unwrap();
+
+	let txid = bitcoind
+		.client
+		.send_to_address(
+			&addr_6,
+			Amount::from_sat(premine_amount_sat),
+			None,
+			None,
+			None,
+			None,
+			None,
+			None,
+		)
+		.unwrap();
+	wait_for_tx(&electrsd.client, txid);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+
+	recovered_node.sync_wallets().unwrap();
+	assert_eq!(
+		recovered_node.list_balances().spendable_onchain_balance_sats,
+		premine_amount_sat * 3
+	);
+}
+
+#[test]
+fn onchain_wallet_address_types() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	let config = random_config(true);
+	let node = setup_node(&chain_source, config.clone(), None);
 
+	let premine_amount_sat = 1_000_000;
+	let native_segwit_addr = node.onchain_payment().new_address(None).unwrap();
+	assert!(native_segwit_addr.script_pubkey().is_p2wpkh());
 	premine_and_distribute_funds(
 		&bitcoind.client,
 		&electrsd.client,
-		vec![addr_1],
+		vec![native_segwit_addr],
 		Amount::from_sat(premine_amount_sat),
 	);
-	original_node.sync_wallets().unwrap();
-	assert_eq!(original_node.list_balances().spendable_onchain_balance_sats, premine_amount_sat);
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().spendable_onchain_balance_sats, premine_amount_sat);
+
+	node.stop().unwrap();
+	drop(node);
+
+	// Switch the existing node over to Taproot addresses, which doesn't require a full rescan.
+	setup_builder!(builder, config);
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	builder.set_chain_source_esplora(esplora_url, None);
+	builder.set_onchain_wallet_address_type(AddressType::Taproot);
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	let node = builder.build_with_store(test_sync_store).unwrap();
+	assert!(node.status().latest_onchain_wallet_sync_timestamp.is_some());
+	node.start().unwrap();
+
+	let taproot_addr = node.onchain_payment().new_address(None).unwrap();
+	assert!(taproot_addr.script_pubkey().is_p2tr());
+	let nested_segwit_addr =
+		node.onchain_payment().new_address(Some(AddressType::NestedSegwit)).unwrap();
+	assert!(nested_segwit_addr.script_pubkey().is_p2sh());
 
-	let addr_2 = original_node.onchain_payment().new_address().unwrap();
+	// The funds received before stay where they are, and remain spendable.
+	node.sync_wallets().unwrap();
+	assert!(bitcoind.client.get_raw_mempool().unwrap().is_empty());
+	let utxos = node.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 1);
+	assert!(utxos[0].address.script_pubkey().is_p2wpkh());
+	assert!(utxos[0].confirmations > 0);
+	let balances = node.list_balances();
+	assert_eq!(balances.total_onchain_balance_sats, premine_amount_sat);
+	assert_eq!(balances.spendable_onchain_balance_sats, premine_amount_sat);
+
+	// Funds received on addresses of other types are tracked, too.
+	let mut txids = Vec::new();
+	for (addr, amount_sat) in [(&nested_segwit_addr, 100_000), (&taproot_addr, 50_000)] {
+		let txid = bitcoind
+			.client
+			.send_to_address(addr, Amount::from_sat(amount_sat), None, None, None, None, None, None)
+			.unwrap();
+		txids.push(txid);
+	}
+	for txid in txids {
+		wait_for_tx(&electrsd.client, txid);
+	}
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
 
+	node.sync_wallets().unwrap();
+	assert!(bitcoind.client.get_raw_mempool().unwrap().is_empty());
+	let utxos = node.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 3);
+	let spendable_sats = node.list_balances().spendable_onchain_balance_sats;
+	assert_eq!(spendable_sats, premine_amount_sat + 150_000);
+
+	// Funds of the Taproot wallet are spent first.
+	let addr = bitcoind.client.get_new_address(None, None).unwrap().assume_checked();
+	let txid = node.onchain_payment().send_to_address(&addr, 30_000, None, None).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node.sync_wallets().unwrap();
+	let utxos = node.onchain_payment().list_utxos();
+	assert_eq!(utxos.len(), 3);
+	assert!(utxos.iter().any(|u| u.address.script_pubkey().is_p2wpkh()));
+	assert!(utxos.iter().any(|u| u.address.script_pubkey().is_p2sh()));
+	let spendable_sats = node.list_balances().spendable_onchain_balance_sats;
+	assert!(spendable_sats < premine_amount_sat + 120_000);
+
+	// Funds of the other wallets are spent once the ones of the Taproot wallet don't suffice.
+	let txid = node.onchain_payment().send_to_address(&addr, 500_000, None, None).unwrap();
+	wait_for_tx(&electrsd.client, txid);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node.sync_wallets().unwrap();
+	assert!(node.list_balances().spendable_onchain_balance_sats < spendable_sats - 500_000);
+	assert!(node.onchain_payment().list_utxos().iter().any(|u| u
+		.address
+		.script_pubkey()
+		.is_p2tr()
+		&& u.confirmations > 0));
+
+	// Incoming transactions paying the wallets of other address types can be bumped via CPFP.
 	let txid = bitcoind
 		.client
 		.send_to_address(
-			&addr_2,
-			Amount::from_sat(premine_amount_sat),
+			&nested_segwit_addr,
+			Amount::from_sat(100_000),
 			None,
 			None,
 			None,
@@ -407,38 +1574,98 @@ fn onchain_wallet_recovery() {
 		)
 		.unwrap();
 	wait_for_tx(&electrsd.client, txid);
+	node.sync_wallets().unwrap();
+	let child_txid = node.onchain_payment().bump_fee_cpfp(txid, 100).unwrap();
+	wait_for_tx(&electrsd.client, child_txid);
+	let child_tx = electrsd.client.transaction_get(&child_txid).unwrap();
+	assert!(child_tx.input.iter().any(|txin| txin.previous_output.txid == txid));
+}
 
-	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
-
-	original_node.sync_wallets().unwrap();
-	assert_eq!(
-		original_node.list_balances().spendable_onchain_balance_sats,
-		premine_amount_sat * 2
-	);
+#[test]
+fn onchain_watch_only_descriptors() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+	do_onchain_watch_only_descriptors(&chain_source, &bitcoind, &electrsd);
+}
 
-	original_node.stop().unwrap();
-	drop(original_node);
+#[test]
+fn onchain_watch_only_descriptors_bitcoind() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::BitcoindRpc(&bitcoind);
+	do_onchain_watch_only_descriptors(&chain_source, &bitcoind, &electrsd);
+}
 
-	// Now we start from scratch, only the seed remains the same.
-	let recovered_config = random_config(true);
-	let recovered_node = setup_node(&chain_source, recovered_config, Some(seed_bytes));
+fn do_onchain_watch_only_descriptors(
+	chain_source: &TestChainSource, bitcoind: &BitcoinD, electrsd: &ElectrsD,
+) {
+	let config = random_config(true);
+	let node = setup_node(chain_source, config.clone(), None);
+
+	// Fund a cold-storage wallet before its descriptor is imported.
+	let secp = Secp256k1::new();
+	let cold_xprv = Xpriv::new_master(Network::Regtest, &[23u8; 64]).unwrap();
+	let account_path = DerivationPath::from_str("m/84'/1'/0'").unwrap();
+	let account_xpub =
+		Xpub::from_priv(&secp, &cold_xprv.derive_priv(&secp, &account_path).unwrap());
+	let descriptor = format!("wpkh({}/<0;1>/*)", account_xpub);
+	let mut cold_wallet = bdk_wallet::Wallet::create(
+		Bip84(cold_xprv, KeychainKind::External),
+		Bip84(cold_xprv, KeychainKind::Internal),
+	)
+	.network(Network::Regtest)
+	.create_wallet_no_persist()
+	.unwrap();
+
+	let premine_amount_sat = 500_000;
+	let cold_addr_1 = cold_wallet.reveal_next_address(KeychainKind::External).address;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![cold_addr_1],
+		Amount::from_sat(premine_amount_sat),
+	);
+	node.sync_wallets().unwrap();
+	assert!(node.list_balances().watch_only_balances.is_empty());
 
-	recovered_node.sync_wallets().unwrap();
+	// Descriptors need to be valid and must not contain private keys.
 	assert_eq!(
-		recovered_node.list_balances().spendable_onchain_balance_sats,
-		premine_amount_sat * 2
+		Err(NodeError::InvalidDescriptor),
+		node.onchain_payment().import_watch_only_descriptor("wpkh(foo)".to_string(), 0)
+	);
+	assert_eq!(
+		Err(NodeError::InvalidDescriptor),
+		node.onchain_payment()
+			.import_watch_only_descriptor(format!("wpkh({}/84'/1'/0'/0/*)", cold_xprv), 0)
 	);
 
-	// Check we sync even when skipping some addresses.
-	let _addr_3 = recovered_node.onchain_payment().new_address().unwrap();
-	let _addr_4 = recovered_node.onchain_payment().new_address().unwrap();
-	let _addr_5 = recovered_node.onchain_payment().new_address().unwrap();
-	let addr_6 = recovered_node.onchain_payment().new_address().unwrap();
+	node.onchain_payment().import_watch_only_descriptor(descriptor.clone(), 0).unwrap();
+
+	// When syncing via Bitcoin Core RPC, the chain is rescanned in the background.
+	let expected_balances = vec![WatchOnlyBalance {
+		descriptor: descriptor.clone(),
+		total_balance_sats: premine_amount_sat,
+		confirmed_balance_sats: premine_amount_sat,
+	}];
+	for _ in 0..30 {
+		node.sync_wallets().unwrap();
+		if node.list_balances().watch_only_balances == expected_balances {
+			break;
+		}
+		std::thread::sleep(std::time::Duration::from_secs(1));
+	}
+
+	let balances = node.list_balances();
+	assert_eq!(balances.total_onchain_balance_sats, 0);
+	assert_eq!(balances.watch_only_balances, expected_balances);
 
+	// Funds received after the import are picked up by regular syncing, even if they're received
+	// on an address following the last used one.
+	let cold_addr_2 =
+		cold_wallet.reveal_addresses_to(KeychainKind::External, 10).last().unwrap().address;
 	let txid = bitcoind
 		.client
 		.send_to_address(
-			&addr_6,
+			&cold_addr_2,
 			Amount::from_sat(premine_amount_sat),
 			None,
 			None,
@@ -449,14 +1676,24 @@ fn onchain_wallet_recovery() {
 		)
 		.unwrap();
 	wait_for_tx(&electrsd.client, txid);
-
 	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 1);
+	node.sync_wallets().unwrap();
 
-	recovered_node.sync_wallets().unwrap();
-	assert_eq!(
-		recovered_node.list_balances().spendable_onchain_balance_sats,
-		premine_amount_sat * 3
-	);
+	let watch_only_balances = node.list_balances().watch_only_balances;
+	assert_eq!(watch_only_balances.len(), 1);
+	assert_eq!(watch_only_balances[0].confirmed_balance_sats, premine_amount_sat * 2);
+
+	// Importing the same descriptor again is a no-op.
+	node.onchain_payment().import_watch_only_descriptor(descriptor.clone(), 0).unwrap();
+	assert_eq!(node.list_balances().watch_only_balances, watch_only_balances);
+
+	// The imported descriptors are persisted across restarts.
+	node.stop().unwrap();
+	drop(node);
+	let node = setup_node(chain_source, config, None);
+	node.sync_wallets().unwrap();
+	assert_eq!(node.list_balances().watch_only_balances, watch_only_balances);
+	assert_eq!(node.list_balances().total_onchain_balance_sats, 0);
 }
 
 #[test]
@@ -473,6 +1710,40 @@ fn sign_verify_msg() {
 	assert!(node.verify_signature(msg, sig.as_str(), &pkey));
 }
 
+#[test]
+fn pay_human_readable_name_requires_verified_resolution() {
+	let (_bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+
+	// Node B stands in for a resolver that never responds with a valid proof.
+	let node_b = setup_node(&chain_source, random_config(true), None);
+	let mut config_a = random_config(true);
+	config_a.dns_resolvers_node_ids.push(node_b.node_id());
+	let node_a = setup_node(&chain_source, config_a, None);
+
+	let node_b_addr = node_b.listening_addresses().unwrap().first().unwrap().clone();
+	node_a.connect(node_b.node_id(), node_b_addr, false).unwrap();
+
+	assert_eq!(
+		node_a.pay_human_readable_name("not a name", 10_000_000).map(|_| ()),
+		Err(NodeError::InvalidHumanReadableName)
+	);
+	assert_eq!(
+		node_a.pay_human_readable_name("₿alice@example.com", 0).map(|_| ()),
+		Err(NodeError::InvalidAmount)
+	);
+	assert_eq!(
+		node_a.pay_human_readable_name("₿alice@example.com", 10_000_000).map(|_| ()),
+		Err(NodeError::HumanReadableNameResolutionFailed)
+	);
+
+	// Without any configured resolvers, resolution fails right away.
+	assert_eq!(
+		node_b.pay_human_readable_name("₿alice@example.com", 10_000_000).map(|_| ()),
+		Err(NodeError::HumanReadableNameResolutionFailed)
+	);
+}
+
 #[test]
 fn connection_restart_behavior() {
 	do_connection_restart_behavior(true);
@@ -567,7 +1838,7 @@ fn simple_bolt12_send_receive() {
 	let chain_source = TestChainSource::Esplora(&electrsd);
 	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
 
-	let address_a = node_a.onchain_payment().new_address().unwrap();
+	let address_a = node_a.onchain_payment().new_address(None).unwrap();
 	let premine_amount_sat = 5_000_000;
 	premine_and_distribute_funds(
 		&bitcoind.client,
@@ -767,6 +2038,109 @@ fn simple_bolt12_send_receive() {
 		},
 	}
 	assert_eq!(node_a_payments.first().unwrap().amount_msat, Some(overpaid_amount));
+
+	// Check the offers we issued are tracked and can be deactivated.
+	assert_eq!(node_b.bolt12_payment().list_offers().len(), 2);
+	let offer_details = node_b.bolt12_payment().offer(&offer.id()).unwrap();
+	assert_eq!(offer_details.offer, offer);
+	assert_eq!(offer_details.amount_msat, Some(offer_amount_msat));
+	assert_eq!(offer_details.quantity, Some(1));
+	assert!(offer_details.is_active);
+	let offer_stats = node_b.bolt12_payment().offer_stats(&offer.id());
+	assert_eq!(offer_stats.payments_received, 1);
+	assert_eq!(offer_stats.amount_received_msat, expected_amount_msat);
+	assert_eq!(offer_stats.payments_pending, 0);
+
+	assert_eq!(
+		node_b.bolt12_payment().deactivate_offer(&OfferId([42u8; 32])),
+		Err(NodeError::InvalidOfferId)
+	);
+	node_b.bolt12_payment().deactivate_offer(&offer.id()).unwrap();
+	assert!(!node_b.bolt12_payment().offer(&offer.id()).unwrap().is_active);
+	node_a.bolt12_payment().send(&offer, Some(1), None).unwrap();
+	expect_event!(node_a, PaymentFailed);
+}
+
+#[test]
+fn manually_handled_bolt12_invoices() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+
+	println!("== Node A ==");
+	let mut config_a = random_config(true);
+	config_a.manually_handle_bolt12_invoices = true;
+	let node_a = setup_node(&chain_source, config_a, None);
+
+	println!("\n== Node B ==");
+	let config_b = random_config(true);
+	let node_b = setup_node(&chain_source, config_b, None);
+
+	let address_a = node_a.onchain_payment().new_address(None).unwrap();
+	let premine_amount_sat = 5_000_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![address_a],
+		Amount::from_sat(premine_amount_sat),
+	);
+
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_b, 4_000_000, true, &electrsd);
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// Sleep until we broadcasted a node announcement.
+	while node_b.status().latest_node_announcement_broadcast_timestamp.is_none() {
+		std::thread::sleep(std::time::Duration::from_millis(10));
+	}
+
+	// Sleep one more sec to make sure the node announcement propagates.
+	std::thread::sleep(std::time::Duration::from_secs(1));
+
+	let expected_amount_msat = 100_000_000;
+	let offer = node_b.bolt12_payment().receive(expected_amount_msat, "asdf", None, None).unwrap();
+
+	// Only invoices we're waiting on can be paid.
+	assert_eq!(
+		node_a.bolt12_payment().pay_received_invoice(&PaymentId([42u8; 32])),
+		Err(NodeError::InvalidPaymentId)
+	);
+
+	// Approve the received invoice.
+	let payment_id = node_a.bolt12_payment().send(&offer, None, None).unwrap();
+	match node_a.wait_next_event() {
+		ref e @ Event::Bolt12InvoiceReceived {
+			payment_id: invoice_payment_id, amount_msat, ..
+		} => {
+			println!("{} got event {:?}", node_a.node_id(), e);
+			assert_eq!(invoice_payment_id, payment_id);
+			assert_eq!(amount_msat, expected_amount_msat);
+			node_a.event_handled();
+		},
+		ref e => {
+			panic!("node_a got unexpected event!: {:?}", e);
+		},
+	}
+	node_a.bolt12_payment().pay_received_invoice(&payment_id).unwrap();
+	expect_payment_successful_event!(node_a, Some(payment_id), None);
+	expect_payment_received_event!(node_b, expected_amount_msat);
+
+	// Reject the received invoice.
+	let payment_id = node_a.bolt12_payment().send(&offer, None, None).unwrap();
+	expect_event!(node_a, Bolt12InvoiceReceived);
+	node_a.bolt12_payment().abandon(&payment_id).unwrap();
+	expect_event!(node_a, PaymentFailed);
+	assert_eq!(
+		node_a.bolt12_payment().pay_received_invoice(&payment_id),
+		Err(NodeError::InvalidPaymentId)
+	);
+	assert_eq!(node_a.bolt12_payment().abandon(&payment_id), Err(NodeError::InvalidPaymentId));
 }
 
 #[test]
@@ -775,7 +2149,7 @@ fn generate_bip21_uri() {
 	let chain_source = TestChainSource::Esplora(&electrsd);
 	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
 
-	let address_a = node_a.onchain_payment().new_address().unwrap();
+	let address_a = node_a.onchain_payment().new_address(None).unwrap();
 	let premined_sats = 5_000_000;
 
 	premine_and_distribute_funds(
@@ -809,6 +2183,107 @@ fn generate_bip21_uri() {
 		},
 		Err(e) => panic!("Failed to generate URI: {:?}", e),
 	}
+
+	let variable_amount_uri =
+		node_b.unified_qr_payment().receive_variable_amount("asdf", expiry_sec).unwrap();
+	assert!(!variable_amount_uri.contains("amount="));
+	assert!(variable_amount_uri.contains("lightning="));
+	assert!(variable_amount_uri.contains("lno="));
+
+	let mut policy =
+		UnifiedPaymentPolicy { include_bolt12_offer: false, ..UnifiedPaymentPolicy::default() };
+	let uri = node_b
+		.unified_qr_payment()
+		.receive_with_policy(expected_amount_sats, "asdf", expiry_sec, policy.clone())
+		.unwrap();
+	assert!(uri.contains("lightning="));
+	assert!(!uri.contains("lno="));
+
+	// Amounts above the threshold are only to be paid on-chain.
+	policy.prefer_onchain_above_sats = Some(expected_amount_sats - 1);
+	let onchain_uri = node_b
+		.unified_qr_payment()
+		.receive_with_policy(expected_amount_sats, "asdf", expiry_sec, policy)
+		.unwrap();
+	assert!(!onchain_uri.contains("lightning="));
+	assert!(!onchain_uri.contains("lno="));
+
+	// We never pay on-chain if the policy doesn't allow for it.
+	let policy = UnifiedPaymentPolicy {
+		send_order: vec![UnifiedPaymentMethod::Bolt12, UnifiedPaymentMethod::Bolt11],
+		..UnifiedPaymentPolicy::default()
+	};
+	assert_eq!(
+		node_a.unified_qr_payment().send_with_policy(&onchain_uri, policy, None).err(),
+		Some(NodeError::PaymentSendingFailed)
+	);
+}
+
+#[test]
+fn unified_qr_receive_via_jit_channel() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+
+	// A regular node stands in for the LSP. As it doesn't speak LSPS2, any JIT channel request
+	// sent to it times out.
+	println!("== LSP ==");
+	let lsp_node = setup_node(&chain_source, random_config(false), None);
+	let lsp_node_id = lsp_node.node_id();
+	let lsp_address = lsp_node.listening_addresses().unwrap().first().unwrap().clone();
+
+	println!("\n== Client ==");
+	let config = random_config(false);
+	let esplora_url = format!("http://{}", electrsd.esplora_url.as_ref().unwrap());
+	let mut sync_config = EsploraSyncConfig::default();
+	sync_config.onchain_wallet_sync_interval_secs = 100000;
+	sync_config.lightning_wallet_sync_interval_secs = 100000;
+	setup_builder!(builder, config);
+	builder.set_chain_source_esplora(esplora_url, Some(sync_config));
+	builder.set_liquidity_source_lsps2(lsp_address, lsp_node_id, None);
+	let test_sync_store = Arc::new(TestSyncStore::new(config.storage_dir_path.clone().into()));
+	let client_node = builder.build_with_store(test_sync_store).unwrap();
+	client_node.start().unwrap();
+
+	// Lacking any inbound liquidity, we request a JIT channel from the LSP for the invoice, which
+	// fails as the LSP doesn't respond.
+	let expiry_sec = 4_000;
+	assert_eq!(
+		client_node.unified_qr_payment().receive(100_000, "asdf", expiry_sec),
+		Err(NodeError::InvoiceCreationFailed)
+	);
+	assert_eq!(
+		client_node.unified_qr_payment().receive_variable_amount("asdf", expiry_sec),
+		Err(NodeError::InvoiceCreationFailed)
+	);
+
+	let address_lsp = lsp_node.onchain_payment().new_address(None).unwrap();
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![address_lsp],
+		Amount::from_sat(5_000_000),
+	);
+	lsp_node.sync_wallets().unwrap();
+	open_channel(&lsp_node, &client_node, 1_000_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	lsp_node.sync_wallets().unwrap();
+	client_node.sync_wallets().unwrap();
+	expect_channel_ready_event!(lsp_node, client_node.node_id());
+	expect_channel_ready_event!(client_node, lsp_node_id);
+
+	// Once we have enough inbound liquidity, we don't need a JIT channel.
+	let uri = client_node.unified_qr_payment().receive(100_000, "asdf", expiry_sec).unwrap();
+	assert!(uri.contains("lightning="));
+	assert!(uri.contains("lno="));
+
+	// But we still do for amounts exceeding our inbound liquidity.
+	assert_eq!(
+		client_node.unified_qr_payment().receive(2_000_000, "asdf", expiry_sec),
+		Err(NodeError::InvoiceCreationFailed)
+	);
+
+	client_node.stop().unwrap();
+	lsp_node.stop().unwrap();
 }
 
 #[test]
@@ -817,7 +2292,7 @@ fn unified_qr_send_receive() {
 	let chain_source = TestChainSource::Esplora(&electrsd);
 	let (node_a, node_b) = setup_two_nodes(&chain_source, false, true, false);
 
-	let address_a = node_a.onchain_payment().new_address().unwrap();
+	let address_a = node_a.onchain_payment().new_address(None).unwrap();
 	let premined_sats = 5_000_000;
 
 	premine_and_distribute_funds(
@@ -850,15 +2325,15 @@ fn unified_qr_send_receive() {
 
 	let uqr_payment = node_b.unified_qr_payment().receive(expected_amount_sats, "asdf", expiry_sec);
 	let uri_str = uqr_payment.clone().unwrap();
-	let offer_payment_id: PaymentId = match node_a.unified_qr_payment().send(&uri_str) {
-		Ok(QrPaymentResult::Bolt12 { payment_id }) => {
+	let offer_payment_id: PaymentId = match node_a.unified_qr_payment().send(&uri_str, None) {
+		Ok(QrPaymentResult::Bolt12 { payment_id, .. }) => {
 			println!("\nBolt12 payment sent successfully with PaymentID: {:?}", payment_id);
 			payment_id
 		},
-		Ok(QrPaymentResult::Bolt11 { payment_id: _ }) => {
+		Ok(QrPaymentResult::Bolt11 { .. }) => {
 			panic!("Expected Bolt12 payment but got Bolt11");
 		},
-		Ok(QrPaymentResult::Onchain { txid: _ }) => {
+		Ok(QrPaymentResult::Onchain { .. }) => {
 			panic!("Expected Bolt12 payment but get On-chain transaction");
 		},
 		Err(e) => {
@@ -871,15 +2346,15 @@ fn unified_qr_send_receive() {
 	// Cut off the BOLT12 part to fallback to BOLT11.
 	let uri_str_without_offer = uri_str.split("&lno=").next().unwrap();
 	let invoice_payment_id: PaymentId =
-		match node_a.unified_qr_payment().send(uri_str_without_offer) {
-			Ok(QrPaymentResult::Bolt12 { payment_id: _ }) => {
+		match node_a.unified_qr_payment().send(uri_str_without_offer, None) {
+			Ok(QrPaymentResult::Bolt12 { .. }) => {
 				panic!("Expected Bolt11 payment but got Bolt12");
 			},
-			Ok(QrPaymentResult::Bolt11 { payment_id }) => {
+			Ok(QrPaymentResult::Bolt11 { payment_id, .. }) => {
 				println!("\nBolt11 payment sent successfully with PaymentID: {:?}", payment_id);
 				payment_id
 			},
-			Ok(QrPaymentResult::Onchain { txid: _ }) => {
+			Ok(QrPaymentResult::Onchain { .. }) => {
 				panic!("Expected Bolt11 payment but got on-chain transaction");
 			},
 			Err(e) => {
@@ -888,20 +2363,88 @@ fn unified_qr_send_receive() {
 		};
 	expect_payment_successful_event!(node_a, Some(invoice_payment_id), None);
 
+	// Paying the already paid invoice fails, after which we fall back to the offer.
+	let policy = UnifiedPaymentPolicy {
+		send_order: vec![UnifiedPaymentMethod::Bolt11, UnifiedPaymentMethod::Bolt12],
+		..UnifiedPaymentPolicy::default()
+	};
+	let fallback_payment_id =
+		match node_a.unified_qr_payment().send_with_policy(&uri_str, policy, None) {
+			Ok(QrPaymentResult::Bolt12 { payment_id, failed_attempts }) => {
+				assert_eq!(
+					failed_attempts,
+					vec![QrPaymentAttemptFailure {
+						method: UnifiedPaymentMethod::Bolt11,
+						error: NodeError::DuplicatePayment,
+					}]
+				);
+				payment_id
+			},
+			Ok(_) => panic!("Expected Bolt12 payment after failed Bolt11 attempt"),
+			Err(e) => panic!("Expected Bolt12 payment but got error: {:?}", e),
+		};
+	expect_payment_successful_event!(node_a, Some(fallback_payment_id), None);
+
+	// We don't fall back to paying on-chain after a Lightning payment method failed if the policy
+	// disallows it.
+	let policy = UnifiedPaymentPolicy {
+		send_order: vec![UnifiedPaymentMethod::Bolt11, UnifiedPaymentMethod::Onchain],
+		allow_onchain_fallback: false,
+		..UnifiedPaymentPolicy::default()
+	};
+	assert_eq!(
+		node_a.unified_qr_payment().send_with_policy(uri_str_without_offer, policy, None).err(),
+		Some(NodeError::DuplicatePayment)
+	);
+
+	// Lightning payment methods are skipped if we lack the outbound liquidity, leaving us to pay
+	// on-chain, for which we lack the funds, too.
+	let exceeding_uri_str =
+		node_b.unified_qr_payment().receive(4_500_000, "asdf", expiry_sec).unwrap();
+	let policy = UnifiedPaymentPolicy {
+		skip_lightning_without_liquidity: true,
+		..UnifiedPaymentPolicy::default()
+	};
+	assert_eq!(
+		node_a.unified_qr_payment().send_with_policy(&exceeding_uri_str, policy, None).err(),
+		Some(NodeError::InsufficientFunds)
+	);
+
+	// URIs lacking an on-chain address, as commonly resolved from BIP 353 names, can be paid, too.
+	let offer = node_b.bolt12_payment().receive(100_000_000, "asdf", None, None).unwrap();
+	let addressless_uri_str = format!("bitcoin:?amount=0.001&lno={}", offer);
+	let addressless_payment_id = match node_a.unified_qr_payment().send(&addressless_uri_str, None)
+	{
+		Ok(QrPaymentResult::Bolt12 { payment_id, failed_attempts }) => {
+			assert!(failed_attempts.is_empty());
+			payment_id
+		},
+		Ok(QrPaymentResult::Bolt11 { .. }) => {
+			panic!("Expected Bolt12 payment but got Bolt11");
+		},
+		Ok(QrPaymentResult::Onchain { .. }) => {
+			panic!("Expected Bolt12 payment but got on-chain transaction");
+		},
+		Err(e) => {
+			panic!("Expected Bolt12 payment but got error: {:?}", e);
+		},
+	};
+	expect_payment_successful_event!(node_a, Some(addressless_payment_id), None);
+
 	let expect_onchain_amount_sats = 800_000;
 	let onchain_uqr_payment =
 		node_b.unified_qr_payment().receive(expect_onchain_amount_sats, "asdf", 4_000).unwrap();
 
 	// Cut off any lightning part to fallback to on-chain only.
 	let uri_str_without_lightning = onchain_uqr_payment.split("&lightning=").next().unwrap();
-	let txid = match node_a.unified_qr_payment().send(&uri_str_without_lightning) {
-		Ok(QrPaymentResult::Bolt12 { payment_id: _ }) => {
+	let txid = match node_a.unified_qr_payment().send(&uri_str_without_lightning, None) {
+		Ok(QrPaymentResult::Bolt12 { .. }) => {
 			panic!("Expected on-chain payment but got Bolt12")
 		},
-		Ok(QrPaymentResult::Bolt11 { payment_id: _ }) => {
+		Ok(QrPaymentResult::Bolt11 { .. }) => {
 			panic!("Expected on-chain payment but got Bolt11");
 		},
-		Ok(QrPaymentResult::Onchain { txid }) => {
+		Ok(QrPaymentResult::Onchain { txid, .. }) => {
 			println!("\nOn-chain transaction successful with Txid: {}", txid);
 			txid
 		},
@@ -917,5 +2460,105 @@ fn unified_qr_send_receive() {
 	node_b.sync_wallets().unwrap();
 
 	assert_eq!(node_b.list_balances().total_onchain_balance_sats, 800_000);
-	assert_eq!(node_b.list_balances().total_lightning_balance_sats, 200_000);
+	assert_eq!(node_b.list_balances().total_lightning_balance_sats, 400_000);
+}
+
+#[test]
+fn unified_qr_payjoin_send_receive() {
+	let (bitcoind, electrsd) = setup_bitcoind_and_electrsd();
+	let chain_source = TestChainSource::Esplora(&electrsd);
+
+	println!("== Node A ==");
+	let config_a = random_config(true);
+	let node_a = setup_node(&chain_source, config_a, None);
+
+	println!("\n== Node B ==");
+	let mut config_b = random_config(true);
+	let payjoin_port = random_port();
+	config_b.payjoin_config = Some(PayjoinConfig {
+		listening_address: format!("127.0.0.1:{}", payjoin_port).parse().unwrap(),
+		endpoint_url: format!("http://127.0.0.1:{}/payjoin", payjoin_port),
+	});
+	let node_b = setup_node(&chain_source, config_b, None);
+
+	let address_a = node_a.onchain_payment().new_address(None).unwrap();
+	let address_b = node_b.onchain_payment().new_address(None).unwrap();
+	let premined_sats = 5_000_000;
+	premine_and_distribute_funds(
+		&bitcoind.client,
+		&electrsd.client,
+		vec![address_a, address_b],
+		Amount::from_sat(premined_sats),
+	);
+
+	node_a.sync_wallets().unwrap();
+	open_channel(&node_a, &node_b, 1_000_000, true, &electrsd);
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+	expect_channel_ready_event!(node_a, node_b.node_id());
+	expect_channel_ready_event!(node_b, node_a.node_id());
+
+	// Cut off any lightning part to fallback to on-chain only.
+	let receive_onchain_only = |amount_sats| {
+		let uri_str = node_b.unified_qr_payment().receive(amount_sats, "asdf", 4_000).unwrap();
+		assert!(uri_str.contains("pj="));
+		uri_str
+			.split('&')
+			.filter(|param| !param.starts_with("lightning=") && !param.starts_with("lno="))
+			.collect::<Vec<_>>()
+			.join("&")
+	};
+
+	// With all of its UTXOs frozen, the receiver has nothing to contribute and rejects the payjoin
+	// request, which has the sender fall back to a regular transaction.
+	let utxos_b = node_b.onchain_payment().list_utxos();
+	assert_eq!(utxos_b.len(), 1);
+	node_b.onchain_payment().freeze_utxo(utxos_b[0].outpoint).unwrap();
+
+	let expected_amount_sats = 100_000;
+	let uri_str = receive_onchain_only(expected_amount_sats);
+	let fallback_txid = match node_a.unified_qr_payment().send(&uri_str, None) {
+		Ok(QrPaymentResult::Onchain { txid, .. }) => txid,
+		Ok(_) => panic!("Expected on-chain payment"),
+		Err(e) => panic!("Expected on-chain payment but got error: {:?}", e),
+	};
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, fallback_txid);
+	let fallback_tx = electrsd.client.transaction_get(&fallback_txid).unwrap();
+	assert_eq!(fallback_tx.input.len(), 1);
+	assert!(fallback_tx.input.iter().all(|txin| txin.previous_output != utxos_b[0].outpoint));
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	// Once the UTXO is unfrozen, paying a new URI results in a payjoin.
+	node_b.onchain_payment().unfreeze_utxo(utxos_b[0].outpoint).unwrap();
+	let onchain_balance_b = node_b.list_balances().total_onchain_balance_sats;
+	let uri_str = receive_onchain_only(expected_amount_sats);
+	let txid = match node_a.unified_qr_payment().send(&uri_str, None) {
+		Ok(QrPaymentResult::Onchain { txid, .. }) => txid,
+		Ok(_) => panic!("Expected on-chain payment"),
+		Err(e) => panic!("Expected on-chain payment but got error: {:?}", e),
+	};
+
+	generate_blocks_and_wait(&bitcoind.client, &electrsd.client, 6);
+	wait_for_tx(&electrsd.client, txid);
+
+	// The payjoin transaction spends an input of each node.
+	let tx = electrsd.client.transaction_get(&txid).unwrap();
+	assert_eq!(tx.input.len(), 2);
+
+	node_a.sync_wallets().unwrap();
+	node_b.sync_wallets().unwrap();
+
+	// The receiver may only pay a negligible share of the fee for its own input.
+	let received_sats = node_b.list_balances().total_onchain_balance_sats - onchain_balance_b;
+	assert!(received_sats <= expected_amount_sats);
+	assert!(received_sats >= expected_amount_sats - 10);
+
+	node_a.stop().unwrap();
+	node_b.stop().unwrap();
 }